@@ -0,0 +1,142 @@
+//! How long the CPU side of a frame takes. Run with `cargo bench`, adding
+//! `--features image` for the dithering benchmarks.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use f16_hid::binding::{Value, Watch};
+use f16_hid::geometry::Rect;
+use f16_hid::layout::{Damage, Layout};
+use f16_hid::transport::MockTransport;
+use f16_hid::widgets::{BarBorder, BarGraph, Widget};
+use f16_hid::{Bitmap8, ColumnIndex, ColumnUpdate, Command, Display, LedMatrix, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+fn gradient() -> Bitmap8 {
+    let mut frame = Bitmap8::new();
+
+    for x in 0 .. DISPLAY_WIDTH {
+        for y in 0 .. DISPLAY_HEIGHT {
+            frame.draw_point(x, y, (y * 255 / DISPLAY_HEIGHT) as u8).unwrap();
+        }
+    }
+
+    frame
+}
+
+fn packing(c: &mut Criterion) {
+    let mut matrix = LedMatrix::with_transport("bench", MockTransport::new());
+    let column = ColumnUpdate::new(ColumnIndex::new(4).unwrap(), [0x80u8; DISPLAY_HEIGHT]);
+    let mut bitmap = f16_hid::Bitmap::new();
+    bitmap.fill(0xaa);
+
+    c.bench_function("pack stage column", |b| {
+        b.iter(|| matrix.execute(Command::StageColumnBuffer(black_box(column))).unwrap())
+    });
+
+    c.bench_function("pack draw", |b| {
+        b.iter(|| matrix.execute(Command::Draw(Box::new(black_box(bitmap.clone())))).unwrap())
+    });
+}
+
+/// A number that changes every frame
+struct Number {
+    watch: Watch<u8>,
+}
+
+impl Widget for Number {
+    fn render(&mut self, canvas: &mut Bitmap8, area: Rect) {
+        canvas.draw_text(area.origin, &self.watch.get().to_string(), 0xff);
+    }
+
+    fn is_dirty(&mut self) -> bool {
+        self.watch.changed()
+    }
+}
+
+fn frame_assembly(c: &mut Criterion) {
+    let mut layout = Layout::new();
+    let level = Value::new(0u8);
+
+    let mut graph = BarGraph::new(8).border(BarBorder::Ends, 0).divider(Some(4));
+    graph.set_values(&[10.0, 20.0, 30.0, 40.0, 50.0, 60.0, 70.0, 80.0]);
+    layout.add(Rect::new((0, 14), (DISPLAY_WIDTH, 20)), graph);
+
+    layout.add(Rect::new((0, 0), (DISPLAY_WIDTH, 12)), Number { watch: level.watch() });
+
+    c.bench_function("layout render", |b| {
+        b.iter(|| {
+            level.set(level.get().wrapping_add(1));
+            layout.render()
+        })
+    });
+}
+
+fn diffing(c: &mut Criterion) {
+    let before = gradient();
+    let mut after = before.clone();
+    after.fill_rect(Rect::new((2, 10), (3, 5)), 0);
+
+    c.bench_function("damage between", |b| {
+        b.iter(|| Damage::between(black_box(&before), black_box(&after)))
+    });
+
+    c.bench_function("frame diff", |b| {
+        b.iter(|| f16_hid::diff::FrameDiff::new(black_box(&before), black_box(&after)).changed())
+    });
+}
+
+/// Whole greyscale frames, every column different from the last, to a mock
+/// that throws them away. Reported as frames per second.
+fn upload(c: &mut Criterion) {
+    let frames = [gradient(), {
+        let mut shifted = gradient();
+        shifted.rotate_down(DISPLAY_HEIGHT / 2);
+        shifted
+    }];
+
+    let mut group = c.benchmark_group("full frame");
+    group.throughput(Throughput::Elements(1));
+
+    for (name, fast) in [("present", false), ("present fast", true)] {
+        let mock = MockTransport::new();
+        let mut display = Display::new(LedMatrix::with_transport("bench", mock.clone()));
+        let mut frame = 0;
+
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                frame = (frame + 1) % frames.len();
+                *display.back_mut() = frames[frame].clone();
+
+                match fast {
+                    true => display.present_fast().unwrap(),
+                    false => display.present().unwrap(),
+                }
+
+                mock.take_written()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+#[cfg(feature = "image")]
+fn dithering(c: &mut Criterion) {
+    use f16_hid::imaging::{Dither, ImageOptions};
+    use image::{DynamicImage, GrayImage, Luma};
+
+    let image = DynamicImage::ImageLuma8(GrayImage::from_fn(90, 340, |x, y| Luma([((x + y) % 256) as u8])));
+
+    for (name, dither) in [("ordered", Dither::Ordered), ("floyd steinberg", Dither::FloydSteinberg)] {
+        let options = ImageOptions { dither, levels: 4, ..ImageOptions::default() };
+
+        c.bench_function(&format!("dither {}", name), |b| {
+            b.iter(|| Bitmap8::from_image(black_box(&image), &options))
+        });
+    }
+}
+
+#[cfg(not(feature = "image"))]
+fn dithering(_: &mut Criterion) {}
+
+criterion_group!(benches, packing, frame_assembly, diffing, upload, dithering);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+use std::io::{BufRead, Write};
+use f16_hid::calibration::{self, Judgement};
+use f16_hid::roles::RoleConfig;
+use f16_hid::{discovery, LedMatrix};
+
+fn main() {
+    let path = std::env::args().nth(1).or_else(|| {
+        discovery::discover(&RoleConfig::new())
+            .expect("Unable to list serial ports")
+            .into_iter()
+            .next()
+            .map(|x| x.path)
+    });
+
+    let path = match path {
+        Some(x) => x,
+        None => {
+            eprintln!("No LED matrix modules found. Usage: calibrate [port]");
+            std::process::exit(1);
+        },
+    };
+
+    let mut matrix = LedMatrix::new(&path).expect("Unable to open the LED matrix");
+    let stdin = std::io::stdin();
+
+    println!("The top and bottom of the panel show two brightnesses. Say whether");
+    println!("the middle looks [d]immer, [b]righter or [h]alfway between them.");
+
+    let mut judge = |_low, _middle, _high| {
+        loop {
+            print!("Middle is [d]immer, [b]righter or [h]alfway? ");
+            std::io::stdout().flush().expect("Unable to write to stdout");
+
+            let mut answer = String::new();
+            stdin.lock().read_line(&mut answer).expect("Unable to read from stdin");
+
+            match answer.trim() {
+                "d" | "dimmer" => return Judgement::Dimmer,
+                "b" | "brighter" => return Judgement::Brighter,
+                "h" | "halfway" => return Judgement::Halfway,
+                _ => println!("Didn't catch that"),
+            }
+        }
+    };
+
+    let lut = calibration::calibrate(&mut matrix, &mut judge, 3).expect("Calibration failed");
+    matrix.clear().expect("Unable to clear the LED matrix");
+
+    lut.save_default().expect("Unable to save brightness table");
+
+    println!("Saved to {:?}", calibration::GammaLut::default_path());
+}
@@ -0,0 +1,25 @@
+use f16_hid::geometry::Rect;
+use f16_hid::layout::Grid;
+use f16_hid::monitor::{CpuSource, DiskSource, MemorySource, MonitorRunner, NetworkSource, TemperatureSource};
+use f16_hid::LedMatrix;
+
+fn main() {
+    let mut runner = MonitorRunner::new();
+
+    // Modules with a role from the setup example come first, left then right
+    for found in LedMatrix::discover().expect("Unable to list serial ports") {
+        runner.add_panel(found.open().expect("Unable to open the LED matrix module"));
+    }
+
+    // Gauges across the top, a bar per core below
+    let grid = Grid::new(Rect::display(), 2, 2);
+
+    runner.add_source(0, grid.cell(0, 0), MemorySource::new());
+    runner.add_source(0, grid.cell(1, 0), TemperatureSource::new());
+    runner.add_source(0, grid.span(0, 1, 2, 1), CpuSource::new());
+    runner.add_source(1, grid.cell(0, 0), NetworkSource::new(12_500_000));
+    runner.add_source(1, grid.cell(1, 0), DiskSource::new());
+
+    runner.on_error(|path, error| eprintln!("Unable to update {}: {:?}", path, error));
+    runner.run();
+}
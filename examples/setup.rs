@@ -0,0 +1,47 @@
+use std::io::{BufRead, Write};
+use f16_hid::roles::{Role, RoleConfig};
+use f16_hid::{discovery, setup};
+
+fn main() {
+    let mut paths: Vec<String> = std::env::args().skip(1).collect();
+
+    if paths.is_empty() {
+        paths = discovery::discover(&RoleConfig::new())
+            .expect("Unable to list serial ports")
+            .into_iter()
+            .map(|x| x.path)
+            .collect();
+    }
+
+    if paths.is_empty() {
+        eprintln!("No LED matrix modules found. Usage: setup [port ...]");
+        std::process::exit(1);
+    }
+
+    let paths: Vec<&str> = paths.iter().map(|x| x.as_str()).collect();
+    let mut roles = RoleConfig::load_default().expect("Unable to read role config");
+    let stdin = std::io::stdin();
+
+    let assigned = setup::assign_roles(&paths, &mut roles, |candidate| {
+        loop {
+            print!("Module {} at {} ({}) is flashing. Is it [l]eft, [r]ight, [e]xternal or [s]kip? ",
+                candidate.number, candidate.path, candidate.serial);
+            std::io::stdout().flush().ok()?;
+
+            let mut answer = String::new();
+            stdin.lock().read_line(&mut answer).ok()?;
+
+            match answer.trim() {
+                "l" | "left" => return Some(Role::Left),
+                "r" | "right" => return Some(Role::Right),
+                "e" | "external" => return Some(Role::External(candidate.serial.to_owned())),
+                "s" | "skip" => return None,
+                _ => println!("Didn't catch that"),
+            }
+        }
+    }).expect("Setup failed");
+
+    roles.save_default().expect("Unable to save role config");
+
+    println!("Assigned {} module(s). Saved to {:?}", assigned, RoleConfig::default_path());
+}
@@ -0,0 +1,22 @@
+//! Shows frames written to stdin, see `f16_hid::stream` for the format:
+//!
+//! python3 frames.py | cargo run --example stream [port]
+
+use f16_hid::Display;
+
+fn main() {
+    let display = match std::env::args().nth(1) {
+        Some(path) => Display::open(&path),
+        None => Display::open_default(),
+    };
+
+    let mut display = display.expect("Unable to open the LED matrix");
+
+    match display.serve_reader(std::io::stdin().lock()) {
+        Ok(count) => eprintln!("Showed {} frames", count),
+        Err(error) => {
+            eprintln!("Stopped: {}", error);
+            std::process::exit(1);
+        },
+    }
+}
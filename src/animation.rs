@@ -0,0 +1,344 @@
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::events::is_link_lost;
+use crate::{Bitmap8, LedMatrix, RECONNECT_DELAY};
+
+/// How many frames in a row can fail before the animator gives up
+pub const DEFAULT_MAX_FAILURES: u32 = 5;
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Frame {
+    pub bitmap: Bitmap8,
+    /// How long the frame stays up before the next one
+    #[cfg_attr(feature = "serde", serde(rename = "duration_ms", with = "crate::serde_impls::millis"))]
+    pub duration: Duration,
+}
+
+/// A list of greyscale frames, each with its own duration
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Animation {
+    frames: Vec<Frame>,
+}
+
+impl Animation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every frame shown for the same amount of time
+    pub fn from_frames(frames: impl IntoIterator<Item = Bitmap8>, duration: Duration) -> Self {
+        Self {
+            frames: frames.into_iter().map(|bitmap| Frame { bitmap, duration }).collect(),
+        }
+    }
+
+    pub fn push(&mut self, bitmap: Bitmap8, duration: Duration) {
+        self.frames.push(Frame { bitmap, duration });
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Time to play every frame once
+    pub fn duration(&self) -> Duration {
+        self.frames.iter().map(|x| x.duration).sum()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PlayMode {
+    /// Play through once and leave the last frame up
+    #[default]
+    Once,
+    /// Start again from the first frame after the last
+    Loop,
+    /// Play forwards then backwards, forever
+    PingPong,
+}
+
+/// Plays an `Animation` on a matrix. Frames are paced against when they were
+/// due rather than when they were drawn, so slow writes don't make the
+/// animation drift. A frame that fails to draw is retried, reconnecting the
+/// port if it went away, until too many fail in a row.
+pub struct Animator<C: Clock = SystemClock> {
+    animation: Animation,
+    mode: PlayMode,
+    clock: C,
+    /// Frame to draw once `due` arrives, `None` once the last frame is done
+    next: Option<usize>,
+    forward: bool,
+    due: Option<Instant>,
+    finished: bool,
+    failures: u32,
+    max_failures: u32,
+    retry_pause: Duration,
+}
+
+impl Animator<SystemClock> {
+    pub fn new(animation: Animation, mode: PlayMode) -> Self {
+        Self::with_clock(animation, mode, SystemClock)
+    }
+}
+
+impl<C: Clock> Animator<C> {
+    pub fn with_clock(animation: Animation, mode: PlayMode, clock: C) -> Self {
+        let mut animator = Self {
+            animation,
+            mode,
+            clock,
+            next: None,
+            forward: true,
+            due: None,
+            finished: false,
+            failures: 0,
+            max_failures: DEFAULT_MAX_FAILURES,
+            retry_pause: RECONNECT_DELAY,
+        };
+
+        animator.restart();
+        animator
+    }
+
+    pub fn animation(&self) -> &Animation {
+        &self.animation
+    }
+
+    pub fn mode(&self) -> PlayMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: PlayMode) {
+        self.mode = mode;
+    }
+
+    /// How many frames in a row can fail to draw before `tick()` returns the
+    /// error
+    pub fn set_max_failures(&mut self, failures: u32) {
+        self.max_failures = failures;
+    }
+
+    /// How long to wait before trying a failed frame again
+    pub fn set_retry_pause(&mut self, pause: Duration) {
+        self.retry_pause = pause;
+    }
+
+    /// Go back to the first frame, drawn on the next `tick()`
+    pub fn restart(&mut self) {
+        self.next = if self.animation.is_empty() { None } else { Some(0) };
+        self.forward = true;
+        self.due = None;
+        self.finished = self.animation.is_empty();
+        self.failures = 0;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// How long until the next frame is due
+    pub fn time_to_next_frame(&self) -> Duration {
+        match self.due {
+            Some(due) => due.saturating_duration_since(self.clock.now()),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Draw the next frame if it's due. Returns whether the animation is
+    /// still playing. Call this from a main loop, or use `play()`.
+    pub fn tick(&mut self, matrix: &mut LedMatrix) -> Result<bool, Error> {
+        let index = match self.poll() {
+            Some(x) => x,
+            None => return Ok(!self.finished),
+        };
+
+        let result = if matrix.is_connected() {
+            matrix.stage_frame(&self.animation.frames[index].bitmap)
+        } else {
+            Err(Error::new(ErrorKind::NotConnected, "Port isn't open"))
+        };
+
+        match result {
+            Ok(()) => {
+                self.failures = 0;
+                self.drawn(index);
+            },
+            Err(error) => {
+                self.failures += 1;
+
+                if self.failures > self.max_failures {
+                    self.failures = 0;
+                    return Err(error);
+                }
+
+                if is_link_lost(&error) {
+                    // A failure here shows up as NotConnected on the next try
+                    let _ = matrix.reconnect();
+                }
+
+                // The frame stays due so it's tried again after the pause
+                self.due = Some(self.clock.now() + self.retry_pause);
+            },
+        }
+
+        Ok(!self.finished)
+    }
+
+    /// Block until the animation finishes, which for the looping modes is
+    /// never
+    pub fn play(&mut self, matrix: &mut LedMatrix) -> Result<(), Error> {
+        while self.tick(matrix)? {
+            self.clock.sleep(self.time_to_next_frame());
+        }
+
+        Ok(())
+    }
+
+    /// The frame to draw now, if one is due
+    pub(crate) fn poll(&mut self) -> Option<usize> {
+        if let Some(due) = self.due {
+            if self.clock.now() < due {
+                return None;
+            }
+        }
+
+        if self.next.is_none() {
+            self.finished = true;
+        }
+
+        self.next
+    }
+
+    /// Record that a frame made it to the screen and work out what's next
+    pub(crate) fn drawn(&mut self, index: usize) {
+        let now = self.clock.now();
+        let duration = self.animation.frames[index].duration;
+
+        // Keep to the schedule unless we've fallen a whole frame behind
+        let start = match self.due {
+            Some(due) if now.saturating_duration_since(due) < duration => due,
+            _ => now,
+        };
+
+        self.due = Some(start + duration);
+        self.next = self.following(index);
+    }
+
+    fn following(&mut self, index: usize) -> Option<usize> {
+        let last = self.animation.len() - 1;
+
+        match self.mode {
+            PlayMode::Once => (index < last).then_some(index + 1),
+            PlayMode::Loop => Some(if index < last { index + 1 } else { 0 }),
+            PlayMode::PingPong => {
+                if last == 0 {
+                    return Some(0);
+                }
+
+                if index == last {
+                    self.forward = false;
+                } else if index == 0 {
+                    self.forward = true;
+                }
+
+                Some(if self.forward { index + 1 } else { index - 1 })
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn animation(frames: usize) -> Animation {
+        Animation::from_frames((0 .. frames).map(|_| Bitmap8::new()), Duration::from_millis(100))
+    }
+
+    /// Frames drawn over `steps` ticks, pretending every draw works
+    fn sequence(mode: PlayMode, frames: usize, steps: usize) -> Vec<usize> {
+        let clock = ManualClock::new();
+        let mut animator = Animator::with_clock(animation(frames), mode, clock.clone());
+        let mut drawn = Vec::new();
+
+        for _ in 0 .. steps {
+            if let Some(index) = animator.poll() {
+                animator.drawn(index);
+                drawn.push(index);
+            }
+            clock.advance(animator.time_to_next_frame());
+        }
+
+        drawn
+    }
+
+    #[test]
+    fn play_modes() {
+        assert_eq!(sequence(PlayMode::Once, 3, 6), [0, 1, 2]);
+        assert_eq!(sequence(PlayMode::Loop, 3, 6), [0, 1, 2, 0, 1, 2]);
+        assert_eq!(sequence(PlayMode::PingPong, 3, 7), [0, 1, 2, 1, 0, 1, 2]);
+        assert_eq!(sequence(PlayMode::PingPong, 1, 3), [0, 0, 0]);
+    }
+
+    #[test]
+    fn once_finishes_after_last_frame_duration() {
+        let clock = ManualClock::new();
+        let mut animator = Animator::with_clock(animation(1), PlayMode::Once, clock.clone());
+
+        assert_eq!(animator.poll(), Some(0));
+        animator.drawn(0);
+
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(animator.poll(), None);
+        assert!(!animator.is_finished());
+
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(animator.poll(), None);
+        assert!(animator.is_finished());
+    }
+
+    #[test]
+    fn pacing_absorbs_late_draws() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let mut animator = Animator::with_clock(animation(3), PlayMode::Loop, clock.clone());
+
+        let index = animator.poll().unwrap();
+        animator.drawn(index);
+
+        // Drawn 30 ms late, but the next frame is still due on schedule
+        clock.advance(Duration::from_millis(130));
+        let index = animator.poll().unwrap();
+        animator.drawn(index);
+        assert_eq!(animator.due, Some(start + Duration::from_millis(200)));
+
+        // Too far behind to catch up, so the schedule starts again from now
+        clock.advance(Duration::from_millis(500));
+        let index = animator.poll().unwrap();
+        animator.drawn(index);
+        assert_eq!(animator.due, Some(start + Duration::from_millis(730)));
+    }
+
+    #[test]
+    fn empty_animation_is_finished() {
+        let mut animator = Animator::with_clock(Animation::new(), PlayMode::Loop, ManualClock::new());
+
+        assert!(animator.is_finished());
+        assert_eq!(animator.poll(), None);
+    }
+}
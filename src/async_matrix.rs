@@ -0,0 +1,278 @@
+//! A `LedMatrix` for tokio applications. Writes, replies and reconnects are
+//! awaited instead of blocking a thread, which matters for GUIs and daemons
+//! that have better things to do during a 500 ms reconnect.
+
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::{SerialPort, SerialPortBuilderExt, SerialStream};
+
+use crate::bootloader;
+use crate::capabilities::{Capabilities, CommandKind, UnsupportedPolicy};
+use crate::gamma::GammaMap;
+use crate::remap::Remap;
+use crate::response::{Response, RESPONSE_LENGTH};
+use crate::{
+    encode, Bitmap8, ColumnIndex, ColumnUpdate, Command, CONNECT_DELAY, DEFAULT_BAUD_RATE,
+    MAX_COMMAND_LENGTH, RECONNECT_DELAY,
+};
+
+pub struct AsyncLedMatrix {
+    path: String,
+    baud_rate: u32,
+    port: Option<SerialStream>,
+    remap: Remap,
+    gamma: GammaMap,
+    capabilities: Capabilities,
+    unsupported: UnsupportedPolicy,
+    timeout: Duration,
+}
+
+impl AsyncLedMatrix {
+    /// Open a port. This has to be called from inside a tokio runtime.
+    pub fn new(path: &str) -> Result<Self, serialport::Error> {
+        Self::with_baud_rate(path, DEFAULT_BAUD_RATE)
+    }
+
+    pub fn with_baud_rate(path: &str, baud_rate: u32) -> Result<Self, serialport::Error> {
+        let port = tokio_serial::new(path, baud_rate).open_native_async()?;
+
+        Ok(Self::from_stream(path, baud_rate, port))
+    }
+
+    fn from_stream(path: &str, baud_rate: u32, port: SerialStream) -> Self {
+        Self {
+            path: path.to_owned(),
+            baud_rate,
+            port: Some(port),
+            remap: Remap::identity(),
+            gamma: GammaMap::linear(),
+            capabilities: Capabilities::unknown(),
+            unsupported: UnsupportedPolicy::Send,
+            timeout: CONNECT_DELAY,
+        }
+    }
+
+    /// Close the port, give the OS a moment to let go of it and open it again
+    pub async fn reconnect(&mut self) -> Result<(), serialport::Error> {
+        self.port = None;
+        tokio::time::sleep(RECONNECT_DELAY).await;
+
+        self.port = Some(tokio_serial::new(&self.path, self.baud_rate).open_native_async()?);
+
+        Ok(())
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn baud_rate(&self) -> u32 {
+        self.baud_rate
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.port.is_some()
+    }
+
+    /// How long a write or a reply can take before giving up with `TimedOut`
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn set_remap(&mut self, remap: Remap) {
+        self.remap = remap;
+    }
+
+    pub fn remap(&self) -> &Remap {
+        &self.remap
+    }
+
+    pub fn set_gamma(&mut self, gamma: GammaMap) {
+        self.gamma = gamma;
+    }
+
+    pub fn gamma(&self) -> &GammaMap {
+        &self.gamma
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    pub fn supports(&self, kind: CommandKind) -> bool {
+        self.capabilities.supports(kind)
+    }
+
+    /// See `LedMatrix::set_unsupported_policy()`
+    pub fn set_unsupported_policy(&mut self, policy: UnsupportedPolicy) {
+        self.unsupported = policy;
+    }
+
+    pub fn unsupported_policy(&self) -> UnsupportedPolicy {
+        self.unsupported
+    }
+
+    /// See `LedMatrix::refresh_capabilities()`
+    pub async fn refresh_capabilities(&mut self) -> Result<Capabilities, Error> {
+        if let Response::Version(version) = self.query(Command::Version).await? {
+            self.capabilities = Capabilities::new(version);
+        }
+
+        Ok(self.capabilities)
+    }
+
+    /// Send a command padded out to full length, like `LedMatrix::execute()`
+    pub async fn execute(&mut self, command: Command<'_>) -> Result<usize, Error> {
+        let mut buffer = [0u8; MAX_COMMAND_LENGTH];
+
+        bootloader::refuse(&command)?;
+        crate::refuse_query(&command)?;
+        self.unsupported.check(&self.capabilities, &command)?;
+        encode(&self.remap, &self.gamma, command, &mut buffer)?;
+
+        let timeout = self.timeout;
+        let port = self.port()?;
+
+        with_timeout(timeout, async {
+            port.write_all(&buffer).await?;
+            port.flush().await
+        }).await?;
+
+        Ok(buffer.len())
+    }
+
+    /// Send a command and wait for its reply, like `LedMatrix::query()`
+    pub async fn query(&mut self, command: Command<'_>) -> Result<Response, Error> {
+        let mut buffer = [0u8; MAX_COMMAND_LENGTH];
+        let mut response = [0u8; RESPONSE_LENGTH];
+        let id = command.id();
+
+        bootloader::refuse(&command)?;
+        crate::refuse_set(&command)?;
+        self.unsupported.check(&self.capabilities, &command)?;
+        let length = encode(&self.remap, &self.gamma, command, &mut buffer)?;
+
+        let timeout = self.timeout;
+        let port = self.port()?;
+
+        // Anything already waiting isn't the reply
+        while port.bytes_to_read()? > 0 {
+            let mut discard = [0u8; 64];
+
+            match port.try_read(&mut discard) {
+                Ok(0) => break,
+                Ok(_) => (),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) => return Err(error),
+            }
+        }
+
+        with_timeout(timeout, async {
+            port.write_all(&buffer[..length]).await?;
+            port.flush().await?;
+            port.read_exact(&mut response).await
+        }).await?;
+
+        Response::parse(id, &response).ok_or_else(|| {
+            Error::new(ErrorKind::InvalidInput, "Command doesn't have a reply")
+        })
+    }
+
+    /// See `LedMatrix::enter_bootloader()`
+    pub async fn enter_bootloader(&mut self, _confirmation: bootloader::Confirmation) -> Result<(), Error> {
+        let mut buffer = [0u8; MAX_COMMAND_LENGTH];
+        encode(&self.remap, &self.gamma, Command::Bootloader, &mut buffer)?;
+
+        let timeout = self.timeout;
+        let port = self.port()?;
+
+        let result = with_timeout(timeout, async {
+            port.write_all(&buffer).await?;
+            port.flush().await
+        }).await;
+
+        self.port = None;
+
+        result
+    }
+
+    /// Stage every column of a greyscale frame and draw it. Unlike
+    /// `LedMatrix` there's no filter pipeline.
+    pub async fn set_frame(&mut self, bitmap: &Bitmap8) -> Result<(), Error> {
+        for index in ColumnIndex::all() {
+            self.execute(Command::StageColumnBuffer(ColumnUpdate::from_bitmap(bitmap, index))).await?;
+        }
+
+        self.execute(Command::DrawBuffer).await?;
+
+        Ok(())
+    }
+
+    fn port(&mut self) -> Result<&mut SerialStream, Error> {
+        self.port.as_mut()
+            .ok_or_else(|| Error::new(ErrorKind::NotConnected, "Port isn't open"))
+    }
+}
+
+async fn with_timeout<T>(timeout: Duration, future: impl std::future::Future<Output = Result<T, Error>>) -> Result<T, Error> {
+    match tokio::time::timeout(timeout, future).await {
+        Ok(x) => x,
+        Err(_) => Err(Error::new(ErrorKind::TimedOut, "Device didn't respond in time")),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::FirmwareVersion;
+
+    #[tokio::test]
+    async fn execute_pads_command() {
+        let (ours, mut theirs) = SerialStream::pair().unwrap();
+        let mut matrix = AsyncLedMatrix::from_stream("pty", DEFAULT_BAUD_RATE, ours);
+
+        matrix.execute(Command::Brightness(0x40)).await.unwrap();
+
+        let mut written = [0u8; MAX_COMMAND_LENGTH];
+        theirs.read_exact(&mut written).await.unwrap();
+
+        assert_eq!(written[.. 4], [0x32, 0xac, 0x00, 0x40]);
+        assert!(written[4 ..].iter().all(|x| *x == 0));
+    }
+
+    #[tokio::test]
+    async fn query_reads_reply() {
+        let (ours, mut theirs) = SerialStream::pair().unwrap();
+        let mut matrix = AsyncLedMatrix::from_stream("pty", DEFAULT_BAUD_RATE, ours);
+
+        let device = tokio::spawn(async move {
+            let mut request = [0u8; 3];
+            theirs.read_exact(&mut request).await.unwrap();
+
+            let mut reply = [0u8; RESPONSE_LENGTH];
+            reply[1] = 0x18;
+            theirs.write_all(&reply).await.unwrap();
+
+            request
+        });
+
+        let response = matrix.query(Command::Version).await.unwrap();
+
+        assert_eq!(device.await.unwrap(), [0x32, 0xac, 0x20]);
+        assert_eq!(response, Response::Version(FirmwareVersion::new(0, 1, 8)));
+    }
+
+    #[tokio::test]
+    async fn silence_times_out() {
+        let (ours, _theirs) = SerialStream::pair().unwrap();
+        let mut matrix = AsyncLedMatrix::from_stream("pty", DEFAULT_BAUD_RATE, ours);
+
+        matrix.set_timeout(Duration::from_millis(10));
+
+        let error = matrix.query(Command::Version).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+    }
+}
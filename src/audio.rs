@@ -0,0 +1,399 @@
+//! A spectrum analyser for the panel, a bar for each band of frequencies
+//! with peaks that hang and then fall. Samples come from wherever the
+//! application gets them, such as a `cpal` input stream's callback:
+//!
+//! ```no_run
+//! use f16_hid::audio::SpectrumWidget;
+//!
+//! let spectrum = SpectrumWidget::new(48_000);
+//! let sink = spectrum.sink();
+//!
+//! // On the audio thread, interleaved channels mixed down already
+//! # let samples = [0.0f32; 256];
+//! sink.push(&samples);
+//! ```
+//!
+//! The widget goes in a `Layout` like any other and redraws itself as the
+//! layout calls `update()`.
+
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::geometry::Rect;
+use crate::widgets::Widget;
+use crate::{Bitmap8, DISPLAY_WIDTH};
+
+/// Samples looked at for each update. A power of two, as the FFT needs.
+pub const FFT_SIZE: usize = 1024;
+/// How often the widget asks to be updated, about 30 frames a second
+pub const SPECTRUM_INTERVAL: Duration = Duration::from_millis(33);
+/// Lowest and highest frequencies the bands are spread across, evenly on a
+/// log scale the way hearing works
+pub const LOWEST_FREQUENCY: f32 = 40.0;
+pub const HIGHEST_FREQUENCY: f32 = 16_000.0;
+
+/// Where samples for a `SpectrumWidget` go in. Clones share the same
+/// buffer, so hand one to the audio thread.
+#[derive(Clone)]
+pub struct SampleSink {
+    samples: Arc<Mutex<VecDeque<f32>>>,
+}
+
+impl SampleSink {
+    fn new() -> Self {
+        Self {
+            samples: Arc::new(Mutex::new(VecDeque::with_capacity(FFT_SIZE))),
+        }
+    }
+
+    /// Mono samples from -1 to 1. Only the latest `FFT_SIZE` are kept.
+    pub fn push(&self, samples: &[f32]) {
+        let mut buffer = self.samples();
+        let keep = samples.len().min(FFT_SIZE);
+
+        buffer.extend(&samples[samples.len() - keep ..]);
+
+        let excess = buffer.len().saturating_sub(FFT_SIZE);
+        buffer.drain(.. excess);
+    }
+
+    /// Mono 16 bit samples, as most capture APIs give them
+    pub fn push_i16(&self, samples: &[i16]) {
+        let converted: Vec<f32> = samples.iter().map(|x| *x as f32 / i16::MAX as f32).collect();
+        self.push(&converted);
+    }
+
+    /// The latest samples, padded with silence at the front if there
+    /// aren't enough yet
+    fn latest(&self) -> Vec<f32> {
+        let buffer = self.samples();
+        let mut latest = vec![0.0; FFT_SIZE - buffer.len()];
+        latest.extend(buffer.iter());
+
+        latest
+    }
+
+    fn samples(&self) -> MutexGuard<'_, VecDeque<f32>> {
+        self.samples.lock().expect("Sample buffer lock poisoned")
+    }
+}
+
+struct Peak {
+    level: f32,
+    /// When it was last pushed up
+    since: Instant,
+}
+
+/// Bars for bands of frequencies from low on the left to high on the
+/// right, each topped with a peak marker that holds for a moment before
+/// falling. One band per column by default.
+pub struct SpectrumWidget<C: Clock = SystemClock> {
+    sink: SampleSink,
+    sample_rate: u32,
+    bands: usize,
+    /// Quietest level drawn, in decibels below full scale
+    floor: f32,
+    smoothing: f32,
+    peak_hold: Option<Duration>,
+    /// Fraction of the full height a peak falls in a second
+    peak_fall: f32,
+    foreground: u8,
+    peak_value: u8,
+    levels: Vec<f32>,
+    peaks: Vec<Peak>,
+    last_update: Instant,
+    clock: C,
+    dirty: bool,
+}
+
+impl SpectrumWidget<SystemClock> {
+    /// A band for every column, for audio at `sample_rate`
+    pub fn new(sample_rate: u32) -> Self {
+        Self::with_clock(sample_rate, SystemClock)
+    }
+}
+
+impl<C: Clock> SpectrumWidget<C> {
+    pub fn with_clock(sample_rate: u32, clock: C) -> Self {
+        let now = clock.now();
+
+        Self {
+            sink: SampleSink::new(),
+            sample_rate,
+            bands: DISPLAY_WIDTH,
+            floor: 60.0,
+            smoothing: 0.7,
+            peak_hold: Some(Duration::from_millis(500)),
+            peak_fall: 1.0,
+            foreground: 0x80,
+            peak_value: 0xff,
+            levels: vec![0.0; DISPLAY_WIDTH],
+            peaks: (0 .. DISPLAY_WIDTH).map(|_| Peak { level: 0.0, since: now }).collect(),
+            last_update: now,
+            clock,
+            dirty: true,
+        }
+    }
+
+    /// Somewhere to push samples to, from any thread
+    pub fn sink(&self) -> SampleSink {
+        self.sink.clone()
+    }
+
+    /// How many bars, each as wide as the area allows. Eight leaves room
+    /// for a border on a full width area.
+    pub fn bands(mut self, bands: usize) -> Self {
+        let now = self.clock.now();
+
+        self.bands = bands;
+        self.levels = vec![0.0; bands];
+        self.peaks = (0 .. bands).map(|_| Peak { level: 0.0, since: now }).collect();
+        self
+    }
+
+    /// Decibels below full scale that count as an empty bar, 60 by default
+    pub fn floor(mut self, decibels: f32) -> Self {
+        self.floor = decibels.abs().max(1.0);
+        self
+    }
+
+    /// How much of the last level a falling bar keeps each update, from 0
+    /// for none at all to just under 1 for a slow, smooth fall. Bars
+    /// always jump straight up.
+    pub fn smoothing(mut self, smoothing: f32) -> Self {
+        self.smoothing = smoothing.clamp(0.0, 0.99);
+        self
+    }
+
+    /// How long a peak hangs before falling, `None` to leave peaks off
+    pub fn peak_hold(mut self, hold: Option<Duration>) -> Self {
+        self.peak_hold = hold;
+        self
+    }
+
+    /// How fast a peak falls once it lets go, in bar heights per second
+    pub fn peak_fall(mut self, speed: f32) -> Self {
+        self.peak_fall = speed.max(0.0);
+        self
+    }
+
+    pub fn foreground(mut self, value: u8) -> Self {
+        self.foreground = value;
+        self
+    }
+
+    pub fn peak_value(mut self, value: u8) -> Self {
+        self.peak_value = value;
+        self
+    }
+
+    /// Each band's level from 0 to 1, as last drawn
+    pub fn levels(&self) -> &[f32] {
+        &self.levels
+    }
+
+    /// Each band's peak from 0 to 1
+    pub fn peaks(&self) -> Vec<f32> {
+        self.peaks.iter().map(|x| x.level).collect()
+    }
+
+    /// Loudness of each band from 0 to 1 in the latest samples
+    fn measure(&self) -> Vec<f32> {
+        let mut real = self.sink.latest();
+        let mut imaginary = vec![0.0; FFT_SIZE];
+
+        // Hann window, so the edges of the buffer don't smear every band
+        for (index, sample) in real.iter_mut().enumerate() {
+            *sample *= 0.5 - 0.5 * (2.0 * PI * index as f32 / (FFT_SIZE - 1) as f32).cos();
+        }
+
+        fft(&mut real, &mut imaginary);
+
+        let bin_width = self.sample_rate as f32 / FFT_SIZE as f32;
+        let highest = HIGHEST_FREQUENCY.min(self.sample_rate as f32 / 2.0);
+        let ratio = (highest / LOWEST_FREQUENCY).powf(1.0 / self.bands.max(1) as f32);
+
+        (0 .. self.bands).map(|band| {
+            let low = LOWEST_FREQUENCY * ratio.powi(band as i32);
+            let high = low * ratio;
+
+            // At least one bin each, even where the low bands are narrower
+            // than a bin
+            let first = ((low / bin_width) as usize).clamp(1, FFT_SIZE / 2 - 1);
+            let last = ((high / bin_width) as usize).clamp(first + 1, FFT_SIZE / 2);
+
+            let peak = (first .. last)
+                .map(|bin| (real[bin] * real[bin] + imaginary[bin] * imaginary[bin]).sqrt())
+                .fold(0.0, f32::max);
+
+            // A full scale sine comes out at a quarter of the FFT size
+            // through the window
+            let amplitude = peak / (FFT_SIZE as f32 / 4.0);
+            let decibels = 20.0 * amplitude.max(f32::MIN_POSITIVE).log10();
+
+            ((decibels + self.floor) / self.floor).clamp(0.0, 1.0)
+        }).collect()
+    }
+}
+
+impl<C: Clock> Widget for SpectrumWidget<C> {
+    fn render(&mut self, canvas: &mut Bitmap8, area: Rect) {
+        if self.bands == 0 {
+            return;
+        }
+
+        // Whatever doesn't divide evenly is left blank on the right
+        let width = (area.size.width / self.bands).max(1);
+        let height = area.size.height;
+
+        for (band, level) in self.levels.iter().enumerate() {
+            let x = area.left() + (band * width) as i32;
+
+            if x >= area.right() {
+                break;
+            }
+
+            let length = (height as f32 * level).round() as usize;
+            canvas.fill_rect(Rect::new((x, area.bottom() - length as i32), (width, length)), self.foreground);
+
+            let peak = (height as f32 * self.peaks[band].level).round() as usize;
+
+            if self.peak_hold.is_some() && peak > 0 {
+                canvas.fill_rect(Rect::new((x, area.bottom() - peak as i32), (width, 1)), self.peak_value);
+            }
+        }
+    }
+
+    fn is_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    fn update_interval(&self) -> Option<Duration> {
+        Some(SPECTRUM_INTERVAL)
+    }
+
+    fn update(&mut self) {
+        let now = self.clock.now();
+        let elapsed = now.saturating_duration_since(self.last_update).as_secs_f32();
+        self.last_update = now;
+
+        let measured = self.measure();
+
+        for (band, new) in measured.into_iter().enumerate() {
+            let level = &mut self.levels[band];
+            let smoothed = if new >= *level { new } else { *level * self.smoothing + new * (1.0 - self.smoothing) };
+
+            if smoothed != *level {
+                *level = smoothed;
+                self.dirty = true;
+            }
+
+            let peak = &mut self.peaks[band];
+
+            if *level >= peak.level {
+                peak.level = *level;
+                peak.since = now;
+            } else if self.peak_hold.is_some_and(|hold| now.saturating_duration_since(peak.since) > hold) {
+                peak.level = (peak.level - self.peak_fall * elapsed).max(*level);
+                self.dirty = true;
+            }
+        }
+    }
+}
+
+/// In-place radix-2 FFT. Both slices must be the same power of two long.
+fn fft(real: &mut [f32], imaginary: &mut [f32]) {
+    let length = real.len();
+    let bits = length.trailing_zeros();
+
+    for index in 0 .. length {
+        let reversed = index.reverse_bits() >> (usize::BITS - bits);
+
+        if reversed > index {
+            real.swap(index, reversed);
+            imaginary.swap(index, reversed);
+        }
+    }
+
+    let mut size = 2;
+
+    while size <= length {
+        let step = -2.0 * PI / size as f32;
+
+        for start in (0 .. length).step_by(size) {
+            for offset in 0 .. size / 2 {
+                let (sin, cos) = (step * offset as f32).sin_cos();
+                let even = start + offset;
+                let odd = even + size / 2;
+
+                let odd_real = real[odd] * cos - imaginary[odd] * sin;
+                let odd_imaginary = real[odd] * sin + imaginary[odd] * cos;
+
+                real[odd] = real[even] - odd_real;
+                imaginary[odd] = imaginary[even] - odd_imaginary;
+                real[even] += odd_real;
+                imaginary[even] += odd_imaginary;
+            }
+        }
+
+        size *= 2;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::geometry::Point;
+
+    fn sine(frequency: f32, amplitude: f32) -> Vec<f32> {
+        (0 .. FFT_SIZE).map(|x| amplitude * (2.0 * PI * frequency * x as f32 / 48_000.0).sin()).collect()
+    }
+
+    #[test]
+    fn a_tone_lights_its_band() {
+        let clock = ManualClock::new();
+        let mut spectrum = SpectrumWidget::with_clock(48_000, clock.clone());
+        spectrum.sink().push(&sine(1_000.0, 1.0));
+        spectrum.update();
+
+        let levels = spectrum.levels().to_vec();
+        let loudest = (0 .. levels.len()).max_by(|a, b| levels[*a].total_cmp(&levels[*b])).unwrap();
+
+        // 1 kHz is in the fifth of nine log spaced bands from 40 Hz
+        assert_eq!(loudest, 4);
+        assert!(levels[loudest] > 0.9, "{:?}", levels);
+        assert!(levels[0] < 0.5, "{:?}", levels);
+
+        let mut canvas = Bitmap8::new();
+        spectrum.render(&mut canvas, Rect::display());
+        assert_eq!(canvas.pixel(Point::new(4, 33)), Some(0x80));
+        assert_eq!(canvas.pixel(Point::new(0, 0)), Some(0));
+    }
+
+    #[test]
+    fn peaks_hold_then_fall() {
+        let clock = ManualClock::new();
+        let mut spectrum = SpectrumWidget::with_clock(48_000, clock.clone()).smoothing(0.0);
+        let sink = spectrum.sink();
+
+        sink.push(&sine(1_000.0, 1.0));
+        spectrum.update();
+        let top = spectrum.peaks()[4];
+
+        // Silence drops the bar straight away but the peak hangs on
+        sink.push(&[0.0; FFT_SIZE]);
+        clock.advance(Duration::from_millis(400));
+        spectrum.update();
+        assert_eq!(spectrum.levels()[4], 0.0);
+        assert_eq!(spectrum.peaks()[4], top);
+
+        clock.advance(Duration::from_millis(200));
+        spectrum.update();
+        assert!((spectrum.peaks()[4] - (top - 0.2)).abs() < 0.01);
+    }
+}
@@ -0,0 +1,597 @@
+//! A small machine-readable code that fits the panel, for pairing flows and
+//! for test rigs that check what a module shows with a camera. A QR code
+//! needs 21 pixels a side, more than the panel is wide, so this is a code
+//! of its own with a byte to a row:
+//!
+//! ```text
+//! #########   start marker
+//! #........   length, column 0 is a timing track lit on odd rows
+//! .########   data, one byte a row, most significant bit on the left
+//! #.#...#..
+//! ...
+//! ..#.##..#   CRC-8 of the length and data
+//! #.#.#.#.#   end marker, which also tells up from down
+//! ```
+//!
+//! ```
+//! use f16_hid::barcode;
+//!
+//! let frame = barcode::encode(b"pair:4821")?;
+//! assert_eq!(barcode::decode(&frame)?, b"pair:4821");
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! For rigs reading with an off the shelf decoder there's also a standard
+//! ECC 200 Data Matrix, the 8x32 rectangle turned to run down the panel.
+//! It holds up to 10 characters, or 20 digits, with Reed-Solomon check
+//! codewords. Lit LEDs are its dark modules, so the reader has to accept
+//! inverted codes. `decode_data_matrix()` checks the codewords but doesn't
+//! correct them.
+
+use std::io::{Error, ErrorKind};
+
+use crate::{Bitmap8, Command, LedMatrix, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Most bytes a code holds, the rows left over by the markers, length and
+/// checksum
+pub const MAX_PAYLOAD: usize = DISPLAY_HEIGHT - 4;
+
+/// Row holding the length, with data after it
+const LENGTH_ROW: usize = 1;
+const CHECKSUM_ROW: usize = DISPLAY_HEIGHT - 2;
+const END_ROW: usize = DISPLAY_HEIGHT - 1;
+
+/// CRC-8 with polynomial 0x07
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |crc, byte| {
+        (0 .. 8).fold(crc ^ byte, |crc, _| match crc & 0x80 {
+            0 => crc << 1,
+            _ => (crc << 1) ^ 0x07,
+        })
+    })
+}
+
+fn end_marker(x: usize) -> bool {
+    x.is_multiple_of(2)
+}
+
+/// Draw `data` as a code, lit pixels at 0xff. Rows after the data are
+/// left blank apart from the timing track. Fails for more than
+/// `MAX_PAYLOAD` bytes.
+pub fn encode(data: &[u8]) -> Result<Bitmap8, Error> {
+    if data.len() > MAX_PAYLOAD {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} bytes won't fit, a code holds {}", data.len(), MAX_PAYLOAD)
+        ));
+    }
+
+    let mut rows = [0u8; DISPLAY_HEIGHT];
+    rows[LENGTH_ROW] = data.len() as u8;
+    rows[LENGTH_ROW + 1 .. LENGTH_ROW + 1 + data.len()].copy_from_slice(data);
+    rows[CHECKSUM_ROW] = crc8(&rows[LENGTH_ROW ..= LENGTH_ROW + data.len()]);
+
+    let mut frame = Bitmap8::new();
+    let mut set = |x: usize, y: usize| frame.data[x * DISPLAY_HEIGHT + y] = 0xff;
+
+    for x in 0 .. DISPLAY_WIDTH {
+        set(x, 0);
+
+        if end_marker(x) {
+            set(x, END_ROW);
+        }
+    }
+
+    for (y, byte) in rows.iter().enumerate().take(END_ROW).skip(LENGTH_ROW) {
+        if y % 2 == 1 {
+            set(0, y);
+        }
+
+        for bit in 0 .. 8 {
+            if byte & (0x80 >> bit) != 0 {
+                set(bit + 1, y);
+            }
+        }
+    }
+
+    Ok(frame)
+}
+
+/// Read a code back, with pixels at 0x80 or more counted as lit. Fails with
+/// `ErrorKind::InvalidData` if the markers, timing track or checksum are
+/// wrong. A frame upside down is read the right way up.
+pub fn decode(frame: &Bitmap8) -> Result<Vec<u8>, Error> {
+    match read(frame, false) {
+        Ok(data) => Ok(data),
+        Err(error) => read(frame, true).map_err(|_| error),
+    }
+}
+
+fn read(frame: &Bitmap8, flipped: bool) -> Result<Vec<u8>, Error> {
+    let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message);
+    let lit = |x: usize, y: usize| {
+        let (x, y) = match flipped {
+            true => (DISPLAY_WIDTH - 1 - x, DISPLAY_HEIGHT - 1 - y),
+            false => (x, y),
+        };
+
+        frame.data[x * DISPLAY_HEIGHT + y] >= 0x80
+    };
+
+    if !(0 .. DISPLAY_WIDTH).all(|x| lit(x, 0)) {
+        return Err(invalid("No start marker"));
+    }
+
+    if !(0 .. DISPLAY_WIDTH).all(|x| lit(x, END_ROW) == end_marker(x)) {
+        return Err(invalid("No end marker"));
+    }
+
+    let mut rows = [0u8; DISPLAY_HEIGHT];
+
+    for (y, byte) in rows.iter_mut().enumerate().take(END_ROW).skip(LENGTH_ROW) {
+        if lit(0, y) != (y % 2 == 1) {
+            return Err(invalid("Timing track is broken"));
+        }
+
+        *byte = (0 .. 8).filter(|bit| lit(bit + 1, y)).fold(0, |byte, bit| byte | 0x80 >> bit);
+    }
+
+    let length = rows[LENGTH_ROW] as usize;
+    if length > MAX_PAYLOAD {
+        return Err(invalid("Length is too long"));
+    }
+
+    if crc8(&rows[LENGTH_ROW ..= LENGTH_ROW + length]) != rows[CHECKSUM_ROW] {
+        return Err(invalid("Checksum doesn't match"));
+    }
+
+    Ok(rows[LENGTH_ROW + 1 .. LENGTH_ROW + 1 + length].to_vec())
+}
+
+/// Rows and columns of the Data Matrix symbol, finder patterns included
+const DM_ROWS: usize = 8;
+const DM_COLUMNS: usize = 32;
+/// Each of the symbol's two data regions, inside their finder patterns
+const DM_REGION_ROWS: usize = 6;
+const DM_REGION_COLUMNS: usize = 14;
+const DM_DATA_CODEWORDS: usize = 10;
+const DM_CHECK_CODEWORDS: usize = 11;
+/// Panel row the symbol's first column goes on, leaving a quiet row above
+const DM_TOP: usize = 1;
+
+/// ASCII encodation codewords
+const DM_PAD: u8 = 129;
+const DM_DIGITS: u8 = 130;
+const DM_UPPER_SHIFT: u8 = 235;
+
+/// Draw `data` as a Data Matrix, lit pixels at 0xff. Pairs of digits take
+/// one codeword, bytes over 127 take two and anything else one. Fails for
+/// more than fit in 10 codewords.
+pub fn encode_data_matrix(data: &[u8]) -> Result<Bitmap8, Error> {
+    let mut codewords = Vec::new();
+    let mut index = 0;
+
+    while index < data.len() {
+        match (data[index], data.get(index + 1)) {
+            (a, Some(b)) if a.is_ascii_digit() && b.is_ascii_digit() => {
+                codewords.push(DM_DIGITS + (a - b'0') * 10 + (b - b'0'));
+                index += 1;
+            },
+            (a, _) if a < 128 => codewords.push(a + 1),
+            (a, _) => codewords.extend([DM_UPPER_SHIFT, a - 127]),
+        }
+
+        index += 1;
+    }
+
+    if codewords.len() > DM_DATA_CODEWORDS {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} bytes won't fit, they take {} of {} codewords", data.len(), codewords.len(), DM_DATA_CODEWORDS)
+        ));
+    }
+
+    // The first pad is as it is and the rest scrambled by position, counting from one
+    if codewords.len() < DM_DATA_CODEWORDS {
+        codewords.push(DM_PAD);
+    }
+
+    while codewords.len() < DM_DATA_CODEWORDS {
+        let pad = DM_PAD as usize + (149 * (codewords.len() + 1)) % 253 + 1;
+        let pad = if pad > 254 { pad - 254 } else { pad };
+        codewords.push(pad as u8);
+    }
+
+    let check = reed_solomon(&codewords, DM_CHECK_CODEWORDS);
+    codewords.extend(check);
+
+    let mut frame = Bitmap8::new();
+    let mut set = |row: usize, column: usize| frame.data[(DM_ROWS - 1 - row) * DISPLAY_HEIGHT + DM_TOP + column] = 0xff;
+
+    for row in 0 .. DM_ROWS {
+        for column in 0 .. DM_COLUMNS {
+            if finder(row, column) == Some(true) {
+                set(row, column);
+            }
+        }
+    }
+
+    for (index, cell) in placement(DM_REGION_ROWS, 2 * DM_REGION_COLUMNS).into_iter().enumerate() {
+        let dark = match cell {
+            Cell::Bit(codeword, mask) => codewords[codeword] & mask != 0,
+            Cell::Dark => true,
+            Cell::Free => false,
+        };
+
+        if dark {
+            let (row, column) = data_module(index);
+            set(row, column);
+        }
+    }
+
+    Ok(frame)
+}
+
+/// Read a Data Matrix back, with pixels at 0x80 or more counted as lit.
+/// Fails with `ErrorKind::InvalidData` if the finder pattern or check
+/// codewords are wrong, or it uses an encodation other than ASCII. A frame
+/// upside down is read the right way up.
+pub fn decode_data_matrix(frame: &Bitmap8) -> Result<Vec<u8>, Error> {
+    match read_data_matrix(frame, false) {
+        Ok(data) => Ok(data),
+        Err(error) => read_data_matrix(frame, true).map_err(|_| error),
+    }
+}
+
+fn read_data_matrix(frame: &Bitmap8, flipped: bool) -> Result<Vec<u8>, Error> {
+    let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message);
+    let dark = |row: usize, column: usize| {
+        let (x, y) = (DM_ROWS - 1 - row, DM_TOP + column);
+        let (x, y) = match flipped {
+            true => (DISPLAY_WIDTH - 1 - x, DISPLAY_HEIGHT - 1 - y),
+            false => (x, y),
+        };
+
+        frame.data[x * DISPLAY_HEIGHT + y] >= 0x80
+    };
+
+    for row in 0 .. DM_ROWS {
+        for column in 0 .. DM_COLUMNS {
+            if finder(row, column).is_some_and(|x| x != dark(row, column)) {
+                return Err(invalid("No finder pattern"));
+            }
+        }
+    }
+
+    let mut codewords = [0u8; DM_DATA_CODEWORDS + DM_CHECK_CODEWORDS];
+
+    for (index, cell) in placement(DM_REGION_ROWS, 2 * DM_REGION_COLUMNS).into_iter().enumerate() {
+        let (row, column) = data_module(index);
+
+        if let Cell::Bit(codeword, mask) = cell {
+            if dark(row, column) {
+                codewords[codeword] |= mask;
+            }
+        }
+    }
+
+    let (codewords, check) = codewords.split_at(DM_DATA_CODEWORDS);
+    if reed_solomon(codewords, DM_CHECK_CODEWORDS) != check {
+        return Err(invalid("Check codewords don't match"));
+    }
+
+    let mut data = Vec::new();
+    let mut codewords = codewords.iter().copied();
+
+    while let Some(codeword) = codewords.next() {
+        match codeword {
+            1 ..= 128 => data.push(codeword - 1),
+            DM_PAD => break,
+            130 ..= 229 => data.extend([b'0' + (codeword - DM_DIGITS) / 10, b'0' + (codeword - DM_DIGITS) % 10]),
+            DM_UPPER_SHIFT => match codewords.next() {
+                Some(shifted @ 1 ..= 128) => data.push(shifted + 127),
+                _ => return Err(invalid("Upper shift with nothing to shift")),
+            },
+            _ => return Err(invalid("Only ASCII encodation is read")),
+        }
+    }
+
+    Ok(data)
+}
+
+/// What the finder pattern has at a module of the symbol, `None` inside a
+/// data region. Each region has its own: solid along the left and bottom,
+/// alternating along the top and right.
+fn finder(row: usize, column: usize) -> Option<bool> {
+    let x = column % (DM_REGION_COLUMNS + 2);
+
+    if row == DM_ROWS - 1 || x == 0 {
+        Some(true)
+    } else if x == DM_REGION_COLUMNS + 1 {
+        Some(row % 2 == 1)
+    } else if row == 0 {
+        Some(x.is_multiple_of(2))
+    } else {
+        None
+    }
+}
+
+/// Where a module of the mapping matrix, by index, sits in the symbol
+fn data_module(index: usize) -> (usize, usize) {
+    let (row, column) = (index / (2 * DM_REGION_COLUMNS), index % (2 * DM_REGION_COLUMNS));
+    let region = column / DM_REGION_COLUMNS;
+
+    (1 + row, region * (DM_REGION_COLUMNS + 2) + 1 + column % DM_REGION_COLUMNS)
+}
+
+/// Check codewords for `data` over GF(256) with polynomial 0x12d, as ECC 200
+/// uses
+fn reed_solomon(data: &[u8], count: usize) -> Vec<u8> {
+    let mut exp = [0u8; 255];
+    let mut log = [0usize; 256];
+    let mut value = 1usize;
+
+    for (power, entry) in exp.iter_mut().enumerate() {
+        *entry = value as u8;
+        log[value] = power;
+        value <<= 1;
+
+        if value > 0xff {
+            value ^= 0x12d;
+        }
+    }
+
+    let multiply = |a: u8, b: u8| match (a, b) {
+        (0, _) | (_, 0) => 0,
+        (a, b) => exp[(log[a as usize] + log[b as usize]) % 255],
+    };
+
+    // Generator with roots a^1 to a^count, highest power first
+    let mut generator = vec![1u8];
+
+    for root in &exp[1 ..= count] {
+        let mut next = generator.clone();
+        next.push(0);
+
+        for (index, coefficient) in generator.iter().enumerate() {
+            next[index + 1] ^= multiply(*coefficient, *root);
+        }
+
+        generator = next;
+    }
+
+    let mut check = vec![0u8; count];
+
+    for byte in data {
+        let feedback = byte ^ check[0];
+        check.rotate_left(1);
+        check[count - 1] = 0;
+
+        for (entry, coefficient) in check.iter_mut().zip(&generator[1 ..]) {
+            *entry ^= multiply(feedback, *coefficient);
+        }
+    }
+
+    check
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Cell {
+    Free,
+    /// A bit of a codeword, by index and mask
+    Bit(usize, u8),
+    /// Filler for a corner no codeword reaches
+    Dark,
+}
+
+/// Where each codeword bit goes in a mapping matrix, while it's worked out
+struct Placement {
+    rows: isize,
+    columns: isize,
+    cells: Vec<Cell>,
+    codeword: usize,
+}
+
+impl Placement {
+    fn is_free(&self, row: isize, column: isize) -> bool {
+        self.cells[(row * self.columns + column) as usize] == Cell::Free
+    }
+
+    /// Place the next codeword, its bits going to `modules` most
+    /// significant first. Modules off the edge wrap round to the other side.
+    fn place(&mut self, modules: [(isize, isize); 8]) {
+        for (bit, (mut row, mut column)) in modules.into_iter().enumerate() {
+            if row < 0 {
+                row += self.rows;
+                column += 4 - ((self.rows + 4) % 8);
+            }
+
+            if column < 0 {
+                column += self.columns;
+                row += 4 - ((self.columns + 4) % 8);
+            }
+
+            self.cells[(row * self.columns + column) as usize] = Cell::Bit(self.codeword, 0x80 >> bit);
+        }
+
+        self.codeword += 1;
+    }
+}
+
+/// Which codeword bit goes in each module of a `rows` by `columns` mapping
+/// matrix, row by row, following ECC 200's diagonal placement
+fn placement(rows: usize, columns: usize) -> Vec<Cell> {
+    let (nrow, ncol) = (rows as isize, columns as isize);
+    let mut placement = Placement { rows: nrow, columns: ncol, cells: vec![Cell::Free; rows * columns], codeword: 0 };
+
+    // The usual shape a codeword takes, then the four special cases at the corners
+    let utah = |row: isize, column: isize| [
+        (row - 2, column - 2), (row - 2, column - 1), (row - 1, column - 2), (row - 1, column - 1),
+        (row - 1, column), (row, column - 2), (row, column - 1), (row, column),
+    ];
+    let corners = [
+        [(nrow - 1, 0), (nrow - 1, 1), (nrow - 1, 2), (0, ncol - 2), (0, ncol - 1), (1, ncol - 1), (2, ncol - 1), (3, ncol - 1)],
+        [(nrow - 3, 0), (nrow - 2, 0), (nrow - 1, 0), (0, ncol - 4), (0, ncol - 3), (0, ncol - 2), (0, ncol - 1), (1, ncol - 1)],
+        [(nrow - 3, 0), (nrow - 2, 0), (nrow - 1, 0), (0, ncol - 2), (0, ncol - 1), (1, ncol - 1), (2, ncol - 1), (3, ncol - 1)],
+        [(nrow - 1, 0), (nrow - 1, ncol - 1), (0, ncol - 3), (0, ncol - 2), (0, ncol - 1), (1, ncol - 3), (1, ncol - 2), (1, ncol - 1)],
+    ];
+
+    let (mut row, mut column) = (4, 0);
+
+    loop {
+        let corner = match (row, column) {
+            (r, 0) if r == nrow => Some(0),
+            (r, 0) if r == nrow - 2 && ncol % 4 != 0 => Some(1),
+            (r, 0) if r == nrow - 2 && ncol % 8 == 4 => Some(2),
+            (r, 2) if r == nrow + 4 && ncol % 8 == 0 => Some(3),
+            _ => None,
+        };
+
+        if let Some(corner) = corner {
+            placement.place(corners[corner]);
+        }
+
+        // Up and to the right...
+        loop {
+            if row < nrow && column >= 0 && placement.is_free(row, column) {
+                placement.place(utah(row, column));
+            }
+
+            row -= 2;
+            column += 2;
+
+            if row < 0 || column >= ncol {
+                break;
+            }
+        }
+
+        row += 1;
+        column += 3;
+
+        // ...then down and to the left
+        loop {
+            if row >= 0 && column < ncol && placement.is_free(row, column) {
+                placement.place(utah(row, column));
+            }
+
+            row += 2;
+            column -= 2;
+
+            if row >= nrow || column < 0 {
+                break;
+            }
+        }
+
+        row += 3;
+        column += 1;
+
+        if row >= nrow && column >= ncol {
+            break;
+        }
+    }
+
+    // Sizes that leave the bottom right corner empty fill it with a fixed pattern
+    let mut cells = placement.cells;
+    let last = rows * columns - 1;
+
+    if cells[last] == Cell::Free {
+        cells[last] = Cell::Dark;
+        cells[last - columns - 1] = Cell::Dark;
+    }
+
+    cells
+}
+
+impl LedMatrix {
+    /// Show `data` as a code, see `barcode`. It goes as an on/off `Draw`,
+    /// so filters and gamma can't smudge it.
+    pub fn draw_code(&mut self, data: &[u8]) -> Result<(), Error> {
+        let binary = encode(data)?.to_binary(0x80);
+        self.execute(Command::Draw(Box::new(binary)))?;
+
+        Ok(())
+    }
+
+    /// Show `data` as a Data Matrix, see `barcode`, the same way as
+    /// `draw_code()`
+    pub fn draw_data_matrix(&mut self, data: &[u8]) -> Result<(), Error> {
+        let binary = encode_data_matrix(data)?.to_binary(0x80);
+        self.execute(Command::Draw(Box::new(binary)))?;
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{FrameFilter, Rotate180};
+
+    #[test]
+    fn codes_read_back() {
+        for data in [&b""[..], b"\x00\xff", &[0xa5; MAX_PAYLOAD]] {
+            assert_eq!(decode(&encode(data).unwrap()).unwrap(), data);
+        }
+
+        let mut upside_down = encode(b"hello").unwrap();
+        Rotate180.apply(&mut upside_down);
+        assert_eq!(decode(&upside_down).unwrap(), b"hello");
+
+        let mut damaged = encode(b"hello").unwrap();
+        damaged.data[4 * DISPLAY_HEIGHT + 3] ^= 0xff;
+        assert_eq!(decode(&damaged).unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(decode(&Bitmap8::new()).is_err());
+
+        assert_eq!(encode(&[0; MAX_PAYLOAD + 1]).err().map(|x| x.kind()), Some(ErrorKind::InvalidInput));
+    }
+
+    #[test]
+    fn reed_solomon_matches_the_standard() {
+        // "123456" in a 10x10 symbol, from ISO/IEC 16022
+        assert_eq!(reed_solomon(&[142, 164, 186], 5), [114, 25, 5, 88, 102]);
+    }
+
+    #[test]
+    fn every_codeword_bit_is_placed_once() {
+        // 10x10, 16x16 and this crate's 8x32
+        for (rows, columns, codewords) in [(8, 8, 8), (14, 14, 24), (DM_REGION_ROWS, 2 * DM_REGION_COLUMNS, 21)] {
+            let cells = placement(rows, columns);
+            let mut bits: Vec<_> = cells.iter().filter_map(|x| match x {
+                Cell::Bit(codeword, mask) => Some((*codeword, *mask)),
+                _ => None,
+            }).collect();
+
+            bits.sort();
+            bits.dedup();
+            assert_eq!(bits.len(), codewords * 8);
+
+            // Any left over make the fixed corner pattern, half of it dark
+            let dark = cells.iter().filter(|x| **x == Cell::Dark).count();
+            assert_eq!(dark, (rows * columns - codewords * 8) / 2);
+        }
+    }
+
+    #[test]
+    fn data_matrices_read_back() {
+        for data in [&b""[..], b"pair:4821", b"01234567890123456789", b"\xe9t\xe9!", b"ABCDEFGHIJ"] {
+            assert_eq!(decode_data_matrix(&encode_data_matrix(data).unwrap()).unwrap(), data);
+        }
+
+        // The symbol's solid edge runs across the top, with a quiet row above
+        let frame = encode_data_matrix(b"hi").unwrap();
+        assert!((0 .. DM_ROWS).all(|x| frame.data[x * DISPLAY_HEIGHT + DM_TOP] == 0xff));
+        assert!((0 .. DISPLAY_WIDTH).all(|x| frame.data[x * DISPLAY_HEIGHT] == 0));
+
+        let mut upside_down = frame.clone();
+        Rotate180.apply(&mut upside_down);
+        assert_eq!(decode_data_matrix(&upside_down).unwrap(), b"hi");
+
+        let mut damaged = frame;
+        damaged.data[4 * DISPLAY_HEIGHT + 6] ^= 0xff;
+        assert_eq!(decode_data_matrix(&damaged).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        assert_eq!(encode_data_matrix(b"ABCDEFGHIJK").err().map(|x| x.kind()), Some(ErrorKind::InvalidInput));
+        assert!(encode_data_matrix(&[0x80; 6]).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use std::io::Error;
+
+use crate::capabilities::CommandKind;
+use crate::{bootloader, Bitmap8, ColumnIndex, ColumnUpdate, Command, LedMatrix, DISPLAY_WIDTH, MAX_COMMAND_LENGTH};
+
+/// Commands packed one after another and sent with a single write, instead
+/// of a write each. A frame is nine staged columns and a draw, which is ten
+/// trips through the USB stack done one at a time:
+///
+/// ```
+/// # use f16_hid::{transport::MockTransport, LedMatrix};
+/// use f16_hid::{Bitmap8, Command};
+///
+/// # let mut matrix = LedMatrix::with_transport("mock", MockTransport::new());
+/// let mut batch = matrix.batch();
+/// batch.push(Command::Brightness(0x40))?;
+/// batch.stage_frame(&Bitmap8::new())?;
+/// batch.flush()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// Commands are checked and packed as they're pushed, so a bad one is
+/// turned away before anything is sent. Nothing is sent until `flush()`,
+/// and a batch dropped without flushing sends nothing at all.
+///
+/// A failed write is retried as the matrix's reconnect policy says, carrying
+/// on from the first command that didn't make it. Column retries don't come
+/// into it.
+pub struct CommandBatch<'a> {
+    matrix: &'a mut LedMatrix,
+    buffer: Vec<u8>,
+}
+
+impl<'a> CommandBatch<'a> {
+    pub(crate) fn new(matrix: &'a mut LedMatrix) -> Self {
+        Self {
+            matrix,
+            buffer: Vec::with_capacity((DISPLAY_WIDTH + 1) * MAX_COMMAND_LENGTH),
+        }
+    }
+
+    /// Pack a command onto the end of the batch, padded to full length as
+    /// `execute()` sends it. Queries are turned away, their replies would
+    /// go unread.
+    pub fn push(&mut self, command: Command) -> Result<&mut Self, Error> {
+        let mut packet = [0u8; MAX_COMMAND_LENGTH];
+
+        bootloader::refuse(&command)?;
+        crate::refuse_query(&command)?;
+        self.matrix.unsupported.check(&self.matrix.capabilities, &command)?;
+        self.matrix.encode(command, &mut packet)?;
+
+        self.buffer.extend_from_slice(&packet);
+
+        Ok(self)
+    }
+
+    /// Every column of a greyscale frame and the draw, filters applied.
+    /// On firmware too old to stage columns it's a black and white draw.
+    pub fn stage_frame(&mut self, bitmap: &Bitmap8) -> Result<&mut Self, Error> {
+        let frame = self.matrix.filtered(bitmap);
+
+        if !self.matrix.supports(CommandKind::StageColumn) {
+            let binary = frame.to_binary(crate::capabilities::BINARY_FALLBACK_THRESHOLD);
+            return self.push(Command::Draw(Box::new(binary)));
+        }
+
+        for index in ColumnIndex::all() {
+            self.push(Command::StageColumnBuffer(ColumnUpdate::from_bitmap(&frame, index)))?;
+        }
+
+        self.push(Command::DrawBuffer)
+    }
+
+    /// Commands waiting to be sent
+    pub fn len(&self) -> usize {
+        self.buffer.len() / MAX_COMMAND_LENGTH
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// The packed bytes, exactly as they'll be written
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Drop everything pushed so far
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+
+    /// Send the lot in one write. Returns how many bytes went out, which is
+    /// nothing for an empty batch.
+    pub fn flush(self) -> Result<usize, Error> {
+        if self.buffer.is_empty() {
+            return Ok(0);
+        }
+
+        self.matrix.send_packets(&self.buffer)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MockTransport;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn sends_the_same_bytes_as_one_at_a_time() {
+        let mut frame = Bitmap8::new();
+        frame.draw_point(4, 20, 0x80).unwrap();
+
+        let single = MockTransport::new();
+        let mut matrix = LedMatrix::with_transport("mock", single.clone());
+        matrix.execute(Command::Brightness(0x40)).unwrap();
+        matrix.stage_frame(&frame).unwrap();
+
+        let batched = MockTransport::new();
+        let mut matrix = LedMatrix::with_transport("mock", batched.clone());
+        let mut batch = matrix.batch();
+        batch.push(Command::Brightness(0x40)).unwrap().stage_frame(&frame).unwrap();
+        assert_eq!(batch.len(), 11);
+
+        assert_eq!(batch.flush().unwrap(), 11 * MAX_COMMAND_LENGTH);
+        assert_eq!(batched.written(), single.written());
+    }
+
+    #[test]
+    fn bad_commands_are_turned_away_before_sending() {
+        let mock = MockTransport::new();
+        let mut matrix = LedMatrix::with_transport("mock", mock.clone());
+        let mut batch = matrix.batch();
+
+        batch.push(Command::Sleep(true)).unwrap();
+        let error = batch.push(Command::Pattern(crate::Patterns::Percentage(101))).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert_eq!(batch.len(), 1);
+
+        // Not flushed, so never sent
+        drop(batch);
+        assert!(mock.written().is_empty());
+    }
+}
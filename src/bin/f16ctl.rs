@@ -0,0 +1,270 @@
+//! `f16ctl`, for poking at the LED matrix from a shell without writing any
+//! Rust. Run it with no arguments for the list of commands.
+
+use std::error::Error;
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+use f16_hid::marquee::Marquee;
+use f16_hid::roles::{Role, RoleConfig};
+use f16_hid::setup::{self, Candidate};
+use f16_hid::text::{Orientation, TextStyle, FONT_3X5};
+use f16_hid::{Bitmap8, Command, Display, LedMatrix, Patterns, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+const USAGE: &str = "\
+Usage: f16ctl [--device PATH] COMMAND
+
+Commands:
+    list                 Modules found on the USB bus
+    brightness PERCENT   0 to 100
+    pattern NAME         gradient, double-gradient, lotus, lotus2, zigzag,
+                         full, panic or percentage N
+    draw IMAGE           A PNG or BMP, scaled to fit (needs the image feature)
+    text TEXT            Down the panel, scrolling if it's too long
+    clear                Turn every LED off
+    sleep on|off
+    setup                Flash each module in turn and ask which it is,
+                         saving the answers as their roles
+
+Without --device the first module found is used, preferring ones with a
+role assigned by setup. With it, setup only asks about that module.";
+
+/// How often a scrolling text is redrawn
+const TICK: Duration = Duration::from_millis(10);
+
+/// What was asked for on the command line
+#[derive(Debug, PartialEq)]
+enum Action {
+    List,
+    Brightness(u8),
+    Pattern(Patterns),
+    Draw(String),
+    Text(String),
+    Clear,
+    Sleep(bool),
+    Setup,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let (device, action) = match parse(&args) {
+        Ok(x) => x,
+        Err(error) => {
+            eprintln!("f16ctl: {}\n\n{}", error, USAGE);
+            std::process::exit(2);
+        },
+    };
+
+    if let Err(error) = run(device.as_deref(), action) {
+        eprintln!("f16ctl: {}", error);
+        std::process::exit(1);
+    }
+}
+
+/// Work out the device and action from the arguments, not counting the
+/// program name
+fn parse(args: &[String]) -> Result<(Option<String>, Action), String> {
+    let mut args: Vec<&str> = args.iter().map(|x| x.as_str()).collect();
+    let mut device = None;
+
+    if args.first() == Some(&"--device") {
+        if args.len() < 2 {
+            return Err("--device needs a path".to_owned());
+        }
+
+        device = Some(args[1].to_owned());
+        args.drain(.. 2);
+    }
+
+    let action = match args[..] {
+        [] => return Err("No command given".to_owned()),
+        ["list"] => Action::List,
+        ["brightness", percent] => {
+            let percent = percent.parse().ok().filter(|x| *x <= 100).ok_or("Brightness is 0 to 100")?;
+            Action::Brightness(percent)
+        },
+        ["pattern", "percentage", percent] => {
+            let percent = percent.parse().ok().filter(|x| *x <= 100).ok_or("Percentage is 0 to 100")?;
+            Action::Pattern(Patterns::Percentage(percent))
+        },
+        ["pattern", name] => Action::Pattern(pattern(name)?),
+        ["draw", path] => Action::Draw(path.to_owned()),
+        ["text", text] => Action::Text(text.to_owned()),
+        ["clear"] => Action::Clear,
+        ["sleep", "on"] => Action::Sleep(true),
+        ["sleep", "off"] => Action::Sleep(false),
+        ["setup"] => Action::Setup,
+        _ => return Err(format!("Don't know how to {:?}", args.join(" "))),
+    };
+
+    Ok((device, action))
+}
+
+fn open(device: Option<&str>) -> Result<Display, serialport::Error> {
+    match device {
+        Some(path) => Display::open(path),
+        None => Display::open_default(),
+    }
+}
+
+fn run(device: Option<&str>, action: Action) -> Result<(), Box<dyn Error>> {
+    match action {
+        Action::List => {
+            for found in LedMatrix::discover()? {
+                match &found.role {
+                    Some(role) => println!("{}\t{}\t{}", found.path, found.key(), role),
+                    None => println!("{}\t{}", found.path, found.key()),
+                }
+            }
+        },
+        Action::Brightness(percent) => open(device)?.set_brightness_percent(percent)?,
+        Action::Pattern(pattern) => {
+            open(device)?.matrix_mut().execute(Command::Pattern(pattern))?;
+        },
+        Action::Draw(path) => draw(device, &path)?,
+        Action::Text(text) => show_text(device, &text)?,
+        Action::Clear => open(device)?.set_frame(&Bitmap8::new())?,
+        Action::Sleep(sleep) => {
+            open(device)?.matrix_mut().execute(Command::Sleep(sleep))?;
+        },
+        Action::Setup => assign(device)?,
+    }
+
+    Ok(())
+}
+
+fn pattern(name: &str) -> Result<Patterns, String> {
+    Ok(match name {
+        "gradient" => Patterns::Gradient,
+        "double-gradient" => Patterns::DoubleGradient,
+        "lotus" => Patterns::DisplayLotus,
+        "lotus2" => Patterns::DisplayLotus2,
+        "zigzag" => Patterns::ZigZag,
+        "full" => Patterns::FullBrightness,
+        "panic" => Patterns::DisplayPanic,
+        _ => return Err(format!("No pattern called {:?}", name)),
+    })
+}
+
+#[cfg(feature = "image")]
+fn draw(device: Option<&str>, path: &str) -> Result<(), Box<dyn Error>> {
+    use f16_hid::imaging::{Fit, ImageOptions};
+
+    let options = ImageOptions { fit: Fit::Contain, ..ImageOptions::default() };
+    let frame = Bitmap8::open_image(path, &options)?;
+    open(device)?.set_frame(&frame)?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "image"))]
+fn draw(_device: Option<&str>, _path: &str) -> Result<(), Box<dyn Error>> {
+    Err("Built without the image feature, so can't read images".into())
+}
+
+/// Ask which module is which and save the answers for next time
+fn assign(device: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let mut roles = RoleConfig::load_default()?;
+    let stdin = std::io::stdin();
+
+    let ask = |candidate: &Candidate| loop {
+        print!("Module {} at {} ({}) is flashing. Is it [l]eft, [r]ight, [e]xternal or [s]kip? ",
+            candidate.number, candidate.path, candidate.serial);
+        std::io::stdout().flush().ok()?;
+
+        let mut answer = String::new();
+        stdin.lock().read_line(&mut answer).ok()?;
+
+        match answer.trim() {
+            "l" | "left" => return Some(Role::Left),
+            "r" | "right" => return Some(Role::Right),
+            "e" | "external" => return Some(Role::External(candidate.serial.to_owned())),
+            "s" | "skip" => return None,
+            _ => println!("Didn't catch that"),
+        }
+    };
+
+    match device {
+        Some(path) => {
+            setup::assign_roles(&[path], &mut roles, ask)?;
+        },
+        None => {
+            setup::discover_and_assign(&mut roles, ask)?;
+        },
+    }
+
+    roles.save_default()?;
+    println!("Saved to {:?}", RoleConfig::default_path().unwrap_or_default());
+
+    Ok(())
+}
+
+/// Text that fits is left showing, anything longer scrolls past once
+fn show_text(device: Option<&str>, text: &str) -> Result<(), Box<dyn Error>> {
+    let style = TextStyle::new(&FONT_3X5, 0xff).orientation(Orientation::Vertical);
+    let size = style.measure(text);
+    let mut display = open(device)?;
+
+    if size.height <= DISPLAY_HEIGHT {
+        // Vertical lines are laid out from the right, as in `Marquee`
+        let mut frame = Bitmap8::new();
+        let x = (DISPLAY_WIDTH as i32 - size.width as i32) / 2 + size.width as i32 - style.font.height as i32;
+        let y = (DISPLAY_HEIGHT as i32 - size.height as i32) / 2;
+
+        frame.draw_text_styled((x, y), text, &style);
+        display.set_frame(&frame)?;
+
+        return Ok(());
+    }
+
+    let mut marquee = Marquee::new(text);
+    marquee.set_looping(false);
+
+    while !marquee.is_finished() {
+        if marquee.tick() {
+            display.set_frame(&marquee.frame())?;
+        }
+
+        std::thread::sleep(TICK);
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(line: &str) -> Result<(Option<String>, Action), String> {
+        parse(&line.split_whitespace().map(String::from).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn commands_are_parsed() {
+        assert_eq!(parse_str("brightness 50"), Ok((None, Action::Brightness(50))));
+        assert_eq!(parse_str("pattern zigzag"), Ok((None, Action::Pattern(Patterns::ZigZag))));
+        assert_eq!(parse_str("pattern percentage 30"), Ok((None, Action::Pattern(Patterns::Percentage(30)))));
+        assert_eq!(parse_str("draw image.png"), Ok((None, Action::Draw("image.png".to_owned()))));
+        assert_eq!(parse_str("sleep off"), Ok((None, Action::Sleep(false))));
+        assert_eq!(parse_str("list"), Ok((None, Action::List)));
+        assert_eq!(parse_str("setup"), Ok((None, Action::Setup)));
+
+        // Text keeps its spaces when the shell passes it as one argument
+        let args = ["text".to_owned(), "HI THERE".to_owned()];
+        assert_eq!(parse(&args), Ok((None, Action::Text("HI THERE".to_owned()))));
+
+        let device = Some("/dev/ttyACM1".to_owned());
+        assert_eq!(parse_str("--device /dev/ttyACM1 clear"), Ok((device, Action::Clear)));
+    }
+
+    #[test]
+    fn bad_arguments_are_refused() {
+        for line in ["", "--device", "--device /dev/ttyACM0", "brightness 101", "brightness lots",
+            "pattern percentage 101", "pattern sparkles", "sleep maybe", "list everything", "frobnicate"]
+        {
+            assert!(parse_str(line).is_err(), "{:?} was accepted", line);
+        }
+    }
+}
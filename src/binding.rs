@@ -0,0 +1,172 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+type Subscriber<T> = Box<dyn FnMut(&T) + Send>;
+
+struct Inner<T> {
+    value: T,
+    version: u64,
+    subscribers: Vec<Subscriber<T>>,
+}
+
+/// A value widgets can bind to. Clones share the same value, so the code
+/// producing data keeps one and hands another to the widget. Setting an equal
+/// value is ignored, which is what lets the renderer skip frames where nothing
+/// actually changed.
+pub struct Value<T> {
+    inner: Arc<Mutex<Inner<T>>>,
+}
+
+impl<T> Clone for Value<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> Value<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                value,
+                version: 0,
+                subscribers: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn get(&self) -> T {
+        self.lock().value.clone()
+    }
+
+    /// Store a new value and tell subscribers about it. Returns false and
+    /// does nothing if the value didn't change.
+    pub fn set(&self, value: T) -> bool {
+        let mut inner = self.lock();
+
+        if inner.value == value {
+            return false;
+        }
+
+        inner.value = value.clone();
+        inner.version += 1;
+
+        // Run callbacks without holding the lock so they can read the value
+        let mut subscribers = std::mem::take(&mut inner.subscribers);
+        drop(inner);
+
+        for subscriber in subscribers.iter_mut() {
+            subscriber(&value);
+        }
+
+        let mut inner = self.lock();
+        subscribers.append(&mut inner.subscribers);
+        inner.subscribers = subscribers;
+
+        true
+    }
+
+    /// Change the value in place. Subscribers only hear about it if the
+    /// result differs from what was there.
+    pub fn update<F: FnOnce(&mut T)>(&self, f: F) -> bool {
+        let mut value = self.get();
+        f(&mut value);
+        self.set(value)
+    }
+
+    /// Goes up by one every time the value changes
+    pub fn version(&self) -> u64 {
+        self.lock().version
+    }
+
+    /// Call `f` with every new value
+    pub fn subscribe<F: FnMut(&T) + Send + 'static>(&self, f: F) {
+        self.lock().subscribers.push(Box::new(f));
+    }
+
+    /// Polling alternative to `subscribe()` for render loops
+    pub fn watch(&self) -> Watch<T> {
+        Watch {
+            value: self.clone(),
+            seen: None,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner<T>> {
+        self.inner.lock().expect("Value lock poisoned")
+    }
+}
+
+/// Remembers which version of a `Value` was last looked at
+pub struct Watch<T> {
+    value: Value<T>,
+    seen: Option<u64>,
+}
+
+impl<T: Clone + PartialEq> Watch<T> {
+    /// True the first time it's called and whenever the value has changed
+    /// since the last call
+    pub fn changed(&mut self) -> bool {
+        let version = self.value.version();
+        let changed = self.seen != Some(version);
+        self.seen = Some(version);
+        changed
+    }
+
+    pub fn get(&self) -> T {
+        self.value.get()
+    }
+
+    pub fn value(&self) -> &Value<T> {
+        &self.value
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_values_are_ignored() {
+        let value = Value::new(5);
+
+        assert!(!value.set(5));
+        assert_eq!(value.version(), 0);
+        assert!(value.set(6));
+        assert_eq!(value.version(), 1);
+    }
+
+    #[test]
+    fn subscribers_see_changes() {
+        let value = Value::new(0u8);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = seen.clone();
+        let reader = value.clone();
+
+        value.subscribe(move |x| {
+            // Reading inside a callback mustn't deadlock
+            assert_eq!(reader.get(), *x);
+            recorder.lock().unwrap().push(*x);
+        });
+
+        value.set(1);
+        value.set(1);
+        value.update(|x| *x += 1);
+
+        assert_eq!(*seen.lock().unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn watch_tracks_versions() {
+        let value = Value::new("idle".to_owned());
+        let mut watch = value.watch();
+
+        assert!(watch.changed());
+        assert!(!watch.changed());
+
+        value.set("busy".to_owned());
+        assert!(watch.changed());
+        assert_eq!(watch.get(), "busy");
+    }
+}
@@ -0,0 +1,66 @@
+//! Rebooting a module into its bootloader to flash new firmware. The module
+//! drops off USB and comes back as a different device, so this is kept
+//! apart from the everyday commands: `execute()` and `query()` refuse
+//! `Command::Bootloader` and `enter_bootloader()` wants a `Confirmation`.
+
+use std::io::{Error, ErrorKind};
+
+use crate::capabilities::CommandKind;
+use crate::Command;
+
+/// Proof that the caller means it. There's no other way to make one, so a
+/// reboot can't come from a stray `Command` passing through generic code.
+#[derive(Debug)]
+pub struct Confirmation {
+    _private: (),
+}
+
+impl Confirmation {
+    /// The module will stop being an LED matrix until it's flashed or power
+    /// cycled
+    pub fn reboot_into_bootloader() -> Self {
+        Self { _private: () }
+    }
+}
+
+/// Stop the bootloader command going out through the everyday paths,
+/// including as a `Command::Raw`
+pub(crate) fn refuse(command: &Command) -> Result<(), Error> {
+    if command.kind() == Some(CommandKind::Bootloader) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "The bootloader command has to go through enter_bootloader()"
+        ));
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MockTransport;
+    use crate::{LedMatrix, ShutdownScreen};
+
+    #[test]
+    fn only_enter_bootloader_reboots() {
+        let mock = MockTransport::new();
+        let mut matrix = LedMatrix::with_transport("mock", mock.clone());
+
+        let error = matrix.execute(Command::Bootloader).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(matrix.execute_raw(0x02, &[]).is_err());
+        assert!(mock.take_written().is_empty());
+
+        matrix.enter_bootloader(Confirmation::reboot_into_bootloader()).unwrap();
+        assert_eq!(mock.take_written()[..3], [0x32, 0xac, 0x02]);
+
+        // The port is let go rather than reconnected to whatever turns up,
+        // and nothing more is sent to it on drop
+        assert!(!matrix.is_connected());
+        matrix.set_shutdown_screen(ShutdownScreen::Sleep, None);
+        drop(matrix);
+        assert!(mock.written().is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use crate::clock::{Clock, SystemClock};
+
+/// How long preparing a frame can take before it's worth knowing about. A
+/// frame takes roughly 9 column writes to send, so prep much past a few
+/// milliseconds starts eating into the frame rate the link can manage.
+pub const DEFAULT_FRAME_PREP: Duration = Duration::from_millis(4);
+
+type Callback = Box<dyn FnMut(Duration, Duration) + Send>;
+
+/// Running totals for a `PerformanceBudget`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BudgetStats {
+    pub frames: u64,
+    /// Frames that went over budget
+    pub overruns: u64,
+    pub last: Duration,
+    pub worst: Duration,
+}
+
+/// Keeps an eye on how long frames take to prepare. Give one to
+/// `LedMatrix::set_performance_budget()` and it times the filters every
+/// greyscale frame goes through, calling `on_exceeded` callbacks whenever a
+/// frame takes longer than the budget.
+pub struct PerformanceBudget {
+    frame_prep: Duration,
+    clock: Box<dyn Clock + Send>,
+    exceeded: Vec<Callback>,
+    stats: BudgetStats,
+}
+
+impl PerformanceBudget {
+    pub fn new(frame_prep: Duration) -> Self {
+        Self::with_clock(frame_prep, SystemClock)
+    }
+
+    pub fn with_clock<C: Clock + Send + 'static>(frame_prep: Duration, clock: C) -> Self {
+        Self {
+            frame_prep,
+            clock: Box::new(clock),
+            exceeded: Vec::new(),
+            stats: BudgetStats::default(),
+        }
+    }
+
+    pub fn frame_prep(&self) -> Duration {
+        self.frame_prep
+    }
+
+    pub fn set_frame_prep(&mut self, frame_prep: Duration) {
+        self.frame_prep = frame_prep;
+    }
+
+    /// Called with how long a frame took and the budget it went over
+    pub fn on_exceeded(&mut self, callback: impl FnMut(Duration, Duration) + Send + 'static) {
+        self.exceeded.push(Box::new(callback));
+    }
+
+    pub fn stats(&self) -> BudgetStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = BudgetStats::default();
+    }
+
+    /// Time `work` as one frame's prep
+    pub fn measure<T>(&mut self, work: impl FnOnce() -> T) -> T {
+        let start = self.clock.now();
+        let result = work();
+        let elapsed = self.clock.elapsed(start);
+
+        self.record(elapsed);
+
+        result
+    }
+
+    /// Count a frame that took `elapsed` to prepare. Returns whether it was
+    /// within budget.
+    pub fn record(&mut self, elapsed: Duration) -> bool {
+        self.stats.frames += 1;
+        self.stats.last = elapsed;
+        self.stats.worst = self.stats.worst.max(elapsed);
+
+        if elapsed <= self.frame_prep {
+            return true;
+        }
+
+        self.stats.overruns += 1;
+
+        for callback in &mut self.exceeded {
+            callback(elapsed, self.frame_prep);
+        }
+
+        false
+    }
+}
+
+impl Default for PerformanceBudget {
+    fn default() -> Self {
+        Self::new(DEFAULT_FRAME_PREP)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::transport::MockTransport;
+    use crate::{Bitmap8, LedMatrix};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn slow_filters_go_over_budget() {
+        let clock = ManualClock::new();
+        let warnings = Arc::new(AtomicU32::new(0));
+
+        let mut budget = PerformanceBudget::with_clock(Duration::from_millis(2), clock.clone());
+        let counter = warnings.clone();
+        budget.on_exceeded(move |_, _| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+
+        let mut matrix = LedMatrix::with_transport("mock", MockTransport::new());
+        matrix.set_performance_budget(Some(budget));
+
+        let slow = clock.clone();
+        matrix.filters_mut().push(move |_: &mut Bitmap8| slow.advance(Duration::from_millis(5)));
+        matrix.stage_frame(&Bitmap8::new()).unwrap();
+
+        let stats = matrix.performance_budget().unwrap().stats();
+        assert_eq!(stats.frames, 1);
+        assert_eq!(stats.overruns, 1);
+        assert_eq!(stats.worst, Duration::from_millis(5));
+        assert_eq!(warnings.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,310 @@
+use std::time::Duration;
+
+use crate::capabilities::UnsupportedPolicy;
+use crate::clock::{Clock, SharedClock};
+use crate::config::{Config, Mounting};
+use crate::gamma::GammaMap;
+use crate::reconnect::ReconnectPolicy;
+use crate::recorder::Recorder;
+use crate::throttle::Throttle;
+use crate::transport::Transport;
+use crate::verify::Verification;
+use crate::{LedMatrix, ShutdownScreen, StartupScreen, CONNECT_DELAY, DEFAULT_BAUD_RATE, DEFAULT_COLUMN_RETRIES, PROBE_TIMEOUT};
+
+/// Opens an `LedMatrix` with settings other than the defaults. Slow USB
+/// hubs and some platforms need more patience than others.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use f16_hid::LedMatrix;
+///
+/// let matrix = LedMatrix::builder("/dev/ttyACM0")
+///     .write_timeout(Duration::from_millis(250))
+///     .reconnect_delay(Duration::from_secs(1))
+///     .open()?;
+/// # Ok::<(), serialport::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct LedMatrixBuilder {
+    pub(crate) path: String,
+    pub(crate) baud_rate: u32,
+    pub(crate) connect_timeout: Duration,
+    pub(crate) write_timeout: Duration,
+    pub(crate) reconnect_policy: ReconnectPolicy,
+    pub(crate) column_retries: u32,
+    pub(crate) info: bool,
+    pub(crate) capabilities: bool,
+    pub(crate) unsupported: UnsupportedPolicy,
+    pub(crate) startup_screen: StartupScreen,
+    pub(crate) shutdown_screen: ShutdownScreen,
+    pub(crate) shutdown_brightness: Option<u8>,
+    pub(crate) gamma: GammaMap,
+    pub(crate) mounting: Mounting,
+    pub(crate) verification: Verification,
+    pub(crate) throttle: Throttle,
+    pub(crate) recorder: Option<Recorder>,
+    pub(crate) clock: SharedClock,
+}
+
+impl LedMatrixBuilder {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_owned(),
+            baud_rate: DEFAULT_BAUD_RATE,
+            connect_timeout: PROBE_TIMEOUT,
+            write_timeout: CONNECT_DELAY,
+            reconnect_policy: ReconnectPolicy::default(),
+            column_retries: DEFAULT_COLUMN_RETRIES,
+            info: false,
+            capabilities: false,
+            unsupported: UnsupportedPolicy::Send,
+            startup_screen: StartupScreen::Nothing,
+            shutdown_screen: ShutdownScreen::Unchanged,
+            shutdown_brightness: None,
+            gamma: GammaMap::linear(),
+            mounting: Mounting::Normal,
+            verification: Verification::Off,
+            throttle: Throttle::new(),
+            recorder: None,
+            clock: SharedClock::default(),
+        }
+    }
+
+    pub fn baud(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+
+    /// How long to wait for the firmware's first answer when checking it's
+    /// there, as `autodetect()` and `info()` do
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// How long reads and writes on the port can block, before and after
+    /// reconnecting
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    /// Wait before the first reconnect attempt. Later attempts back off
+    /// from there as the reconnect policy says.
+    pub fn reconnect_delay(mut self, delay: Duration) -> Self {
+        self.reconnect_policy.initial_delay = delay;
+        self
+    }
+
+    pub fn reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = policy;
+        self
+    }
+
+    /// See `LedMatrix::set_column_retries()`
+    pub fn column_retries(mut self, retries: u32) -> Self {
+        self.column_retries = retries;
+        self
+    }
+
+    /// Read everything the device will say about itself once it's open, and
+    /// again after every reconnect. See `LedMatrix::with_info()`.
+    pub fn info(mut self, info: bool) -> Self {
+        self.info = info;
+        self
+    }
+
+    /// Read the firmware version on open, and again after every
+    /// reconnect, so commands it's too old for can be refused or worked
+    /// around. `info()` reads it too.
+    pub fn capabilities(mut self, capabilities: bool) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// See `LedMatrix::set_unsupported_policy()`
+    pub fn unsupported(mut self, policy: UnsupportedPolicy) -> Self {
+        self.unsupported = policy;
+        self
+    }
+
+    /// See `LedMatrix::set_gamma()`
+    pub fn gamma(mut self, gamma: GammaMap) -> Self {
+        self.gamma = gamma;
+        self
+    }
+
+    /// An upside down module gets a `filter::Rotate180` ahead of any other
+    /// filters
+    pub fn mounting(mut self, mounting: Mounting) -> Self {
+        self.mounting = mounting;
+        self
+    }
+
+    /// See `LedMatrix::set_verification()`
+    pub fn verification(mut self, verification: Verification) -> Self {
+        self.verification = verification;
+        self
+    }
+
+    /// See `LedMatrix::set_throttle()`
+    pub fn throttle(mut self, throttle: Throttle) -> Self {
+        self.throttle = throttle;
+        self
+    }
+
+    /// Record everything written to the port, see `recorder`
+    pub fn recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// See `LedMatrix::set_clock()`
+    pub fn clock(mut self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /// Take every setting a `Config` has for the matrix itself
+    pub fn config(mut self, config: &Config) -> Self {
+        self.connect_timeout = config.connect_timeout;
+        self.write_timeout = config.write_timeout;
+        self.reconnect_policy.max_retries = config.reconnect_retries;
+        self.reconnect_policy.initial_delay = config.reconnect_delay;
+        self.column_retries = config.column_retries;
+        self.gamma = config.gamma_map();
+        self.mounting = config.mounting;
+        self.throttle = config.throttle;
+        self.shutdown_screen = config.on_exit.into();
+        self
+    }
+
+    /// Show something once the port is open, and again after every
+    /// reconnect. See `LedMatrix::set_startup_screen()`.
+    pub fn startup_screen(mut self, screen: StartupScreen) -> Self {
+        self.startup_screen = screen;
+        self
+    }
+
+    /// Leave something on the panel when the matrix is dropped. See
+    /// `LedMatrix::set_shutdown_screen()`.
+    pub fn shutdown_screen(mut self, screen: ShutdownScreen, brightness: Option<u8>) -> Self {
+        self.shutdown_screen = screen;
+        self.shutdown_brightness = brightness;
+        self
+    }
+
+    pub fn open(self) -> Result<LedMatrix, serialport::Error> {
+        let port = serialport::new(&self.path, self.baud_rate)
+            .timeout(self.write_timeout)
+            .open()?;
+
+        self.finish(LedMatrix::from_builder(&self, Box::new(port), None))
+    }
+
+    /// Build on something other than a serial port, like a `MockTransport`.
+    /// Reconnecting carries on with a clone of the same transport.
+    pub fn open_transport<T: Transport + Clone + 'static>(self, mut transport: T) -> Result<LedMatrix, serialport::Error> {
+        transport.set_timeout(self.write_timeout)?;
+        let matrix = self.build_transport(transport);
+
+        self.finish(matrix)
+    }
+
+    pub(crate) fn build_transport<T: Transport + Clone + 'static>(&self, transport: T) -> LedMatrix {
+        let first = Box::new(transport.clone());
+
+        LedMatrix::from_builder(self, first, Some(Box::new(move || Ok(Box::new(transport.clone())))))
+    }
+
+    pub(crate) fn finish(&self, mut matrix: LedMatrix) -> Result<LedMatrix, serialport::Error> {
+        if self.info {
+            matrix.probe_info = true;
+            matrix.within_timeout(self.connect_timeout, |x| x.refresh_info().map(|_| ()))?;
+        } else if self.capabilities {
+            matrix.probe_capabilities = true;
+            matrix.within_timeout(self.connect_timeout, |x| x.refresh_capabilities().map(|_| ()))?;
+        }
+
+        matrix.play_startup_screen()?;
+
+        Ok(matrix)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MockTransport;
+    use crate::Command;
+
+    #[test]
+    fn settings_reach_the_matrix() {
+        let mock = MockTransport::new();
+
+        let matrix = LedMatrix::builder("mock")
+            .baud(9_600)
+            .write_timeout(Duration::from_millis(300))
+            .reconnect_delay(Duration::from_secs(2))
+            .column_retries(0)
+            .open_transport(mock.clone())
+            .unwrap();
+
+        assert_eq!(matrix.baud_rate(), 9_600);
+        assert_eq!(matrix.reconnect_policy().initial_delay, Duration::from_secs(2));
+        assert_eq!(matrix.column_retries(), 0);
+        assert_eq!(mock.timeout(), Duration::from_millis(300));
+    }
+
+    #[test]
+    fn config_reaches_the_matrix() {
+        let config: Config = "column_retries = 7\ngamma = 2.2\nmounting = \"upside-down\"".parse().unwrap();
+        let matrix = LedMatrix::builder("mock").config(&config).build_transport(MockTransport::new());
+
+        assert_eq!(matrix.column_retries(), 7);
+        assert!(!matrix.gamma().is_linear());
+        assert_eq!(matrix.filters().len(), 1);
+    }
+
+    #[test]
+    fn info_is_read_on_open() {
+        let mock = MockTransport::new();
+        mock.push_reply(&[0, 0x19, 0]);
+
+        let matrix = LedMatrix::builder("mock")
+            .info(true)
+            .open_transport(mock.clone())
+            .unwrap();
+
+        assert_eq!(matrix.info().version.map(|x| x.to_string()), Some("0.1.9".to_owned()));
+        assert_eq!(mock.written()[..3], [0x32, 0xac, Command::Version.id()]);
+        // Back to the normal timeout once the device has answered
+        assert_eq!(mock.timeout(), CONNECT_DELAY);
+    }
+
+    #[test]
+    fn old_firmware_is_worked_around() {
+        let mock = MockTransport::new();
+        mock.push_reply(&[0, 0x10, 0]);
+
+        let mut matrix = LedMatrix::builder("mock")
+            .capabilities(true)
+            .unsupported(UnsupportedPolicy::Refuse)
+            .open_transport(mock.clone())
+            .unwrap();
+        mock.take_written();
+
+        let error = matrix.execute(Command::PwmFreq(crate::PwmFrequency::Hz29000)).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+
+        // No staging on 0.1.0, so greyscale goes out as one binary draw
+        let mut frame = crate::Bitmap8::new();
+        frame.draw_point(0, 0, 0xff).unwrap();
+        matrix.stage_frame(&frame).unwrap();
+
+        let written = mock.take_written();
+        assert_eq!(written.len(), crate::MAX_COMMAND_LENGTH);
+        assert_eq!(written[2..4], [Command::Draw(Box::default()).id(), 0x01]);
+    }
+}
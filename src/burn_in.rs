@@ -0,0 +1,218 @@
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::filter::FrameFilter;
+use crate::geometry::Point;
+use crate::{Bitmap8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Offsets the image cycles through, one step per shift interval
+const OFFSETS: [(i32, i32); 5] = [(0, 0), (1, 0), (0, 1), (-1, 0), (0, -1)];
+
+pub const DEFAULT_SHIFT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+pub const DEFAULT_INVERT_INTERVAL: Duration = Duration::from_secs(30 * 60);
+pub const DEFAULT_INVERT_DURATION: Duration = Duration::from_secs(10);
+pub const DEFAULT_STATIC_AFTER: Duration = Duration::from_secs(10 * 60);
+
+/// Evens out LED wear on dashboards that show the same layout for months.
+/// Add it to a matrix's filters and it nudges the whole image a pixel up,
+/// down, left or right every few minutes, and every so often briefly
+/// inverts bright pixels that haven't changed in a long while.
+///
+/// It only does anything when a frame is sent, so it works best with
+/// something that redraws regularly.
+pub struct BurnInGuard<C: Clock = SystemClock> {
+    clock: C,
+    shift_interval: Option<Duration>,
+    invert_interval: Option<Duration>,
+    invert_duration: Duration,
+    static_after: Duration,
+    threshold: u8,
+    started: Option<Instant>,
+    /// Each pixel's value as drawn, before any shifting, and when it last
+    /// changed
+    history: Vec<(u8, Instant)>,
+}
+
+impl BurnInGuard<SystemClock> {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl Default for BurnInGuard<SystemClock> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clock> BurnInGuard<C> {
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            clock,
+            shift_interval: Some(DEFAULT_SHIFT_INTERVAL),
+            invert_interval: Some(DEFAULT_INVERT_INTERVAL),
+            invert_duration: DEFAULT_INVERT_DURATION,
+            static_after: DEFAULT_STATIC_AFTER,
+            threshold: 0x80,
+            started: None,
+            history: Vec::new(),
+        }
+    }
+
+    /// How long the image stays at each offset. `None` stops shifting.
+    pub fn set_shift_interval(&mut self, interval: Option<Duration>) {
+        self.shift_interval = interval;
+    }
+
+    /// How often static pixels are inverted and for how long. `None` stops
+    /// inverting.
+    pub fn set_inversion(&mut self, interval: Option<Duration>, duration: Duration) {
+        self.invert_interval = interval;
+        self.invert_duration = duration;
+    }
+
+    /// How long a pixel has to stay the same before it counts as static
+    pub fn set_static_after(&mut self, duration: Duration) {
+        self.static_after = duration;
+    }
+
+    /// Dimmest value worth inverting
+    pub fn set_threshold(&mut self, value: u8) {
+        self.threshold = value;
+    }
+
+    /// Where the image is drawn relative to where it was meant to be
+    pub fn offset(&self) -> Point {
+        let (elapsed, _) = self.elapsed();
+
+        let step = match self.shift_interval {
+            Some(interval) if !interval.is_zero() => (elapsed.as_nanos() / interval.as_nanos()) as usize,
+            _ => 0,
+        };
+        let (x, y) = OFFSETS[step % OFFSETS.len()];
+
+        Point::new(x, y)
+    }
+
+    /// Whether static pixels are being inverted right now
+    pub fn is_inverting(&self) -> bool {
+        let (elapsed, _) = self.elapsed();
+
+        match self.invert_interval {
+            Some(interval) if !interval.is_zero() && elapsed >= interval => {
+                elapsed.as_nanos() % interval.as_nanos() < self.invert_duration.as_nanos()
+            },
+            _ => false,
+        }
+    }
+
+    fn elapsed(&self) -> (Duration, Instant) {
+        let now = self.clock.now();
+        let elapsed = self.started.map(|x| now.saturating_duration_since(x)).unwrap_or_default();
+
+        (elapsed, now)
+    }
+}
+
+impl<C: Clock> FrameFilter for BurnInGuard<C> {
+    fn apply(&mut self, frame: &mut Bitmap8) {
+        let now = self.clock.now();
+        self.started.get_or_insert(now);
+
+        if self.history.is_empty() {
+            self.history = frame.data.iter().map(|x| (*x, now)).collect();
+        }
+
+        for (value, entry) in frame.data.iter().zip(self.history.iter_mut()) {
+            if entry.0 != *value {
+                *entry = (*value, now);
+            }
+        }
+
+        if self.is_inverting() {
+            for (value, (_, since)) in frame.data.iter_mut().zip(self.history.iter()) {
+                if *value >= self.threshold && now.saturating_duration_since(*since) >= self.static_after {
+                    *value = 0xff - *value;
+                }
+            }
+        }
+
+        let offset = self.offset();
+
+        if offset != Point::ZERO {
+            let mut shifted = Bitmap8::new();
+
+            for x in 0 .. DISPLAY_WIDTH {
+                for y in 0 .. DISPLAY_HEIGHT {
+                    let point = Point::new(x as i32, y as i32);
+                    shifted.set_pixel(point + offset, frame.data[x * DISPLAY_HEIGHT + y]);
+                }
+            }
+
+            frame.data = shifted.data;
+        }
+    }
+
+    fn moves_pixels(&self) -> bool {
+        true
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn image_shifts_on_schedule() {
+        let clock = ManualClock::new();
+        let mut guard = BurnInGuard::with_clock(clock.clone());
+        guard.set_inversion(None, Duration::ZERO);
+
+        let mut frame = Bitmap8::new();
+        frame.set_pixel(Point::new(4, 4), 0xff);
+
+        let mut output = frame.clone();
+        guard.apply(&mut output);
+        assert_eq!(output.pixel(Point::new(4, 4)), Some(0xff));
+
+        clock.advance(DEFAULT_SHIFT_INTERVAL);
+        let mut output = frame.clone();
+        guard.apply(&mut output);
+        assert_eq!(guard.offset(), Point::new(1, 0));
+        assert_eq!(output.pixel(Point::new(5, 4)), Some(0xff));
+        assert_eq!(output.pixel(Point::new(4, 4)), Some(0));
+    }
+
+    #[test]
+    fn only_static_bright_pixels_invert() {
+        let clock = ManualClock::new();
+        let mut guard = BurnInGuard::with_clock(clock.clone());
+        guard.set_shift_interval(None);
+        guard.set_inversion(Some(Duration::from_secs(60)), Duration::from_secs(5));
+        guard.set_static_after(Duration::from_secs(30));
+
+        let mut frame = Bitmap8::new();
+        frame.set_pixel(Point::new(0, 0), 0xf0);
+        frame.set_pixel(Point::new(1, 0), 0x10);
+        guard.apply(&mut frame.clone());
+
+        // Something new turns up just before the inversion
+        clock.advance(Duration::from_secs(59));
+        frame.set_pixel(Point::new(2, 0), 0xf0);
+        guard.apply(&mut frame.clone());
+
+        clock.advance(Duration::from_secs(2));
+        let mut output = frame.clone();
+        guard.apply(&mut output);
+
+        assert!(guard.is_inverting());
+        assert_eq!(output.pixel(Point::new(0, 0)), Some(0x0f));
+        assert_eq!(output.pixel(Point::new(1, 0)), Some(0x10));
+        assert_eq!(output.pixel(Point::new(2, 0)), Some(0xf0));
+
+        clock.advance(Duration::from_secs(5));
+        assert!(!guard.is_inverting());
+    }
+}
@@ -0,0 +1,346 @@
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::filter::FrameFilter;
+use crate::geometry::Rect;
+use crate::roles::{config_directory, CONFIG_DIRECTORY};
+use crate::{Bitmap8, LedMatrix, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+pub const GAMMA_FILE: &str = "gamma.conf";
+
+/// Rows in each band of the calibration screen: low at the top, the value
+/// being judged in the middle, high at the bottom
+const BAND_HEIGHT: usize = DISPLAY_HEIGHT / 3;
+
+/// How close a light meter reading has to be to halfway to count
+const METER_TOLERANCE: f64 = 0.02;
+
+/// Maps the brightness a frame asks for to the value sent to the panel. The
+/// LEDs don't look twice as bright at 0x80 as at 0x40, so a table measured
+/// with `calibrate()` makes steps look even. Add it to a matrix's filters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GammaLut {
+    table: [u8; 256],
+}
+
+impl GammaLut {
+    /// Sends every value as is
+    pub fn identity() -> Self {
+        let mut table = [0; 256];
+
+        for (index, value) in table.iter_mut().enumerate() {
+            *value = index as u8;
+        }
+
+        Self { table }
+    }
+
+    /// The usual power curve. Above 1 darkens the middle values.
+    pub fn from_gamma(gamma: f64) -> Self {
+        let mut table = [0; 256];
+
+        for (index, value) in table.iter_mut().enumerate() {
+            *value = ((index as f64 / 255.0).powf(gamma) * 255.0).round() as u8;
+        }
+
+        Self { table }
+    }
+
+    /// Build from measured points, each a value sent to the panel and how
+    /// bright it looked from 0.0 (off) to 1.0 (full). Fully off and fully on
+    /// are added if missing, and the values in between are interpolated.
+    pub fn from_points(points: &[(u8, f64)]) -> Self {
+        let mut points: Vec<(f64, f64)> = points.iter()
+            .map(|(raw, perceived)| (perceived.clamp(0.0, 1.0), *raw as f64))
+            .chain([(0.0, 0.0), (1.0, 255.0)])
+            .collect();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+
+        // Judging by eye isn't always consistent, don't let the curve go back
+        for index in 1 .. points.len() {
+            points[index].1 = points[index].1.max(points[index - 1].1);
+        }
+
+        let mut table = [0; 256];
+
+        for (index, value) in table.iter_mut().enumerate() {
+            let wanted = index as f64 / 255.0;
+            let after = points.iter().position(|x| x.0 >= wanted).unwrap_or(points.len() - 1);
+            let (high, low) = (points[after], points[after.saturating_sub(1)]);
+
+            let raw = if high.0 > low.0 {
+                low.1 + (high.1 - low.1) * (wanted - low.0) / (high.0 - low.0)
+            } else {
+                high.1
+            };
+
+            *value = raw.round().clamp(0.0, 255.0) as u8;
+        }
+
+        Self { table }
+    }
+
+    /// Value sent to the panel for a requested brightness
+    pub fn map(&self, value: u8) -> u8 {
+        self.table[value as usize]
+    }
+
+    pub fn table(&self) -> &[u8; 256] {
+        &self.table
+    }
+
+    /// Where the table lives for the current user, next to the role config
+    pub fn default_path() -> Option<PathBuf> {
+        config_directory().map(|x| x.join(CONFIG_DIRECTORY).join(GAMMA_FILE))
+    }
+
+    /// Load from the default location. A missing file is the identity table.
+    pub fn load_default() -> Result<Self, Error> {
+        match Self::default_path() {
+            Some(path) => Self::load(path),
+            None => Err(Error::new(ErrorKind::NotFound, "Unable to find a config directory")),
+        }
+    }
+
+    pub fn save_default(&self) -> Result<(), Error> {
+        match Self::default_path() {
+            Some(path) => self.save(path),
+            None => Err(Error::new(ErrorKind::NotFound, "Unable to find a config directory")),
+        }
+    }
+
+    /// Load from a file. A missing file is the identity table.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => contents.parse(),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::identity()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Write to a file, creating parent directories as needed
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(path, self.to_string())
+    }
+}
+
+impl Default for GammaLut {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl FrameFilter for GammaLut {
+    fn apply(&mut self, frame: &mut Bitmap8) {
+        for value in frame.data.iter_mut() {
+            *value = self.table[*value as usize];
+        }
+    }
+}
+
+/// All 256 entries in order, sixteen to a line
+impl fmt::Display for GammaLut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# LED matrix brightness table, the value sent for 0 to 255")?;
+
+        for row in self.table.chunks(16) {
+            let row: Vec<_> = row.iter().map(|x| x.to_string()).collect();
+            writeln!(f, "{}", row.join(" "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for GammaLut {
+    type Err = Error;
+
+    fn from_str(contents: &str) -> Result<Self, Self::Err> {
+        let values = contents.lines()
+            .map(|x| x.trim())
+            .filter(|x| !x.starts_with('#'))
+            .flat_map(|x| x.split_whitespace())
+            .map(|x| x.parse::<u8>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Expected numbers from 0 to 255"))?;
+
+        let table = values.try_into()
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Expected 256 entries"))?;
+
+        Ok(Self { table })
+    }
+}
+
+/// How the middle band looks next to halfway between the other two
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Judgement {
+    Dimmer,
+    Halfway,
+    Brighter,
+}
+
+/// Whoever or whatever decides how bright things look during calibration.
+/// Closures taking `(low, middle, high)` work as judges too, handy for
+/// asking a person at a prompt.
+pub trait Judge {
+    /// The panel is showing `low` in its top band, `middle` in the middle and
+    /// `high` at the bottom. Judges are free to draw something else to take
+    /// their measurement.
+    fn judge(&mut self, matrix: &mut LedMatrix, low: u8, middle: u8, high: u8) -> Result<Judgement, Error>;
+}
+
+impl<F: FnMut(u8, u8, u8) -> Judgement> Judge for F {
+    fn judge(&mut self, _matrix: &mut LedMatrix, low: u8, middle: u8, high: u8) -> Result<Judgement, Error> {
+        Ok(self(low, middle, high))
+    }
+}
+
+/// Judges with a light meter instead of an eye: a webcam pointed at the
+/// panel, a phone's light sensor, anything that can read a number. The panel
+/// is filled with each value in turn and `measure` is called for a reading.
+pub struct LightMeter<F> {
+    measure: F,
+}
+
+impl<F: FnMut() -> Result<f64, Error>> LightMeter<F> {
+    pub fn new(measure: F) -> Self {
+        Self { measure }
+    }
+
+    fn reading(&mut self, matrix: &mut LedMatrix, value: u8) -> Result<f64, Error> {
+        let mut frame = Bitmap8::new();
+        frame.fill(value);
+        matrix.stage_frame(&frame)?;
+
+        (self.measure)()
+    }
+}
+
+impl<F: FnMut() -> Result<f64, Error>> Judge for LightMeter<F> {
+    fn judge(&mut self, matrix: &mut LedMatrix, low: u8, middle: u8, high: u8) -> Result<Judgement, Error> {
+        let low = self.reading(matrix, low)?;
+        let high = self.reading(matrix, high)?;
+        let middle = self.reading(matrix, middle)?;
+
+        let halfway = (low + high) / 2.0;
+        let tolerance = (high - low).abs() * METER_TOLERANCE;
+
+        Ok(if middle < halfway - tolerance {
+            Judgement::Dimmer
+        } else if middle > halfway + tolerance {
+            Judgement::Brighter
+        } else {
+            Judgement::Halfway
+        })
+    }
+}
+
+/// Measure how the panel's brightness really steps and build a table that
+/// evens it out. Starting from off and full, the judge is asked to find the
+/// value that looks halfway between, then halfway between that and each end,
+/// and so on `depth` levels down. Three levels takes seven midpoints.
+///
+/// The matrix's filters are left out while measuring so an old table can't
+/// skew the new one. Save the result with `save_default()`.
+pub fn calibrate<J: Judge>(matrix: &mut LedMatrix, judge: &mut J, depth: u32) -> Result<GammaLut, Error> {
+    let filters = std::mem::take(matrix.filters_mut());
+    let points = measure(matrix, judge, depth);
+    *matrix.filters_mut() = filters;
+
+    Ok(GammaLut::from_points(&points?))
+}
+
+fn measure<J: Judge>(matrix: &mut LedMatrix, judge: &mut J, depth: u32) -> Result<Vec<(u8, f64)>, Error> {
+    let mut points = Vec::new();
+    let mut spans = vec![((0u8, 0.0), (0xffu8, 1.0), depth)];
+
+    while let Some(((low, dark), (high, light), depth)) = spans.pop() {
+        if depth == 0 || high - low < 2 {
+            continue;
+        }
+
+        let middle = midpoint(matrix, judge, low, high)?;
+        let perceived = (dark + light) / 2.0;
+        points.push((middle, perceived));
+
+        spans.push(((low, dark), (middle, perceived), depth - 1));
+        spans.push(((middle, perceived), (high, light), depth - 1));
+    }
+
+    Ok(points)
+}
+
+/// Binary search for the value that looks halfway between `low` and `high`
+fn midpoint<J: Judge>(matrix: &mut LedMatrix, judge: &mut J, low: u8, high: u8) -> Result<u8, Error> {
+    let (mut lower, mut upper) = (low, high);
+
+    while upper - lower > 1 {
+        let middle = lower + (upper - lower) / 2;
+        matrix.stage_frame(&bands(low, middle, high))?;
+
+        match judge.judge(matrix, low, middle, high)? {
+            Judgement::Dimmer => lower = middle,
+            Judgement::Brighter => upper = middle,
+            Judgement::Halfway => return Ok(middle),
+        }
+    }
+
+    Ok(lower + (upper - lower) / 2)
+}
+
+fn bands(low: u8, middle: u8, high: u8) -> Bitmap8 {
+    let mut frame = Bitmap8::new();
+    frame.fill(middle);
+    frame.fill_rect(Rect::new((0, 0), (DISPLAY_WIDTH, BAND_HEIGHT)), low);
+    frame.fill_rect(Rect::new((0, (DISPLAY_HEIGHT - BAND_HEIGHT) as i32), (DISPLAY_WIDTH, BAND_HEIGHT)), high);
+
+    frame
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MockTransport;
+
+    #[test]
+    fn tables_round_trip() {
+        let lut = GammaLut::from_gamma(2.2);
+        let parsed: GammaLut = lut.to_string().parse().unwrap();
+
+        assert_eq!(parsed, lut);
+        assert!("1 2 3".parse::<GammaLut>().is_err());
+        assert_eq!(GammaLut::identity().map(0x80), 0x80);
+    }
+
+    #[test]
+    fn calibration_evens_out_a_square_root_panel() {
+        let mut matrix = LedMatrix::with_transport("mock", MockTransport::new());
+        matrix.filters_mut().push(GammaLut::from_gamma(3.0));
+
+        // A panel where 0x40 already looks half as bright as full
+        let looks = |x: u8| (x as f64 / 255.0).sqrt();
+        let mut judge = |low: u8, middle: u8, high: u8| {
+            let halfway = (looks(low) + looks(high)) / 2.0;
+
+            match looks(middle) {
+                x if (x - halfway).abs() < 0.005 => Judgement::Halfway,
+                x if x < halfway => Judgement::Dimmer,
+                _ => Judgement::Brighter,
+            }
+        };
+
+        let lut = calibrate(&mut matrix, &mut judge, 3).unwrap();
+
+        assert!(lut.map(0x80).abs_diff(0x40) <= 2, "{}", lut.map(0x80));
+        assert!(lut.map(0x40).abs_diff(0x10) <= 2, "{}", lut.map(0x40));
+        assert_eq!(lut.map(0xff), 0xff);
+        assert_eq!(matrix.filters().len(), 1);
+    }
+}
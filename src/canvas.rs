@@ -0,0 +1,218 @@
+//! Drawing in the coordinates a viewer sees, whichever way the module is
+//! mounted. `Bitmap8` keeps its pixels in the firmware's column order, but
+//! through `Canvas` that never shows: `(x, y)` is always across then down.
+//!
+//! ```
+//! use f16_hid::canvas::{Canvas, Rotation};
+//! use f16_hid::Bitmap8;
+//!
+//! let mut frame = Bitmap8::new();
+//!
+//! // A module lying on its side, 34 wide and 9 tall
+//! let mut landscape = frame.rotated(Rotation::Clockwise90);
+//! assert_eq!(landscape.size().width, 34);
+//! landscape.set_pixel((33, 0).into(), 0xff);
+//! ```
+
+use crate::geometry::{Point, Rect, Size};
+use crate::{Bitmap8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// How far a drawing is turned clockwise on the way to the panel
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    UpsideDown,
+    Clockwise270,
+}
+
+impl Rotation {
+    pub fn degrees(&self) -> u16 {
+        match self {
+            Self::None => 0,
+            Self::Clockwise90 => 90,
+            Self::UpsideDown => 180,
+            Self::Clockwise270 => 270,
+        }
+    }
+
+    /// `None` unless it's a whole number of quarter turns
+    pub fn from_degrees(degrees: u16) -> Option<Self> {
+        match degrees % 360 {
+            0 => Some(Self::None),
+            90 => Some(Self::Clockwise90),
+            180 => Some(Self::UpsideDown),
+            270 => Some(Self::Clockwise270),
+            _ => None,
+        }
+    }
+
+    /// Whether width and height trade places
+    pub fn is_sideways(&self) -> bool {
+        matches!(self, Self::Clockwise90 | Self::Clockwise270)
+    }
+
+    /// Size of a canvas drawn at this rotation
+    pub fn size(&self) -> Size {
+        if self.is_sideways() {
+            Size::new(DISPLAY_HEIGHT, DISPLAY_WIDTH)
+        } else {
+            Size::display()
+        }
+    }
+
+    /// Where a point on the rotated canvas lands on the panel
+    pub fn to_panel(&self, point: Point) -> Point {
+        let (width, height) = (DISPLAY_WIDTH as i32, DISPLAY_HEIGHT as i32);
+
+        match self {
+            Self::None => point,
+            Self::Clockwise90 => Point::new(width - 1 - point.y, point.x),
+            Self::UpsideDown => Point::new(width - 1 - point.x, height - 1 - point.y),
+            Self::Clockwise270 => Point::new(point.y, height - 1 - point.x),
+        }
+    }
+}
+
+/// Something to draw on with `(x, y)` running across and down
+pub trait Canvas {
+    fn size(&self) -> Size;
+
+    /// Value of a pixel, `None` if it's off the canvas
+    fn pixel(&self, point: Point) -> Option<u8>;
+
+    /// Set a pixel, ignoring anything off the canvas
+    fn set_pixel(&mut self, point: Point, value: u8);
+
+    fn bounds(&self) -> Rect {
+        Rect::new(Point::ZERO, self.size())
+    }
+
+    fn fill(&mut self, value: u8) {
+        self.fill_rect(self.bounds(), value);
+    }
+
+    fn fill_rect(&mut self, area: Rect, value: u8) {
+        let area = area.intersection(&self.bounds());
+
+        for y in area.top() .. area.bottom() {
+            for x in area.left() .. area.right() {
+                self.set_pixel(Point::new(x, y), value);
+            }
+        }
+    }
+
+    /// Copy all of `other` onto this, its top left corner at `origin`
+    fn blit(&mut self, other: &dyn Canvas, origin: Point) {
+        let size = other.size();
+
+        for y in 0 .. size.height as i32 {
+            for x in 0 .. size.width as i32 {
+                if let Some(value) = other.pixel(Point::new(x, y)) {
+                    self.set_pixel(origin + Point::new(x, y), value);
+                }
+            }
+        }
+    }
+}
+
+/// The panel as it is, following the bitmap's edge mode
+impl Canvas for Bitmap8 {
+    fn size(&self) -> Size {
+        Size::display()
+    }
+
+    fn pixel(&self, point: Point) -> Option<u8> {
+        Bitmap8::pixel(self, point)
+    }
+
+    fn set_pixel(&mut self, point: Point, value: u8) {
+        Bitmap8::set_pixel(self, point, value)
+    }
+
+    fn fill_rect(&mut self, area: Rect, value: u8) {
+        Bitmap8::fill_rect(self, area, value)
+    }
+}
+
+/// A `Bitmap8` seen at a rotation, from `Bitmap8::rotated()`. Anything off
+/// the rotated canvas is dropped whatever the bitmap's edge mode.
+pub struct Rotated<'a> {
+    bitmap: &'a mut Bitmap8,
+    rotation: Rotation,
+}
+
+impl Rotated<'_> {
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    fn on_panel(&self, point: Point) -> Option<(usize, usize)> {
+        if !self.bounds().contains(point) {
+            return None;
+        }
+
+        self.rotation.to_panel(point).on_panel()
+    }
+}
+
+impl Canvas for Rotated<'_> {
+    fn size(&self) -> Size {
+        self.rotation.size()
+    }
+
+    fn pixel(&self, point: Point) -> Option<u8> {
+        let (x, y) = self.on_panel(point)?;
+
+        Some(self.bitmap.data[x * DISPLAY_HEIGHT + y])
+    }
+
+    fn set_pixel(&mut self, point: Point, value: u8) {
+        if let Some((x, y)) = self.on_panel(point) {
+            self.bitmap.data[x * DISPLAY_HEIGHT + y] = value;
+        }
+    }
+}
+
+impl Bitmap8 {
+    /// Draw on this as though the module were turned by `rotation`
+    pub fn rotated(&mut self, rotation: Rotation) -> Rotated<'_> {
+        Rotated { bitmap: self, rotation }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotations_land_in_the_right_corner() {
+        let corners = [
+            (Rotation::None, (0, 0)),
+            (Rotation::Clockwise90, (8, 0)),
+            (Rotation::UpsideDown, (8, 33)),
+            (Rotation::Clockwise270, (0, 33)),
+        ];
+
+        for (rotation, (x, y)) in corners {
+            let mut frame = Bitmap8::new();
+            frame.rotated(rotation).set_pixel(Point::ZERO, 0xff);
+
+            assert_eq!(frame.pixel(Point::new(x, y)), Some(0xff), "{:?}", rotation);
+            assert_eq!(Rotation::from_degrees(rotation.degrees()), Some(rotation));
+        }
+
+        // A row across a landscape canvas is a column down the panel
+        let mut frame = Bitmap8::new();
+        let mut landscape = frame.rotated(Rotation::Clockwise90);
+        assert_eq!(landscape.size(), Size::new(34, 9));
+        landscape.fill_rect(Rect::new((0, 0), (40, 1)), 0x80);
+        assert_eq!(landscape.pixel(Point::new(34, 0)), None);
+
+        let mut expected = Bitmap8::new();
+        expected.fill_rect(Rect::new((8, 0), (1, 34)), 0x80);
+        assert_eq!(frame.data(), expected.data());
+    }
+}
@@ -0,0 +1,201 @@
+#[cfg(feature = "std")]
+use std::io::{Error, ErrorKind};
+
+use crate::response::FirmwareVersion;
+#[cfg(feature = "std")]
+use crate::Command;
+
+/// Greyscale frames sent to firmware that can't stage columns are drawn
+/// in black and white instead, lit wherever they're at least this bright
+pub const BINARY_FALLBACK_THRESHOLD: u8 = 0x80;
+
+/// Every command the LED matrix firmware understands, whether or not this
+/// crate has typed support for it yet
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CommandKind {
+    Brightness,
+    Pattern,
+    Bootloader,
+    Sleep,
+    Animate,
+    Panic,
+    Draw,
+    StageColumn,
+    DrawBuffer,
+    StartGame,
+    GameControl,
+    GameStatus,
+    AnimationPeriod,
+    PwmFrequency,
+    DebugMode,
+    Version,
+}
+
+impl CommandKind {
+    pub const ALL: [CommandKind; 16] = [
+        Self::Brightness,
+        Self::Pattern,
+        Self::Bootloader,
+        Self::Sleep,
+        Self::Animate,
+        Self::Panic,
+        Self::Draw,
+        Self::StageColumn,
+        Self::DrawBuffer,
+        Self::StartGame,
+        Self::GameControl,
+        Self::GameStatus,
+        Self::AnimationPeriod,
+        Self::PwmFrequency,
+        Self::DebugMode,
+        Self::Version,
+    ];
+
+    /// The command the firmware knows by this ID, if any
+    pub fn from_id(id: u8) -> Option<Self> {
+        let kind = match id {
+            0x00 => Self::Brightness,
+            0x01 => Self::Pattern,
+            0x02 => Self::Bootloader,
+            0x03 => Self::Sleep,
+            0x04 => Self::Animate,
+            0x05 => Self::Panic,
+            0x06 => Self::Draw,
+            0x07 => Self::StageColumn,
+            0x08 => Self::DrawBuffer,
+            0x10 => Self::StartGame,
+            0x11 => Self::GameControl,
+            0x12 => Self::GameStatus,
+            0x1c => Self::AnimationPeriod,
+            0x1e => Self::PwmFrequency,
+            0x1f => Self::DebugMode,
+            0x20 => Self::Version,
+            _ => return None,
+        };
+
+        Some(kind)
+    }
+
+    /// Oldest firmware known to handle this command
+    pub fn introduced_in(&self) -> FirmwareVersion {
+        match self {
+            Self::Brightness |
+            Self::Pattern |
+            Self::Bootloader |
+            Self::Sleep |
+            Self::Animate |
+            Self::Panic |
+            Self::Draw |
+            Self::Version => FirmwareVersion::new(0, 1, 0),
+            Self::StageColumn |
+            Self::DrawBuffer => FirmwareVersion::new(0, 1, 2),
+            Self::StartGame |
+            Self::GameControl |
+            Self::GameStatus => FirmwareVersion::new(0, 1, 5),
+            Self::AnimationPeriod |
+            Self::PwmFrequency |
+            Self::DebugMode => FirmwareVersion::new(0, 1, 8),
+        }
+    }
+}
+
+/// What a particular firmware version can do
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    version: Option<FirmwareVersion>,
+}
+
+impl Capabilities {
+    pub fn new(version: FirmwareVersion) -> Self {
+        Self { version: Some(version) }
+    }
+
+    /// For when the version couldn't be read. Everything is assumed to work
+    /// so old behaviour isn't taken away from anyone.
+    pub fn unknown() -> Self {
+        Self { version: None }
+    }
+
+    pub fn version(&self) -> Option<FirmwareVersion> {
+        self.version
+    }
+
+    pub fn supports(&self, kind: CommandKind) -> bool {
+        match self.version {
+            Some(version) => version >= kind.introduced_in(),
+            None => true,
+        }
+    }
+
+    /// Every command this firmware handles
+    pub fn supported(&self) -> impl Iterator<Item = CommandKind> + '_ {
+        CommandKind::ALL.into_iter().filter(|x| self.supports(*x))
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::unknown()
+    }
+}
+
+/// What happens to a command the connected firmware is too old for. Only
+/// matters once the version has been read, see
+/// `LedMatrix::refresh_capabilities()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnsupportedPolicy {
+    /// Send it anyway and let the firmware ignore it
+    #[default]
+    Send,
+    /// Fail with `ErrorKind::Unsupported` without sending anything
+    Refuse,
+}
+
+#[cfg(feature = "std")]
+impl UnsupportedPolicy {
+    pub(crate) fn check(&self, capabilities: &Capabilities, command: &Command) -> Result<(), Error> {
+        let (kind, version) = match (self, command.kind(), capabilities.version()) {
+            (Self::Refuse, Some(kind), Some(version)) if !capabilities.supports(kind) => (kind, version),
+            _ => return Ok(()),
+        };
+
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("{:?} needs firmware {} or later, this is {}", kind, kind.introduced_in(), version)
+        ))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_firmware_lacks_games() {
+        let capabilities = Capabilities::new(FirmwareVersion::new(0, 1, 2));
+
+        assert!(capabilities.supports(CommandKind::StageColumn));
+        assert!(!capabilities.supports(CommandKind::StartGame));
+        assert!(!capabilities.supported().any(|x| x == CommandKind::PwmFrequency));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn refusing_names_the_version_needed() {
+        let capabilities = Capabilities::new(FirmwareVersion::new(0, 1, 0));
+        let error = UnsupportedPolicy::Refuse.check(&capabilities, &Command::DrawBuffer).unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+        assert!(error.to_string().contains("0.1.2"));
+        assert!(UnsupportedPolicy::Send.check(&capabilities, &Command::DrawBuffer).is_ok());
+        assert!(UnsupportedPolicy::Refuse.check(&Capabilities::unknown(), &Command::DrawBuffer).is_ok());
+    }
+
+    #[test]
+    fn unknown_firmware_supports_everything() {
+        let capabilities = Capabilities::unknown();
+
+        assert_eq!(capabilities.supported().count(), CommandKind::ALL.len());
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Source of time for anything that paces itself, so tests can skip the
+/// waiting. The renderer, marquee, animations and schedules take one, and so
+/// does `LedMatrix` for its throttle, reconnects, verification and identify
+/// flashes, see `LedMatrix::set_clock()`.
+///
+/// Some things stay on real time. Measuring how long hardware took, as the
+/// self-test, tracing and the system monitors do, only means anything against
+/// the real clock. So do `ConnectionWatch::wait_connected()` and `Console`,
+/// which wait on another thread or on the port.
+pub trait Clock {
+    fn now(&self) -> Instant;
+
+    fn sleep(&self, duration: Duration);
+
+    /// How long it's been since `earlier`, never negative
+    fn elapsed(&self, earlier: Instant) -> Duration {
+        self.now().saturating_duration_since(earlier)
+    }
+}
+
+/// The real clock
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+/// Clock that only moves when told to. Sleeping on it advances time instantly
+/// instead of blocking. Clones share the same time, so a test can keep one
+/// copy and hand the other to whatever it's testing.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().expect("Clock lock poisoned");
+        *now += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().expect("Clock lock poisoned")
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration)
+    }
+}
+
+/// Any clock, for things like `LedMatrix` that aren't generic over one but
+/// still have to be `Send`. Clones share the same clock.
+#[derive(Clone)]
+pub(crate) struct SharedClock(Arc<dyn Clock + Send + Sync>);
+
+impl SharedClock {
+    pub(crate) fn new(clock: impl Clock + Send + Sync + 'static) -> Self {
+        Self(Arc::new(clock))
+    }
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        Self::new(SystemClock)
+    }
+}
+
+impl fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedClock")
+    }
+}
+
+impl Clock for SharedClock {
+    fn now(&self) -> Instant {
+        self.0.now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.0.sleep(duration)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_only_moves_when_told() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+
+        assert_eq!(clock.elapsed(start), Duration::ZERO);
+
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(clock.elapsed(start), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn manual_clock_sleep_is_instant() {
+        let clock = ManualClock::new();
+        let shared = clock.clone();
+        let start = clock.now();
+
+        shared.sleep(Duration::from_secs(3600));
+
+        assert_eq!(clock.elapsed(start), Duration::from_secs(3600));
+    }
+}
@@ -0,0 +1,388 @@
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::display::DEFAULT_FRAME_RETRIES;
+use crate::gamma::GammaMap;
+use crate::pacer::FramePacer;
+use crate::reconnect::ReconnectPolicy;
+use crate::roles::{config_directory, CONFIG_DIRECTORY};
+use crate::throttle::Throttle;
+use crate::{ShutdownScreen, CONNECT_DELAY, DEFAULT_COLUMN_RETRIES, PROBE_TIMEOUT};
+
+pub const CONFIG_FILE: &str = "config.toml";
+/// Environment variables named this followed by a key in capitals, like
+/// `F16_HID_MAX_FPS`, override the file
+pub const ENV_PREFIX: &str = "F16_HID_";
+
+/// Which way up the module is mounted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mounting {
+    #[default]
+    Normal,
+    /// Turned around, so every frame is rotated to match
+    UpsideDown,
+}
+
+impl fmt::Display for Mounting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Normal => write!(f, "normal"),
+            Self::UpsideDown => write!(f, "upside-down"),
+        }
+    }
+}
+
+impl FromStr for Mounting {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "normal" => Ok(Self::Normal),
+            "upside-down" => Ok(Self::UpsideDown),
+            _ => Err("Unknown mounting"),
+        }
+    }
+}
+
+/// What the panel is left showing when the application exits, the choices
+/// of `ShutdownScreen` that make sense in a file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExitScreen {
+    /// Whatever was last drawn
+    #[default]
+    Unchanged,
+    /// Every LED off
+    Clear,
+    Sleep,
+}
+
+impl fmt::Display for ExitScreen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unchanged => write!(f, "unchanged"),
+            Self::Clear => write!(f, "clear"),
+            Self::Sleep => write!(f, "sleep"),
+        }
+    }
+}
+
+impl FromStr for ExitScreen {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "unchanged" => Ok(Self::Unchanged),
+            "clear" => Ok(Self::Clear),
+            "sleep" => Ok(Self::Sleep),
+            _ => Err("expected unchanged, clear or sleep"),
+        }
+    }
+}
+
+impl From<ExitScreen> for ShutdownScreen {
+    fn from(screen: ExitScreen) -> Self {
+        match screen {
+            ExitScreen::Unchanged => Self::Unchanged,
+            ExitScreen::Clear => Self::Clear,
+            ExitScreen::Sleep => Self::Sleep,
+        }
+    }
+}
+
+/// When the matrix should go to sleep by itself. Each needs its own
+/// feature, see `display_power::DisplayPowerSync` and
+/// `power::SuspendMonitor`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PowerPolicy {
+    /// Whenever the laptop's own screen is off or the lid is shut
+    pub sleep_with_screen: bool,
+    /// While the host is suspended
+    pub sleep_on_suspend: bool,
+}
+
+/// Every setting worth tuning in one place, for applications that would
+/// rather hand users a file than a dozen options. Start from the defaults,
+/// a file or the environment, then pass it to `LedMatrixBuilder::config()`,
+/// `Display::apply_config()` and `pacer()`.
+///
+/// Stored as TOML, one `key = value` per line. Lines starting with `#` are
+/// comments and anything not mentioned keeps its default.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub write_timeout: Duration,
+    pub connect_timeout: Duration,
+    /// Reconnects `execute()` tries when the port goes away
+    pub reconnect_retries: u32,
+    /// Wait before the first reconnect, later ones back off from there
+    pub reconnect_delay: Duration,
+    pub column_retries: u32,
+    /// Attempts a `Display` gives each frame after the first fails
+    pub frame_retries: u32,
+    /// 1.0 sends greyscale values as they are
+    pub gamma: f64,
+    /// `None` for as fast as the application goes
+    pub max_fps: Option<f64>,
+    pub power: PowerPolicy,
+    pub mounting: Mounting,
+    /// Limits on how fast commands go out, for firmware that drops them
+    pub throttle: Throttle,
+    /// What's left on the panel when the matrix is dropped
+    pub on_exit: ExitScreen,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            write_timeout: CONNECT_DELAY,
+            connect_timeout: PROBE_TIMEOUT,
+            reconnect_retries: ReconnectPolicy::default().max_retries,
+            reconnect_delay: ReconnectPolicy::default().initial_delay,
+            column_retries: DEFAULT_COLUMN_RETRIES,
+            frame_retries: DEFAULT_FRAME_RETRIES,
+            gamma: 1.0,
+            max_fps: None,
+            power: PowerPolicy::default(),
+            mounting: Mounting::Normal,
+            throttle: Throttle::new(),
+            on_exit: ExitScreen::Unchanged,
+        }
+    }
+}
+
+/// Every key, in the order they're written
+const KEYS: [&str; 14] = [
+    "write_timeout_ms",
+    "connect_timeout_ms",
+    "reconnect_retries",
+    "reconnect_delay_ms",
+    "column_retries",
+    "frame_retries",
+    "gamma",
+    "max_fps",
+    "sleep_with_screen",
+    "sleep_on_suspend",
+    "mounting",
+    "command_gap_ms",
+    "max_commands_per_second",
+    "on_exit",
+];
+
+impl Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The defaults with any `F16_HID_*` environment variables applied
+    pub fn from_env() -> Result<Self, Error> {
+        let mut config = Self::new();
+        config.apply_env()?;
+
+        Ok(config)
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        config_directory().map(|x| x.join(CONFIG_DIRECTORY).join(CONFIG_FILE))
+    }
+
+    /// Load from the default location with the environment applied over
+    /// the top. A missing file is the defaults.
+    pub fn load_default() -> Result<Self, Error> {
+        let mut config = match Self::default_path() {
+            Some(path) => Self::load(path)?,
+            None => Self::new(),
+        };
+
+        config.apply_env()?;
+
+        Ok(config)
+    }
+
+    pub fn save_default(&self) -> Result<(), Error> {
+        match Self::default_path() {
+            Some(path) => self.save(path),
+            None => Err(Error::new(ErrorKind::NotFound, "Unable to find a config directory")),
+        }
+    }
+
+    /// Load from a file. A missing file is the defaults.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => contents.parse(),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::new()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Write to a file, creating parent directories as needed
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(path, self.to_string())
+    }
+
+    /// Override settings from `F16_HID_*` environment variables
+    pub fn apply_env(&mut self) -> Result<(), Error> {
+        self.apply_vars(std::env::vars())
+    }
+
+    fn apply_vars(&mut self, vars: impl IntoIterator<Item = (String, String)>) -> Result<(), Error> {
+        for (name, value) in vars {
+            let key = match name.strip_prefix(ENV_PREFIX) {
+                Some(x) => x.to_ascii_lowercase(),
+                None => continue,
+            };
+
+            self.set(&key, &value).map_err(|message| {
+                Error::new(ErrorKind::InvalidData, format!("{}: {}", name, message))
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Change one setting by its key in the file
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), &'static str> {
+        let value = value.trim();
+        // Strings can be quoted, as TOML wants them
+        let value = value.strip_prefix('"').and_then(|x| x.strip_suffix('"')).unwrap_or(value);
+
+        let millis = |value: &str| value.parse::<u64>().map(Duration::from_millis).map_err(|_| "expected milliseconds");
+        let count = |value: &str| value.parse::<u32>().map_err(|_| "expected a whole number");
+        let flag = |value: &str| value.parse::<bool>().map_err(|_| "expected true or false");
+
+        match key {
+            "write_timeout_ms" => self.write_timeout = millis(value)?,
+            "connect_timeout_ms" => self.connect_timeout = millis(value)?,
+            "reconnect_retries" => self.reconnect_retries = count(value)?,
+            "reconnect_delay_ms" => self.reconnect_delay = millis(value)?,
+            "column_retries" => self.column_retries = count(value)?,
+            "frame_retries" => self.frame_retries = count(value)?,
+            "gamma" => {
+                self.gamma = value.parse().ok()
+                    .filter(|x: &f64| *x > 0.0)
+                    .ok_or("expected a positive number")?;
+            },
+            "max_fps" => {
+                let fps: f64 = value.parse().map_err(|_| "expected a number")?;
+                self.max_fps = (fps > 0.0).then_some(fps);
+            },
+            "sleep_with_screen" => self.power.sleep_with_screen = flag(value)?,
+            "sleep_on_suspend" => self.power.sleep_on_suspend = flag(value)?,
+            "mounting" => self.mounting = value.parse()?,
+            "command_gap_ms" => self.throttle = self.throttle.min_gap(millis(value)?),
+            "max_commands_per_second" => self.throttle = self.throttle.max_per_second(count(value)?),
+            "on_exit" => self.on_exit = value.parse()?,
+            _ => return Err("unknown setting"),
+        }
+
+        Ok(())
+    }
+
+    pub fn gamma_map(&self) -> GammaMap {
+        GammaMap::curve(self.gamma)
+    }
+
+    /// Something to hold a render loop to `max_fps`, if there is one
+    pub fn pacer(&self) -> Option<FramePacer> {
+        self.max_fps.map(FramePacer::new)
+    }
+
+    fn value(&self, key: &str) -> String {
+        match key {
+            "write_timeout_ms" => self.write_timeout.as_millis().to_string(),
+            "connect_timeout_ms" => self.connect_timeout.as_millis().to_string(),
+            "reconnect_retries" => self.reconnect_retries.to_string(),
+            "reconnect_delay_ms" => self.reconnect_delay.as_millis().to_string(),
+            "column_retries" => self.column_retries.to_string(),
+            "frame_retries" => self.frame_retries.to_string(),
+            "gamma" => format!("{:?}", self.gamma),
+            "max_fps" => format!("{:?}", self.max_fps.unwrap_or(0.0)),
+            "sleep_with_screen" => self.power.sleep_with_screen.to_string(),
+            "sleep_on_suspend" => self.power.sleep_on_suspend.to_string(),
+            "mounting" => format!("\"{}\"", self.mounting),
+            "command_gap_ms" => self.throttle.gap().as_millis().to_string(),
+            "max_commands_per_second" => self.throttle.rate().unwrap_or(0).to_string(),
+            "on_exit" => format!("\"{}\"", self.on_exit),
+            _ => String::new(),
+        }
+    }
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# LED matrix settings. max_fps = 0.0 and max_commands_per_second = 0 mean no limit.")?;
+
+        for key in KEYS {
+            writeln!(f, "{} = {}", key, self.value(key))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Config {
+    type Err = Error;
+
+    fn from_str(contents: &str) -> Result<Self, Self::Err> {
+        let mut config = Self::new();
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |message: &str| {
+                Error::new(ErrorKind::InvalidData, format!("Line {}: {}", number + 1, message))
+            };
+
+            let (key, value) = line.split_once('=')
+                .ok_or_else(|| invalid("expected 'key = value'"))?;
+
+            config.set(key.trim(), value).map_err(invalid)?;
+        }
+
+        Ok(config)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut config = Config::new();
+        config.max_fps = Some(30.0);
+        config.gamma = 2.2;
+        config.mounting = Mounting::UpsideDown;
+        config.power.sleep_on_suspend = true;
+        config.throttle = Throttle::new().min_gap(Duration::from_millis(3)).max_per_second(250);
+        config.on_exit = ExitScreen::Clear;
+
+        assert_eq!(config.to_string().parse::<Config>().unwrap(), config);
+        assert_eq!("".parse::<Config>().unwrap(), Config::default());
+        assert!("speed = 11".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn environment_overrides() {
+        let mut config: Config = "write_timeout_ms = 300\nmounting = \"upside-down\"".parse().unwrap();
+
+        config.apply_vars([
+            ("F16_HID_WRITE_TIMEOUT_MS".to_owned(), "50".to_owned()),
+            ("HOME".to_owned(), "/root".to_owned()),
+        ]).unwrap();
+
+        assert_eq!(config.write_timeout, Duration::from_millis(50));
+        assert_eq!(config.mounting, Mounting::UpsideDown);
+        assert!(config.apply_vars([("F16_HID_GAMMA".to_owned(), "-1".to_owned())]).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+//! Where a `LedMatrix` is with its port. `LedMatrix::state()` says, and a
+//! `ConnectionWatch` lets another thread see it change without holding the
+//! matrix, for a supervisor that only needs to know when a module is back:
+//!
+//! ```
+//! # use f16_hid::{transport::MockTransport, LedMatrix};
+//! use std::time::Duration;
+//! use f16_hid::connection::ConnectionState;
+//!
+//! # let matrix = LedMatrix::with_transport("mock", MockTransport::new());
+//! let watch = matrix.watch();
+//!
+//! std::thread::spawn(move || {
+//!     if watch.wait_connected(Duration::from_secs(5)) {
+//!         println!("Module's there");
+//!     }
+//! });
+//!
+//! assert_eq!(matrix.state(), ConnectionState::Connected);
+//! ```
+
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::transport::Transport;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ConnectionState {
+    /// The port is open
+    Connected,
+    /// Closed on purpose, by `enter_bootloader()`. Only `reconnect()` or
+    /// `wait_connected()` open it again.
+    Disconnected,
+    /// The port stopped working and is waiting on `reconnect()`, from the
+    /// reconnect policy, a `Display`, a `DeviceManager` or the caller
+    Reconnecting,
+}
+
+/// The port a `LedMatrix` holds, if it's got one
+pub(crate) enum Connection {
+    Connected(Box<dyn Transport>),
+    Disconnected,
+    Reconnecting,
+}
+
+impl Connection {
+    pub(crate) fn state(&self) -> ConnectionState {
+        match self {
+            Self::Connected(_) => ConnectionState::Connected,
+            Self::Disconnected => ConnectionState::Disconnected,
+            Self::Reconnecting => ConnectionState::Reconnecting,
+        }
+    }
+
+    pub(crate) fn port(&mut self) -> Option<&mut Box<dyn Transport>> {
+        match self {
+            Self::Connected(port) => Some(port),
+            _ => None,
+        }
+    }
+}
+
+struct Shared {
+    state: Mutex<ConnectionState>,
+    changed: Condvar,
+}
+
+/// A matrix's connection state, readable from any thread, see the
+/// `connection` module. Clones watch the same matrix.
+#[derive(Clone)]
+pub struct ConnectionWatch {
+    shared: Arc<Shared>,
+}
+
+impl ConnectionWatch {
+    pub(crate) fn new(state: ConnectionState) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(state),
+                changed: Condvar::new(),
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ConnectionState> {
+        self.shared.state.lock().expect("Connection lock poisoned")
+    }
+
+    pub(crate) fn set(&self, state: ConnectionState) {
+        let mut current = self.lock();
+
+        if *current != state {
+            *current = state;
+            self.shared.changed.notify_all();
+        }
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        *self.lock()
+    }
+
+    /// Block until the matrix is connected or `timeout` passes, returning
+    /// whether it's connected. Something else has to do the reconnecting.
+    pub fn wait_connected(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.lock();
+
+        while *state != ConnectionState::Connected {
+            let left = deadline.saturating_duration_since(Instant::now());
+
+            if left.is_zero() {
+                return false;
+            }
+
+            state = self.shared.changed.wait_timeout(state, left).expect("Connection lock poisoned").0;
+        }
+
+        true
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootloader::Confirmation;
+    use crate::reconnect::ReconnectPolicy;
+    use crate::transport::MockTransport;
+    use crate::{Command, LedMatrix};
+    use std::io::ErrorKind;
+
+    #[test]
+    fn states_follow_the_port() {
+        fn send<T: Send>(_: &T) {}
+
+        let mock = MockTransport::new();
+        let mut matrix = LedMatrix::with_transport("mock", mock.clone());
+        matrix.set_reconnect_policy(ReconnectPolicy::never());
+        send(&matrix);
+
+        let watch = matrix.watch();
+        assert_eq!(watch.state(), ConnectionState::Connected);
+
+        mock.fail_next_write(ErrorKind::BrokenPipe);
+        assert!(matrix.execute(Command::Brightness(1)).is_err());
+        assert_eq!(matrix.state(), ConnectionState::Reconnecting);
+        assert!(!watch.wait_connected(Duration::from_millis(1)));
+
+        // Another thread sees it come back
+        let waiter = std::thread::spawn(move || watch.wait_connected(Duration::from_secs(10)));
+        matrix.wait_connected(Duration::from_secs(1)).unwrap();
+        assert!(waiter.join().unwrap());
+
+        matrix.enter_bootloader(Confirmation::reboot_into_bootloader()).unwrap();
+        assert_eq!(matrix.state(), ConnectionState::Disconnected);
+        assert_eq!(matrix.execute(Command::Brightness(1)).unwrap_err().kind(), ErrorKind::NotConnected);
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::{Command, LedMatrix};
+
+/// How often the blocking line iterator checks the port for more output
+pub const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// Lines longer than this are split, in case the firmware never ends one
+pub const MAX_LINE_LENGTH: usize = 1024;
+
+/// The firmware's debug output as a stream of lines. Opening a console turns
+/// debug mode on and dropping it turns it back off.
+///
+/// Commands can still be sent through `matrix()` while the console is open,
+/// which is the point: watch what the firmware makes of this crate's traffic.
+pub struct Console<'a> {
+    matrix: &'a mut LedMatrix,
+    partial: Vec<u8>,
+    lines: VecDeque<String>,
+}
+
+impl<'a> Console<'a> {
+    pub fn open(matrix: &'a mut LedMatrix) -> Result<Self, std::io::Error> {
+        matrix.execute(Command::DebugMode(true))?;
+
+        Ok(Self {
+            matrix,
+            partial: Vec::new(),
+            lines: VecDeque::new(),
+        })
+    }
+
+    pub fn matrix(&mut self) -> &mut LedMatrix {
+        self.matrix
+    }
+
+    /// Next complete line if one has arrived. Doesn't wait.
+    pub fn try_next_line(&mut self) -> Result<Option<String>, std::io::Error> {
+        if self.lines.is_empty() {
+            self.matrix.drain_input()?;
+            let bytes = self.matrix.take_unsolicited();
+            self.lines.extend(split_lines(&mut self.partial, &bytes));
+        }
+
+        Ok(self.lines.pop_front())
+    }
+
+    /// Whatever's been received that isn't a complete line yet
+    pub fn partial_line(&self) -> String {
+        String::from_utf8_lossy(&self.partial).into_owned()
+    }
+}
+
+impl Iterator for Console<'_> {
+    type Item = Result<String, std::io::Error>;
+
+    /// Waits for the next line. Only ends if the port fails.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.try_next_line() {
+                Ok(Some(line)) => return Some(Ok(line)),
+                Ok(None) => std::thread::sleep(POLL_INTERVAL),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+impl Drop for Console<'_> {
+    fn drop(&mut self) {
+        if self.matrix.is_connected() {
+            let _ = self.matrix.execute(Command::DebugMode(false));
+        }
+    }
+}
+
+/// Add `bytes` to the partial line and return every line it completed,
+/// without their line endings
+pub(crate) fn split_lines(partial: &mut Vec<u8>, bytes: &[u8]) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for byte in bytes {
+        match byte {
+            b'\n' => {
+                if partial.last() == Some(&b'\r') {
+                    partial.pop();
+                }
+                lines.push(String::from_utf8_lossy(partial).into_owned());
+                partial.clear();
+            },
+            x => {
+                partial.push(*x);
+
+                if partial.len() >= MAX_LINE_LENGTH {
+                    lines.push(String::from_utf8_lossy(partial).into_owned());
+                    partial.clear();
+                }
+            }
+        }
+    }
+
+    lines
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_span_reads() {
+        let mut partial = Vec::new();
+
+        assert!(split_lines(&mut partial, b"Sleep").is_empty());
+        assert_eq!(split_lines(&mut partial, b"ing\r\nWoke\nBri"), vec!["Sleeping", "Woke"]);
+        assert_eq!(partial, b"Bri");
+    }
+
+    #[test]
+    fn endless_lines_are_split() {
+        let mut partial = Vec::new();
+        let bytes = vec![b'x'; MAX_LINE_LENGTH + 5];
+
+        let lines = split_lines(&mut partial, &bytes);
+
+        assert_eq!(lines.len(), 1);
+        assert_eq!(partial.len(), 5);
+    }
+}
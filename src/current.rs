@@ -0,0 +1,206 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::filter::FrameFilter;
+use crate::{Bitmap8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Roughly what one LED draws lit at 0xff with the brightness at 0xff
+pub const LED_CURRENT_MA: f64 = 1.5;
+/// Roughly what the module draws with every LED off
+pub const IDLE_CURRENT_MA: f64 = 20.0;
+
+/// How much current the module draws for what it shows, for keeping an
+/// eye on battery life. The LED driver dims by PWM, so an LED draws in
+/// proportion to its value and to the global brightness, both out of 0xff.
+///
+/// The defaults are rough figures for the Framework module. Measure your
+/// own if the numbers matter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CurrentModel {
+    pub led_ma: f64,
+    pub idle_ma: f64,
+}
+
+impl Default for CurrentModel {
+    fn default() -> Self {
+        Self {
+            led_ma: LED_CURRENT_MA,
+            idle_ma: IDLE_CURRENT_MA,
+        }
+    }
+}
+
+impl CurrentModel {
+    /// Estimated draw in milliamps showing `frame` at `brightness`
+    pub fn estimate(&self, frame: &Bitmap8, brightness: u8) -> f64 {
+        self.idle_ma + self.lit_ma(frame, brightness)
+    }
+
+    /// Draw with every LED at 0xff, the most the module can take
+    pub fn full_white(&self, brightness: u8) -> f64 {
+        let lit = (DISPLAY_WIDTH * DISPLAY_HEIGHT) as f64;
+
+        self.idle_ma + self.led_ma * lit * brightness as f64 / 255.0
+    }
+
+    /// The part of the draw that comes from the LEDs
+    fn lit_ma(&self, frame: &Bitmap8, brightness: u8) -> f64 {
+        let total: u32 = frame.data().iter().map(|x| *x as u32).sum();
+
+        self.led_ma * (total as f64 / 255.0) * (brightness as f64 / 255.0)
+    }
+}
+
+/// Estimated draw in milliamps showing `frame` at `brightness`, by the
+/// default `CurrentModel`
+pub fn estimate(frame: &Bitmap8, brightness: u8) -> f64 {
+    CurrentModel::default().estimate(frame, brightness)
+}
+
+struct BudgetState {
+    limit_ma: Option<f64>,
+    brightness: u8,
+    /// Estimated draw of the last frame, after any scaling
+    last_ma: f64,
+    scaled_frames: u64,
+}
+
+/// Keeps frames under a current limit by dimming them evenly when they'd
+/// go over, handy on battery where a panel of full white is a real drain.
+/// Add a clone to the end of the matrix's filters and keep the other to
+/// change the limit, say when the charger comes out, and to pass on
+/// brightness changes.
+///
+/// Frames are measured as they leave the filters, before the matrix's
+/// gamma map. A curve that darkens midtones makes the real draw lower than
+/// the estimate, never higher.
+#[derive(Clone)]
+pub struct PowerBudget {
+    model: CurrentModel,
+    state: Arc<Mutex<BudgetState>>,
+}
+
+impl PowerBudget {
+    /// Stay under `limit_ma`, assuming the brightness is `brightness`
+    pub fn new(limit_ma: f64, brightness: u8) -> Self {
+        Self::with_model(limit_ma, brightness, CurrentModel::default())
+    }
+
+    pub fn with_model(limit_ma: f64, brightness: u8, model: CurrentModel) -> Self {
+        Self {
+            model,
+            state: Arc::new(Mutex::new(BudgetState {
+                limit_ma: Some(limit_ma),
+                brightness,
+                last_ma: model.idle_ma,
+                scaled_frames: 0,
+            })),
+        }
+    }
+
+    pub fn model(&self) -> CurrentModel {
+        self.model
+    }
+
+    /// `None` lets every frame through as it is, while still estimating
+    pub fn set_limit(&self, limit_ma: Option<f64>) {
+        self.state().limit_ma = limit_ma;
+    }
+
+    pub fn limit(&self) -> Option<f64> {
+        self.state().limit_ma
+    }
+
+    /// Tell the budget the brightness was changed. It only learns of it
+    /// this way, the matrix doesn't pass it on.
+    pub fn set_brightness(&self, brightness: u8) {
+        self.state().brightness = brightness;
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.state().brightness
+    }
+
+    /// Estimated draw in milliamps of the last frame through, as sent
+    pub fn last_estimate(&self) -> f64 {
+        self.state().last_ma
+    }
+
+    /// Frames that had to be dimmed to fit
+    pub fn scaled_frames(&self) -> u64 {
+        self.state().scaled_frames
+    }
+
+    fn state(&self) -> MutexGuard<'_, BudgetState> {
+        self.state.lock().expect("Power budget lock poisoned")
+    }
+}
+
+impl FrameFilter for PowerBudget {
+    fn apply(&mut self, frame: &mut Bitmap8) {
+        let mut state = self.state();
+        let lit = self.model.lit_ma(frame, state.brightness);
+
+        let allowed = match state.limit_ma {
+            Some(limit) => (limit - self.model.idle_ma).max(0.0),
+            None => lit,
+        };
+
+        if lit > allowed {
+            // Round down so rounding can't tip the frame back over
+            let scale = allowed / lit;
+
+            for pixel in frame.data.iter_mut() {
+                *pixel = (*pixel as f64 * scale) as u8;
+            }
+
+            state.scaled_frames += 1;
+        }
+
+        state.last_ma = self.model.estimate(frame, state.brightness);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw_scales_with_values_and_brightness() {
+        let model = CurrentModel { led_ma: 2.0, idle_ma: 10.0 };
+        let mut frame = Bitmap8::new();
+        assert_eq!(model.estimate(&frame, 0xff), 10.0);
+
+        frame.fill(0xff);
+        assert_eq!(model.estimate(&frame, 0xff), model.full_white(0xff));
+        assert_eq!(model.estimate(&frame, 0xff), 10.0 + 2.0 * 306.0);
+        assert!((model.estimate(&frame, 0x80) - (10.0 + 2.0 * 306.0 * 128.0 / 255.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn frames_are_dimmed_to_fit() {
+        let budget = PowerBudget::with_model(110.0, 0xff, CurrentModel { led_ma: 1.0, idle_ma: 10.0 });
+        let mut filter = budget.clone();
+
+        let mut frame = Bitmap8::new();
+        frame.fill(0xff);
+        filter.apply(&mut frame);
+
+        assert!(budget.last_estimate() <= 110.0);
+        assert!(budget.last_estimate() > 105.0);
+        assert_eq!(budget.scaled_frames(), 1);
+
+        // Dim enough already
+        budget.set_brightness(0x20);
+        let mut frame = Bitmap8::new();
+        frame.fill(0xff);
+        filter.apply(&mut frame);
+        assert_eq!(frame.data()[0], 0xff);
+
+        budget.set_limit(None);
+        budget.set_brightness(0xff);
+        filter.apply(&mut frame);
+        assert_eq!(frame.data()[0], 0xff);
+        assert_eq!(budget.scaled_frames(), 1);
+    }
+}
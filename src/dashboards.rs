@@ -0,0 +1,330 @@
+//! Ready made screens. `SystemDashboard` is the `computer_stats` example
+//! grown up: CPU, memory and network on as many modules as are plugged in.
+//!
+//! ```no_run
+//! f16_hid::dashboards::SystemDashboard::open()?.run();
+//! # Ok::<(), serialport::Error>(())
+//! ```
+
+use std::time::{Duration, Instant};
+
+use sysinfo::{Networks, System};
+
+use crate::events::Events;
+use crate::geometry::Rect;
+use crate::pacer::FramePacer;
+use crate::trace;
+use crate::widgets::{BarBorder, BarGraph, Widget};
+use crate::{Bitmap8, Command, Display, LedMatrix, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Rows at the bottom of each panel taken up by the CPU meter, border included
+const METER_HEIGHT: usize = 20;
+/// Cores shown on each panel, four either side of the middle column
+const CORES_PER_PANEL: usize = 8;
+
+#[derive(Clone, Debug)]
+pub struct DashboardConfig {
+    pub brightness: u8,
+    pub background: u8,
+    /// Value for the bars themselves
+    pub foreground: u8,
+    /// Time between updates. sysinfo can't measure CPU use any faster than
+    /// `sysinfo::MINIMUM_CPU_UPDATE_INTERVAL`.
+    pub interval: Duration,
+    pub show_memory: bool,
+    pub show_network: bool,
+    /// Network throughput in bytes per second that fills a gauge
+    pub network_full_scale: u64,
+    /// How long to wait before retrying a module that stopped responding
+    pub retry_pause: Duration,
+}
+
+impl Default for DashboardConfig {
+    fn default() -> Self {
+        Self {
+            brightness: 0xff,
+            background: 2,
+            foreground: 20,
+            interval: sysinfo::MINIMUM_CPU_UPDATE_INTERVAL,
+            show_memory: true,
+            show_network: true,
+            network_full_scale: 12_500_000,
+            retry_pause: Duration::from_secs(2),
+        }
+    }
+}
+
+/// One reading of everything the dashboard shows
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Sample {
+    /// Use of each core in percent
+    pub cpus: Vec<u8>,
+    /// Memory use in percent
+    pub memory: u8,
+    /// Bytes per second across every interface
+    pub received: u64,
+    pub transmitted: u64,
+}
+
+pub struct SystemDashboard {
+    displays: Vec<Display>,
+    config: DashboardConfig,
+    system: System,
+    networks: Networks,
+    last_sample: Option<Instant>,
+    events: Events,
+}
+
+impl SystemDashboard {
+    pub fn new(displays: Vec<Display>, config: DashboardConfig) -> Self {
+        Self {
+            displays,
+            config,
+            system: System::new(),
+            networks: Networks::new_with_refreshed_list(),
+            last_sample: None,
+            events: Events::new(),
+        }
+    }
+
+    /// Open every module plugged in, in role order, with the default look
+    pub fn open() -> Result<Self, serialport::Error> {
+        let displays = LedMatrix::discover()?
+            .iter()
+            .map(|x| x.open().map(Display::new))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if displays.is_empty() {
+            return Err(serialport::Error::new(serialport::ErrorKind::NoDevice, "No LED matrix modules found"));
+        }
+
+        Ok(Self::new(displays, DashboardConfig::default()))
+    }
+
+    pub fn config(&self) -> &DashboardConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: DashboardConfig) {
+        self.config = config;
+    }
+
+    pub fn displays_mut(&mut self) -> &mut [Display] {
+        &mut self.displays
+    }
+
+    /// Read the current CPU, memory and network use
+    pub fn sample(&mut self) -> Sample {
+        self.system.refresh_cpu();
+        self.system.refresh_memory();
+        self.networks.refresh();
+
+        let now = Instant::now();
+        let elapsed = self.last_sample
+            .map(|x| now - x)
+            .unwrap_or(self.config.interval)
+            .as_secs_f64()
+            .max(0.001);
+        self.last_sample = Some(now);
+
+        let per_second = |bytes: u64| (bytes as f64 / elapsed) as u64;
+        let total = self.system.total_memory().max(1);
+
+        Sample {
+            cpus: self.system.cpus().iter().map(|x| x.cpu_usage().clamp(0.0, 100.0) as u8).collect(),
+            memory: (self.system.used_memory() * 100 / total) as u8,
+            received: per_second(self.networks.values().map(|x| x.received()).sum()),
+            transmitted: per_second(self.networks.values().map(|x| x.transmitted()).sum()),
+        }
+    }
+
+    /// Called with the module's port path whenever one fails to update,
+    /// from `update()` or `run()`
+    pub fn on_error(&mut self, callback: impl FnMut(&str, &std::io::Error) + Send + 'static) {
+        self.events.on_error(callback);
+    }
+
+    /// Take a sample and show it. Every module is updated even if one of
+    /// them fails, the first error is returned.
+    pub fn update(&mut self) -> Result<(), std::io::Error> {
+        let sample = self.sample();
+        let panels = self.displays.len();
+        let mut result = Ok(());
+
+        for (index, display) in self.displays.iter_mut().enumerate() {
+            let frame = render(&self.config, &sample, index, panels);
+
+            if let Err(error) = display.set_frame(&frame) {
+                let path = display.matrix().path();
+                trace::event!(warn, path = %path, error = %error, "Unable to update dashboard");
+                self.events.error(path, &error);
+
+                if result.is_ok() {
+                    result = Err(error);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Show the dashboard forever, retrying modules that fail. Errors only
+    /// go to `on_error()` callbacks, and to `tracing` with that feature on.
+    pub fn run(&mut self) -> ! {
+        for display in &mut self.displays {
+            display.set_retry_pause(self.config.retry_pause);
+
+            if let Err(error) = display.matrix_mut().execute(Command::Brightness(self.config.brightness)) {
+                let path = display.matrix().path();
+                trace::event!(warn, path = %path, error = %error, "Unable to set brightness");
+                self.events.error(path, &error);
+            }
+        }
+
+        let mut pacer = FramePacer::with_period(self.config.interval);
+
+        loop {
+            // Each failure has already been reported
+            let _ = self.update();
+
+            pacer.set_period(self.config.interval);
+            pacer.wait();
+        }
+    }
+}
+
+/// Draw one panel of the dashboard. Each panel gets the next eight cores
+/// along the bottom. Above that the first panel shows memory and the second
+/// network use, or a single panel shows both side by side.
+pub fn render(config: &DashboardConfig, sample: &Sample, panel: usize, panels: usize) -> Bitmap8 {
+    let mut bitmap = Bitmap8::new();
+    bitmap.fill(config.background);
+
+    let first_core = panel * CORES_PER_PANEL;
+    let cores = sample.cpus.iter().skip(first_core).take(CORES_PER_PANEL).copied();
+    draw_meter(&mut bitmap, cores, config);
+
+    let network = |bytes: u64| {
+        (bytes.min(config.network_full_scale) * 100 / config.network_full_scale.max(1)) as u8
+    };
+    let half = DISPLAY_WIDTH / 2;
+
+    match (panels, panel) {
+        (1, _) => {
+            if config.show_memory {
+                draw_gauge(&mut bitmap, 0, half, sample.memory, config);
+            }
+            if config.show_network {
+                draw_gauge(&mut bitmap, half + 1, half, network(sample.received + sample.transmitted), config);
+            }
+        },
+        (_, 0) if config.show_memory => {
+            draw_gauge(&mut bitmap, 0, DISPLAY_WIDTH, sample.memory, config);
+        },
+        (_, 1) if config.show_network => {
+            draw_gauge(&mut bitmap, 0, half, network(sample.received), config);
+            draw_gauge(&mut bitmap, half + 1, half, network(sample.transmitted), config);
+        },
+        _ => (),
+    }
+
+    bitmap
+}
+
+/// Bars for up to eight cores in a box at the bottom of the panel, split by
+/// the middle column
+fn draw_meter(bitmap: &mut Bitmap8, values: impl Iterator<Item = u8>, config: &DashboardConfig) {
+    let meter = Rect::new((0, (DISPLAY_HEIGHT - METER_HEIGHT) as i32), (DISPLAY_WIDTH, METER_HEIGHT));
+    let values: Vec<f64> = values.map(|x| x as f64).collect();
+
+    let mut graph = BarGraph::new(CORES_PER_PANEL)
+        .foreground(config.foreground)
+        .background(config.background)
+        .border(BarBorder::Ends, 0)
+        .divider(Some(CORES_PER_PANEL / 2));
+    graph.set_values(&values);
+    graph.render(bitmap, meter);
+}
+
+/// A level filling `width` columns from the bottom of the space above the
+/// CPU meter
+fn draw_gauge(bitmap: &mut Bitmap8, x: usize, width: usize, percent: u8, config: &DashboardConfig) {
+    // Leave a blank row between the gauge and the meter
+    let bottom = DISPLAY_HEIGHT - METER_HEIGHT - 2;
+    let height = bottom + 1;
+    let filled = (height * percent.min(100) as usize) / 100;
+
+    if filled == 0 || width == 0 {
+        return;
+    }
+
+    bitmap.fill_rect(Rect::new((x as i32, (bottom + 1 - filled) as i32), (width, filled)), config.foreground);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(bitmap: &Bitmap8, x: usize, y: usize) -> u8 {
+        bitmap.data()[x * DISPLAY_HEIGHT + y]
+    }
+
+    #[test]
+    fn cores_split_across_panels() {
+        let config = DashboardConfig::default();
+        let mut cpus = vec![0; 16];
+        cpus[8] = 100;
+        let sample = Sample { cpus, ..Sample::default() };
+
+        let left = render(&config, &sample, 0, 2);
+        let right = render(&config, &sample, 1, 2);
+
+        // First core on the right panel is full height, nothing on the left
+        assert_eq!(pixel(&right, 0, DISPLAY_HEIGHT - METER_HEIGHT + 2), config.foreground);
+        assert_eq!(pixel(&left, 0, DISPLAY_HEIGHT - METER_HEIGHT + 2), config.background);
+        // The middle column is the divider
+        assert_eq!(pixel(&right, 4, DISPLAY_HEIGHT - 5), 0);
+    }
+
+    #[test]
+    fn failures_go_to_callbacks() {
+        let mock = crate::transport::MockTransport::new();
+        let mut matrix = LedMatrix::with_transport("mock", mock.clone());
+        matrix.set_reconnect_policy(crate::reconnect::ReconnectPolicy::never());
+
+        let mut display = Display::new(matrix);
+        display.set_retries(0);
+
+        let mut dashboard = SystemDashboard::new(vec![display], DashboardConfig::default());
+        let errors = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = errors.clone();
+        dashboard.on_error(move |path, error| log.lock().unwrap().push((path.to_owned(), error.kind())));
+
+        mock.fail_next_write(std::io::ErrorKind::BrokenPipe);
+        assert!(dashboard.update().is_err());
+        assert_eq!(*errors.lock().unwrap(), [("mock".to_owned(), std::io::ErrorKind::BrokenPipe)]);
+    }
+
+    #[test]
+    fn gauges() {
+        let config = DashboardConfig::default();
+        let sample = Sample {
+            cpus: Vec::new(),
+            memory: 100,
+            received: config.network_full_scale * 2,
+            transmitted: 0,
+        };
+
+        let left = render(&config, &sample, 0, 2);
+        assert_eq!(pixel(&left, 8, 0), config.foreground);
+
+        let right = render(&config, &sample, 1, 2);
+        assert_eq!(pixel(&right, 0, 0), config.foreground);
+        assert_eq!(pixel(&right, 8, 0), config.background);
+
+        let single = render(&config, &sample, 0, 1);
+        assert_eq!(pixel(&single, 0, 0), config.foreground);
+        assert_eq!(pixel(&single, 4, 0), config.background);
+    }
+}
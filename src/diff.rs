@@ -0,0 +1,316 @@
+//! Comparing recorded frames, for checking that a dashboard still renders
+//! the same after upgrading the crate or refactoring.
+//!
+//! ```
+//! use f16_hid::diff::first_divergence;
+//! use f16_hid::Bitmap8;
+//!
+//! let before = vec![Bitmap8::new(); 3];
+//! let mut after = before.clone();
+//! after[2].draw_text((0, 0), "A", 0xff);
+//!
+//! let divergence = first_divergence(&before, &after).unwrap();
+//! println!("{}", divergence);
+//! ```
+
+use std::fmt;
+
+use crate::geometry::{Point, Rect};
+use crate::layout::Damage;
+use crate::{Bitmap8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Which columns and rows of the panel changed between two frames, see
+/// `Bitmap8::diff()`. A pixel that changed marks both its column and its
+/// row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DirtyRegions {
+    pub columns: [bool; DISPLAY_WIDTH],
+    pub rows: [bool; DISPLAY_HEIGHT],
+}
+
+impl Default for DirtyRegions {
+    fn default() -> Self {
+        Self {
+            columns: [false; DISPLAY_WIDTH],
+            rows: [false; DISPLAY_HEIGHT],
+        }
+    }
+}
+
+impl DirtyRegions {
+    /// Everything, for a panel in an unknown state
+    pub fn all() -> Self {
+        Self {
+            columns: [true; DISPLAY_WIDTH],
+            rows: [true; DISPLAY_HEIGHT],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.columns.iter().any(|x| *x)
+    }
+
+    /// Columns with a changed pixel, which is what staging is done by
+    pub fn column_count(&self) -> usize {
+        self.columns.iter().filter(|x| **x).count()
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.rows.iter().filter(|x| **x).count()
+    }
+
+    /// Smallest rectangle holding every changed pixel, `None` if nothing
+    /// changed
+    pub fn bounds(&self) -> Option<Rect> {
+        let first = |x: &[bool]| x.iter().position(|x| *x);
+        let last = |x: &[bool]| x.iter().rposition(|x| *x);
+
+        let (left, right) = (first(&self.columns)?, last(&self.columns)?);
+        let (top, bottom) = (first(&self.rows)?, last(&self.rows)?);
+
+        Some(Rect::new((left as i32, top as i32), (right - left + 1, bottom - top + 1)))
+    }
+}
+
+impl From<DirtyRegions> for Damage {
+    fn from(regions: DirtyRegions) -> Self {
+        Self { columns: regions.columns }
+    }
+}
+
+impl Bitmap8 {
+    /// Where this frame and `other` differ
+    pub fn diff(&self, other: &Bitmap8) -> DirtyRegions {
+        let mut regions = DirtyRegions::default();
+
+        for (index, (a, b)) in self.data.iter().zip(other.data.iter()).enumerate() {
+            if a != b {
+                regions.columns[index / DISPLAY_HEIGHT] = true;
+                regions.rows[index % DISPLAY_HEIGHT] = true;
+            }
+        }
+
+        regions
+    }
+}
+
+/// The difference between two frames
+#[derive(Clone)]
+pub struct FrameDiff {
+    before: Bitmap8,
+    after: Bitmap8,
+}
+
+impl FrameDiff {
+    pub fn new(before: &Bitmap8, after: &Bitmap8) -> Self {
+        Self {
+            before: before.clone(),
+            after: after.clone(),
+        }
+    }
+
+    pub fn before(&self) -> &Bitmap8 {
+        &self.before
+    }
+
+    pub fn after(&self) -> &Bitmap8 {
+        &self.after
+    }
+
+    /// Every pixel that changed, column by column
+    pub fn changed(&self) -> Vec<Point> {
+        (0 .. DISPLAY_WIDTH)
+            .flat_map(|x| (0 .. DISPLAY_HEIGHT).map(move |y| Point::new(x as i32, y as i32)))
+            .filter(|x| self.before.pixel(*x) != self.after.pixel(*x))
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.before.data() == self.after.data()
+    }
+
+    /// The panel as text, one line per row. `.` is off in both, `o` is the
+    /// same in both, `+` got brighter and `-` got dimmer.
+    pub fn to_ascii(&self) -> String {
+        let mut output = String::with_capacity((DISPLAY_WIDTH + 1) * DISPLAY_HEIGHT);
+
+        for y in 0 .. DISPLAY_HEIGHT {
+            for x in 0 .. DISPLAY_WIDTH {
+                let index = x * DISPLAY_HEIGHT + y;
+                let (before, after) = (self.before.data()[index], self.after.data()[index]);
+
+                output.push(match before.cmp(&after) {
+                    _ if before == 0 && after == 0 => '.',
+                    std::cmp::Ordering::Equal => 'o',
+                    std::cmp::Ordering::Less => '+',
+                    std::cmp::Ordering::Greater => '-',
+                });
+            }
+
+            output.push('\n');
+        }
+
+        output
+    }
+
+    /// Before, after and the difference side by side, each pixel `scale`
+    /// pixels square. In the difference, green got brighter and red dimmer.
+    #[cfg(feature = "image")]
+    pub fn to_image(&self, scale: u32) -> image::RgbImage {
+        use image::Rgb;
+
+        let scale = scale.max(1);
+        let pane = DISPLAY_WIDTH as u32 + 1;
+        let mut output = image::RgbImage::from_pixel(pane * 3 * scale, DISPLAY_HEIGHT as u32 * scale, Rgb([32, 32, 64]));
+
+        for x in 0 .. DISPLAY_WIDTH {
+            for y in 0 .. DISPLAY_HEIGHT {
+                let index = x * DISPLAY_HEIGHT + y;
+                let (before, after) = (self.before.data()[index], self.after.data()[index]);
+
+                let difference = match before.cmp(&after) {
+                    std::cmp::Ordering::Equal => Rgb([before / 4; 3]),
+                    std::cmp::Ordering::Less => Rgb([0, 64 + (after - before) / 4 * 3, 0]),
+                    std::cmp::Ordering::Greater => Rgb([64 + (before - after) / 4 * 3, 0, 0]),
+                };
+
+                for (pane_index, colour) in [Rgb([before; 3]), Rgb([after; 3]), difference].into_iter().enumerate() {
+                    let left = (pane_index as u32 * pane + x as u32) * scale;
+
+                    for dx in 0 .. scale {
+                        for dy in 0 .. scale {
+                            output.put_pixel(left + dx, y as u32 * scale + dy, colour);
+                        }
+                    }
+                }
+            }
+        }
+
+        output
+    }
+
+    /// Write `to_image()` out as a PNG
+    #[cfg(feature = "image")]
+    pub fn save_png(&self, path: impl AsRef<std::path::Path>, scale: u32) -> Result<(), image::ImageError> {
+        self.to_image(scale).save_with_format(path, image::ImageFormat::Png)
+    }
+}
+
+/// Where two frame sequences first stop matching
+#[derive(Clone)]
+pub enum Divergence {
+    /// Both have a frame at `index` but they differ
+    Frame { index: usize, diff: Box<FrameDiff> },
+    /// Every frame they share matches, but one sequence is longer
+    Length { before: usize, after: usize },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Frame { index, diff } => {
+                writeln!(f, "Frame {} differs in {} pixels:", index, diff.changed().len())?;
+                write!(f, "{}", diff.to_ascii())
+            },
+            Self::Length { before, after } => {
+                writeln!(f, "Frames match but there were {} before and {} after", before, after)
+            },
+        }
+    }
+}
+
+impl fmt::Debug for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Compare two recorded sequences frame by frame. `None` means they're the
+/// same.
+pub fn first_divergence<'a>(
+    before: impl IntoIterator<Item = &'a Bitmap8>,
+    after: impl IntoIterator<Item = &'a Bitmap8>,
+) -> Option<Divergence> {
+    let mut before = before.into_iter();
+    let mut after = after.into_iter();
+    let mut index = 0;
+
+    loop {
+        match (before.next(), after.next()) {
+            (Some(x), Some(y)) => {
+                if x.data() != y.data() {
+                    return Some(Divergence::Frame { index, diff: Box::new(FrameDiff::new(x, y)) });
+                }
+            },
+            (None, None) => return None,
+            (Some(_), None) => {
+                return Some(Divergence::Length { before: index + 1 + before.count(), after: index })
+            },
+            (None, Some(_)) => {
+                return Some(Divergence::Length { before: index, after: index + 1 + after.count() })
+            },
+        }
+
+        index += 1;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dirty_regions_cover_the_changes() {
+        let before = Bitmap8::new();
+        let mut after = before.clone();
+        assert!(before.diff(&after).is_empty());
+        assert_eq!(before.diff(&after).bounds(), None);
+
+        after.set_pixel(Point::new(2, 3), 0x80);
+        after.set_pixel(Point::new(6, 20), 0x10);
+        let regions = before.diff(&after);
+
+        assert_eq!((regions.column_count(), regions.row_count()), (2, 2));
+        assert!(regions.columns[2] && regions.columns[6] && regions.rows[20]);
+        assert_eq!(regions.bounds(), Some(Rect::new((2, 3), (5, 18))));
+        assert_eq!(Damage::from(regions).count(), 2);
+    }
+
+    #[test]
+    fn identical_sequences_match() {
+        let frames = vec![Bitmap8::new(); 4];
+
+        assert!(first_divergence(&frames, &frames.clone()).is_none());
+    }
+
+    #[test]
+    fn finds_first_changed_frame() {
+        let before = vec![Bitmap8::new(); 4];
+        let mut after = before.clone();
+        after[1].set_pixel(Point::new(2, 3), 0x80);
+        after[3].fill(1);
+
+        let diff = match first_divergence(&before, &after) {
+            Some(Divergence::Frame { index: 1, diff }) => diff,
+            x => panic!("Unexpected result {:?}", x),
+        };
+
+        assert_eq!(diff.changed(), [Point::new(2, 3)]);
+
+        let ascii = diff.to_ascii();
+        assert_eq!(ascii.lines().nth(3), Some("..+......"));
+        assert_eq!(ascii.lines().count(), DISPLAY_HEIGHT);
+    }
+
+    #[test]
+    fn length_mismatch() {
+        let before = vec![Bitmap8::new(); 2];
+        let after = vec![Bitmap8::new(); 5];
+
+        assert!(matches!(
+            first_divergence(&before, &after),
+            Some(Divergence::Length { before: 2, after: 5 })
+        ));
+    }
+}
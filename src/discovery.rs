@@ -0,0 +1,147 @@
+use serialport::{SerialPortInfo, SerialPortType};
+
+use crate::platform;
+use crate::roles::{Role, RoleConfig};
+use crate::LedMatrix;
+
+/// Framework Computer's USB vendor ID
+pub const FRAMEWORK_VID: u16 = 0x32ac;
+/// USB product ID of the LED Matrix input module
+pub const LED_MATRIX_PID: u16 = 0x0020;
+
+/// An LED matrix found on the USB bus, not yet opened
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveredMatrix {
+    pub path: String,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    /// Where the module sits, if it's been assigned a role. USB can't tell
+    /// left from right on its own, see the `setup` module.
+    pub role: Option<Role>,
+}
+
+impl DiscoveredMatrix {
+    pub fn open(&self) -> Result<LedMatrix, serialport::Error> {
+        LedMatrix::new(&self.path)
+    }
+
+    /// Name to file this module under, the serial number if it has one
+    pub fn key(&self) -> &str {
+        self.serial_number.as_deref().unwrap_or(&self.path)
+    }
+}
+
+/// Find every LED matrix module, with roles filled in from `roles`. Modules
+/// with a role come first (left, right, then external) followed by the rest
+/// in port order, numerically so `COM10` follows `COM9`. On macOS only the
+/// `/dev/cu.*` name of each module is listed.
+pub fn discover(roles: &RoleConfig) -> Result<Vec<DiscoveredMatrix>, serialport::Error> {
+    let ports = serialport::available_ports()?;
+
+    Ok(filter(ports, roles))
+}
+
+pub(crate) fn filter(ports: Vec<SerialPortInfo>, roles: &RoleConfig) -> Vec<DiscoveredMatrix> {
+    let names: Vec<String> = ports.iter().map(|x| x.port_name.clone()).collect();
+    let names: Vec<&str> = names.iter().map(|x| x.as_str()).collect();
+
+    let mut found: Vec<DiscoveredMatrix> = ports.into_iter()
+        .filter(|port| !platform::is_macos_dial_in(&port.port_name, &names))
+        .filter_map(|port| match port.port_type {
+            SerialPortType::UsbPort(info) if info.vid == FRAMEWORK_VID && info.pid == LED_MATRIX_PID => {
+                let role = info.serial_number.as_deref()
+                    .and_then(|x| roles.role(x))
+                    .cloned();
+
+                Some(DiscoveredMatrix {
+                    path: port.port_name,
+                    serial_number: info.serial_number,
+                    manufacturer: info.manufacturer,
+                    product: info.product,
+                    role,
+                })
+            },
+            _ => None,
+        })
+        .collect();
+
+    found.sort_by(|a, b| match (&a.role, &b.role) {
+        (Some(x), Some(y)) => x.cmp(y),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => platform::compare_port_names(&a.path, &b.path),
+    });
+
+    found
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serialport::UsbPortInfo;
+
+    fn port(path: &str, vid: u16, pid: u16, serial: &str) -> SerialPortInfo {
+        SerialPortInfo {
+            port_name: path.to_owned(),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid,
+                pid,
+                serial_number: Some(serial.to_owned()),
+                manufacturer: Some("Framework".to_owned()),
+                product: Some("LED Matrix Input Module".to_owned()),
+            }),
+        }
+    }
+
+    #[test]
+    fn only_matrices_are_found() {
+        let ports = vec![
+            port("/dev/ttyACM0", FRAMEWORK_VID, LED_MATRIX_PID, "AAAA"),
+            port("/dev/ttyACM1", 0x1234, 0x5678, "BBBB"),
+            SerialPortInfo { port_name: "/dev/ttyS0".to_owned(), port_type: SerialPortType::Unknown },
+        ];
+
+        let found = filter(ports, &RoleConfig::new());
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, "/dev/ttyACM0");
+        assert_eq!(found[0].key(), "AAAA");
+    }
+
+    #[test]
+    fn roles_decide_order() {
+        let mut roles = RoleConfig::new();
+        roles.assign("RIGHT", Role::Right);
+        roles.assign("LEFT", Role::Left);
+
+        let ports = vec![
+            port("/dev/ttyACM0", FRAMEWORK_VID, LED_MATRIX_PID, "UNKNOWN"),
+            port("/dev/ttyACM1", FRAMEWORK_VID, LED_MATRIX_PID, "RIGHT"),
+            port("/dev/ttyACM2", FRAMEWORK_VID, LED_MATRIX_PID, "LEFT"),
+        ];
+
+        let found = filter(ports, &roles);
+        let paths: Vec<&str> = found.iter().map(|x| x.path.as_str()).collect();
+
+        assert_eq!(paths, vec!["/dev/ttyACM2", "/dev/ttyACM1", "/dev/ttyACM0"]);
+        assert_eq!(found[0].role, Some(Role::Left));
+        assert_eq!(found[2].role, None);
+    }
+
+    #[test]
+    fn each_module_is_listed_once() {
+        let ports = vec![
+            port("/dev/tty.usbmodem1", FRAMEWORK_VID, LED_MATRIX_PID, "AAAA"),
+            port("/dev/cu.usbmodem1", FRAMEWORK_VID, LED_MATRIX_PID, "AAAA"),
+            port("COM10", FRAMEWORK_VID, LED_MATRIX_PID, "BBBB"),
+            port("COM9", FRAMEWORK_VID, LED_MATRIX_PID, "CCCC"),
+        ];
+
+        let found = filter(ports, &RoleConfig::new());
+        let paths: Vec<&str> = found.iter().map(|x| x.path.as_str()).collect();
+
+        assert_eq!(paths, vec!["/dev/cu.usbmodem1", "COM9", "COM10"]);
+    }
+}
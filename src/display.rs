@@ -0,0 +1,868 @@
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::config::Config;
+use crate::events::is_link_lost;
+use crate::fade::{BrightnessFade, FrameFade};
+use crate::gamma::{perceptual_brightness, GammaMap};
+use crate::geometry::Rect;
+use crate::idle::{IdleAction, IdleDimmer};
+use crate::overlay::{Overlay, OverlayId, OverlayStack};
+use crate::reconnect::ReconnectPolicy;
+use crate::response::Response;
+use crate::sender::FrameSender;
+use crate::{Bitmap8, Command, LedMatrix, DEFAULT_BRIGHTNESS, RECONNECT_DELAY};
+
+/// How many times a frame is retried before giving up
+pub const DEFAULT_FRAME_RETRIES: u32 = 3;
+
+/// Firmware state that changes when the module resets behind our back
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Liveness {
+    brightness: u8,
+    animating: bool,
+}
+
+/// A matrix that shows whole greyscale frames and copes with the port going
+/// away underneath it. This is what most applications want instead of
+/// staging columns by hand:
+///
+/// ```no_run
+/// use f16_hid::prelude::*;
+///
+/// let mut display = Display::open_default()?;
+///
+/// display.brightness(0x40)?;
+/// display.back_mut().draw_text((0, 0), "HI", 0xff);
+/// display.present()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// It's double buffered. Drawing goes into the back buffer, and nothing on
+/// the panel changes until `present()` sends it all at once, so a frame
+/// drawn in several steps never shows half done. Only the columns that
+/// differ from what's already on the panel are sent, a frame of only off
+/// and full pixels goes as one on/off `Draw`, and a frame that's already
+/// showing isn't sent at all, see `FrameSender`.
+pub struct Display<C: Clock = SystemClock> {
+    matrix: LedMatrix,
+    back: Bitmap8,
+    /// What's on the panel
+    front: Bitmap8,
+    /// The last frame the application set, without overlays
+    scene: Bitmap8,
+    overlays: OverlayStack,
+    /// Brightness scales for parts of the scene, in the order they were set
+    regions: Vec<(Rect, f32)>,
+    sender: FrameSender,
+    brightness: Option<u8>,
+    retries: u32,
+    retry_pause: Duration,
+    clock: C,
+    integrity_interval: Option<Duration>,
+    last_check: Option<Instant>,
+    liveness: Option<Liveness>,
+    idle: Option<IdleDimmer>,
+}
+
+impl Display<SystemClock> {
+    pub fn new(matrix: LedMatrix) -> Self {
+        Self::with_clock(matrix, SystemClock)
+    }
+
+    pub fn open(path: &str) -> Result<Self, serialport::Error> {
+        Ok(Self::new(LedMatrix::new(path)?))
+    }
+
+    /// Open the first module found, preferring ones with a role assigned
+    pub fn open_default() -> Result<Self, serialport::Error> {
+        let found = LedMatrix::discover()?;
+
+        match found.first() {
+            Some(x) => Ok(Self::new(x.open()?)),
+            None => Err(serialport::Error::new(serialport::ErrorKind::NoDevice, "No LED matrix modules found")),
+        }
+    }
+}
+
+impl<C: Clock> Display<C> {
+    /// Retrying is left to the display, which retries whole frames, so the
+    /// matrix's own reconnect policy is turned off
+    pub fn with_clock(mut matrix: LedMatrix, clock: C) -> Self {
+        matrix.set_reconnect_policy(ReconnectPolicy::never());
+
+        Self {
+            matrix,
+            back: Bitmap8::new(),
+            front: Bitmap8::new(),
+            scene: Bitmap8::new(),
+            overlays: OverlayStack::default(),
+            regions: Vec::new(),
+            sender: FrameSender::new(),
+            brightness: None,
+            retries: DEFAULT_FRAME_RETRIES,
+            retry_pause: RECONNECT_DELAY,
+            clock,
+            integrity_interval: None,
+            last_check: None,
+            liveness: None,
+            idle: None,
+        }
+    }
+
+    /// Take the settings a `Config` has for the display. The matrix's own
+    /// go through `LedMatrixBuilder::config()`.
+    pub fn apply_config(&mut self, config: &Config) {
+        self.retries = config.frame_retries;
+        self.retry_pause = config.reconnect_delay;
+    }
+
+    /// How many more attempts a frame gets after the first one fails
+    pub fn set_retries(&mut self, retries: u32) {
+        self.retries = retries;
+    }
+
+    /// How long to wait before each retry
+    pub fn set_retry_pause(&mut self, pause: Duration) {
+        self.retry_pause = pause;
+    }
+
+    /// Whether frames of only off and full pixels go as one `Draw`, see
+    /// `FrameSender::set_binary_draws()`
+    pub fn set_binary_draws(&mut self, enabled: bool) {
+        self.sender.set_binary_draws(enabled);
+    }
+
+    /// Check the module hasn't reset after drawing, at most once per
+    /// `interval`. A reset module comes back showing its own startup screen,
+    /// so this bounds how long that can go unnoticed. `None` turns it off.
+    pub fn set_integrity_check(&mut self, interval: Option<Duration>) {
+        self.integrity_interval = interval;
+        self.last_check = None;
+        self.liveness = None;
+    }
+
+    /// Ask the firmware for its brightness and animation state and compare
+    /// them with the last check. If they changed without us asking, the
+    /// module probably reset, so the current frame is sent again. Returns
+    /// whether that happened. Brightness set through `brightness()` is
+    /// restored too.
+    pub fn verify(&mut self) -> Result<bool, Error> {
+        // Set first so firmware that won't answer isn't asked every frame
+        self.last_check = Some(self.clock.now());
+
+        let brightness = self.matrix.query_parameter(0x00)?;
+        let animating = self.matrix.query_parameter(0x04)?;
+
+        let state = match (Response::parse(0x00, &brightness), Response::parse(0x04, &animating)) {
+            (Some(Response::Brightness(brightness)), Some(Response::Animating(animating))) => {
+                Liveness { brightness, animating }
+            },
+            _ => return Ok(false),
+        };
+
+        let previous = self.liveness.replace(state);
+
+        if previous.is_none() || previous == Some(state) {
+            return Ok(false);
+        }
+
+        if let Some(brightness) = self.brightness {
+            self.matrix.execute(Command::Brightness(brightness))?;
+        }
+        self.sender.invalidate();
+        self.matrix.stage_frame(&self.front.clone())?;
+
+        Ok(true)
+    }
+
+    pub fn matrix(&self) -> &LedMatrix {
+        &self.matrix
+    }
+
+    /// Call `invalidate()` after drawing on the matrix directly
+    pub fn matrix_mut(&mut self) -> &mut LedMatrix {
+        &mut self.matrix
+    }
+
+    /// Send every column with the next frame, for when something other
+    /// than this display has drawn on the panel
+    pub fn invalidate(&mut self) {
+        self.sender.invalidate();
+    }
+
+    pub fn into_inner(self) -> LedMatrix {
+        self.matrix
+    }
+
+    /// The last frame successfully shown, overlays and all
+    pub fn frame(&self) -> &Bitmap8 {
+        &self.front
+    }
+
+    /// Same as `frame()`
+    pub fn front(&self) -> &Bitmap8 {
+        &self.front
+    }
+
+    /// The last frame the application set, as it is under any overlays
+    pub fn scene(&self) -> &Bitmap8 {
+        &self.scene
+    }
+
+    /// The frame being drawn
+    pub fn back(&self) -> &Bitmap8 {
+        &self.back
+    }
+
+    /// Where the next frame is drawn. Nothing changes on the panel until
+    /// `present()`.
+    pub fn back_mut(&mut self) -> &mut Bitmap8 {
+        &mut self.back
+    }
+
+    /// Same as `back_mut()`
+    pub fn canvas(&mut self) -> &mut Bitmap8 {
+        &mut self.back
+    }
+
+    /// Show the back buffer. It's left as it is, so the next frame can be
+    /// drawn over this one.
+    pub fn present(&mut self) -> Result<(), Error> {
+        let back = self.back.clone();
+        self.set_frame(&back)
+    }
+
+    /// Show the back buffer the quickest way there is, every column and the
+    /// draw in one write from a buffer kept between frames. Nothing is left
+    /// out for being unchanged, so this suits frames that change all over,
+    /// like video and busy animations. `present()` sends less when only a
+    /// part changes. See `FrameSender::send_packed()`.
+    pub fn present_fast(&mut self) -> Result<(), Error> {
+        let back = self.back.clone();
+        self.show(&back, true)
+    }
+
+    /// Show the back buffer and swap, leaving the frame that was on the
+    /// panel before in the back buffer. Overlays aren't part of it.
+    pub fn swap(&mut self) -> Result<(), Error> {
+        let previous = self.scene.clone();
+        self.present()?;
+        self.back = previous;
+
+        Ok(())
+    }
+
+    /// Set the panel brightness. It's put back if the module resets while
+    /// the integrity check is on. While the idle dimmer has the panel
+    /// dimmed or asleep it's kept for when it wakes.
+    pub fn brightness(&mut self, brightness: u8) -> Result<(), Error> {
+        if let Some(dimmer) = self.idle.as_mut().filter(|x| x.is_idle()) {
+            dimmer.restore = Some(brightness);
+            self.brightness = Some(brightness);
+            return Ok(());
+        }
+
+        self.matrix.execute(Command::Brightness(brightness))?;
+        self.brightness = Some(brightness);
+
+        // Expected to change, so it isn't mistaken for a reset
+        self.liveness = None;
+
+        Ok(())
+    }
+
+    /// Like `brightness()` but as a percentage of how bright the panel looks
+    /// at full, see `gamma::perceptual_brightness()`
+    pub fn set_brightness_percent(&mut self, percent: u8) -> Result<(), Error> {
+        self.brightness(perceptual_brightness(percent))
+    }
+
+    /// Ease the brightness to `target` over `duration`, blocking until it
+    /// gets there. Starts from the last brightness set through this
+    /// display, or the firmware's default if there wasn't one.
+    pub fn fade_to_brightness(&mut self, target: u8, duration: Duration) -> Result<(), Error> {
+        let fade = BrightnessFade::new(self.brightness.unwrap_or(DEFAULT_BRIGHTNESS), target, duration);
+        let interval = fade.interval();
+        let start = self.clock.now();
+
+        for (step, value) in fade.enumerate() {
+            self.clock.sleep(self.until(start + interval * (step as u32 + 1)));
+
+            // Steps at the dim end can round to the same value
+            if self.brightness != Some(value) {
+                self.brightness(value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Cross-fade from what's on the panel to `frame` over `duration`,
+    /// blocking until it's done. The back buffer is left alone.
+    pub fn fade_frame(&mut self, frame: &Bitmap8, duration: Duration) -> Result<(), Error> {
+        // From the scene rather than the front, which has the region
+        // brightness and overlays in already and would get them twice
+        let fade = FrameFade::new(&self.scene, frame, duration);
+        let interval = fade.interval();
+        let start = self.clock.now();
+
+        for (step, between) in fade.enumerate() {
+            self.clock.sleep(self.until(start + interval * (step as u32 + 1)));
+            self.set_frame(&between)?;
+        }
+
+        Ok(())
+    }
+
+    /// Dim or sleep after a while without anything new to show, see
+    /// `IdleDimmer`. `None` turns it off, waking the panel if it's idle.
+    pub fn set_idle_dimmer(&mut self, dimmer: Option<IdleDimmer>) -> Result<(), Error> {
+        self.wake()?;
+        self.idle = dimmer;
+
+        Ok(())
+    }
+
+    pub fn idle_dimmer(&self) -> Option<&IdleDimmer> {
+        self.idle.as_ref()
+    }
+
+    /// Dim or sleep if nothing has changed for the idle dimmer's timeout.
+    /// Call it every so often. Returns whether the panel is idle.
+    pub fn check_idle(&mut self) -> Result<bool, Error> {
+        let now = self.clock.now();
+
+        let action = match &mut self.idle {
+            Some(dimmer) => match dimmer.due(now) {
+                true => dimmer.action(),
+                false => return Ok(dimmer.is_idle()),
+            },
+            None => return Ok(false),
+        };
+
+        match action {
+            IdleAction::Dim(value) => self.matrix.execute(Command::Brightness(value))?,
+            IdleAction::Sleep => self.matrix.execute(Command::Sleep(true))?,
+        };
+
+        // Expected to change, so it isn't mistaken for a reset
+        self.liveness = None;
+
+        if let Some(dimmer) = &mut self.idle {
+            dimmer.restore = Some(self.brightness.unwrap_or(DEFAULT_BRIGHTNESS));
+        }
+
+        Ok(true)
+    }
+
+    /// Count as activity, bringing the panel back if it's idle. New frames
+    /// do this by themselves.
+    pub fn wake(&mut self) -> Result<(), Error> {
+        let now = self.clock.now();
+
+        let (action, restore) = match &mut self.idle {
+            Some(dimmer) => {
+                dimmer.last_active = Some(now);
+
+                match dimmer.restore {
+                    Some(restore) => (dimmer.action(), restore),
+                    None => return Ok(()),
+                }
+            },
+            None => return Ok(()),
+        };
+
+        if action == IdleAction::Sleep {
+            self.matrix.execute(Command::Sleep(false))?;
+        }
+        self.matrix.execute(Command::Brightness(restore))?;
+        self.liveness = None;
+
+        if let Some(dimmer) = &mut self.idle {
+            dimmer.restore = None;
+        }
+
+        Ok(())
+    }
+
+    /// Put `overlay` over whatever the application shows for `duration`,
+    /// then take it down again and put back the frame underneath. Frames
+    /// set in the meantime are shown under it. Overlays shown later go on
+    /// top of earlier ones.
+    ///
+    /// Call `check_overlays()` from the main loop to take overlays down
+    /// when they're due, as setting a frame only does if there is one.
+    pub fn show_overlay(&mut self, overlay: Overlay, duration: Duration) -> Result<OverlayId, Error> {
+        let until = self.clock.now() + duration;
+        let id = self.overlays.push(overlay, until);
+        self.refresh_overlays()?;
+
+        Ok(id)
+    }
+
+    /// Take an overlay down before it's due. Returns whether it was up.
+    pub fn dismiss_overlay(&mut self, id: OverlayId) -> Result<bool, Error> {
+        if !self.overlays.remove(id) {
+            return Ok(false);
+        }
+
+        self.refresh_overlays()?;
+
+        Ok(true)
+    }
+
+    /// Take every overlay down
+    pub fn clear_overlays(&mut self) -> Result<(), Error> {
+        if self.overlays.clear() {
+            self.refresh_overlays()?;
+        }
+
+        Ok(())
+    }
+
+    /// Overlays up right now
+    pub fn overlays(&self) -> usize {
+        self.overlays.len()
+    }
+
+    /// Take down any overlays that are due. Returns whether any were.
+    pub fn check_overlays(&mut self) -> Result<bool, Error> {
+        if !self.overlays.expire(self.clock.now()) {
+            return Ok(false);
+        }
+
+        self.refresh_overlays()?;
+
+        Ok(true)
+    }
+
+    /// Show the scene again with the overlays as they are now
+    fn refresh_overlays(&mut self) -> Result<(), Error> {
+        let scene = self.scene.clone();
+        self.set_frame(&scene)
+    }
+
+    /// How long from now until `when`, nothing if it's passed
+    fn until(&self, when: Instant) -> Duration {
+        when.saturating_duration_since(self.clock.now())
+    }
+
+    /// Correction for this panel's greyscale values, see `GammaMap`
+    pub fn set_gamma(&mut self, gamma: GammaMap) {
+        self.matrix.set_gamma(gamma);
+    }
+
+    /// Scale the brightness of whatever is drawn in `area`, from the next
+    /// frame on. The firmware has one brightness for the whole panel, so
+    /// this is done to the pixels before they're sent: a clock at 0.3 next
+    /// to an alert at full. Setting the same area again replaces its scale,
+    /// and where areas overlap their scales multiply. Overlays are drawn
+    /// after, at the brightness they were given.
+    pub fn set_region_brightness(&mut self, area: Rect, scale: f32) {
+        let scale = scale.max(0.0);
+
+        match self.regions.iter_mut().find(|(x, _)| *x == area) {
+            Some(region) => region.1 = scale,
+            None => self.regions.push((area, scale)),
+        }
+    }
+
+    /// Put every region back to the brightness it was drawn at
+    pub fn clear_region_brightness(&mut self) {
+        self.regions.clear();
+    }
+
+    /// `frame` with the region brightness scales applied
+    fn scaled(&self, frame: &Bitmap8) -> Bitmap8 {
+        let mut frame = frame.clone();
+
+        for (area, scale) in &self.regions {
+            for x in area.columns() {
+                let column = frame.column_mut(x).expect("Rect::columns() is on the panel");
+
+                for pixel in &mut column[area.rows()] {
+                    *pixel = (*pixel as f32 * scale + 0.5).min(255.0) as u8;
+                }
+            }
+        }
+
+        frame
+    }
+
+    /// Show the matrix's shutdown screen, see `LedMatrix::set_shutdown_screen()`
+    pub fn shutdown(&mut self) -> Result<(), Error> {
+        self.matrix.shutdown()
+    }
+
+    /// Show a frame, sending only the columns that changed. Time outs are
+    /// retried and a broken port is reopened, up to the retry limit. Returns
+    /// the last error if it never got through.
+    ///
+    /// Any overlays are drawn over it, see `show_overlay()`.
+    pub fn set_frame(&mut self, frame: &Bitmap8) -> Result<(), Error> {
+        self.show(frame, false)
+    }
+
+    /// `set_frame()`, sending the whole frame packed into one write if
+    /// `packed` is set
+    fn show(&mut self, frame: &Bitmap8, packed: bool) -> Result<(), Error> {
+        self.scene = frame.clone();
+        self.overlays.expire(self.clock.now());
+
+        let frame = &self.overlays.compose(&self.scaled(frame));
+        let mut attempt = 0;
+
+        if self.idle.is_some() && frame.data() != self.front.data() {
+            self.wake()?;
+        }
+
+        loop {
+            let result = if !self.matrix.is_connected() {
+                Err(Error::new(ErrorKind::NotConnected, "Port isn't open"))
+            } else if packed {
+                self.sender.send_packed(&mut self.matrix, frame).map(|_| ())
+            } else {
+                self.sender.send(&mut self.matrix, frame).map(|_| ())
+            };
+
+            let error = match result {
+                Ok(()) => {
+                    self.front = frame.clone();
+
+                    if self.integrity_due() {
+                        // Anything wrong with the port shows up on the next frame
+                        let _ = self.verify();
+                    }
+
+                    return Ok(());
+                },
+                Err(error) => error,
+            };
+
+            if attempt >= self.retries {
+                return Err(error);
+            }
+            attempt += 1;
+
+            match error.kind() {
+                ErrorKind::TimedOut => (),
+                _ if is_link_lost(&error) => {
+                    self.clock.sleep(self.retry_pause);
+
+                    // A failure here shows up as NotConnected on the next try
+                    let _ = self.matrix.reconnect();
+
+                    // The module may have reset and lost what it was showing
+                    self.sender.invalidate();
+                    continue;
+                },
+                _ => return Err(error),
+            }
+
+            self.clock.sleep(self.retry_pause);
+        }
+    }
+
+    fn integrity_due(&self) -> bool {
+        match (self.integrity_interval, self.last_check) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(interval), Some(last)) => self.clock.elapsed(last) >= interval,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::transport::MockTransport;
+    use crate::{DISPLAY_HEIGHT, DISPLAY_WIDTH, MAX_COMMAND_LENGTH};
+
+    #[test]
+    fn present_sends_what_changed() {
+        let mock = MockTransport::new();
+        let mut display = Display::with_clock(LedMatrix::with_transport("mock", mock.clone()), ManualClock::new());
+        let sent = |mock: &MockTransport| mock.take_written().len() / MAX_COMMAND_LENGTH;
+
+        // A blank panel goes as a single on/off Draw
+        display.present().unwrap();
+        assert_eq!(sent(&mock), 1);
+
+        // Greyscale has to be staged, and the staging buffer is unknown
+        display.back_mut().draw_point(8, 33, 0x40).unwrap();
+        display.present().unwrap();
+        assert_eq!(sent(&mock), DISPLAY_WIDTH + 1);
+
+        display.present().unwrap();
+        assert_eq!(sent(&mock), 0);
+
+        // Drawn in two steps, shown in one
+        display.back_mut().draw_point(3, 0, 0xff).unwrap();
+        display.back_mut().draw_point(3, 1, 0xff).unwrap();
+        assert_eq!(sent(&mock), 0);
+        display.swap().unwrap();
+        assert_eq!(sent(&mock), 2);
+
+        assert_eq!(display.front().data()[3 * DISPLAY_HEIGHT], 0xff);
+        assert_eq!(display.back().data()[3 * DISPLAY_HEIGHT], 0);
+
+        display.invalidate();
+        display.swap().unwrap();
+        assert_eq!(sent(&mock), DISPLAY_WIDTH + 1);
+    }
+
+    #[test]
+    fn regions_are_scaled_before_sending() {
+        let mut display = Display::with_clock(LedMatrix::with_transport("mock", MockTransport::new()), ManualClock::new());
+        let clock = Rect::new((0, 0), (DISPLAY_WIDTH, 10));
+
+        display.back_mut().fill(0xc8);
+        display.set_region_brightness(clock, 0.25);
+        display.set_region_brightness(Rect::new((-3, 5), (5, 10)), 0.5);
+        display.present().unwrap();
+
+        let pixel = |display: &Display<ManualClock>, x: usize, y: usize| display.front().column(x).unwrap()[y];
+        assert_eq!(pixel(&display, 8, 0), 0x32);
+        assert_eq!(pixel(&display, 0, 5), 0x19);
+        assert_eq!(pixel(&display, 0, 12), 0x64);
+        assert_eq!(pixel(&display, 4, 20), 0xc8);
+        assert_eq!(display.scene().data(), display.back().data());
+
+        // Same area again replaces its scale
+        display.set_region_brightness(clock, 2.0);
+        display.present().unwrap();
+        assert_eq!(pixel(&display, 8, 0), 0xff);
+
+        display.clear_region_brightness();
+        display.present().unwrap();
+        assert_eq!(display.front().data(), display.back().data());
+    }
+
+    #[test]
+    fn fades_start_from_the_unscaled_scene() {
+        let mock = MockTransport::new();
+        let mut display = Display::with_clock(LedMatrix::with_transport("mock", mock.clone()), ManualClock::new());
+        let mut lit = Bitmap8::new();
+        lit.fill(0xff);
+
+        display.set_region_brightness(Rect::new((0, 0), (DISPLAY_WIDTH, DISPLAY_HEIGHT)), 0.5);
+        display.set_frame(&lit).unwrap();
+        let shown = display.front().clone();
+        assert_eq!(shown.data()[0], 0x80);
+        mock.take_written();
+
+        // Fading to what's showing doesn't dip through a darker frame
+        display.fade_frame(&lit, Duration::from_millis(200)).unwrap();
+        assert!(mock.take_written().is_empty());
+        assert_eq!(display.front().data(), shown.data());
+
+        display.fade_frame(&Bitmap8::new(), Duration::from_millis(200)).unwrap();
+        assert!(display.front().data().iter().all(|x| *x == 0));
+    }
+
+    /// A display checking its integrity, showing a full frame at half
+    /// brightness with the firmware at 0x40
+    fn checked_display() -> (Display<ManualClock>, MockTransport, ManualClock) {
+        let clock = ManualClock::new();
+        let mock = MockTransport::new();
+        let mut display = Display::with_clock(LedMatrix::with_transport("mock", mock.clone()), clock.clone());
+
+        display.brightness(0x40).unwrap();
+        display.set_region_brightness(Rect::new((0, 0), (DISPLAY_WIDTH, DISPLAY_HEIGHT)), 0.5);
+        display.back_mut().fill(0xff);
+        display.present().unwrap();
+        display.set_integrity_check(Some(Duration::from_secs(10)));
+
+        // The first check only takes note of the state
+        mock.push_reply(&[0x40]);
+        mock.push_reply(&[0]);
+        assert!(!display.verify().unwrap());
+        mock.take_written();
+
+        (display, mock, clock)
+    }
+
+    #[test]
+    fn unchanged_modules_are_left_alone() {
+        let (mut display, mock, _) = checked_display();
+
+        mock.push_reply(&[0x40]);
+        mock.push_reply(&[0]);
+        assert!(!display.verify().unwrap());
+
+        // Only the two queries, nothing sent again
+        assert_eq!(mock.take_written(), [0x32, 0xac, 0x00, 0x32, 0xac, 0x04]);
+    }
+
+    #[test]
+    fn reset_modules_are_sent_everything_again() {
+        let (mut display, mock, _) = checked_display();
+
+        // Back at the firmware's default brightness and animating
+        mock.push_reply(&[DEFAULT_BRIGHTNESS]);
+        mock.push_reply(&[1]);
+        assert!(display.verify().unwrap());
+
+        let written = mock.take_written();
+        assert_eq!(written[.. 6], [0x32, 0xac, 0x00, 0x32, 0xac, 0x04]);
+
+        let packets: Vec<&[u8]> = written[6 ..].chunks(MAX_COMMAND_LENGTH).collect();
+        assert_eq!(packets.len(), 1 + DISPLAY_WIDTH + 1);
+        assert_eq!(packets[0][2 .. 4], [0x00, 0x40]);
+
+        // The frame as it was shown, scaled the once
+        for (x, packet) in packets[1 ..= DISPLAY_WIDTH].iter().enumerate() {
+            assert_eq!(packet[2 .. 4], [0x07, x as u8]);
+            assert_eq!(packet[4 .. DISPLAY_HEIGHT + 4], *display.front().column(x).unwrap());
+        }
+
+        assert!(display.front().data().iter().all(|x| *x == 0x80));
+        assert_eq!(packets[DISPLAY_WIDTH + 1][2], 0x08);
+    }
+
+    #[test]
+    fn integrity_checks_wait_for_the_interval() {
+        let (mut display, mock, clock) = checked_display();
+        assert!(!display.integrity_due());
+
+        clock.advance(Duration::from_secs(9));
+        assert!(!display.integrity_due());
+        display.present().unwrap();
+        assert!(mock.take_written().is_empty());
+
+        // Due now, and checked with the next frame
+        clock.advance(Duration::from_secs(1));
+        assert!(display.integrity_due());
+
+        mock.push_reply(&[0x40]);
+        mock.push_reply(&[0]);
+        display.present().unwrap();
+        assert_eq!(mock.take_written(), [0x32, 0xac, 0x00, 0x32, 0xac, 0x04]);
+        assert!(!display.integrity_due());
+
+        display.set_integrity_check(None);
+        clock.advance(Duration::from_secs(60));
+        assert!(!display.integrity_due());
+    }
+
+    #[test]
+    fn present_fast_sends_the_whole_frame() {
+        let mock = MockTransport::new();
+        let mut display = Display::with_clock(LedMatrix::with_transport("mock", mock.clone()), ManualClock::new());
+
+        display.back_mut().draw_point(8, 33, 0x40).unwrap();
+        display.present().unwrap();
+        let staged = mock.take_written();
+
+        // The same packets as a full present(), and it knows what's showing
+        display.present_fast().unwrap();
+        assert_eq!(mock.take_written(), staged);
+        display.present().unwrap();
+        assert!(mock.take_written().is_empty());
+
+        display.back_mut().draw_point(0, 0, 0x10).unwrap();
+        display.present_fast().unwrap();
+        assert_eq!(mock.take_written().len(), (DISPLAY_WIDTH + 1) * MAX_COMMAND_LENGTH);
+        assert_eq!(display.front().data(), display.back().data());
+    }
+
+    #[test]
+    fn fades_take_their_time() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let mock = MockTransport::new();
+        let mut display = Display::with_clock(LedMatrix::with_transport("mock", mock.clone()), clock.clone());
+
+        display.brightness(0).unwrap();
+        mock.take_written();
+
+        display.fade_to_brightness(0xff, Duration::from_millis(200)).unwrap();
+        let values: Vec<u8> = mock.take_written().chunks(MAX_COMMAND_LENGTH).map(|x| x[3]).collect();
+        assert_eq!(values.last(), Some(&0xff));
+        assert!(values.windows(2).all(|x| x[0] < x[1]));
+        assert_eq!(clock.now() - start, Duration::from_millis(200));
+
+        let mut lit = Bitmap8::new();
+        lit.fill(0xff);
+        display.fade_frame(&lit, Duration::from_millis(100)).unwrap();
+        assert_eq!(display.front().data(), lit.data());
+        assert_eq!(clock.now() - start, Duration::from_millis(300));
+    }
+
+    #[test]
+    fn idle_panels_dim_and_come_back() {
+        let clock = ManualClock::new();
+        let mock = MockTransport::new();
+        let mut display = Display::with_clock(LedMatrix::with_transport("mock", mock.clone()), clock.clone());
+        let commands = |mock: &MockTransport| -> Vec<(u8, u8)> {
+            mock.take_written().chunks(MAX_COMMAND_LENGTH).map(|x| (x[2], x[3])).collect()
+        };
+
+        display.brightness(0x80).unwrap();
+        display.set_idle_dimmer(Some(IdleDimmer::new(Duration::from_secs(60), IdleAction::Dim(0x08)))).unwrap();
+        display.present().unwrap();
+        mock.take_written();
+
+        assert!(!display.check_idle().unwrap());
+        clock.advance(Duration::from_secs(59));
+        assert!(!display.check_idle().unwrap());
+
+        // The same frame again isn't activity
+        display.present().unwrap();
+        clock.advance(Duration::from_secs(1));
+        assert!(display.check_idle().unwrap());
+        assert_eq!(commands(&mock), [(0x00, 0x08)]);
+        assert!(display.check_idle().unwrap());
+        assert!(mock.written().is_empty());
+
+        // Set while dimmed, so it waits for waking up
+        display.brightness(0x60).unwrap();
+        assert!(mock.written().is_empty());
+
+        display.back_mut().draw_point(0, 0, 0x80).unwrap();
+        display.present().unwrap();
+        assert_eq!(commands(&mock)[.. 2], [(0x00, 0x60), (0x07, 0x00)]);
+        assert!(!display.idle_dimmer().unwrap().is_idle());
+
+        display.set_idle_dimmer(Some(IdleDimmer::new(Duration::ZERO, IdleAction::Sleep))).unwrap();
+        assert!(display.check_idle().unwrap());
+        display.wake().unwrap();
+        assert_eq!(commands(&mock), [(0x03, 0x01), (0x03, 0x00), (0x00, 0x60)]);
+    }
+
+    #[test]
+    fn overlays_come_down_and_hand_back() {
+        let clock = ManualClock::new();
+        let mock = MockTransport::new();
+        let mut display = Display::with_clock(LedMatrix::with_transport("mock", mock.clone()), clock.clone());
+
+        let mut icon = Bitmap8::new();
+        icon.fill(0xff);
+        let volume = display.show_overlay(Overlay::new(&icon, Rect::new((0, 0), (9, 9))), Duration::from_secs(2)).unwrap();
+        let note = display.show_overlay(Overlay::full(&Bitmap8::new()).transparent(0), Duration::from_secs(5)).unwrap();
+        assert_eq!(display.overlays(), 2);
+        assert_eq!(display.frame().data()[0], 0xff);
+
+        // The application carries on underneath
+        display.back_mut().fill(0x20);
+        display.present().unwrap();
+        assert_eq!(display.frame().data()[0], 0xff);
+        assert_eq!(display.frame().data()[20], 0x20);
+        assert_eq!(display.scene().data()[0], 0x20);
+
+        clock.advance(Duration::from_secs(2));
+        mock.take_written();
+        assert!(display.check_overlays().unwrap());
+        assert_eq!(display.frame().data(), display.scene().data());
+        assert!(!mock.written().is_empty());
+        assert!(!display.check_overlays().unwrap());
+
+        assert!(!display.dismiss_overlay(volume).unwrap());
+        assert!(display.dismiss_overlay(note).unwrap());
+        assert_eq!(display.overlays(), 0);
+    }
+}
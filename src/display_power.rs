@@ -0,0 +1,151 @@
+use std::path::{Path, PathBuf};
+
+use crate::{Command, LedMatrix};
+
+pub const LID_DIRECTORY: &str = "/proc/acpi/button/lid";
+pub const DRM_DIRECTORY: &str = "/sys/class/drm";
+
+/// Follows the laptop's lid switch and internal panel power (DPMS) and puts
+/// the matrix to sleep whenever the screen goes dark. Linux only, since it
+/// reads the state straight out of procfs and sysfs.
+///
+/// Nothing here blocks. Call `poll()` from the application's main loop.
+pub struct DisplayPowerSync {
+    lid_directory: PathBuf,
+    drm_directory: PathBuf,
+    screen_on: Option<bool>,
+}
+
+impl DisplayPowerSync {
+    pub fn new() -> Self {
+        Self::with_paths(LID_DIRECTORY, DRM_DIRECTORY)
+    }
+
+    /// Read state from somewhere other than the usual places
+    pub fn with_paths<P: AsRef<Path>, Q: AsRef<Path>>(lid_directory: P, drm_directory: Q) -> Self {
+        Self {
+            lid_directory: lid_directory.as_ref().to_owned(),
+            drm_directory: drm_directory.as_ref().to_owned(),
+            screen_on: None,
+        }
+    }
+
+    /// `Some(true)` if any lid reports open. `None` if there's no lid switch.
+    pub fn lid_open(&self) -> Option<bool> {
+        let mut found = None;
+
+        for entry in std::fs::read_dir(&self.lid_directory).ok()?.flatten() {
+            let state = match std::fs::read_to_string(entry.path().join("state")) {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+
+            let open = state.contains("open");
+            found = Some(found.unwrap_or(false) || open);
+        }
+
+        found
+    }
+
+    /// `Some(true)` if any internal (eDP/LVDS) panel reports DPMS on. `None`
+    /// if no internal panel was found.
+    pub fn internal_display_on(&self) -> Option<bool> {
+        let mut found = None;
+
+        for entry in std::fs::read_dir(&self.drm_directory).ok()?.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+
+            if !(name.contains("-eDP-") || name.contains("-LVDS-")) {
+                continue;
+            }
+
+            let dpms = match std::fs::read_to_string(entry.path().join("dpms")) {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+
+            let on = dpms.trim() == "On";
+            found = Some(found.unwrap_or(false) || on);
+        }
+
+        found
+    }
+
+    /// Whether the laptop screen is visible. Anything that can't be read is
+    /// assumed to be on so the matrix doesn't go dark for no reason.
+    pub fn screen_on(&self) -> bool {
+        self.lid_open().unwrap_or(true) && self.internal_display_on().unwrap_or(true)
+    }
+
+    /// Check the screen and sleep or wake the matrix if it changed since the
+    /// last poll. Returns the new state when a command was sent.
+    pub fn poll(&mut self, matrix: &mut LedMatrix) -> Result<Option<bool>, std::io::Error> {
+        let screen_on = self.screen_on();
+
+        if self.screen_on == Some(screen_on) {
+            return Ok(None);
+        }
+
+        matrix.execute(Command::Sleep(!screen_on))?;
+        self.screen_on = Some(screen_on);
+
+        Ok(Some(screen_on))
+    }
+}
+
+impl Default for DisplayPowerSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_tree(name: &str, lid: &str, dpms: &str) -> (PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(format!("f16_hid_power_{}_{}", name, std::process::id()));
+        let lid_directory = root.join("lid");
+        let drm_directory = root.join("drm");
+
+        std::fs::create_dir_all(lid_directory.join("LID0")).unwrap();
+        std::fs::create_dir_all(drm_directory.join("card1-eDP-1")).unwrap();
+        std::fs::create_dir_all(drm_directory.join("card1-HDMI-A-1")).unwrap();
+        std::fs::write(lid_directory.join("LID0/state"), format!("state:      {}\n", lid)).unwrap();
+        std::fs::write(drm_directory.join("card1-eDP-1/dpms"), format!("{}\n", dpms)).unwrap();
+        std::fs::write(drm_directory.join("card1-HDMI-A-1/dpms"), "On\n").unwrap();
+
+        (lid_directory, drm_directory)
+    }
+
+    #[test]
+    fn lid_closed() {
+        let (lid, drm) = fake_tree("closed", "closed", "On");
+        let sync = DisplayPowerSync::with_paths(&lid, &drm);
+
+        assert_eq!(sync.lid_open(), Some(false));
+        assert!(!sync.screen_on());
+
+        std::fs::remove_dir_all(lid.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn internal_panel_blanked() {
+        let (lid, drm) = fake_tree("blanked", "open", "Off");
+        let sync = DisplayPowerSync::with_paths(&lid, &drm);
+
+        assert_eq!(sync.internal_display_on(), Some(false));
+        assert!(!sync.screen_on());
+
+        std::fs::remove_dir_all(lid.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn missing_state_is_on() {
+        let sync = DisplayPowerSync::with_paths("/nonexistent/lid", "/nonexistent/drm");
+
+        assert!(sync.screen_on());
+    }
+}
@@ -0,0 +1,93 @@
+use std::io::{Error, ErrorKind};
+
+type Callback = Box<dyn FnMut(&str) + Send>;
+type ErrorCallback = Box<dyn FnMut(&str, &Error) + Send>;
+
+/// Callbacks for a device's link coming and going. Each one is handed the
+/// device's name: the port path for a `LedMatrix`, the managed name for a
+/// `DeviceManager`.
+#[derive(Default)]
+pub struct Events {
+    connect: Vec<Callback>,
+    disconnect: Vec<Callback>,
+    error: Vec<ErrorCallback>,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_connect(&mut self, callback: impl FnMut(&str) + Send + 'static) {
+        self.connect.push(Box::new(callback));
+    }
+
+    pub fn on_disconnect(&mut self, callback: impl FnMut(&str) + Send + 'static) {
+        self.disconnect.push(Box::new(callback));
+    }
+
+    pub fn on_error(&mut self, callback: impl FnMut(&str, &Error) + Send + 'static) {
+        self.error.push(Box::new(callback));
+    }
+
+    pub(crate) fn connected(&mut self, name: &str) {
+        for callback in &mut self.connect {
+            callback(name);
+        }
+    }
+
+    pub(crate) fn disconnected(&mut self, name: &str) {
+        for callback in &mut self.disconnect {
+            callback(name);
+        }
+    }
+
+    pub(crate) fn error(&mut self, name: &str, error: &Error) {
+        for callback in &mut self.error {
+            callback(name, error);
+        }
+    }
+}
+
+/// Errors that mean the port has to be reopened rather than just retried
+pub(crate) fn is_link_lost(error: &Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::BrokenPipe |
+        ErrorKind::NotConnected |
+        ErrorKind::ConnectionReset |
+        ErrorKind::UnexpectedEof
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn callbacks_run_in_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut events = Events::new();
+
+        let connects = log.clone();
+        events.on_connect(move |name| connects.lock().unwrap().push(format!("up {name}")));
+        let disconnects = log.clone();
+        events.on_disconnect(move |name| disconnects.lock().unwrap().push(format!("down {name}")));
+        let errors = log.clone();
+        events.on_error(move |name, error| errors.lock().unwrap().push(format!("{name}: {:?}", error.kind())));
+
+        events.error("a", &Error::from(ErrorKind::BrokenPipe));
+        events.disconnected("a");
+        events.connected("b");
+
+        assert_eq!(*log.lock().unwrap(), ["a: BrokenPipe", "down a", "up b"]);
+    }
+
+    #[test]
+    fn link_lost_kinds() {
+        assert!(is_link_lost(&Error::from(ErrorKind::BrokenPipe)));
+        assert!(!is_link_lost(&Error::from(ErrorKind::TimedOut)));
+    }
+}
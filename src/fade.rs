@@ -0,0 +1,170 @@
+//! Easing from one brightness to another and from one frame to the next,
+//! so changes don't land with a jolt. `Display::fade_to_brightness()` and
+//! `Display::fade_frame()` play them through. The steps are plain
+//! iterators, for applications that would rather play them from their own
+//! loop:
+//!
+//! ```
+//! use std::time::Duration;
+//! use f16_hid::fade::BrightnessFade;
+//!
+//! let fade = BrightnessFade::new(0, 0xff, Duration::from_millis(500));
+//! let pause = fade.interval();
+//!
+//! for value in fade {
+//!     // Send Command::Brightness(value), wait `pause`
+//! }
+//! ```
+
+use std::time::Duration;
+
+use crate::gamma::PERCEPTUAL_GAMMA;
+use crate::Bitmap8;
+
+/// Time between the steps of a fade, 50 a second
+pub const FADE_STEP: Duration = Duration::from_millis(20);
+
+/// How many steps fit in `duration`, at least one
+fn steps(duration: Duration) -> u32 {
+    (duration.as_nanos().div_ceil(FADE_STEP.as_nanos()) as u32).max(1)
+}
+
+/// Brightness values from one level to another, evenly spaced in how bright
+/// they look rather than in the numbers, so the fade doesn't seem to rush
+/// at the dim end. Yields one value a step, the last being the target.
+#[derive(Clone, Debug)]
+pub struct BrightnessFade {
+    from: f64,
+    to: f64,
+    steps: u32,
+    step: u32,
+    interval: Duration,
+}
+
+impl BrightnessFade {
+    pub fn new(from: u8, to: u8, duration: Duration) -> Self {
+        let steps = steps(duration);
+        let perceived = |value: u8| (value as f64 / 255.0).powf(1.0 / PERCEPTUAL_GAMMA);
+
+        Self {
+            from: perceived(from),
+            to: perceived(to),
+            steps,
+            step: 0,
+            interval: duration / steps,
+        }
+    }
+
+    /// Time to leave between values
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl Iterator for BrightnessFade {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.step >= self.steps {
+            return None;
+        }
+
+        self.step += 1;
+
+        let amount = self.step as f64 / self.steps as f64;
+        let perceived = self.from + (self.to - self.from) * amount;
+
+        Some((perceived.powf(PERCEPTUAL_GAMMA) * 255.0).round() as u8)
+    }
+}
+
+/// Each pixel `amount` of the way from `from` to `to`, 0 being all `from`
+/// and 1 all `to`
+pub fn crossfade(from: &Bitmap8, to: &Bitmap8, amount: f64) -> Bitmap8 {
+    let amount = amount.clamp(0.0, 1.0);
+    let mut frame = from.clone();
+
+    for (pixel, target) in frame.data.iter_mut().zip(to.data()) {
+        *pixel = (*pixel as f64 + (*target as f64 - *pixel as f64) * amount).round() as u8;
+    }
+
+    frame
+}
+
+/// Frames cross-fading from one to another, one a step, the last being
+/// the target
+#[derive(Clone)]
+pub struct FrameFade {
+    from: Bitmap8,
+    to: Bitmap8,
+    steps: u32,
+    step: u32,
+    interval: Duration,
+}
+
+impl FrameFade {
+    pub fn new(from: &Bitmap8, to: &Bitmap8, duration: Duration) -> Self {
+        let steps = steps(duration);
+
+        Self {
+            from: from.clone(),
+            to: to.clone(),
+            steps,
+            step: 0,
+            interval: duration / steps,
+        }
+    }
+
+    /// Time to leave between frames
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl Iterator for FrameFade {
+    type Item = Bitmap8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.step >= self.steps {
+            return None;
+        }
+
+        self.step += 1;
+
+        Some(crossfade(&self.from, &self.to, self.step as f64 / self.steps as f64))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brightness_fades_evenly_to_the_eye() {
+        let values: Vec<u8> = BrightnessFade::new(0, 0xff, Duration::from_millis(100)).collect();
+
+        assert_eq!(values.len(), 5);
+        assert_eq!(values.last(), Some(&0xff));
+        assert!(values.windows(2).all(|x| x[0] < x[1]));
+        // Halfway along looks half as bright, which is well under half
+        assert!(values[2] < 0x60, "{:?}", values);
+
+        let down: Vec<u8> = BrightnessFade::new(0x80, 0, Duration::ZERO).collect();
+        assert_eq!(down, [0]);
+    }
+
+    #[test]
+    fn frames_cross_fade() {
+        let from = Bitmap8::new();
+        let mut to = Bitmap8::new();
+        to.fill(200);
+
+        assert_eq!(crossfade(&from, &to, 0.25).data()[0], 50);
+
+        let frames: Vec<Bitmap8> = FrameFade::new(&from, &to, Duration::from_millis(40)).collect();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].data()[0], 100);
+        assert_eq!(frames[1].data(), to.data());
+    }
+}
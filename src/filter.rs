@@ -0,0 +1,156 @@
+use crate::{Bitmap8, DISPLAY_HEIGHT};
+
+/// Post-processing applied to every greyscale frame on its way to a device.
+/// Closures taking `&mut Bitmap8` work as filters too.
+pub trait FrameFilter {
+    fn apply(&mut self, frame: &mut Bitmap8);
+
+    /// Whether the filter can move pixels to other columns. When it can,
+    /// partial updates have to send the whole frame.
+    fn moves_pixels(&self) -> bool {
+        false
+    }
+}
+
+impl<F: FnMut(&mut Bitmap8)> FrameFilter for F {
+    fn apply(&mut self, frame: &mut Bitmap8) {
+        self(frame)
+    }
+
+    // A closure could do anything, so assume the worst
+    fn moves_pixels(&self) -> bool {
+        true
+    }
+}
+
+/// Filters run in the order they were added
+#[derive(Default)]
+pub struct Pipeline {
+    filters: Vec<Box<dyn FrameFilter + Send>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push<F: FrameFilter + Send + 'static>(&mut self, filter: F) {
+        self.filters.push(Box::new(filter));
+    }
+
+    pub fn clear(&mut self) {
+        self.filters.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.filters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    pub fn moves_pixels(&self) -> bool {
+        self.filters.iter().any(|x| x.moves_pixels())
+    }
+
+    pub fn apply(&mut self, frame: &mut Bitmap8) {
+        for filter in self.filters.iter_mut() {
+            filter.apply(frame);
+        }
+    }
+}
+
+/// Turn the image upside down, for a module mounted the other way up
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rotate180;
+
+impl FrameFilter for Rotate180 {
+    fn apply(&mut self, frame: &mut Bitmap8) {
+        // Column major storage, so reversing everything flips both axes
+        frame.data.reverse();
+    }
+
+    fn moves_pixels(&self) -> bool {
+        true
+    }
+}
+
+/// Swap light for dark
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Invert;
+
+impl FrameFilter for Invert {
+    fn apply(&mut self, frame: &mut Bitmap8) {
+        for value in frame.data.iter_mut() {
+            *value = 0xff - *value;
+        }
+    }
+}
+
+/// Multiply every pixel by `value / 255`
+#[derive(Clone, Copy, Debug)]
+pub struct Scale(pub u8);
+
+impl FrameFilter for Scale {
+    fn apply(&mut self, frame: &mut Bitmap8) {
+        for value in frame.data.iter_mut() {
+            *value = ((*value as u16 * self.0 as u16) / 0xff) as u8;
+        }
+    }
+}
+
+/// Mirror the rows, for when only the vertical direction is backwards
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FlipVertical;
+
+impl FrameFilter for FlipVertical {
+    fn apply(&mut self, frame: &mut Bitmap8) {
+        for column in frame.data.chunks_mut(DISPLAY_HEIGHT) {
+            column.reverse();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DISPLAY_WIDTH;
+
+    #[test]
+    fn filters_run_in_order() {
+        let mut pipeline = Pipeline::new();
+        let mut frame = Bitmap8::new();
+        frame.fill(100);
+
+        pipeline.push(Invert);
+        pipeline.push(|frame: &mut Bitmap8| frame.draw_point(0, 0, 1).unwrap());
+
+        pipeline.apply(&mut frame);
+
+        assert_eq!(frame.data()[0], 1);
+        assert_eq!(frame.data()[1], 155);
+        assert!(pipeline.moves_pixels());
+    }
+
+    #[test]
+    fn rotate_moves_corners() {
+        let mut frame = Bitmap8::new();
+        frame.draw_point(0, 0, 9).unwrap();
+
+        Rotate180.apply(&mut frame);
+
+        assert_eq!(frame.data()[(DISPLAY_WIDTH - 1) * DISPLAY_HEIGHT + DISPLAY_HEIGHT - 1], 9);
+    }
+
+    #[test]
+    fn scale_halves() {
+        let mut frame = Bitmap8::new();
+        frame.fill(200);
+
+        Scale(0x80).apply(&mut frame);
+
+        assert_eq!(frame.data()[0], 100);
+    }
+}
@@ -0,0 +1,379 @@
+//! The games built into the firmware. They run entirely on the module, the
+//! host only starts them and passes on key presses. A `GameSession` does
+//! that from any `GameInput`, such as the terminal with the `crossterm`
+//! feature:
+//!
+//! ```no_run
+//! # #[cfg(feature = "crossterm")] {
+//! use f16_hid::games::{Game, GameSession, KeyboardInput};
+//! use f16_hid::LedMatrix;
+//!
+//! let mut matrix = LedMatrix::new("/dev/ttyACM0")?;
+//! let mut session = GameSession::new(Game::Snake, KeyboardInput::new()?);
+//! session.run(&mut matrix)?;
+//! # }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+#[cfg(feature = "std")]
+use std::collections::VecDeque;
+#[cfg(feature = "std")]
+use std::io::Error;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "std")]
+use crate::clock::{Clock, SystemClock};
+#[cfg(feature = "std")]
+use crate::{Command, LedMatrix};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Game {
+    Snake,
+    Pong,
+    Tetris,
+    GameOfLife(LifeStart),
+}
+
+/// What the game of life starts from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LifeStart {
+    /// Whatever is on the panel right now
+    #[default]
+    CurrentFrame,
+    Pattern1,
+    Blinker,
+    Toad,
+    Beacon,
+    Glider,
+    BeaconToadBlinker,
+}
+
+impl Game {
+    /// Returns how many bytes were used
+    pub(crate) fn pack(self, data: &mut [u8]) -> usize {
+        data[0] = match self {
+            Self::Snake => 0,
+            Self::Pong => 1,
+            Self::Tetris => 2,
+            Self::GameOfLife(_) => 3,
+        };
+
+        match self {
+            Self::GameOfLife(start) => {
+                data[1] = start as u8;
+                2
+            },
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GameKey {
+    Up,
+    Down,
+    Left,
+    Right,
+    /// Leave the game and go back to what was showing before
+    Quit,
+    /// Second player's paddle in Pong
+    Left2,
+    Right2,
+}
+
+impl GameKey {
+    pub(crate) fn index(&self) -> u8 {
+        *self as u8
+    }
+}
+
+/// What a player asked for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GameAction {
+    /// Passed on to the game. `GameKey::Quit` ends the session like `Quit`.
+    Key(GameKey),
+    /// Start the game over from the beginning
+    Restart,
+    /// Leave the game and end the session
+    Quit,
+}
+
+/// Somewhere key presses come from, a terminal, a gamepad, a socket...
+#[cfg(feature = "std")]
+pub trait GameInput {
+    /// Wait up to `timeout` for the next action. `None` if there wasn't one.
+    fn poll(&mut self, timeout: Duration) -> Result<Option<GameAction>, Error>;
+}
+
+/// Wait between control commands by default. The firmware handles one key
+/// per tick of the game, so presses sent closer than this get lost.
+#[cfg(feature = "std")]
+pub const DEFAULT_KEY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long `GameSession` waits on its input when it has nothing to send
+#[cfg(feature = "std")]
+const IDLE_POLL: Duration = Duration::from_millis(100);
+
+/// Most presses held back waiting their turn. Past this the oldest go, so
+/// mashing a key doesn't leave the game catching up for seconds after.
+#[cfg(feature = "std")]
+const MAX_PENDING: usize = 4;
+
+/// Plays one of the firmware's games from a `GameInput`: starts it, passes
+/// key presses on no faster than the game takes them, starts it again on
+/// `Restart` and leaves it on `Quit`. Use `run()` to play until the player
+/// quits, or `start()` and `step()` from a loop of your own.
+#[cfg(feature = "std")]
+pub struct GameSession<I: GameInput, C: Clock = SystemClock> {
+    game: Game,
+    input: I,
+    interval: Duration,
+    pending: VecDeque<GameKey>,
+    last_sent: Option<Instant>,
+    running: bool,
+    clock: C,
+}
+
+#[cfg(feature = "std")]
+impl<I: GameInput> GameSession<I, SystemClock> {
+    pub fn new(game: Game, input: I) -> Self {
+        Self::with_clock(game, input, SystemClock)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<I: GameInput, C: Clock> GameSession<I, C> {
+    pub fn with_clock(game: Game, input: I, clock: C) -> Self {
+        Self {
+            game,
+            input,
+            interval: DEFAULT_KEY_INTERVAL,
+            pending: VecDeque::new(),
+            last_sent: None,
+            running: false,
+            clock,
+        }
+    }
+
+    /// Least time between two control commands
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    pub fn game(&self) -> Game {
+        self.game
+    }
+
+    /// Play something else from the next `start()` or `Restart`
+    pub fn set_game(&mut self, game: Game) {
+        self.game = game;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn input_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    /// Start the game, dropping any presses still waiting
+    pub fn start(&mut self, matrix: &mut LedMatrix) -> Result<(), Error> {
+        self.pending.clear();
+        matrix.execute(Command::StartGame(self.game))?;
+        self.running = true;
+
+        Ok(())
+    }
+
+    /// Wait for input once and send whatever is due. Returns whether the
+    /// game is still going.
+    pub fn step(&mut self, matrix: &mut LedMatrix) -> Result<bool, Error> {
+        if !self.running {
+            return Ok(false);
+        }
+
+        let wait = match self.pending.is_empty() {
+            true => IDLE_POLL,
+            false => self.until_due(),
+        };
+
+        match self.input.poll(wait)? {
+            Some(GameAction::Quit) | Some(GameAction::Key(GameKey::Quit)) => {
+                self.pending.clear();
+                self.running = false;
+                matrix.execute(Command::GameControl(GameKey::Quit))?;
+
+                return Ok(false);
+            },
+            Some(GameAction::Restart) => self.start(matrix)?,
+            Some(GameAction::Key(key)) => {
+                if self.pending.len() == MAX_PENDING {
+                    self.pending.pop_front();
+                }
+
+                self.pending.push_back(key);
+            },
+            None => {},
+        }
+
+        if !self.pending.is_empty() && self.until_due().is_zero() {
+            let key = self.pending.pop_front().expect("Checked there's a key");
+            matrix.execute(Command::GameControl(key))?;
+            self.last_sent = Some(self.clock.now());
+        }
+
+        Ok(true)
+    }
+
+    /// Start the game and play until the player quits
+    pub fn run(&mut self, matrix: &mut LedMatrix) -> Result<(), Error> {
+        self.start(matrix)?;
+        while self.step(matrix)? {}
+
+        Ok(())
+    }
+
+    /// How long until another control command can go
+    fn until_due(&self) -> Duration {
+        match self.last_sent {
+            Some(sent) => self.interval.saturating_sub(self.clock.elapsed(sent)),
+            None => Duration::ZERO,
+        }
+    }
+}
+
+/// Keys from the terminal, which is put in raw mode until this is dropped.
+/// The arrow keys steer, A and D move the second player's paddle in Pong,
+/// R restarts, and Q, Escape or Ctrl+C quit.
+#[cfg(feature = "crossterm")]
+pub struct KeyboardInput {
+    _raw: (),
+}
+
+#[cfg(feature = "crossterm")]
+impl KeyboardInput {
+    pub fn new() -> Result<Self, Error> {
+        crossterm::terminal::enable_raw_mode()?;
+        Ok(Self { _raw: () })
+    }
+
+    fn action(event: crossterm::event::KeyEvent) -> Option<GameAction> {
+        use crossterm::event::{KeyCode, KeyEventKind, KeyModifiers};
+
+        if event.kind == KeyEventKind::Release {
+            return None;
+        }
+
+        let key = match event.code {
+            KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) => return Some(GameAction::Quit),
+            KeyCode::Char('q') | KeyCode::Esc => return Some(GameAction::Quit),
+            KeyCode::Char('r') => return Some(GameAction::Restart),
+            KeyCode::Up => GameKey::Up,
+            KeyCode::Down => GameKey::Down,
+            KeyCode::Left => GameKey::Left,
+            KeyCode::Right => GameKey::Right,
+            KeyCode::Char('a') => GameKey::Left2,
+            KeyCode::Char('d') => GameKey::Right2,
+            _ => return None,
+        };
+
+        Some(GameAction::Key(key))
+    }
+}
+
+#[cfg(feature = "crossterm")]
+impl GameInput for KeyboardInput {
+    fn poll(&mut self, timeout: Duration) -> Result<Option<GameAction>, Error> {
+        if !crossterm::event::poll(timeout)? {
+            return Ok(None);
+        }
+
+        match crossterm::event::read()? {
+            crossterm::event::Event::Key(event) => Ok(Self::action(event)),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(feature = "crossterm")]
+impl Drop for KeyboardInput {
+    fn drop(&mut self) {
+        let _ = crossterm::terminal::disable_raw_mode();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn game_of_life_sends_its_start() {
+        let mut data = [0u8; 2];
+
+        assert_eq!(Game::Tetris.pack(&mut data), 1);
+        assert_eq!(data[0], 2);
+
+        assert_eq!(Game::GameOfLife(LifeStart::Glider).pack(&mut data), 2);
+        assert_eq!(data, [3, 5]);
+        assert_eq!(GameKey::Right2.index(), 6);
+    }
+
+    #[test]
+    fn game_status_is_a_bare_query() {
+        let mut data = [0xffu8; crate::MAX_COMMAND_LENGTH];
+
+        assert_eq!(crate::Command::GameStatus.pack(&mut data).unwrap(), 1);
+        assert_eq!(data[0], 0x12);
+        assert_eq!(crate::Command::GameStatus.kind(), Some(crate::capabilities::CommandKind::GameStatus));
+    }
+
+    /// Plays back actions, one a poll, waiting out the timeout on `None`
+    struct Script {
+        actions: VecDeque<Option<GameAction>>,
+        clock: crate::clock::ManualClock,
+    }
+
+    impl GameInput for Script {
+        fn poll(&mut self, timeout: Duration) -> Result<Option<GameAction>, Error> {
+            let action = self.actions.pop_front().flatten();
+
+            if action.is_none() {
+                self.clock.sleep(timeout);
+            }
+
+            Ok(action)
+        }
+    }
+
+    #[test]
+    fn sessions_pace_keys_and_restart() {
+        let clock = crate::clock::ManualClock::new();
+        let mock = crate::transport::MockTransport::new();
+        let mut matrix = LedMatrix::with_transport("mock", mock.clone());
+
+        let (up, left) = (Some(GameAction::Key(GameKey::Up)), Some(GameAction::Key(GameKey::Left)));
+        let actions = [up, left, None, Some(GameAction::Restart), None, Some(GameAction::Quit), up];
+        let script = Script { actions: actions.into(), clock: clock.clone() };
+        let mut session = GameSession::with_clock(Game::Pong, script, clock);
+
+        session.run(&mut matrix).unwrap();
+        assert!(!session.is_running());
+        assert!(!session.step(&mut matrix).unwrap());
+
+        // Left waits out the interval after Up instead of following it
+        // straight away, and the key after quitting is never read
+        let sent: Vec<(u8, u8)> = mock.written().chunks(crate::MAX_COMMAND_LENGTH).map(|x| (x[2], x[3])).collect();
+        let (start, control) = (0x10, 0x11);
+        assert_eq!(sent, [
+            (start, 1),
+            (control, GameKey::Up.index()),
+            (control, GameKey::Left.index()),
+            (start, 1),
+            (control, GameKey::Quit.index()),
+        ]);
+        assert_eq!(session.input_mut().actions.len(), 1);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::calibration::GammaLut;
+
+/// Gamma used to turn a brightness percentage into something that looks
+/// like that percentage
+pub const PERCEPTUAL_GAMMA: f64 = 2.2;
+
+/// How greyscale values are corrected on their way to one panel. Raw values
+/// look washed out at the low end of these LEDs, so a curve above 1 spreads
+/// the dim end out. Set it with `LedMatrix::set_gamma()` or
+/// `Display::set_gamma()` and every staged column is corrected as it's
+/// packed, after the filters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GammaMap {
+    table: GammaLut,
+    linear: bool,
+}
+
+impl GammaMap {
+    /// Values go out as they are
+    pub fn linear() -> Self {
+        Self {
+            table: GammaLut::identity(),
+            linear: true,
+        }
+    }
+
+    /// A power curve, `value ^ gamma` scaled to 0-255
+    pub fn curve(gamma: f64) -> Self {
+        Self::table(GammaLut::from_gamma(gamma))
+    }
+
+    /// Any table, like one measured with `calibration::calibrate()`
+    pub fn table(table: GammaLut) -> Self {
+        let linear = table == GammaLut::identity();
+
+        Self { table, linear }
+    }
+
+    pub fn is_linear(&self) -> bool {
+        self.linear
+    }
+
+    pub fn map(&self, value: u8) -> u8 {
+        self.table.map(value)
+    }
+
+    pub fn lut(&self) -> &GammaLut {
+        &self.table
+    }
+
+    pub(crate) fn apply_column(&self, column: &mut [u8]) {
+        for value in column.iter_mut() {
+            *value = self.table.map(*value);
+        }
+    }
+}
+
+impl Default for GammaMap {
+    fn default() -> Self {
+        Self::linear()
+    }
+}
+
+/// `Command::Brightness` value for a percentage of full brightness as it
+/// looks, not as it's counted. Anything above zero stays at least 1 so it
+/// doesn't turn the panel off.
+pub fn perceptual_brightness(percent: u8) -> u8 {
+    if percent == 0 {
+        return 0;
+    }
+
+    let fraction = percent.min(100) as f64 / 100.0;
+
+    (fraction.powf(PERCEPTUAL_GAMMA) * 255.0).round().max(1.0) as u8
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curves() {
+        assert!(GammaMap::linear().is_linear());
+        assert!(GammaMap::curve(1.0).is_linear());
+        assert!(!GammaMap::curve(2.2).is_linear());
+        assert!(GammaMap::curve(2.2).map(0x80) < 0x40);
+
+        assert_eq!(perceptual_brightness(0), 0);
+        assert_eq!(perceptual_brightness(1), 1);
+        assert_eq!(perceptual_brightness(50), 55);
+        assert_eq!(perceptual_brightness(200), 0xff);
+    }
+}
@@ -0,0 +1,814 @@
+//! Ambient animations computed on the host, for when the firmware's own
+//! patterns and games aren't what's wanted. Generators are iterators of
+//! `Bitmap8` frames sized for the panel, ready to hand to a `Display` or an
+//! `Animation`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::geometry::{EdgeMode, Point};
+use crate::random::Rng;
+use crate::{Bitmap8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Which cells are born and which survive, by how many of their eight
+/// neighbours are alive. Written the usual way as `B3/S23`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Rule {
+    /// Bit `n` set when a dead cell with `n` live neighbours comes alive
+    birth: u16,
+    /// Bit `n` set when a live cell with `n` live neighbours stays alive
+    survival: u16,
+}
+
+impl Rule {
+    /// Conway's Game of Life
+    pub const LIFE: Rule = Rule { birth: 1 << 3, survival: 1 << 2 | 1 << 3 };
+    /// Life with birth on six as well, known for its replicators
+    pub const HIGHLIFE: Rule = Rule { birth: 1 << 3 | 1 << 6, survival: 1 << 2 | 1 << 3 };
+    /// Every live cell dies each generation, which makes for fast sparks
+    pub const SEEDS: Rule = Rule { birth: 1 << 2, survival: 0 };
+
+    /// A rule from neighbour counts. Counts over eight are ignored.
+    pub fn new(birth: &[u8], survival: &[u8]) -> Self {
+        Self {
+            birth: mask(birth),
+            survival: mask(survival),
+        }
+    }
+
+    /// Whether a cell is alive next generation
+    pub fn next(&self, alive: bool, neighbours: u8) -> bool {
+        let rule = if alive { self.survival } else { self.birth };
+
+        rule & (1 << neighbours) != 0
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Self::LIFE
+    }
+}
+
+fn mask(counts: &[u8]) -> u16 {
+    counts.iter()
+        .filter(|x| **x <= 8)
+        .fold(0, |mask, x| mask | 1 << x)
+}
+
+fn counts(mask: u16) -> String {
+    (0 ..= 8).filter(|x| mask & (1 << x) != 0).map(|x| x.to_string()).collect()
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "B{}/S{}", counts(self.birth), counts(self.survival))
+    }
+}
+
+impl FromStr for Rule {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (birth, survival) = value.trim().split_once('/').ok_or("Rule needs a B part and an S part")?;
+        let birth = birth.strip_prefix(['B', 'b']).ok_or("Birth counts start with B")?;
+        let survival = survival.strip_prefix(['S', 's']).ok_or("Survival counts start with S")?;
+
+        let parse = |part: &str| part.chars()
+            .map(|x| match x.to_digit(10) {
+                Some(count) if count <= 8 => Ok(count as u8),
+                _ => Err("Neighbour counts are digits from 0 to 8"),
+            })
+            .collect::<Result<Vec<u8>, _>>();
+
+        Ok(Self::new(&parse(birth)?, &parse(survival)?))
+    }
+}
+
+/// A cellular automaton the size of the panel, the Game of Life unless told
+/// otherwise. As an iterator it steps a generation and yields the frame
+/// each time, and stops once a step changes nothing, so a loop can reseed
+/// when it settles:
+///
+/// ```
+/// use f16_hid::generators::{Automaton, Rule};
+/// use f16_hid::random::Rng;
+///
+/// let mut rng = Rng::new(7);
+/// let life = Automaton::random(Rule::LIFE, &mut rng, 30);
+///
+/// for frame in life.take(100) {
+///     // display.set_frame(&frame)?;
+/// }
+/// ```
+///
+/// The panel is a torus by default, cells off one edge wrapping round to
+/// the other. With `EdgeMode::Clip` everything off the panel counts as
+/// dead.
+#[derive(Clone, Debug)]
+pub struct Automaton {
+    rule: Rule,
+    edges: EdgeMode,
+    value: u8,
+    /// Column major, like `Bitmap8`
+    cells: Vec<bool>,
+    generation: u64,
+}
+
+impl Automaton {
+    /// Every cell dead
+    pub fn new(rule: Rule) -> Self {
+        Self {
+            rule,
+            edges: EdgeMode::Wrap,
+            value: 0xff,
+            cells: vec![false; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            generation: 0,
+        }
+    }
+
+    /// Roughly `percent` of the cells alive, chosen by `rng`
+    pub fn random(rule: Rule, rng: &mut Rng, percent: u8) -> Self {
+        let mut automaton = Self::new(rule);
+
+        for cell in automaton.cells.iter_mut() {
+            *cell = rng.chance(percent);
+        }
+
+        automaton
+    }
+
+    /// Start from a frame, say what's on the panel now. Pixels at or over
+    /// `threshold` are alive.
+    pub fn from_frame(rule: Rule, frame: &Bitmap8, threshold: u8) -> Self {
+        let mut automaton = Self::new(rule);
+
+        for (cell, pixel) in automaton.cells.iter_mut().zip(frame.data()) {
+            *cell = *pixel >= threshold;
+        }
+
+        automaton
+    }
+
+    /// What happens at the panel's edges. `Saturate` has cells off the
+    /// edge copy the nearest one on it.
+    pub fn edges(mut self, edges: EdgeMode) -> Self {
+        self.edges = edges;
+        self
+    }
+
+    /// Value live cells are drawn with
+    pub fn value(mut self, value: u8) -> Self {
+        self.value = value;
+        self
+    }
+
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    pub fn set_rule(&mut self, rule: Rule) {
+        self.rule = rule;
+    }
+
+    /// Generations stepped so far
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Live cells
+    pub fn population(&self) -> usize {
+        self.cells.iter().filter(|x| **x).count()
+    }
+
+    /// Whether a cell is alive. Anything off the panel is resolved by the
+    /// edge mode.
+    pub fn alive(&self, point: impl Into<Point>) -> bool {
+        match self.edges.resolve(point.into()) {
+            Some((x, y)) => self.cells[x * DISPLAY_HEIGHT + y],
+            None => false,
+        }
+    }
+
+    /// Bring a cell to life or kill it. Points off the panel are ignored.
+    pub fn set(&mut self, point: impl Into<Point>, alive: bool) {
+        if let Some((x, y)) = point.into().on_panel() {
+            self.cells[x * DISPLAY_HEIGHT + y] = alive;
+        }
+    }
+
+    /// Advance a generation. Returns whether any cell changed.
+    pub fn step(&mut self) -> bool {
+        let mut next = Vec::with_capacity(self.cells.len());
+
+        for x in 0 .. DISPLAY_WIDTH as i32 {
+            for y in 0 .. DISPLAY_HEIGHT as i32 {
+                let point = Point::new(x, y);
+                next.push(self.rule.next(self.alive(point), self.neighbours(point)));
+            }
+        }
+
+        let changed = next != self.cells;
+        self.cells = next;
+        self.generation += 1;
+
+        changed
+    }
+
+    /// The cells as a frame
+    pub fn frame(&self) -> Bitmap8 {
+        let mut frame = Bitmap8::new();
+
+        for (pixel, cell) in frame.data.iter_mut().zip(self.cells.iter()) {
+            *pixel = if *cell { self.value } else { 0 };
+        }
+
+        frame
+    }
+
+    fn neighbours(&self, point: Point) -> u8 {
+        let mut count = 0;
+
+        for dx in -1 ..= 1 {
+            for dy in -1 ..= 1 {
+                if (dx, dy) != (0, 0) && self.alive(point + Point::new(dx, dy)) {
+                    count += 1;
+                }
+            }
+        }
+
+        count
+    }
+}
+
+impl Iterator for Automaton {
+    type Item = Bitmap8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.step() {
+            true => Some(self.frame()),
+            false => None,
+        }
+    }
+}
+
+
+/// Smooth random-looking value from -1.0 to 1.0 at a point in 3D space,
+/// Ken Perlin's improved noise with a hash standing in for his permutation
+/// table so every seed gets its own field
+pub fn perlin(x: f32, y: f32, z: f32, seed: u32) -> f32 {
+    let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+    let (fx, fy, fz) = (x - x0, y - y0, z - z0);
+    let (ix, iy, iz) = (x0 as i32, y0 as i32, z0 as i32);
+    let (u, v, w) = (smooth(fx), smooth(fy), smooth(fz));
+
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        gradient(hash(ix + dx, iy + dy, iz + dz, seed), fx - dx as f32, fy - dy as f32, fz - dz as f32)
+    };
+
+    lerp(w,
+        lerp(v,
+            lerp(u, corner(0, 0, 0), corner(1, 0, 0)),
+            lerp(u, corner(0, 1, 0), corner(1, 1, 0))),
+        lerp(v,
+            lerp(u, corner(0, 0, 1), corner(1, 0, 1)),
+            lerp(u, corner(0, 1, 1), corner(1, 1, 1))))
+}
+
+fn hash(x: i32, y: i32, z: i32, seed: u32) -> u32 {
+    let mut hash = seed
+        ^ (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0x5bd1_e995);
+
+    hash ^ (hash >> 15)
+}
+
+/// Dot product with one of twelve edge directions of a cube
+fn gradient(hash: u32, x: f32, y: f32, z: f32) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = match h {
+        0 ..= 3 => y,
+        12 | 14 => x,
+        _ => z,
+    };
+
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+fn smooth(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+    a + t * (b - a)
+}
+
+/// Slowly drifting clouds of light from Perlin noise. Never runs out.
+///
+/// Like every effect here it's an endless iterator, so it goes into an
+/// `Animation` a stretch at a time:
+///
+/// ```
+/// use std::time::Duration;
+/// use f16_hid::animation::Animation;
+/// use f16_hid::generators::Plasma;
+///
+/// let plasma = Plasma::new(1).speed(0.1);
+/// let animation = Animation::from_frames(plasma.take(100), Duration::from_millis(50));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Plasma {
+    seed: u32,
+    scale: f32,
+    speed: f32,
+    time: f32,
+}
+
+impl Plasma {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            scale: 0.15,
+            speed: 0.05,
+            time: 0.0,
+        }
+    }
+
+    /// How far the field moves each frame
+    pub fn speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// How tightly packed the blobs are. Bigger is busier.
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+}
+
+impl Iterator for Plasma {
+    type Item = Bitmap8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut frame = Bitmap8::new();
+
+        for x in 0 .. DISPLAY_WIDTH {
+            for y in 0 .. DISPLAY_HEIGHT {
+                let noise = perlin(x as f32 * self.scale, y as f32 * self.scale, self.time, self.seed);
+                // Perlin noise rarely strays far from zero, so stretch it
+                let value = (noise * 0.7 + 0.5).clamp(0.0, 1.0);
+
+                frame.data[x * DISPLAY_HEIGHT + y] = (value * 255.0) as u8;
+            }
+        }
+
+        self.time += self.speed;
+
+        Some(frame)
+    }
+}
+
+/// Flames licking up from the bottom of the panel, the old Doom fire. Never
+/// runs out.
+#[derive(Clone, Debug)]
+pub struct Fire {
+    rng: Rng,
+    intensity: u8,
+    cooling: u8,
+    /// Column major, like `Bitmap8`
+    heat: Vec<u8>,
+}
+
+impl Fire {
+    pub fn new(rng: Rng) -> Self {
+        Self {
+            rng,
+            intensity: 80,
+            cooling: 24,
+            heat: vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+        }
+    }
+
+    /// Percentage of the bottom row burning at full heat each frame
+    pub fn intensity(mut self, percent: u8) -> Self {
+        self.intensity = percent.min(100);
+        self
+    }
+
+    /// Most heat lost per row as it rises. More makes shorter flames.
+    pub fn cooling(mut self, cooling: u8) -> Self {
+        self.cooling = cooling;
+        self
+    }
+}
+
+impl Iterator for Fire {
+    type Item = Bitmap8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bottom = DISPLAY_HEIGHT - 1;
+
+        for x in 0 .. DISPLAY_WIDTH {
+            self.heat[x * DISPLAY_HEIGHT + bottom] = match self.rng.chance(self.intensity) {
+                true => 0xff,
+                false => self.rng.next_u8() / 2,
+            };
+        }
+
+        // Each row rises from the one below, drifting a column either way
+        for y in 0 .. bottom {
+            for x in 0 .. DISPLAY_WIDTH {
+                let from = (x + DISPLAY_WIDTH + self.rng.below(3) - 1) % DISPLAY_WIDTH;
+                let below = self.heat[from * DISPLAY_HEIGHT + y + 1];
+                let cooling = self.rng.below(self.cooling as usize + 1) as u8;
+
+                self.heat[x * DISPLAY_HEIGHT + y] = below.saturating_sub(cooling);
+            }
+        }
+
+        let mut frame = Bitmap8::new();
+        frame.data.copy_from_slice(&self.heat);
+
+        Some(frame)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Star {
+    x: usize,
+    y: f32,
+    /// From 0.25 to 1.0 of the field's speed. Faster stars are brighter, so
+    /// they look closer.
+    speed: f32,
+}
+
+/// Stars streaming down the panel at different speeds. Never runs out.
+#[derive(Clone, Debug)]
+pub struct Starfield {
+    rng: Rng,
+    speed: f32,
+    stars: Vec<Star>,
+}
+
+impl Starfield {
+    pub fn new(rng: Rng) -> Self {
+        Self {
+            rng,
+            speed: 1.0,
+            stars: Vec::new(),
+        }
+        .stars(12)
+    }
+
+    /// Rows the fastest stars move each frame
+    pub fn speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// How many stars there are, scattered afresh
+    pub fn stars(mut self, count: usize) -> Self {
+        self.stars = (0 .. count)
+            .map(|_| {
+                let y = self.rng.below(DISPLAY_HEIGHT) as f32;
+                self.star(y)
+            })
+            .collect();
+
+        self
+    }
+
+    fn star(&mut self, y: f32) -> Star {
+        Star {
+            x: self.rng.below(DISPLAY_WIDTH),
+            y,
+            speed: 0.25 + self.rng.below(76) as f32 / 100.0,
+        }
+    }
+}
+
+impl Iterator for Starfield {
+    type Item = Bitmap8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut frame = Bitmap8::new();
+
+        for index in 0 .. self.stars.len() {
+            let mut star = self.stars[index];
+            star.y += star.speed * self.speed;
+
+            if star.y >= DISPLAY_HEIGHT as f32 {
+                star = self.star(star.y - DISPLAY_HEIGHT as f32);
+            }
+
+            let value = (star.speed * 255.0) as u8;
+            let pixel = &mut frame.data[star.x * DISPLAY_HEIGHT + star.y as usize];
+            *pixel = (*pixel).max(value);
+
+            self.stars[index] = star;
+        }
+
+        Some(frame)
+    }
+}
+
+/// Drops falling down the panel with fading trails. Never runs out.
+#[derive(Clone, Debug)]
+pub struct Rain {
+    rng: Rng,
+    intensity: u8,
+    speed: f32,
+    trail: usize,
+    /// Column and row of each drop's head
+    drops: Vec<(usize, f32)>,
+}
+
+impl Rain {
+    pub fn new(rng: Rng) -> Self {
+        Self {
+            rng,
+            intensity: 10,
+            speed: 1.0,
+            trail: 4,
+            drops: Vec::new(),
+        }
+    }
+
+    /// Percentage chance each frame of a new drop in each column
+    pub fn intensity(mut self, percent: u8) -> Self {
+        self.intensity = percent.min(100);
+        self
+    }
+
+    /// Rows a drop falls each frame
+    pub fn speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Pixels in a drop, head included
+    pub fn trail(mut self, trail: usize) -> Self {
+        self.trail = trail.max(1);
+        self
+    }
+}
+
+impl Iterator for Rain {
+    type Item = Bitmap8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for drop in self.drops.iter_mut() {
+            drop.1 += self.speed;
+        }
+
+        let gone = (DISPLAY_HEIGHT + self.trail) as f32;
+        self.drops.retain(|x| x.1 < gone);
+
+        for x in 0 .. DISPLAY_WIDTH {
+            if self.rng.chance(self.intensity) {
+                self.drops.push((x, 0.0));
+            }
+        }
+
+        let mut frame = Bitmap8::new();
+
+        for (x, head) in self.drops.iter() {
+            for step in 0 .. self.trail {
+                let y = *head as i32 - step as i32;
+                let value = (0xff * (self.trail - step) / self.trail) as u8;
+
+                if let Some((x, y)) = Point::new(*x as i32, y).on_panel() {
+                    let pixel = &mut frame.data[x * DISPLAY_HEIGHT + y];
+                    *pixel = (*pixel).max(value);
+                }
+            }
+        }
+
+        Some(frame)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Particle {
+    x: f32,
+    y: f32,
+    dx: f32,
+    dy: f32,
+    age: u32,
+}
+
+/// Sparks thrown up from a point and falling back under gravity, fading as
+/// they go. Never runs out.
+#[derive(Clone, Debug)]
+pub struct Particles {
+    rng: Rng,
+    origin: Point,
+    rate: usize,
+    gravity: f32,
+    life: u32,
+    particles: Vec<Particle>,
+}
+
+impl Particles {
+    /// Thrown from the middle of the bottom row
+    pub fn new(rng: Rng) -> Self {
+        Self {
+            rng,
+            origin: Point::new(DISPLAY_WIDTH as i32 / 2, DISPLAY_HEIGHT as i32 - 1),
+            rate: 2,
+            gravity: 0.1,
+            life: 30,
+            particles: Vec::new(),
+        }
+    }
+
+    pub fn origin(mut self, origin: Point) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    /// Particles thrown each frame
+    pub fn rate(mut self, rate: usize) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    /// Rows a frame added to each particle's fall every frame
+    pub fn gravity(mut self, gravity: f32) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    /// Frames a particle lasts, fading all the while
+    pub fn life(mut self, frames: u32) -> Self {
+        self.life = frames.max(1);
+        self
+    }
+}
+
+impl Iterator for Particles {
+    type Item = Bitmap8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for particle in self.particles.iter_mut() {
+            particle.x += particle.dx;
+            particle.y += particle.dy;
+            particle.dy += self.gravity;
+            particle.age += 1;
+        }
+
+        let life = self.life;
+        self.particles.retain(|x| x.age < life);
+
+        for _ in 0 .. self.rate {
+            let particle = Particle {
+                x: self.origin.x as f32,
+                y: self.origin.y as f32,
+                dx: (self.rng.below(101) as f32 - 50.0) / 100.0,
+                dy: -0.5 - self.rng.below(101) as f32 / 100.0,
+                age: 0,
+            };
+
+            self.particles.push(particle);
+        }
+
+        let mut frame = Bitmap8::new();
+
+        for particle in self.particles.iter() {
+            let value = (0xff * (self.life - particle.age) / self.life) as u8;
+            let point = Point::new(particle.x.round() as i32, particle.y.round() as i32);
+
+            if let Some((x, y)) = point.on_panel() {
+                let pixel = &mut frame.data[x * DISPLAY_HEIGHT + y];
+                *pixel = (*pixel).max(value);
+            }
+        }
+
+        Some(frame)
+    }
+}
+
+/// A pattern picked by `rng`, mirrored about the middle column, for telling
+/// things apart at a glance. The same seed always gives the same pattern.
+pub fn identicon(rng: &mut Rng) -> Bitmap8 {
+    let mut frame = Bitmap8::new();
+    let value = 0x80 + rng.below(0x80) as u8;
+
+    for x in 0 ..= DISPLAY_WIDTH / 2 {
+        for y in 0 .. DISPLAY_HEIGHT {
+            if rng.chance(50) {
+                frame.data[x * DISPLAY_HEIGHT + y] = value;
+                frame.data[(DISPLAY_WIDTH - 1 - x) * DISPLAY_HEIGHT + y] = value;
+            }
+        }
+    }
+
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blinkers_blink_and_blocks_stay_put() {
+        let mut life = Automaton::new(Rule::LIFE);
+        for y in 10 .. 13 {
+            life.set((4, y), true);
+        }
+
+        let first = life.next().unwrap();
+        assert_eq!(life.population(), 3);
+        assert!(life.alive((3, 11)) && life.alive((5, 11)) && !life.alive((4, 10)));
+        let second = life.next().unwrap();
+        assert_ne!(first.data(), second.data());
+        assert!(life.alive((4, 10)));
+        assert_eq!(life.generation(), 2);
+
+        let mut block = Bitmap8::new();
+        block.draw_box(0, 0, 1, 1, 0xff);
+        let mut still = Automaton::from_frame(Rule::LIFE, &block, 0x80);
+        assert!(still.next().is_none());
+
+        // Wrapped, a block split across the corners is still a block
+        let mut corners = Automaton::new(Rule::LIFE);
+        for point in [(0, 0), (8, 0), (0, 33), (8, 33)] {
+            corners.set(point, true);
+        }
+        assert!(!corners.clone().step());
+        let mut clipped = corners.edges(EdgeMode::Clip);
+        assert!(clipped.step());
+        assert_eq!(clipped.population(), 0);
+    }
+
+    #[test]
+    fn effects_are_endless_and_repeatable() {
+        let plasma: Vec<Bitmap8> = Plasma::new(3).take(2).collect();
+        assert_ne!(plasma[0].data(), plasma[1].data());
+        assert_eq!(Plasma::new(3).next().unwrap().data(), plasma[0].data());
+        assert!(plasma[0].data().iter().any(|x| *x != plasma[0].data()[0]));
+
+        let fire = Fire::new(Rng::new(1)).intensity(100).cooling(0).nth(40).unwrap();
+        assert!(fire.data().iter().all(|x| *x == 0xff));
+        let cold = Fire::new(Rng::new(1)).intensity(0).nth(40).unwrap();
+        assert!(cold.data().iter().all(|x| *x < 0x80));
+
+        let stars = Starfield::new(Rng::new(2)).stars(5).nth(100).unwrap();
+        let lit = stars.data().iter().filter(|x| **x > 0).count();
+        assert!(lit > 0 && lit <= 5);
+
+        let mut rain = Rain::new(Rng::new(4)).intensity(100).speed(1.0).trail(3);
+        let first = rain.next().unwrap();
+        assert!((0 .. DISPLAY_WIDTH).all(|x| first.data()[x * DISPLAY_HEIGHT] == 0xff));
+        assert_eq!(rain.take(200).count(), 200);
+    }
+
+    #[test]
+    fn particles_and_identicons_follow_the_seed() {
+        let sparks: Vec<Bitmap8> = Particles::new(Rng::new(5)).take(20).collect();
+        let again: Vec<Bitmap8> = Particles::new(Rng::new(5)).take(20).collect();
+        assert!(sparks.iter().zip(&again).all(|(a, b)| a.data() == b.data()));
+        assert_eq!(sparks[0].data()[4 * DISPLAY_HEIGHT + DISPLAY_HEIGHT - 1], 0xff);
+
+        let other = Particles::new(Rng::new(6)).nth(19).unwrap();
+        assert_ne!(other.data(), sparks[19].data());
+
+        // Nothing thrown, nothing shown, and short lived ones go
+        assert!(Particles::new(Rng::new(5)).rate(0).take(5).all(|x| x.data().iter().all(|x| *x == 0)));
+        let mut brief = Particles::new(Rng::new(5)).life(2).rate(1);
+        assert_eq!(brief.next().unwrap().data().iter().filter(|x| **x > 0).count(), 1);
+
+        let icon = identicon(&mut Rng::new(9));
+        assert_eq!(identicon(&mut Rng::new(9)).data(), icon.data());
+        assert_ne!(identicon(&mut Rng::new(10)).data(), icon.data());
+        assert!(icon.data().iter().any(|x| *x > 0));
+
+        for x in 0 .. DISPLAY_WIDTH {
+            let mirrored = DISPLAY_WIDTH - 1 - x;
+            assert_eq!(icon.data()[x * DISPLAY_HEIGHT .. (x + 1) * DISPLAY_HEIGHT],
+                icon.data()[mirrored * DISPLAY_HEIGHT .. (mirrored + 1) * DISPLAY_HEIGHT]);
+        }
+    }
+
+    #[test]
+    fn noise_is_smooth() {
+        assert_eq!(perlin(1.0, 2.0, 3.0, 9), 0.0);
+        assert!((perlin(1.5, 2.5, 0.5, 9) - perlin(1.51, 2.5, 0.5, 9)).abs() < 0.05);
+        assert!((0 .. 100).map(|x| perlin(x as f32 * 0.37, 0.2, 0.7, 9)).all(|x| (-1.5 ..= 1.5).contains(&x)));
+    }
+
+    #[test]
+    fn rules_parse() {
+        assert_eq!("B3/S23".parse(), Ok(Rule::LIFE));
+        assert_eq!("b36/s23".parse(), Ok(Rule::HIGHLIFE));
+        assert_eq!(Rule::SEEDS.to_string(), "B2/S");
+        assert_eq!(Rule::SEEDS.to_string().parse(), Ok(Rule::SEEDS));
+        assert!("B9/S23".parse::<Rule>().is_err());
+        assert!("S23".parse::<Rule>().is_err());
+    }
+}
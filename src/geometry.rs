@@ -0,0 +1,244 @@
+use core::ops::{Add, Sub};
+
+use crate::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// A pixel position. Signed so things can start off the panel, like text
+/// scrolling in from an edge.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    pub const ZERO: Point = Point::new(0, 0);
+
+    pub const fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    /// Column and row on the panel, or `None` if this is off it
+    pub fn on_panel(&self) -> Option<(usize, usize)> {
+        let x = usize::try_from(self.x).ok()?;
+        let y = usize::try_from(self.y).ok()?;
+
+        (x < DISPLAY_WIDTH && y < DISPLAY_HEIGHT).then_some((x, y))
+    }
+}
+
+/// What a canvas does with points that fall off the panel
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum EdgeMode {
+    /// Drop them
+    #[default]
+    Clip,
+    /// Pin them to the nearest edge
+    Saturate,
+    /// Carry on from the opposite edge, as if the panel were a torus. Handy
+    /// for rain, starfields and the game of life.
+    Wrap,
+}
+
+impl EdgeMode {
+    /// Column and row on the panel a point ends up at, if any
+    pub fn resolve(&self, point: Point) -> Option<(usize, usize)> {
+        let (width, height) = (DISPLAY_WIDTH as i32, DISPLAY_HEIGHT as i32);
+
+        match self {
+            EdgeMode::Clip => point.on_panel(),
+            EdgeMode::Saturate => Some((point.x.clamp(0, width - 1) as usize, point.y.clamp(0, height - 1) as usize)),
+            EdgeMode::Wrap => Some((point.x.rem_euclid(width) as usize, point.y.rem_euclid(height) as usize)),
+        }
+    }
+}
+
+impl From<(i32, i32)> for Point {
+    fn from((x, y): (i32, i32)) -> Self {
+        Self::new(x, y)
+    }
+}
+
+impl Add for Point {
+    type Output = Point;
+
+    fn add(self, other: Point) -> Point {
+        Point::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for Point {
+    type Output = Point;
+
+    fn sub(self, other: Point) -> Point {
+        Point::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Size {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Size {
+    pub const fn new(width: usize, height: usize) -> Self {
+        Self { width, height }
+    }
+
+    /// The whole panel
+    pub const fn display() -> Self {
+        Self::new(DISPLAY_WIDTH, DISPLAY_HEIGHT)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+impl From<(usize, usize)> for Size {
+    fn from((width, height): (usize, usize)) -> Self {
+        Self::new(width, height)
+    }
+}
+
+/// An area of pixels, from the top left corner
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub origin: Point,
+    pub size: Size,
+}
+
+impl Rect {
+    pub fn new(origin: impl Into<Point>, size: impl Into<Size>) -> Self {
+        Self {
+            origin: origin.into(),
+            size: size.into(),
+        }
+    }
+
+    /// Everything from `top_left` up to but not including `bottom_right`
+    pub fn from_corners(top_left: Point, bottom_right: Point) -> Self {
+        let width = (bottom_right.x - top_left.x).max(0) as usize;
+        let height = (bottom_right.y - top_left.y).max(0) as usize;
+
+        Self::new(top_left, (width, height))
+    }
+
+    /// The whole panel
+    pub fn display() -> Self {
+        Self::new(Point::ZERO, Size::display())
+    }
+
+    pub fn left(&self) -> i32 {
+        self.origin.x
+    }
+
+    pub fn top(&self) -> i32 {
+        self.origin.y
+    }
+
+    /// First column past the right edge
+    pub fn right(&self) -> i32 {
+        self.origin.x + self.size.width as i32
+    }
+
+    /// First row past the bottom edge
+    pub fn bottom(&self) -> i32 {
+        self.origin.y + self.size.height as i32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size.is_empty()
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        point.x >= self.left() && point.x < self.right() && point.y >= self.top() && point.y < self.bottom()
+    }
+
+    /// The overlap between two rectangles, empty if there isn't one
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let top_left = Point::new(self.left().max(other.left()), self.top().max(other.top()));
+        let bottom_right = Point::new(self.right().min(other.right()), self.bottom().min(other.bottom()));
+
+        Rect::from_corners(top_left, bottom_right)
+    }
+
+    /// The part of this rectangle that's actually on the panel
+    pub fn clipped(&self) -> Rect {
+        self.intersection(&Rect::display())
+    }
+
+    /// Columns of the panel this covers, clipped to the panel
+    pub fn columns(&self) -> core::ops::Range<usize> {
+        let area = self.clipped();
+
+        if area.is_empty() {
+            return 0 .. 0;
+        }
+
+        area.left() as usize .. area.right() as usize
+    }
+
+    /// Rows of the panel this covers, clipped to the panel
+    pub fn rows(&self) -> core::ops::Range<usize> {
+        let area = self.clipped();
+
+        if area.is_empty() {
+            return 0 .. 0;
+        }
+
+        area.top() as usize .. area.bottom() as usize
+    }
+
+    /// Moved by `offset`, same size
+    pub fn translate(&self, offset: Point) -> Rect {
+        Rect::new(self.origin + offset, self.size)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clipping() {
+        let area = Rect::new((-2, 30), (5, 10));
+
+        assert_eq!(area.clipped(), Rect::new((0, 30), (3, 4)));
+        assert_eq!(area.columns(), 0 .. 3);
+        assert_eq!(area.rows(), 30 .. 34);
+
+        let off_panel = Rect::new((20, 0), (5, 5));
+        assert!(off_panel.clipped().is_empty());
+        assert_eq!(off_panel.columns(), 0 .. 0);
+    }
+
+    #[test]
+    fn edges_and_containment() {
+        let area = Rect::from_corners(Point::new(1, 2), Point::new(4, 6));
+
+        assert_eq!(area.size, Size::new(3, 4));
+        assert_eq!((area.right(), area.bottom()), (4, 6));
+        assert!(area.contains(Point::new(3, 5)));
+        assert!(!area.contains(Point::new(4, 5)));
+        assert_eq!(area.translate(Point::new(-1, -2)).origin, Point::ZERO);
+    }
+
+    #[test]
+    fn points_on_panel() {
+        assert_eq!(Point::new(8, 33).on_panel(), Some((8, 33)));
+        assert_eq!(Point::new(9, 0).on_panel(), None);
+        assert_eq!(Point::new(0, -1).on_panel(), None);
+    }
+
+    #[test]
+    fn edge_modes() {
+        let point = Point::new(-1, 35);
+
+        assert_eq!(EdgeMode::Clip.resolve(point), None);
+        assert_eq!(EdgeMode::Saturate.resolve(point), Some((0, 33)));
+        assert_eq!(EdgeMode::Wrap.resolve(point), Some((8, 1)));
+        assert_eq!(EdgeMode::Wrap.resolve(Point::new(-10, -34)), Some((8, 0)));
+    }
+}
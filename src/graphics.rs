@@ -0,0 +1,111 @@
+//! `embedded-graphics` support, so the ecosystem's lines, shapes, fonts and
+//! images can be drawn straight into either bitmap type. Pixels outside the
+//! panel are ignored, like any other clipped display.
+
+use embedded_graphics::pixelcolor::{BinaryColor, Gray8, GrayColor};
+use embedded_graphics::prelude::*;
+
+use crate::{Bitmap, Bitmap8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+const SIZE: Size = Size::new(DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32);
+
+fn on_panel(point: Point) -> Option<(usize, usize)> {
+    let x = usize::try_from(point.x).ok()?;
+    let y = usize::try_from(point.y).ok()?;
+
+    if x < DISPLAY_WIDTH && y < DISPLAY_HEIGHT {
+        Some((x, y))
+    } else {
+        None
+    }
+}
+
+impl OriginDimensions for Bitmap8 {
+    fn size(&self) -> Size {
+        SIZE
+    }
+}
+
+impl DrawTarget for Bitmap8 {
+    type Color = Gray8;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where I: IntoIterator<Item = Pixel<Self::Color>>
+    {
+        for Pixel(point, color) in pixels {
+            if let Some((x, y)) = on_panel(point) {
+                self.data[x * DISPLAY_HEIGHT + y] = color.luma();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.fill(color.luma());
+
+        Ok(())
+    }
+}
+
+impl OriginDimensions for Bitmap {
+    fn size(&self) -> Size {
+        SIZE
+    }
+}
+
+impl DrawTarget for Bitmap {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where I: IntoIterator<Item = Pixel<Self::Color>>
+    {
+        for Pixel(point, color) in pixels {
+            if let Some((x, y)) = on_panel(point) {
+                // Can't fail, on_panel() already checked the bounds
+                let _ = self.draw_point(x, y, color.is_on());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.fill(if color.is_on() { 0xff } else { 0x00 });
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::primitives::{Line, PrimitiveStyle};
+
+    #[test]
+    fn greyscale_line_is_clipped() {
+        let mut bitmap = Bitmap8::new();
+
+        Line::new(Point::new(-5, 0), Point::new(20, 0))
+            .into_styled(PrimitiveStyle::with_stroke(Gray8::new(0x40), 1))
+            .draw(&mut bitmap)
+            .unwrap();
+
+        for x in 0 .. DISPLAY_WIDTH {
+            assert_eq!(bitmap.data()[x * DISPLAY_HEIGHT], 0x40);
+        }
+        assert_eq!(bitmap.data()[1], 0);
+    }
+
+    #[test]
+    fn mono_pixels() {
+        let mut bitmap = Bitmap::new();
+
+        Pixel(Point::new(0, 1), BinaryColor::On).draw(&mut bitmap).unwrap();
+
+        assert_eq!(bitmap.data()[0], 0b10);
+    }
+}
@@ -0,0 +1,263 @@
+//! Several matrices showing the same thing, like the two built in modules
+//! and any more plugged in through dongles. A `MatrixGroup` is a transport
+//! as well as a set of devices, so a `Display` built on `mirror()` draws on
+//! every one of them without knowing there's more than one:
+//!
+//! ```
+//! use f16_hid::group::MatrixGroup;
+//! use f16_hid::simulator::SimulatedMatrix;
+//!
+//! let (left, right) = (SimulatedMatrix::new(), SimulatedMatrix::new());
+//! let group = MatrixGroup::new();
+//! group.insert("left", left.matrix());
+//! group.insert("right", right.matrix());
+//!
+//! let mut display = group.mirror();
+//! display.back_mut().draw_text((0, 0), "HI", 0xff);
+//! display.present()?;
+//! assert_eq!(left.grid().data(), right.grid().data());
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! A device that fails drops out and the rest carry on, the same as with
+//! `DeviceManager`. Writes only fail once every device has. Queries are
+//! answered by the first device that can.
+
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::clock::{Clock, SystemClock};
+use crate::display::Display;
+use crate::manager::{DeviceManager, Health};
+use crate::response::RESPONSE_LENGTH;
+use crate::transport::Transport;
+use crate::{Command, LedMatrix, MAX_COMMAND_LENGTH};
+
+/// How long a mirrored `LedMatrix` waits on a reply by default
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+struct GroupState<C: Clock> {
+    devices: DeviceManager<C>,
+    /// Reply to the last query, waiting to be read
+    readable: VecDeque<u8>,
+    timeout: Duration,
+}
+
+/// A set of named matrices driven together, see the `group` module. Clones
+/// share the same devices.
+pub struct MatrixGroup<C: Clock = SystemClock> {
+    state: Arc<Mutex<GroupState<C>>>,
+}
+
+impl<C: Clock> Clone for MatrixGroup<C> {
+    fn clone(&self) -> Self {
+        Self { state: self.state.clone() }
+    }
+}
+
+impl MatrixGroup<SystemClock> {
+    pub fn new() -> Self {
+        Self::from_manager(DeviceManager::new())
+    }
+}
+
+impl Default for MatrixGroup<SystemClock> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clock> MatrixGroup<C> {
+    /// Group the devices a manager already has, keeping its callbacks and
+    /// retry interval
+    pub fn from_manager(devices: DeviceManager<C>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(GroupState {
+                devices,
+                readable: VecDeque::new(),
+                timeout: DEFAULT_TIMEOUT,
+            })),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, GroupState<C>> {
+        self.state.lock().expect("Group lock poisoned")
+    }
+
+    pub fn insert(&self, name: &str, matrix: LedMatrix) -> Option<LedMatrix> {
+        self.state().devices.insert(name, matrix)
+    }
+
+    pub fn remove(&self, name: &str) -> Option<LedMatrix> {
+        self.state().devices.remove(name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.state().devices.names().map(String::from).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.state().devices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state().devices.is_empty()
+    }
+
+    pub fn health(&self, name: &str) -> Option<Health> {
+        self.state().devices.health(name)
+    }
+
+    /// Anything else the manager does, like adding callbacks or talking to
+    /// one device on its own
+    pub fn with_devices<T>(&self, job: impl FnOnce(&mut DeviceManager<C>) -> T) -> T {
+        job(&mut self.state().devices)
+    }
+
+    /// Send a command to every healthy device. One failing doesn't stop the
+    /// others, and each result is listed against its device's name.
+    pub fn broadcast(&self, command: Command) -> Vec<(String, Result<usize, Error>)> {
+        self.state().devices.execute_all(command)
+    }
+
+    /// Try to bring failed devices back, as `DeviceManager::supervise()`
+    /// does. A device that comes back still shows whatever it did before,
+    /// so call `Display::invalidate()` on a mirror if this returns any.
+    pub fn supervise(&self) -> Vec<String> {
+        self.state().devices.supervise()
+    }
+}
+
+impl<C: Clock + Send + 'static> MatrixGroup<C> {
+    /// One `LedMatrix` whose commands go to every device in the group
+    pub fn matrix(&self) -> LedMatrix {
+        LedMatrix::with_transport("group", self.clone())
+    }
+
+    /// A `Display` showing the same frame on every device in the group
+    pub fn mirror(&self) -> Display {
+        Display::new(self.matrix())
+    }
+}
+
+impl<C: Clock> Read for MatrixGroup<C> {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let mut state = self.state();
+
+        if state.readable.is_empty() && !buffer.is_empty() {
+            return Err(Error::new(ErrorKind::TimedOut, "Nothing to read"));
+        }
+
+        let count = buffer.len().min(state.readable.len());
+
+        for (byte, reply) in buffer.iter_mut().zip(state.readable.drain(.. count)) {
+            *byte = reply;
+        }
+
+        Ok(count)
+    }
+}
+
+impl<C: Clock> Write for MatrixGroup<C> {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize, Error> {
+        let mut state = self.state();
+
+        // Commands come padded to full length and queries short, on their own
+        if buffer.len() < MAX_COMMAND_LENGTH {
+            let mut response = [0u8; RESPONSE_LENGTH];
+            state.devices.ask(buffer, &mut response)?;
+            state.readable.extend(response);
+
+            return Ok(buffer.len());
+        }
+
+        let mut last = None;
+
+        for (_, result) in state.devices.send_all(buffer) {
+            match result {
+                Ok(_) => return Ok(buffer.len()),
+                Err(error) => last = Some(error),
+            }
+        }
+
+        Err(last.unwrap_or_else(|| Error::new(ErrorKind::NotConnected, "Group has no devices")))
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<C: Clock + Send> Transport for MatrixGroup<C> {
+    fn bytes_to_read(&self) -> Result<u32, Error> {
+        Ok(self.state().readable.len() as u32)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.state().timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), Error> {
+        self.state().timeout = timeout;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::reconnect::ReconnectPolicy;
+    use crate::response::Response;
+    use crate::simulator::SimulatedMatrix;
+
+    #[test]
+    fn mirrors_survive_a_failed_device() {
+        let group = MatrixGroup::from_manager(DeviceManager::with_clock(ManualClock::new()));
+        let simulators = [SimulatedMatrix::new(), SimulatedMatrix::new(), SimulatedMatrix::new()];
+
+        for (name, simulator) in ["a", "b", "c"].into_iter().zip(&simulators) {
+            let mut matrix = simulator.matrix();
+            matrix.set_reconnect_policy(ReconnectPolicy::never());
+            group.insert(name, matrix);
+        }
+
+        let results = group.broadcast(Command::Brightness(0x40));
+        assert!(results.iter().all(|(_, x)| x.is_ok()));
+        assert!(simulators.iter().all(|x| x.brightness() == 0x40));
+
+        let mut display = group.mirror();
+        display.back_mut().draw_text((0, 0), "HI", 0xff);
+        display.present().unwrap();
+        assert!(simulators.iter().all(|x| x.grid().data() == display.frame().data()));
+
+        // One unplugged drops out and the others keep drawing
+        simulators[1].set_unplugged(true);
+        display.back_mut().draw_text((0, 10), "OK", 0xff);
+        display.present().unwrap();
+
+        assert!(matches!(group.health("b"), Some(Health::Failed { .. })));
+        assert_eq!(simulators[0].grid().data(), display.frame().data());
+        assert_eq!(simulators[2].grid().data(), display.frame().data());
+        assert_ne!(simulators[1].grid().data(), display.frame().data());
+
+        let brightness = display.matrix_mut().query(Command::GetBrightness).unwrap();
+        assert!(matches!(brightness, Response::Brightness(0x40)));
+
+        // Back again, and shown the whole frame once the mirror is told
+        simulators[1].set_unplugged(false);
+        assert_eq!(group.supervise(), ["b"]);
+        display.invalidate();
+        display.present().unwrap();
+        assert_eq!(simulators[1].grid().data(), display.frame().data());
+
+        for simulator in &simulators {
+            simulator.set_unplugged(true);
+        }
+
+        assert!(display.set_frame(&crate::Bitmap8::new()).is_err());
+    }
+}
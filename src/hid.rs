@@ -0,0 +1,193 @@
+//! Talking to a module over USB HID through `hidapi`, for firmware that
+//! exposes a HID interface instead of, or as well as, the CDC serial one.
+//! The packets are the same either way, one command to an output report
+//! and one reply to an input report, so an `LedMatrix` opened here behaves
+//! exactly like one on a serial port.
+//!
+//! The firmware Framework ships today only has the serial interface.
+//! `open_any()` tries that first and only looks for HID when there's no
+//! serial port to be had:
+//!
+//! ```no_run
+//! let mut matrix = f16_hid::hid::open_any()?;
+//! # Ok::<(), serialport::Error>(())
+//! ```
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::time::Duration;
+
+use hidapi::{HidApi, HidDevice, HidError};
+
+use crate::builder::LedMatrixBuilder;
+use crate::discovery::{FRAMEWORK_VID, LED_MATRIX_PID};
+use crate::response::RESPONSE_LENGTH;
+use crate::transport::Transport;
+use crate::{LedMatrix, CONNECT_DELAY, MAX_COMMAND_LENGTH};
+
+/// Bytes in each report, not counting the report ID
+pub const HID_REPORT_LENGTH: usize = 64;
+
+fn io_error(error: HidError) -> Error {
+    Error::other(error)
+}
+
+/// Paths of every LED matrix with a HID interface, as `HidTransport::open()`
+/// takes them
+pub fn find() -> Result<Vec<String>, Error> {
+    let api = HidApi::new().map_err(io_error)?;
+
+    let paths = api.device_list()
+        .filter(|x| x.vendor_id() == FRAMEWORK_VID && x.product_id() == LED_MATRIX_PID)
+        .map(|x| x.path().to_string_lossy().into_owned())
+        .collect();
+
+    Ok(paths)
+}
+
+/// Open the first LED matrix found, over serial if it has a serial port
+/// and HID if not
+pub fn open_any() -> Result<LedMatrix, serialport::Error> {
+    if let Some(found) = LedMatrix::discover()?.first() {
+        return found.open();
+    }
+
+    match find()?.first() {
+        Some(path) => LedMatrix::builder(path).open_hid(),
+        None => Err(serialport::Error::new(serialport::ErrorKind::NoDevice, "No LED matrix found")),
+    }
+}
+
+/// Put a packet in a report of its own: the report ID, which is always
+/// zero, then the packet padded out with zeros
+fn report(packet: &[u8]) -> [u8; HID_REPORT_LENGTH + 1] {
+    let mut report = [0u8; HID_REPORT_LENGTH + 1];
+    report[1 .. packet.len() + 1].copy_from_slice(packet);
+
+    report
+}
+
+/// A HID device standing in for a serial port. Each write sends at most one
+/// command, so a run of packed commands goes out a report apiece.
+pub struct HidTransport {
+    device: HidDevice,
+    timeout: Duration,
+    /// Reply bytes read but not yet taken. Reading is the only way to find
+    /// out whether HID has anything waiting.
+    readable: RefCell<VecDeque<u8>>,
+}
+
+impl HidTransport {
+    pub fn open(path: &str) -> Result<Self, Error> {
+        let path = CString::new(path).map_err(|_| Error::new(ErrorKind::InvalidInput, "Path has a nul in it"))?;
+        let api = HidApi::new().map_err(io_error)?;
+
+        Ok(Self {
+            device: api.open_path(&path).map_err(io_error)?,
+            timeout: CONNECT_DELAY,
+            readable: RefCell::new(VecDeque::new()),
+        })
+    }
+
+    /// Read one input report into the buffer, waiting up to `timeout`.
+    /// Returns whether there was one.
+    fn fill(&self, timeout: Duration) -> Result<bool, Error> {
+        let mut buffer = [0u8; HID_REPORT_LENGTH];
+        let millis = timeout.as_millis().min(i32::MAX as u128) as i32;
+
+        let count = self.device.read_timeout(&mut buffer, millis).map_err(io_error)?;
+        self.readable.borrow_mut().extend(&buffer[.. count.min(RESPONSE_LENGTH)]);
+
+        Ok(count > 0)
+    }
+}
+
+impl Read for HidTransport {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        if self.readable.get_mut().is_empty() && !buffer.is_empty() && !self.fill(self.timeout)? {
+            return Err(Error::new(ErrorKind::TimedOut, "No report within the timeout"));
+        }
+
+        let readable = self.readable.get_mut();
+        let count = buffer.len().min(readable.len());
+
+        for (byte, reply) in buffer.iter_mut().zip(readable.drain(.. count)) {
+            *byte = reply;
+        }
+
+        Ok(count)
+    }
+}
+
+impl Write for HidTransport {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize, Error> {
+        let length = buffer.len().min(MAX_COMMAND_LENGTH);
+
+        self.device.write(&report(&buffer[.. length])).map_err(io_error)?;
+
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Transport for HidTransport {
+    fn bytes_to_read(&self) -> Result<u32, Error> {
+        if self.readable.borrow().is_empty() {
+            self.fill(Duration::ZERO)?;
+        }
+
+        Ok(self.readable.borrow().len() as u32)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), Error> {
+        self.timeout = timeout;
+        Ok(())
+    }
+}
+
+impl LedMatrixBuilder {
+    /// Open the HID device at the builder's path instead of a serial port.
+    /// The baud rate doesn't come into it. Reconnecting opens the same
+    /// path again.
+    pub fn open_hid(self) -> Result<LedMatrix, serialport::Error> {
+        let mut device = HidTransport::open(&self.path)?;
+        device.set_timeout(self.write_timeout)?;
+
+        let path = self.path.clone();
+        let timeout = self.write_timeout;
+        let reopen = move || -> Result<Box<dyn Transport>, serialport::Error> {
+            let mut device = HidTransport::open(&path)?;
+            device.set_timeout(timeout)?;
+
+            Ok(Box::new(device))
+        };
+
+        let matrix = LedMatrix::from_builder(&self, Box::new(device), Some(Box::new(reopen)));
+
+        self.finish(matrix)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packets_go_one_to_a_report() {
+        let report = report(&[0x32, 0xac, 0x00, 0x40]);
+
+        assert_eq!(report.len(), HID_REPORT_LENGTH + 1);
+        assert_eq!(report[.. 5], [0x00, 0x32, 0xac, 0x00, 0x40]);
+        assert!(report[5 ..].iter().all(|x| *x == 0));
+    }
+}
@@ -0,0 +1,274 @@
+//! Noticing modules being plugged in and unplugged, so a long running
+//! program survives the laptop being docked and undocked.
+//!
+//! The port list is polled through `serialport`, which reads udev's device
+//! database on Linux and the USB device tree on Windows and macOS. A module
+//! that comes back often does so under a different path, `/dev/ttyACM1`
+//! instead of `/dev/ttyACM0` or another COM port, so `Watcher::rebind()`
+//! points a matrix at wherever its module went:
+//!
+//! ```no_run
+//! use f16_hid::hotplug::{self, HotplugEvent};
+//! use f16_hid::Display;
+//!
+//! let mut display = Display::open_default()?;
+//! let mut watcher = hotplug::watch()?;
+//!
+//! loop {
+//!     for event in watcher.poll()? {
+//!         if watcher.rebind(display.matrix_mut(), &event)? {
+//!             // Whatever was on the panel went with the old port
+//!             display.invalidate();
+//!         }
+//!     }
+//!
+//!     // Draw and present
+//! # break;
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use serialport::SerialPortInfo;
+
+use crate::clock::{Clock, SystemClock};
+use crate::discovery::{self, DiscoveredMatrix};
+use crate::roles::RoleConfig;
+use crate::LedMatrix;
+
+/// How often the port list is checked
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HotplugEvent {
+    Attached(DiscoveredMatrix),
+    Detached(DiscoveredMatrix),
+}
+
+impl HotplugEvent {
+    pub fn matrix(&self) -> &DiscoveredMatrix {
+        match self {
+            Self::Attached(x) | Self::Detached(x) => x,
+        }
+    }
+}
+
+type Scanner = Box<dyn FnMut() -> Result<Vec<SerialPortInfo>, serialport::Error> + Send>;
+
+/// Watch for modules coming and going, with roles from the user's saved
+/// config. Modules already plugged in are taken as the starting point, not
+/// reported as attached.
+pub fn watch() -> Result<Watcher, serialport::Error> {
+    Watcher::new(RoleConfig::load_default().unwrap_or_default())
+}
+
+/// Keeps track of which modules are plugged in, see `watch()`
+pub struct Watcher<C: Clock = SystemClock> {
+    roles: RoleConfig,
+    scanner: Scanner,
+    clock: C,
+    interval: Duration,
+    last_scan: Option<Instant>,
+    present: Vec<DiscoveredMatrix>,
+    /// Serial number of every path a module has been seen at, so a matrix
+    /// can be matched with its module after the path is gone
+    serials: HashMap<String, String>,
+    pending: VecDeque<HotplugEvent>,
+}
+
+impl Watcher<SystemClock> {
+    pub fn new(roles: RoleConfig) -> Result<Self, serialport::Error> {
+        Self::with_scanner(roles, serialport::available_ports, SystemClock)
+    }
+}
+
+impl<C: Clock> Watcher<C> {
+    /// Watch a port list from somewhere other than the OS, such as a test
+    pub fn with_scanner(
+        roles: RoleConfig,
+        scanner: impl FnMut() -> Result<Vec<SerialPortInfo>, serialport::Error> + Send + 'static,
+        clock: C,
+    ) -> Result<Self, serialport::Error> {
+        let mut watcher = Self {
+            roles,
+            scanner: Box::new(scanner),
+            clock,
+            interval: DEFAULT_POLL_INTERVAL,
+            last_scan: None,
+            present: Vec::new(),
+            serials: HashMap::new(),
+            pending: VecDeque::new(),
+        };
+
+        watcher.present = watcher.list()?;
+        watcher.remember(&watcher.present.clone());
+
+        Ok(watcher)
+    }
+
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Modules plugged in as of the last scan
+    pub fn present(&self) -> &[DiscoveredMatrix] {
+        &self.present
+    }
+
+    /// Anything that's changed since the last scan, if it's time for
+    /// another one. Doesn't wait.
+    pub fn poll(&mut self) -> Result<Vec<HotplugEvent>, serialport::Error> {
+        let due = match self.last_scan {
+            Some(last) => self.clock.elapsed(last) >= self.interval,
+            None => true,
+        };
+
+        if due {
+            self.refresh()?;
+        }
+
+        Ok(self.pending.drain(..).collect())
+    }
+
+    /// Point `matrix` at its module's new path and reconnect, if `event` is
+    /// that module coming back. Matched by serial number, or by path for
+    /// modules without one. Returns whether it reconnected.
+    pub fn rebind(&self, matrix: &mut LedMatrix, event: &HotplugEvent) -> Result<bool, serialport::Error> {
+        let found = match event {
+            HotplugEvent::Attached(x) => x,
+            HotplugEvent::Detached(_) => return Ok(false),
+        };
+
+        let ours = match (self.serials.get(matrix.path()), &found.serial_number) {
+            (Some(serial), Some(other)) => serial == other,
+            _ => found.path == matrix.path(),
+        };
+
+        if !ours {
+            return Ok(false);
+        }
+
+        matrix.set_path(&found.path);
+        matrix.reconnect()?;
+
+        Ok(true)
+    }
+
+    fn refresh(&mut self) -> Result<(), serialport::Error> {
+        let now = self.list()?;
+
+        for gone in self.present.iter().filter(|x| !now.contains(x)) {
+            self.pending.push_back(HotplugEvent::Detached(gone.clone()));
+        }
+
+        for new in now.iter().filter(|x| !self.present.contains(x)) {
+            self.pending.push_back(HotplugEvent::Attached(new.clone()));
+        }
+
+        self.remember(&now);
+        self.present = now;
+
+        Ok(())
+    }
+
+    fn list(&mut self) -> Result<Vec<DiscoveredMatrix>, serialport::Error> {
+        self.last_scan = Some(self.clock.now());
+
+        Ok(discovery::filter((self.scanner)()?, &self.roles))
+    }
+
+    fn remember(&mut self, found: &[DiscoveredMatrix]) {
+        for matrix in found {
+            if let Some(serial) = &matrix.serial_number {
+                self.serials.insert(matrix.path.clone(), serial.clone());
+            }
+        }
+    }
+}
+
+impl<C: Clock> Iterator for Watcher<C> {
+    type Item = Result<HotplugEvent, serialport::Error>;
+
+    /// Waits for the next event, scanning every `interval()`
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+
+            self.clock.sleep(self.interval);
+
+            if let Err(error) = self.refresh() {
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use serialport::{SerialPortType, UsbPortInfo};
+
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::discovery::{FRAMEWORK_VID, LED_MATRIX_PID};
+    use crate::transport::MockTransport;
+
+    fn port(path: &str, serial: &str) -> SerialPortInfo {
+        SerialPortInfo {
+            port_name: path.to_owned(),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid: FRAMEWORK_VID,
+                pid: LED_MATRIX_PID,
+                serial_number: Some(serial.to_owned()),
+                manufacturer: None,
+                product: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn follows_a_module_to_its_new_path() {
+        let ports = Arc::new(Mutex::new(vec![port("/dev/ttyACM0", "AAAA")]));
+        let clock = ManualClock::new();
+
+        let listed = ports.clone();
+        let mut watcher = Watcher::with_scanner(
+            RoleConfig::new(),
+            move || Ok(listed.lock().unwrap().clone()),
+            clock.clone(),
+        ).unwrap();
+
+        let mut matrix = LedMatrix::with_transport("/dev/ttyACM0", MockTransport::new());
+        assert!(watcher.poll().unwrap().is_empty());
+
+        // Undocked, then back under another name
+        ports.lock().unwrap().clear();
+        clock.advance(DEFAULT_POLL_INTERVAL);
+        let events = watcher.poll().unwrap();
+        assert!(matches!(&events[..], [HotplugEvent::Detached(x)] if x.path == "/dev/ttyACM0"));
+        assert!(!watcher.rebind(&mut matrix, &events[0]).unwrap());
+
+        ports.lock().unwrap().push(port("/dev/ttyACM1", "AAAA"));
+        ports.lock().unwrap().push(port("/dev/ttyACM2", "BBBB"));
+
+        // Not time to look again yet
+        assert!(watcher.poll().unwrap().is_empty());
+
+        clock.advance(DEFAULT_POLL_INTERVAL);
+        let events = watcher.poll().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(!watcher.rebind(&mut matrix, &events[1]).unwrap());
+        assert!(watcher.rebind(&mut matrix, &events[0]).unwrap());
+        assert_eq!(matrix.path(), "/dev/ttyACM1");
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::{Duration, Instant};
+
+/// What an `IdleDimmer` does once nothing has changed for long enough
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdleAction {
+    /// Drop to this brightness
+    Dim(u8),
+    /// Put the module to sleep, which turns every LED off
+    Sleep,
+}
+
+/// Dims or sleeps a `Display` that hasn't shown anything new for a while,
+/// so a dashboard left up overnight isn't burning the panel at full
+/// brightness, and puts it back as soon as a new frame arrives. Give one
+/// to `Display::set_idle_dimmer()` and call `Display::check_idle()` from
+/// the main loop:
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use f16_hid::idle::{IdleAction, IdleDimmer};
+/// use f16_hid::Display;
+///
+/// let mut display = Display::open_default()?;
+/// display.set_idle_dimmer(Some(IdleDimmer::new(Duration::from_secs(600), IdleAction::Dim(0x08))));
+///
+/// loop {
+///     // Draw and present whenever there's something new
+///     display.check_idle()?;
+///     std::thread::sleep(Duration::from_secs(1));
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// Presenting a frame that's the same as the one showing doesn't count as
+/// activity, so redrawing on a timer still lets the panel dim. Call
+/// `Display::wake()` for activity of other kinds, like a key press.
+#[derive(Clone, Debug)]
+pub struct IdleDimmer {
+    timeout: Duration,
+    action: IdleAction,
+    /// When something last changed, `None` until the display first checks
+    pub(crate) last_active: Option<Instant>,
+    /// The brightness to go back to, while idle
+    pub(crate) restore: Option<u8>,
+}
+
+impl IdleDimmer {
+    pub fn new(timeout: Duration, action: IdleAction) -> Self {
+        Self {
+            timeout,
+            action,
+            last_active: None,
+            restore: None,
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn action(&self) -> IdleAction {
+        self.action
+    }
+
+    /// Whether the panel is dimmed or asleep right now
+    pub fn is_idle(&self) -> bool {
+        self.restore.is_some()
+    }
+
+    /// Whether it's time to go idle at `now`
+    pub(crate) fn due(&mut self, now: Instant) -> bool {
+        let last_active = *self.last_active.get_or_insert(now);
+
+        !self.is_idle() && now.saturating_duration_since(last_active) >= self.timeout
+    }
+}
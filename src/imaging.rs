@@ -0,0 +1,307 @@
+//! Loading pictures onto the panel. Images are resized to 9x34, turned grey
+//! and optionally dithered down to fewer brightness levels, which looks much
+//! better than banding on an LED matrix. Animated GIFs come in as an
+//! `Animation`.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek};
+use std::path::Path;
+use std::time::Duration;
+
+use image::codecs::gif::GifDecoder;
+use image::imageops::{self, FilterType};
+use image::{AnimationDecoder, DynamicImage, GrayImage, ImageError, Luma};
+
+use crate::animation::Animation;
+use crate::{Bitmap8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// GIF frames with a shorter delay than this play at `DEFAULT_GIF_DELAY`
+/// instead, as browsers do. Plenty of GIFs say zero and mean "the default".
+pub const MIN_GIF_DELAY: Duration = Duration::from_millis(20);
+pub const DEFAULT_GIF_DELAY: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dither {
+    /// Round each pixel to the nearest level
+    #[default]
+    None,
+    /// 4x4 Bayer pattern. Stable between frames, so good for animation.
+    Ordered,
+    /// Error diffusion. Smoother for still images.
+    FloydSteinberg,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Fit {
+    /// Squash or stretch to fill the panel
+    #[default]
+    Stretch,
+    /// Keep the aspect ratio and centre it, leaving the rest black
+    Contain,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ImageOptions {
+    pub filter: FilterType,
+    pub fit: Fit,
+    pub dither: Dither,
+    /// Brightness levels to reduce to, from 2 (on and off) to 256 (no
+    /// reduction)
+    pub levels: u16,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            filter: FilterType::Triangle,
+            fit: Fit::Stretch,
+            dither: Dither::None,
+            levels: 256,
+        }
+    }
+}
+
+impl Bitmap8 {
+    /// Convert an image for the panel
+    pub fn from_image(image: &DynamicImage, options: &ImageOptions) -> Bitmap8 {
+        let grey = image.to_luma8();
+        let resized = resize(&grey, options);
+
+        let mut values = [0.0f32; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        for (x, y, pixel) in resized.enumerate_pixels() {
+            values[y as usize * DISPLAY_WIDTH + x as usize] = pixel.0[0] as f32;
+        }
+
+        let mut bitmap = Bitmap8::new();
+        let quantised = quantise(&mut values, options.levels, options.dither);
+
+        for y in 0 .. DISPLAY_HEIGHT {
+            for x in 0 .. DISPLAY_WIDTH {
+                bitmap.data[x * DISPLAY_HEIGHT + y] = quantised[y * DISPLAY_WIDTH + x];
+            }
+        }
+
+        bitmap
+    }
+
+    /// Load and convert a PNG or BMP file
+    pub fn open_image(path: impl AsRef<Path>, options: &ImageOptions) -> Result<Bitmap8, ImageError> {
+        let image = image::open(path)?;
+
+        Ok(Self::from_image(&image, options))
+    }
+}
+
+impl Animation {
+    /// Every frame of an animated GIF, each shown for as long as the GIF
+    /// says:
+    ///
+    /// ```no_run
+    /// # use f16_hid::{animation::{Animator, PlayMode}, LedMatrix};
+    /// # let mut matrix = LedMatrix::new("/dev/ttyACM0")?;
+    /// let animation = f16_hid::animation::Animation::from_gif("nyan.gif")?;
+    /// Animator::new(animation, PlayMode::Loop).play(&mut matrix)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_gif(path: impl AsRef<Path>) -> Result<Animation, ImageError> {
+        Self::from_gif_with(path, &ImageOptions { dither: Dither::Ordered, ..ImageOptions::default() })
+    }
+
+    /// Like `from_gif()`, scaling and dithering each frame as asked.
+    /// Ordered dithering doesn't crawl from one frame to the next the way
+    /// error diffusion does.
+    pub fn from_gif_with(path: impl AsRef<Path>, options: &ImageOptions) -> Result<Animation, ImageError> {
+        Self::read_gif(BufReader::new(File::open(path)?), options)
+    }
+
+    /// Decode a GIF from anywhere it can be read
+    pub fn read_gif<R: BufRead + Seek>(reader: R, options: &ImageOptions) -> Result<Animation, ImageError> {
+        let mut animation = Animation::new();
+
+        for frame in GifDecoder::new(reader)?.into_frames() {
+            let frame = frame?;
+
+            let (numerator, denominator) = frame.delay().numer_denom_ms();
+            let delay = Duration::from_secs_f64(numerator as f64 / denominator.max(1) as f64 / 1000.0);
+            let delay = if delay < MIN_GIF_DELAY { DEFAULT_GIF_DELAY } else { delay };
+
+            // Transparent parts are black, which is what they'd be on the
+            // panel anyway
+            let mut grey = DynamicImage::ImageRgba8(frame.into_buffer()).to_luma_alpha8();
+            for pixel in grey.pixels_mut() {
+                pixel.0[0] = (pixel.0[0] as u16 * pixel.0[1] as u16 / 255) as u8;
+                pixel.0[1] = 255;
+            }
+
+            animation.push(Bitmap8::from_image(&DynamicImage::ImageLumaA8(grey), options), delay);
+        }
+
+        Ok(animation)
+    }
+}
+
+fn resize(grey: &GrayImage, options: &ImageOptions) -> GrayImage {
+    let (width, height) = (DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32);
+
+    match options.fit {
+        Fit::Stretch => imageops::resize(grey, width, height, options.filter),
+        Fit::Contain => {
+            let scale = f32::min(
+                width as f32 / grey.width().max(1) as f32,
+                height as f32 / grey.height().max(1) as f32,
+            );
+            let scaled_width = ((grey.width() as f32 * scale).round() as u32).clamp(1, width);
+            let scaled_height = ((grey.height() as f32 * scale).round() as u32).clamp(1, height);
+
+            let scaled = imageops::resize(grey, scaled_width, scaled_height, options.filter);
+            let mut canvas = GrayImage::from_pixel(width, height, Luma([0]));
+
+            imageops::overlay(
+                &mut canvas,
+                &scaled,
+                ((width - scaled_width) / 2) as i64,
+                ((height - scaled_height) / 2) as i64,
+            );
+
+            canvas
+        },
+    }
+}
+
+const BAYER: [[f32; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
+
+/// Reduce a row-major panel-sized image to `levels` evenly spaced values
+fn quantise(values: &mut [f32; DISPLAY_WIDTH * DISPLAY_HEIGHT], levels: u16, dither: Dither) -> [u8; DISPLAY_WIDTH * DISPLAY_HEIGHT] {
+    let steps = levels.clamp(2, 256) as f32 - 1.0;
+    let step = 255.0 / steps;
+    let nearest = |value: f32| (value.clamp(0.0, 255.0) / step).round() * step;
+
+    let mut output = [0u8; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+
+    for y in 0 .. DISPLAY_HEIGHT {
+        for x in 0 .. DISPLAY_WIDTH {
+            let index = y * DISPLAY_WIDTH + x;
+
+            let value = match dither {
+                Dither::None => nearest(values[index]),
+                Dither::Ordered => {
+                    // Threshold offset of up to half a step either way
+                    let offset = (BAYER[y % 4][x % 4] + 0.5) / 16.0 - 0.5;
+                    nearest(values[index] + offset * step)
+                },
+                Dither::FloydSteinberg => {
+                    let old = values[index];
+                    let new = nearest(old);
+                    let error = old - new;
+
+                    let mut spread = |dx: isize, dy: usize, weight: f32| {
+                        let nx = x as isize + dx;
+                        let ny = y + dy;
+
+                        if nx >= 0 && (nx as usize) < DISPLAY_WIDTH && ny < DISPLAY_HEIGHT {
+                            values[ny * DISPLAY_WIDTH + nx as usize] += error * weight;
+                        }
+                    };
+
+                    spread(1, 0, 7.0 / 16.0);
+                    spread(-1, 1, 3.0 / 16.0);
+                    spread(0, 1, 5.0 / 16.0);
+                    spread(1, 1, 1.0 / 16.0);
+
+                    new
+                },
+            };
+
+            output[index] = value.round() as u8;
+        }
+    }
+
+    output
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageBuffer;
+
+    fn flat(value: f32) -> [f32; DISPLAY_WIDTH * DISPLAY_HEIGHT] {
+        [value; DISPLAY_WIDTH * DISPLAY_HEIGHT]
+    }
+
+    fn mean(values: &[u8]) -> f32 {
+        values.iter().map(|x| *x as f32).sum::<f32>() / values.len() as f32
+    }
+
+    #[test]
+    fn two_levels_without_dither_rounds() {
+        let output = quantise(&mut flat(100.0), 2, Dither::None);
+
+        assert!(output.iter().all(|x| *x == 0));
+    }
+
+    #[test]
+    fn dithering_keeps_average_brightness() {
+        for dither in [Dither::Ordered, Dither::FloydSteinberg] {
+            let output = quantise(&mut flat(64.0), 2, dither);
+
+            assert!(output.iter().all(|x| *x == 0 || *x == 255));
+            assert!((mean(&output) - 64.0).abs() < 16.0, "{:?} averaged {}", dither, mean(&output));
+        }
+    }
+
+    #[test]
+    fn image_is_column_major() {
+        // White left half, black right half
+        let image = ImageBuffer::from_fn(90, 340, |x, _| Luma([if x < 40 { 255u8 } else { 0 }]));
+        let options = ImageOptions { filter: FilterType::Nearest, ..ImageOptions::default() };
+
+        let bitmap = Bitmap8::from_image(&DynamicImage::ImageLuma8(image), &options);
+
+        assert_eq!(bitmap.data()[0], 255);
+        assert_eq!(bitmap.data()[8 * DISPLAY_HEIGHT + 33], 0);
+    }
+
+    #[test]
+    fn gif_frames_keep_their_delays() {
+        use image::codecs::gif::GifEncoder;
+        use image::{Delay, Frame, Rgba, RgbaImage};
+
+        let mut gif = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut gif);
+            let white = RgbaImage::from_pixel(9, 34, Rgba([255, 255, 255, 255]));
+            let clear = RgbaImage::from_pixel(9, 34, Rgba([255, 255, 255, 0]));
+
+            encoder.encode_frame(Frame::from_parts(white, 0, 0, Delay::from_numer_denom_ms(250, 1))).unwrap();
+            encoder.encode_frame(Frame::from_parts(clear, 0, 0, Delay::from_numer_denom_ms(0, 1))).unwrap();
+        }
+
+        let options = ImageOptions { filter: FilterType::Nearest, ..ImageOptions::default() };
+        let animation = Animation::read_gif(std::io::Cursor::new(gif), &options).unwrap();
+        let frames = animation.frames();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].duration, Duration::from_millis(250));
+        assert!(frames[0].bitmap.data().iter().all(|x| *x == 255));
+        assert_eq!(frames[1].duration, DEFAULT_GIF_DELAY);
+        assert!(frames[1].bitmap.data().iter().all(|x| *x == 0));
+    }
+
+    #[test]
+    fn contain_letterboxes() {
+        let image = GrayImage::from_pixel(10, 10, Luma([255]));
+        let options = ImageOptions { fit: Fit::Contain, ..ImageOptions::default() };
+
+        let bitmap = Bitmap8::from_image(&DynamicImage::ImageLuma8(image), &options);
+
+        // 9x9 square in the middle of the panel
+        assert_eq!(bitmap.data()[0], 0);
+        assert_eq!(bitmap.data()[DISPLAY_HEIGHT / 2], 255);
+    }
+}
@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+use crate::response::{FirmwareVersion, Response};
+use crate::PwmFrequency;
+
+/// Everything the firmware will tell us about its state, gathered in one go.
+/// Fields are `None` when the firmware didn't answer, which older versions
+/// won't for some of these.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub version: Option<FirmwareVersion>,
+    pub brightness: Option<u8>,
+    pub sleeping: Option<bool>,
+    pub animating: Option<bool>,
+    pub animation_period: Option<Duration>,
+    pub pwm_freq: Option<PwmFrequency>,
+}
+
+impl DeviceInfo {
+    /// Fill in whichever field a response is for
+    pub(crate) fn update(&mut self, response: Response) {
+        match response {
+            Response::Version(x) => self.version = Some(x),
+            Response::Brightness(x) => self.brightness = Some(x),
+            Response::Sleeping(x) => self.sleeping = Some(x),
+            Response::Animating(x) => self.animating = Some(x),
+            Response::AnimationPeriod(x) => self.animation_period = Some(x),
+            Response::PwmFrequency(x) => self.pwm_freq = Some(x),
+        }
+    }
+}
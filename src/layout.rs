@@ -0,0 +1,555 @@
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::geometry::{Point, Rect};
+use crate::widgets::Widget;
+use crate::{Bitmap8, Command, LedMatrix, DISPLAY_WIDTH};
+
+/// Columns changed by a render pass
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Damage {
+    pub columns: [bool; DISPLAY_WIDTH],
+}
+
+impl Damage {
+    pub fn all() -> Self {
+        Self {
+            columns: [true; DISPLAY_WIDTH],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.columns.iter().any(|x| *x)
+    }
+
+    pub fn count(&self) -> usize {
+        self.columns.iter().filter(|x| **x).count()
+    }
+
+    pub fn add(&mut self, area: Rect) {
+        for column in &mut self.columns[area.columns()] {
+            *column = true;
+        }
+    }
+
+    /// Columns that differ between two frames
+    pub fn between(before: &Bitmap8, after: &Bitmap8) -> Self {
+        let mut damage = Self::default();
+
+        for (column, (before, after)) in damage.columns.iter_mut().zip(before.columns().zip(after.columns())) {
+            *column = before != after;
+        }
+
+        damage
+    }
+}
+
+/// Widget updates due this close together are run in the same render
+pub const DEFAULT_COALESCE_WINDOW: Duration = Duration::from_millis(20);
+
+struct Cell {
+    name: Option<String>,
+    area: Rect,
+    /// Higher is drawn later, over anything lower that it overlaps
+    z: i32,
+    widget: Box<dyn Widget>,
+    /// When the widget's next `update()` is due, if it has an interval
+    next_update: Option<Instant>,
+}
+
+/// Widgets placed in fixed areas of the panel. Only widgets that report
+/// themselves dirty are drawn again, and only the columns they cover are sent
+/// to the device.
+///
+/// Widgets with an `update_interval()` are updated on their own schedule
+/// by `render()`. Updates that fall due within the coalesce window of each
+/// other share one render, and `run()` sleeps until the next one rather
+/// than going at the pace of the fastest widget.
+///
+/// Regions can be named, to move, restack or remove them later, and given a
+/// z order for widgets that overlap. Whenever a widget is redrawn so is
+/// everything it overlaps, lowest first, so nothing underneath shows
+/// through that shouldn't and nothing on top is lost.
+///
+/// However many widgets change in a tick, `present()` draws them all into
+/// one frame before sending anything, then stages the changed columns and
+/// draws them with a single `DrawBuffer`. The panel never shows some
+/// widgets updated and others not.
+pub struct Layout<C: Clock = SystemClock> {
+    cells: Vec<Cell>,
+    background: u8,
+    frame: Bitmap8,
+    first_render: bool,
+    clock: C,
+    coalesce_window: Duration,
+}
+
+impl Layout<SystemClock> {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl<C: Clock> Layout<C> {
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            cells: Vec::new(),
+            background: 0,
+            frame: Bitmap8::new(),
+            first_render: true,
+            clock,
+            coalesce_window: DEFAULT_COALESCE_WINDOW,
+        }
+    }
+
+    /// Run widget updates that are due within `window` of each other in the
+    /// same render, rather than sending a frame for each
+    pub fn set_coalesce_window(&mut self, window: Duration) {
+        self.coalesce_window = window;
+    }
+
+    pub fn coalesce_window(&self) -> Duration {
+        self.coalesce_window
+    }
+
+    /// Value every cell is cleared to before its widget draws
+    pub fn set_background(&mut self, value: u8) {
+        self.background = value;
+        self.first_render = true;
+    }
+
+    pub fn add<W: Widget + 'static>(&mut self, area: Rect, widget: W) {
+        self.insert(None, area, widget);
+    }
+
+    /// Add a widget that can be found again by name, replacing any region
+    /// already called that
+    pub fn add_named<W: Widget + 'static>(&mut self, name: &str, area: Rect, widget: W) {
+        self.remove(name);
+        self.insert(Some(name.to_owned()), area, widget);
+    }
+
+    fn insert<W: Widget + 'static>(&mut self, name: Option<String>, area: Rect, widget: W) {
+        // Updated straight away so there's something to draw
+        let next_update = widget.update_interval().map(|_| self.clock.now());
+
+        self.cells.push(Cell {
+            name,
+            area,
+            z: 0,
+            widget: Box::new(widget),
+            next_update,
+        });
+        self.first_render = true;
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn Widget>> {
+        let index = self.find(name)?;
+        self.first_render = true;
+
+        Some(self.cells.remove(index).widget)
+    }
+
+    /// Where a named region is
+    pub fn area(&self, name: &str) -> Option<Rect> {
+        self.find(name).map(|x| self.cells[x].area)
+    }
+
+    /// Move a named region. Returns false if there isn't one.
+    pub fn set_area(&mut self, name: &str, area: Rect) -> bool {
+        match self.find(name) {
+            Some(index) => {
+                self.cells[index].area = area;
+                self.first_render = true;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Restack a named region. Regions start at 0 and ones with the same z
+    /// are drawn in the order they were added. Returns false if there isn't
+    /// one.
+    pub fn set_z(&mut self, name: &str, z: i32) -> bool {
+        match self.find(name) {
+            Some(index) => {
+                self.cells[index].z = z;
+                self.cells.sort_by_key(|x| x.z);
+                self.first_render = true;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Names of the named regions, bottom to top
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.cells.iter().filter_map(|x| x.name.as_deref())
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.cells.iter().position(|x| x.name.as_deref() == Some(name))
+    }
+
+    /// Draw and send everything with the next render, such as after the
+    /// matrix was reconnected and may have lost what was on it
+    pub fn invalidate(&mut self) {
+        self.first_render = true;
+    }
+
+    /// The frame as of the last render
+    pub fn frame(&self) -> &Bitmap8 {
+        &self.frame
+    }
+
+    /// How long until the next widget update is due. `None` if no widget
+    /// has an interval.
+    pub fn time_to_next_update(&self) -> Option<Duration> {
+        let now = self.clock.now();
+
+        self.cells.iter()
+            .filter_map(|x| x.next_update)
+            .min()
+            .map(|x| x.saturating_duration_since(now))
+    }
+
+    /// Call `update()` on every widget that's due, or nearly
+    fn update_due(&mut self) {
+        let now = self.clock.now();
+        let horizon = now + self.coalesce_window;
+
+        for cell in self.cells.iter_mut() {
+            let (due, interval) = match (cell.next_update, cell.widget.update_interval()) {
+                (Some(due), Some(interval)) => (due, interval),
+                (_, interval) => {
+                    // The interval can change, or go away, between updates
+                    cell.next_update = interval.map(|x| now + x);
+                    continue;
+                },
+            };
+
+            if horizon < due {
+                continue;
+            }
+
+            cell.widget.update();
+
+            // Keep to the widget's cadence, unless it's fallen a whole
+            // interval behind
+            let next = due + interval;
+            cell.next_update = Some(if next > now { next } else { now + interval });
+        }
+    }
+
+    /// Update the widgets that are due, draw every dirty widget and report
+    /// which columns changed. The first render after the layout changes
+    /// draws everything.
+    pub fn render(&mut self) -> Damage {
+        self.update_due();
+
+        let mut damage = Damage::default();
+
+        if self.first_render {
+            self.frame.fill(self.background);
+            damage = Damage::all();
+        }
+
+        // Always ask, so widgets consume their change notifications
+        let mut dirty: Vec<bool> = self.cells.iter_mut()
+            .map(|x| x.widget.is_dirty() || self.first_render)
+            .collect();
+
+        // Redrawing a region means redrawing whatever overlaps it, and
+        // whatever overlaps those
+        let mut spreading = true;
+
+        while spreading {
+            spreading = false;
+
+            for i in 0 .. self.cells.len() {
+                for j in 0 .. self.cells.len() {
+                    if dirty[i] && !dirty[j] && !self.cells[i].area.intersection(&self.cells[j].area).is_empty() {
+                        dirty[j] = true;
+                        spreading = true;
+                    }
+                }
+            }
+        }
+
+        // Clear everything first so a region cleared late can't wipe out
+        // one below it that was already drawn
+        for (cell, _) in self.cells.iter().zip(&dirty).filter(|x| *x.1) {
+            self.frame.fill_rect(cell.area, self.background);
+            damage.add(cell.area);
+        }
+
+        for (cell, _) in self.cells.iter_mut().zip(&dirty).filter(|x| *x.1) {
+            cell.widget.render(&mut self.frame, cell.area.clipped());
+        }
+
+        self.first_render = false;
+
+        damage
+    }
+
+    /// Render and send only the damaged columns. Nothing is sent at all when
+    /// no widget changed.
+    pub fn present(&mut self, matrix: &mut LedMatrix) -> Result<Damage, std::io::Error> {
+        let mut damage = self.render();
+
+        if damage.is_empty() {
+            return Ok(damage);
+        }
+
+        // Filters that move pixels around could put a change anywhere
+        if matrix.filters().moves_pixels() {
+            damage = Damage::all();
+        }
+
+        let frame = matrix.filtered(&self.frame);
+
+        for (x, dirty) in damage.columns.iter().enumerate() {
+            if *dirty {
+                matrix.stage_column(&frame, x)?;
+            }
+        }
+
+        matrix.execute(Command::DrawBuffer)?;
+
+        Ok(damage)
+    }
+
+    /// Present forever, sleeping until the next widget update is due in
+    /// between. Widgets without an interval are checked every `idle`. Only
+    /// returns if sending fails.
+    pub fn run(&mut self, matrix: &mut LedMatrix, idle: Duration) -> std::io::Error {
+        loop {
+            if let Err(error) = self.present(matrix) {
+                return error;
+            }
+
+            let wait = self.time_to_next_update().map_or(idle, |x| x.min(idle));
+            self.clock.sleep(wait);
+        }
+    }
+}
+
+impl Default for Layout<SystemClock> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Splits an area into evenly sized rows and columns, to place a layout's
+/// widgets without working out coordinates by hand. Cells that don't divide
+/// evenly differ by a pixel at most.
+///
+/// ```
+/// use f16_hid::geometry::Rect;
+/// use f16_hid::layout::Grid;
+///
+/// // The clock across the top, CPU and battery side by side below it
+/// let grid = Grid::new(Rect::display(), 2, 3);
+/// let clock = grid.span(0, 0, 2, 1);
+/// let cpu = grid.span(0, 1, 1, 2);
+/// let battery = grid.span(1, 1, 1, 2);
+///
+/// assert_eq!(clock.size.height + cpu.size.height, 34);
+/// assert_eq!(cpu.size.width + battery.size.width, 9);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Grid {
+    area: Rect,
+    columns: usize,
+    rows: usize,
+}
+
+impl Grid {
+    pub fn new(area: Rect, columns: usize, rows: usize) -> Self {
+        Self {
+            area,
+            columns: columns.max(1),
+            rows: rows.max(1),
+        }
+    }
+
+    pub fn cell(&self, column: usize, row: usize) -> Rect {
+        self.span(column, row, 1, 1)
+    }
+
+    /// Several cells merged, `columns` across and `rows` down from the one
+    /// given. Clipped to the grid.
+    pub fn span(&self, column: usize, row: usize, columns: usize, rows: usize) -> Rect {
+        let x = |index: usize| self.area.left() + (index.min(self.columns) * self.area.size.width / self.columns) as i32;
+        let y = |index: usize| self.area.top() + (index.min(self.rows) * self.area.size.height / self.rows) as i32;
+
+        Rect::from_corners(
+            Point::new(x(column), y(row)),
+            Point::new(x(column + columns), y(row + rows)),
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binding::{Value, Watch};
+
+    struct Level {
+        watch: Watch<u8>,
+    }
+
+    impl Widget for Level {
+        fn render(&mut self, canvas: &mut Bitmap8, area: Rect) {
+            canvas.fill_rect(Rect::new(area.origin, (area.size.width, self.watch.get() as usize)), 0xff);
+        }
+
+        fn is_dirty(&mut self) -> bool {
+            self.watch.changed()
+        }
+    }
+
+    #[test]
+    fn only_changed_widgets_are_redrawn() {
+        let left = Value::new(3u8);
+        let right = Value::new(5u8);
+        let mut layout = Layout::new();
+
+        layout.add(Rect::new((0, 0), (2, 10)), Level { watch: left.watch() });
+        layout.add(Rect::new((6, 0), (3, 10)), Level { watch: right.watch() });
+
+        assert_eq!(layout.render(), Damage::all());
+        assert!(layout.render().is_empty());
+
+        right.set(7);
+        let damage = layout.render();
+
+        assert_eq!(damage.count(), 3);
+        assert!(damage.columns[6] && damage.columns[8] && !damage.columns[0]);
+        assert_eq!(layout.frame().column(6).unwrap()[6], 0xff);
+        assert_eq!(layout.frame().column(6).unwrap()[7], 0);
+    }
+
+    /// Counts its updates and shows the count as a bar
+    struct Ticker {
+        interval: Duration,
+        updates: usize,
+        dirty: bool,
+    }
+
+    impl Widget for Ticker {
+        fn render(&mut self, canvas: &mut Bitmap8, area: Rect) {
+            canvas.fill_rect(Rect::new(area.origin, (area.size.width, self.updates)), 0xff);
+        }
+
+        fn is_dirty(&mut self) -> bool {
+            std::mem::take(&mut self.dirty)
+        }
+
+        fn update_interval(&self) -> Option<Duration> {
+            Some(self.interval)
+        }
+
+        fn update(&mut self) {
+            self.updates += 1;
+            self.dirty = true;
+        }
+    }
+
+    #[test]
+    fn widgets_update_on_their_own_schedule() {
+        let clock = crate::clock::ManualClock::new();
+        let mut layout = Layout::with_clock(clock.clone());
+
+        let ticker = |millis| Ticker { interval: Duration::from_millis(millis), updates: 0, dirty: false };
+        layout.add(Rect::new((0, 0), (1, 34)), ticker(500));
+        layout.add(Rect::new((8, 0), (1, 34)), ticker(1000));
+
+        layout.render();
+        assert_eq!(layout.time_to_next_update(), Some(Duration::from_millis(500)));
+
+        // Only the faster one is due
+        clock.advance(Duration::from_millis(500));
+        let damage = layout.render();
+        assert!(damage.columns[0] && !damage.columns[8]);
+
+        // Both fall due together and share a render
+        clock.advance(Duration::from_millis(500));
+        let damage = layout.render();
+        assert!(damage.columns[0] && damage.columns[8]);
+        assert_eq!(layout.frame().data()[2], 0xff);
+        assert_eq!(layout.frame().column(8).unwrap()[1], 0xff);
+        assert_eq!(layout.frame().column(8).unwrap()[2], 0);
+
+        assert!(layout.render().is_empty());
+
+        // Nearly due together is close enough
+        let mut layout = Layout::with_clock(clock.clone());
+        layout.add(Rect::new((0, 0), (1, 34)), ticker(1000));
+        layout.add(Rect::new((8, 0), (1, 34)), ticker(1010));
+        layout.render();
+
+        clock.advance(Duration::from_millis(1000));
+        assert_eq!(layout.render().count(), 2);
+        assert_eq!(layout.time_to_next_update(), Some(Duration::from_millis(1000)));
+    }
+
+    #[test]
+    fn overlapping_regions_redraw_in_order() {
+        let under = Value::new(10u8);
+        let over = Value::new(2u8);
+        let mut layout = Layout::new();
+
+        layout.add_named("over", Rect::new((0, 0), (2, 34)), Level { watch: over.watch() });
+        layout.add_named("under", Rect::new((0, 0), (9, 34)), Level { watch: under.watch() });
+        assert!(layout.set_z("over", 1));
+        assert_eq!(layout.names().collect::<Vec<_>>(), ["under", "over"]);
+        layout.render();
+
+        // Only the one underneath changed, yet both are drawn again
+        under.set(1);
+        assert_eq!(layout.render(), Damage::all());
+        assert_eq!(layout.frame().column(8).unwrap()[1], 0);
+        assert_eq!(layout.frame().data()[1], 0xff);
+
+        assert!(layout.remove("over").is_some());
+        assert_eq!(layout.area("under"), Some(Rect::new((0, 0), (9, 34))));
+        assert!(!layout.set_area("over", Rect::display()));
+        layout.render();
+        assert_eq!(layout.frame().data()[1], 0);
+    }
+
+    #[test]
+    fn grid_cells_tile_the_area() {
+        let grid = Grid::new(Rect::display(), 2, 3);
+
+        assert_eq!(grid.cell(0, 0), Rect::new((0, 0), (4, 11)));
+        assert_eq!(grid.cell(1, 2), Rect::new((4, 22), (5, 12)));
+        assert_eq!(grid.span(0, 0, 5, 5), Rect::display());
+    }
+
+    #[test]
+    fn damage_between_frames() {
+        let before = Bitmap8::new();
+        let mut after = before.clone();
+
+        assert!(Damage::between(&before, &after).is_empty());
+
+        after.fill_rect(Rect::new((3, 33), (2, 1)), 1);
+        let damage = Damage::between(&before, &after);
+
+        assert_eq!(damage.count(), 2);
+        assert!(damage.columns[3] && damage.columns[4]);
+    }
+
+    #[test]
+    fn fill_clips_to_panel() {
+        let mut canvas = Bitmap8::new();
+
+        canvas.fill_rect(Rect::new((7, 30), (5, 10)), 1);
+
+        assert_eq!(canvas.column(8).unwrap()[33], 1);
+        assert_eq!(canvas.data().iter().filter(|x| **x == 1).count(), 2 * 4);
+    }
+}
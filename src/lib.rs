@@ -1,33 +1,268 @@
-use std::time::Duration;
-use serialport::SerialPort;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::time::Duration;
+
+#[cfg(feature = "tokio-serial")]
+pub mod async_matrix;
+#[cfg(feature = "std")]
+pub mod animation;
+#[cfg(feature = "audio")]
+pub mod audio;
+#[cfg(feature = "std")]
+pub mod barcode;
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod binding;
+#[cfg(feature = "std")]
+pub mod bootloader;
+#[cfg(feature = "std")]
+pub mod budget;
+#[cfg(feature = "std")]
+pub mod builder;
+#[cfg(feature = "std")]
+pub mod burn_in;
+#[cfg(feature = "std")]
+pub mod calibration;
+pub mod canvas;
+pub mod capabilities;
+#[cfg(feature = "std")]
+pub mod clock;
+#[cfg(feature = "std")]
+pub mod config;
+#[cfg(feature = "std")]
+pub mod connection;
+#[cfg(feature = "std")]
+pub mod console;
+#[cfg(feature = "std")]
+pub mod current;
+#[cfg(feature = "dashboards")]
+pub mod dashboards;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+pub mod discovery;
+#[cfg(feature = "std")]
+pub mod display;
+#[cfg(feature = "std")]
+pub mod events;
+#[cfg(feature = "std")]
+pub mod fade;
+#[cfg(feature = "std")]
+pub mod filter;
+#[cfg(feature = "games")]
+pub mod games;
+#[cfg(feature = "std")]
+pub mod gamma;
+#[cfg(feature = "std")]
+pub mod generators;
+pub mod geometry;
+#[cfg(feature = "embedded-graphics")]
+pub mod graphics;
+#[cfg(feature = "std")]
+pub mod group;
+#[cfg(feature = "hidapi")]
+pub mod hid;
+#[cfg(feature = "std")]
+pub mod hotplug;
+#[cfg(feature = "image")]
+pub mod imaging;
+#[cfg(feature = "std")]
+pub mod idle;
+#[cfg(feature = "std")]
+pub mod info;
+#[cfg(feature = "std")]
+pub mod layout;
+#[cfg(all(target_os = "linux", feature = "display-power"))]
+pub mod display_power;
+#[cfg(feature = "std")]
+pub mod manager;
+#[cfg(feature = "std")]
+pub mod marquee;
+#[cfg(feature = "std")]
+pub mod monitor;
+#[cfg(feature = "std")]
+pub mod overlay;
+#[cfg(feature = "std")]
+pub mod pacer;
+#[cfg(all(target_os = "linux", feature = "power"))]
+pub mod power;
+#[cfg(feature = "std")]
+pub mod pair;
+#[cfg(feature = "std")]
+pub mod platform;
+#[cfg(feature = "std")]
+pub mod prelude;
+#[cfg(feature = "std")]
+pub mod preview;
+#[cfg(feature = "std")]
+pub mod progress;
+#[cfg(feature = "std")]
+pub mod random;
+#[cfg(feature = "std")]
+pub mod reconnect;
+#[cfg(feature = "std")]
+pub mod recorder;
+#[cfg(feature = "std")]
+pub mod remap;
+pub mod response;
+#[cfg(feature = "std")]
+pub mod roles;
+#[cfg(feature = "scenes")]
+pub mod scene;
+#[cfg(feature = "std")]
+pub mod self_test;
+#[cfg(feature = "std")]
+pub mod sender;
+#[cfg(feature = "serde")]
+mod serde_impls;
+#[cfg(feature = "std")]
+pub mod setup;
+#[cfg(feature = "std")]
+pub mod shapes;
+#[cfg(feature = "std")]
+pub mod shared;
+#[cfg(feature = "std")]
+pub mod simulator;
+#[cfg(feature = "std")]
+pub mod stereo;
+#[cfg(feature = "std")]
+pub mod stream;
+pub mod text;
+#[cfg(feature = "std")]
+pub mod throttle;
+#[cfg(feature = "std")]
+pub mod toast;
+#[cfg(feature = "std")]
+mod trace;
+#[cfg(feature = "std")]
+pub mod transport;
+#[cfg(feature = "std")]
+pub mod units;
+#[cfg(feature = "std")]
+pub mod verify;
+#[cfg(feature = "std")]
+pub mod wear;
+#[cfg(feature = "std")]
+pub mod widgets;
+
+#[cfg(feature = "std")]
+pub use batch::CommandBatch;
+#[cfg(feature = "std")]
+pub use builder::LedMatrixBuilder;
+#[cfg(feature = "tokio-serial")]
+pub use async_matrix::AsyncLedMatrix;
+#[cfg(feature = "std")]
+pub use display::Display;
+use capabilities::CommandKind;
+use geometry::{EdgeMode, Point, Rect};
+#[cfg(feature = "std")]
+use crate::{
+    budget::PerformanceBudget,
+    capabilities::{Capabilities, UnsupportedPolicy},
+    clock::{Clock, SharedClock},
+    connection::{Connection, ConnectionState, ConnectionWatch},
+    events::Events,
+    filter::Pipeline,
+    gamma::GammaMap,
+    info::DeviceInfo,
+    reconnect::{ReconnectPolicy, RetriesExhausted},
+    recorder::Recorder,
+    remap::Remap,
+    response::{Response, RESPONSE_LENGTH},
+    throttle::Throttle,
+    transport::Transport,
+    verify::Verification,
+};
 
 pub const DRAW_COMMAND_LENGTH: usize = 39;
 pub const MAX_COMMAND_LENGTH: usize = 42;
+/// Room left for a `Command::Raw` payload after the header and command ID
+pub const MAX_RAW_PAYLOAD: usize = MAX_COMMAND_LENGTH - 3;
+/// Fullest `Patterns::Percentage` the firmware draws
+pub const MAX_PERCENTAGE: u8 = 100;
 pub const DISPLAY_WIDTH: usize = 9;
 pub const DISPLAY_HEIGHT: usize = 34;
 
+/// Default read and write timeout, see `LedMatrixBuilder::write_timeout()`
 pub const CONNECT_DELAY: Duration = Duration::from_millis(100);
+/// Default wait before reconnecting, see `LedMatrixBuilder::reconnect_delay()`
 pub const RECONNECT_DELAY: Duration = Duration::from_millis(500);
 
+pub const DEFAULT_BAUD_RATE: u32 = 115_200;
+/// Rates tried by `LedMatrix::autodetect()`, most likely first. The module
+/// itself doesn't care over USB, but serial bridges and clones do.
+pub const PROBE_BAUD_RATES: [u32; 4] = [115_200, 230_400, 57_600, 9_600];
+/// How long to wait for the firmware to answer a probe unless the builder
+/// says otherwise
+pub const PROBE_TIMEOUT: Duration = Duration::from_millis(250);
+/// Most unsolicited bytes kept around before the oldest are thrown away
+pub const UNSOLICITED_BUFFER_LENGTH: usize = 4096;
+/// How many times a staged column that timed out is resent
+pub const DEFAULT_COLUMN_RETRIES: u32 = 2;
+/// What the firmware powers up at, about 20%
+pub const DEFAULT_BRIGHTNESS: u8 = 51;
+
 #[derive(Clone)]
 /// Bitmaps with 8 bits of definition. This is stored rotated 90 degress given
 /// that the staging commands are column based. Draw commands will automatically
 /// adjust this
 pub struct Bitmap8 {
-    pub(crate) data: [u8; DISPLAY_HEIGHT * DISPLAY_WIDTH]
+    pub(crate) data: [u8; DISPLAY_HEIGHT * DISPLAY_WIDTH],
+    edge_mode: EdgeMode,
 }
 
 impl Bitmap8 {
     pub fn new() -> Self {
         Self {
-            data: [0u8; DISPLAY_HEIGHT * DISPLAY_WIDTH]
+            data: [0u8; DISPLAY_HEIGHT * DISPLAY_WIDTH],
+            edge_mode: EdgeMode::Clip,
         }
     }
 
+    pub fn edge_mode(&self) -> EdgeMode {
+        self.edge_mode
+    }
+
+    /// How `pixel()`, `set_pixel()`, `fill_rect()`, blits, shapes and text
+    /// treat anything that goes off the panel. `draw_point()` and
+    /// `draw_box()` don't care.
+    pub fn set_edge_mode(&mut self, mode: EdgeMode) {
+        self.edge_mode = mode;
+    }
+
     pub fn data(&self) -> &[u8] {
         &self.data
     }
 
+    /// Each column top to bottom, left to right. That's how the pixels are
+    /// kept and how the firmware stages them, so it's free.
+    pub fn columns(&self) -> core::slice::Iter<'_, [u8; DISPLAY_HEIGHT]> {
+        self.data.as_chunks().0.iter()
+    }
+
+    pub fn columns_mut(&mut self) -> core::slice::IterMut<'_, [u8; DISPLAY_HEIGHT]> {
+        self.data.as_chunks_mut().0.iter_mut()
+    }
+
+    /// One column top to bottom, `None` if it's off the panel
+    pub fn column(&self, x: usize) -> Option<&[u8; DISPLAY_HEIGHT]> {
+        self.data.as_chunks().0.get(x)
+    }
+
+    pub fn column_mut(&mut self, x: usize) -> Option<&mut [u8; DISPLAY_HEIGHT]> {
+        self.data.as_chunks_mut().0.get_mut(x)
+    }
+
+    /// Each row left to right, top to bottom. Rows are copied out of the
+    /// columns as they go, so there's no `rows_mut()`.
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = [u8; DISPLAY_WIDTH]> + ExactSizeIterator + '_ {
+        (0 .. DISPLAY_HEIGHT).map(move |y| core::array::from_fn(|x| self.data[x * DISPLAY_HEIGHT + y]))
+    }
+
     pub fn fill(&mut self, value: u8) {
         self.data.fill(value)
     }
@@ -51,10 +286,11 @@ impl Bitmap8 {
         // "given an image buffer that's rotated 90 degress, create a function that takes x1, y1, x2, and y2 and a value as a u8 and draw a box" 
         // Then fiddled
 
+        // Anything off the panel is clipped
         let x_min = x1.min(x2);
-        let x_max = x1.max(x2);
+        let x_max = x1.max(x2).min(DISPLAY_WIDTH - 1);
         let y_min = y1.min(y2);
-        let y_max = y1.max(y2);
+        let y_max = y1.max(y2).min(DISPLAY_HEIGHT - 1);
     
         for y in y_min..=y_max {
             for x in x_min..=x_max {
@@ -64,6 +300,173 @@ impl Bitmap8 {
         }
     
     }
+
+    /// Value of a pixel, `None` if it's off the panel and the edge mode is
+    /// `Clip`
+    pub fn pixel(&self, point: Point) -> Option<u8> {
+        let (x, y) = self.edge_mode.resolve(point)?;
+
+        Some(self.data[x * DISPLAY_HEIGHT + y])
+    }
+
+    /// Set a pixel, following the edge mode for anything off the panel
+    pub fn set_pixel(&mut self, point: Point, value: u8) {
+        if let Some((x, y)) = self.edge_mode.resolve(point) {
+            self.data[x * DISPLAY_HEIGHT + y] = value;
+        }
+    }
+
+    /// Fill a rectangle, following the edge mode for any part off the panel
+    pub fn fill_rect(&mut self, area: Rect, value: u8) {
+        if area.is_empty() {
+            return;
+        }
+
+        let area = match self.edge_mode {
+            EdgeMode::Clip => area,
+            EdgeMode::Saturate => {
+                let (left, top) = EdgeMode::Saturate.resolve(area.origin).unwrap_or_default();
+                let (right, bottom) = EdgeMode::Saturate
+                    .resolve(Point::new(area.right() - 1, area.bottom() - 1))
+                    .unwrap_or_default();
+
+                Rect::from_corners(
+                    Point::new(left as i32, top as i32),
+                    Point::new(right as i32 + 1, bottom as i32 + 1),
+                )
+            },
+            EdgeMode::Wrap => {
+                // Anything wider or taller than the panel covers all of it
+                let width = area.size.width.min(DISPLAY_WIDTH) as i32;
+                let height = area.size.height.min(DISPLAY_HEIGHT) as i32;
+
+                for x in 0 .. width {
+                    for y in 0 .. height {
+                        self.set_pixel(area.origin + Point::new(x, y), value);
+                    }
+                }
+
+                return;
+            },
+        };
+
+        let rows = area.rows();
+
+        for x in area.columns() {
+            let start = x * DISPLAY_HEIGHT;
+            self.data[start + rows.start .. start + rows.end].fill(value);
+        }
+    }
+
+    /// Copy all of `other` onto this, its top left corner at `(x, y)`. Parts
+    /// that land off the panel follow the edge mode.
+    pub fn blit(&mut self, other: &Bitmap8, x: i32, y: i32) {
+        self.blit_where(other, Point::new(x, y), |_| true);
+    }
+
+    /// Like `blit()` but pixels of `other` set to `transparent` are skipped,
+    /// so sprites can have holes in them
+    pub fn blit_masked(&mut self, other: &Bitmap8, x: i32, y: i32, transparent: u8) {
+        self.blit_where(other, Point::new(x, y), |value| value != transparent);
+    }
+
+    fn blit_where(&mut self, other: &Bitmap8, offset: Point, keep: impl Fn(u8) -> bool) {
+        for x in 0 .. DISPLAY_WIDTH {
+            for y in 0 .. DISPLAY_HEIGHT {
+                let value = other.data[x * DISPLAY_HEIGHT + y];
+
+                if keep(value) {
+                    self.set_pixel(offset + Point::new(x as i32, y as i32), value);
+                }
+            }
+        }
+    }
+
+    /// Move everything up `rows`, filling in at the bottom with `fill`. For
+    /// waterfalls and history charts that add a row at a time.
+    pub fn scroll_up(&mut self, rows: usize, fill: u8) {
+        let rows = rows.min(DISPLAY_HEIGHT);
+
+        for column in self.columns_mut() {
+            column.copy_within(rows .., 0);
+            column[DISPLAY_HEIGHT - rows ..].fill(fill);
+        }
+    }
+
+    /// Move everything down `rows`, filling in at the top with `fill`
+    pub fn scroll_down(&mut self, rows: usize, fill: u8) {
+        let rows = rows.min(DISPLAY_HEIGHT);
+
+        for column in self.columns_mut() {
+            column.copy_within(.. DISPLAY_HEIGHT - rows, rows);
+            column[.. rows].fill(fill);
+        }
+    }
+
+    /// Move everything left `columns`, filling in at the right with `fill`.
+    /// Columns are stored whole, so this is a single move.
+    pub fn scroll_left(&mut self, columns: usize, fill: u8) {
+        let shift = columns.min(DISPLAY_WIDTH) * DISPLAY_HEIGHT;
+        let end = self.data.len() - shift;
+
+        self.data.copy_within(shift .., 0);
+        self.data[end ..].fill(fill);
+    }
+
+    /// Move everything right `columns`, filling in at the left with `fill`
+    pub fn scroll_right(&mut self, columns: usize, fill: u8) {
+        let shift = columns.min(DISPLAY_WIDTH) * DISPLAY_HEIGHT;
+        let end = self.data.len() - shift;
+
+        self.data.copy_within(.. end, shift);
+        self.data[.. shift].fill(fill);
+    }
+
+    /// Move everything up `rows`, the rows that go off the top coming back
+    /// in at the bottom
+    pub fn rotate_up(&mut self, rows: usize) {
+        for column in self.columns_mut() {
+            column.rotate_left(rows % DISPLAY_HEIGHT);
+        }
+    }
+
+    pub fn rotate_down(&mut self, rows: usize) {
+        for column in self.columns_mut() {
+            column.rotate_right(rows % DISPLAY_HEIGHT);
+        }
+    }
+
+    /// Move everything left `columns`, the columns that go off the left
+    /// coming back in at the right
+    pub fn rotate_left(&mut self, columns: usize) {
+        self.data.rotate_left(columns % DISPLAY_WIDTH * DISPLAY_HEIGHT);
+    }
+
+    pub fn rotate_right(&mut self, columns: usize) {
+        self.data.rotate_right(columns % DISPLAY_WIDTH * DISPLAY_HEIGHT);
+    }
+
+    /// One bit per pixel, on wherever the value is at least `threshold`.
+    /// When on and off is all a frame needs, `Command::Draw` sends it in one
+    /// packet instead of staging nine columns.
+    pub fn to_binary(&self, threshold: u8) -> Bitmap {
+        let mut bitmap = Bitmap::new();
+
+        // Both are stored column by column, so the pixel order matches
+        for (location, value) in self.data.iter().enumerate() {
+            if *value >= threshold {
+                bitmap.data[location / 8] |= 1 << (location % 8);
+            }
+        }
+
+        bitmap
+    }
+}
+
+impl Default for Bitmap8 {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 
@@ -96,7 +499,7 @@ impl Bitmap {
 
         let location = y + (x * DISPLAY_HEIGHT);
         let byte_index = location / 8;
-        let bitmask = 1 << location % 8;
+        let bitmask = 1 << (location % 8);
 
         if value {
             self.data[byte_index] |= bitmask;
@@ -106,10 +509,39 @@ impl Bitmap {
 
         Ok(())
     }
+
+    /// Whether a pixel is on, `None` if it's off the panel
+    pub fn pixel(&self, point: Point) -> Option<bool> {
+        let (x, y) = point.on_panel()?;
+        let location = y + (x * DISPLAY_HEIGHT);
+
+        Some(self.data[location / 8] & (1 << (location % 8)) != 0)
+    }
+
+    /// Greyscale copy with lit pixels at `on_value` and the rest off
+    pub fn to_greyscale(&self, on_value: u8) -> Bitmap8 {
+        let mut bitmap = Bitmap8::new();
+
+        for (location, value) in bitmap.data.iter_mut().enumerate() {
+            if self.data[location / 8] & (1 << (location % 8)) != 0 {
+                *value = on_value;
+            }
+        }
+
+        bitmap
+    }
 }
 
-#[derive(Clone)]
+impl Default for Bitmap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Patterns {
+    /// A bar filling the panel from the bottom, 0 to `MAX_PERCENTAGE`.
+    /// See `Patterns::progress()` for one that can't be out of range.
     Percentage(u8),
     Gradient,
     DoubleGradient,
@@ -121,17 +553,26 @@ pub enum Patterns {
 }
 
 impl Patterns {
-    fn pack(self, data: &mut [u8]) {
-        match self {
+    /// A percentage bar for `fraction` of the way, clamped to 0.0 to 1.0
+    pub fn progress(fraction: f32) -> Self {
+        // Rounded by hand, f32::round() needs std
+        let percent = fraction.clamp(0.0, 1.0) * MAX_PERCENTAGE as f32 + 0.5;
+
+        Self::Percentage(percent as u8)
+    }
+
+    /// Returns how many bytes were used. Fails for a percentage over
+    /// `MAX_PERCENTAGE`.
+    fn pack(self, data: &mut [u8]) -> Result<usize, PackError> {
+        data[0] = match self {
             Self::Percentage(value) => {
-                data[0] = 0x00;
+                if value > MAX_PERCENTAGE {
+                    return Err(PackError::Percentage(value));
+                }
+
                 data[1] = value;
-                return;
+                return Ok(2);
             },
-            _ => () 
-        }
-
-        data[0] = match self {
             Self::Gradient => 0x01,
             Self::DoubleGradient => 0x02,
             Self::DisplayLotus => 0x03,
@@ -139,236 +580,1799 @@ impl Patterns {
             Self::FullBrightness => 0x05,
             Self::DisplayPanic => 0x06,
             Self::DisplayLotus2 => 0x07,
-            _ => panic!("Should never get here")
         };
+
+        Ok(1)
+    }
+}
+
+/// Why a command couldn't be packed. With `std` these come back from
+/// `LedMatrix` as `std::io::Error`s of kind `InvalidInput`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PackError {
+    /// Over `MAX_PERCENTAGE`
+    Percentage(u8),
+    /// A staged column past the edge of the panel
+    Column(u8),
+    /// A staged column that isn't `DISPLAY_HEIGHT` long
+    ColumnLength(usize),
+    /// A raw payload over `MAX_RAW_PAYLOAD`
+    PayloadLength(usize),
+}
+
+impl core::fmt::Display for PackError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Percentage(value) => write!(f, "Percentage is {}, at most {} fits", value, MAX_PERCENTAGE),
+            Self::Column(index) => write!(f, "Column {} is off the panel", index),
+            Self::ColumnLength(length) => write!(f, "Columns are {} pixels, got {}", DISPLAY_HEIGHT, length),
+            Self::PayloadLength(length) => write!(f, "Raw payload is {} bytes, at most {} fit", length, MAX_RAW_PAYLOAD),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PackError {}
+
+#[cfg(feature = "std")]
+impl From<PackError> for std::io::Error {
+    fn from(error: PackError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, error)
+    }
+}
+
+/// A column of the panel, 0 on the left. Only columns that exist can be
+/// made, so a `ColumnUpdate` never points off the edge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ColumnIndex(u8);
+
+impl ColumnIndex {
+    /// `None` past the right hand edge
+    pub const fn new(index: usize) -> Option<Self> {
+        match index < DISPLAY_WIDTH {
+            true => Some(Self(index as u8)),
+            false => None,
+        }
+    }
+
+    pub const fn get(self) -> usize {
+        self.0 as usize
+    }
+
+    /// Every column, left to right
+    pub fn all() -> impl Iterator<Item = Self> {
+        (0 .. DISPLAY_WIDTH as u8).map(Self)
+    }
+}
+
+impl TryFrom<u8> for ColumnIndex {
+    type Error = PackError;
+
+    fn try_from(index: u8) -> Result<Self, Self::Error> {
+        Self::new(index as usize).ok_or(PackError::Column(index))
     }
 }
 
+/// One column of greyscale pixels to stage with
+/// `Command::StageColumnBuffer`, top to bottom
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColumnUpdate {
+    pub index: ColumnIndex,
+    pub pixels: [u8; DISPLAY_HEIGHT],
+}
+
+impl ColumnUpdate {
+    pub fn new(index: ColumnIndex, pixels: [u8; DISPLAY_HEIGHT]) -> Self {
+        Self { index, pixels }
+    }
+
+    /// For pixels that come from somewhere without a fixed length. Fails
+    /// for a column off the panel or a slice that isn't `DISPLAY_HEIGHT`
+    /// long.
+    pub fn from_slice(index: u8, pixels: &[u8]) -> Result<Self, PackError> {
+        let index = ColumnIndex::try_from(index)?;
+        let pixels = pixels.try_into().map_err(|_| PackError::ColumnLength(pixels.len()))?;
+
+        Ok(Self { index, pixels })
+    }
+
+    /// Column `index` of a greyscale bitmap
+    pub fn from_bitmap(bitmap: &Bitmap8, index: ColumnIndex) -> Self {
+        let pixels = *bitmap.column(index.get()).expect("ColumnIndex is on the panel");
+
+        Self { index, pixels }
+    }
+}
+
+/// PWM frequencies the LED driver can run at. Higher is less likely to
+/// flicker on camera, lower uses less power.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PwmFrequency {
+    #[default]
+    Hz29000,
+    Hz3600,
+    Hz1800,
+    Hz900,
+}
+
+impl PwmFrequency {
+    pub const ALL: [PwmFrequency; 4] = [Self::Hz29000, Self::Hz3600, Self::Hz1800, Self::Hz900];
+
+    /// Position in the firmware's frequency table, which is what goes over
+    /// the wire
+    pub fn index(&self) -> u8 {
+        match self {
+            Self::Hz29000 => 0,
+            Self::Hz3600 => 1,
+            Self::Hz1800 => 2,
+            Self::Hz900 => 3,
+        }
+    }
+
+    pub fn from_index(index: u8) -> Option<Self> {
+        Self::ALL.get(index as usize).copied()
+    }
+
+    pub fn hertz(&self) -> u32 {
+        match self {
+            Self::Hz29000 => 29_000,
+            Self::Hz3600 => 3_600,
+            Self::Hz1800 => 1_800,
+            Self::Hz900 => 900,
+        }
+    }
+}
 
 #[derive(Clone)]
 /// Commands to execute. Firmware implementation of these commands can be found here:
 /// <https://github.com/FrameworkComputer/inputmodule-rs/blob/main/fl16-inputmodules/src/control.rs#L512>
 pub enum Command<'a> {
     Brightness(u8),
+    /// Ask how bright the panel is set. Replies with `Response::Brightness`.
+    GetBrightness,
     Pattern(Patterns),
+    /// Only sent by `LedMatrix::enter_bootloader()`, `execute()` refuses it
     Bootloader,
     Sleep(bool),
+    /// Ask whether the module is asleep. Replies with `Response::Sleeping`.
+    GetSleep,
     Animate,
+    /// Ask whether the firmware is animating. Replies with `Response::Animating`.
+    /// Only `query()` sends it, padded out it would stop the animation.
+    AnimateQuery,
+    /// How long each step of the firmware's own scrolling animation takes,
+    /// to the millisecond. Longer than a `u16` of milliseconds is clamped.
+    #[cfg(feature = "tuning")]
+    AnimatePeriod(Duration),
     Panic,
     Draw(Box<Bitmap>),
-    StageColumnBuffer((u8, &'a [u8])),
+    #[cfg(feature = "tuning")]
+    PwmFreq(PwmFrequency),
+    /// Hold a column of greyscale pixels until `DrawBuffer` shows them all
+    StageColumnBuffer(ColumnUpdate),
     DrawBuffer,
-    Version
+    /// Have the firmware print debug output over the serial port
+    #[cfg(feature = "tuning")]
+    DebugMode(bool),
+    Version,
+    #[cfg(feature = "games")]
+    StartGame(games::Game),
+    /// Press a key in whichever game is running
+    #[cfg(feature = "games")]
+    GameControl(games::GameKey),
+    /// Ask how the running game is going. Current firmware doesn't answer
+    /// it yet, so there's no `Response` for it.
+    #[cfg(feature = "games")]
+    GameStatus,
+    /// Any command ID with its arguments, sent as they are, for firmware
+    /// commands this crate doesn't have typed support for yet. The payload
+    /// can be up to `MAX_RAW_PAYLOAD` bytes. See `LedMatrix::execute_raw()`.
+    Raw { id: u8, payload: &'a [u8] },
 }
 
 impl<'a> Command<'a> {
-    // TODO: Return a result. Some of these commands should have a safety net
-    fn pack(self, data: &mut [u8]) {
+    /// Whether this only asks for something. Sent padded out like any other
+    /// command, the padding would be taken as an argument, so `GetSleep`
+    /// would wake the module. These only go through `query()`.
+    pub fn is_query(&self) -> bool {
+        match self {
+            Self::GetBrightness | Self::GetSleep | Self::AnimateQuery | Self::Version => true,
+            #[cfg(feature = "games")]
+            Self::GameStatus => true,
+            _ => false,
+        }
+    }
+
+    /// `None` for a `Raw` command with an ID the crate doesn't know
+    pub fn kind(&self) -> Option<CommandKind> {
+        let kind = match self {
+            Self::Brightness(_) |
+            Self::GetBrightness => CommandKind::Brightness,
+            Self::Pattern(_) => CommandKind::Pattern,
+            Self::Bootloader => CommandKind::Bootloader,
+            Self::Sleep(_) |
+            Self::GetSleep => CommandKind::Sleep,
+            Self::Animate |
+            Self::AnimateQuery => CommandKind::Animate,
+            #[cfg(feature = "tuning")]
+            Self::AnimatePeriod(_) => CommandKind::AnimationPeriod,
+            Self::Panic => CommandKind::Panic,
+            Self::Draw(_) => CommandKind::Draw,
+            #[cfg(feature = "tuning")]
+            Self::PwmFreq(_) => CommandKind::PwmFrequency,
+            Self::StageColumnBuffer(_) => CommandKind::StageColumn,
+            Self::DrawBuffer => CommandKind::DrawBuffer,
+            #[cfg(feature = "tuning")]
+            Self::DebugMode(_) => CommandKind::DebugMode,
+            Self::Version => CommandKind::Version,
+            #[cfg(feature = "games")]
+            Self::StartGame(_) => CommandKind::StartGame,
+            #[cfg(feature = "games")]
+            Self::GameControl(_) => CommandKind::GameControl,
+            #[cfg(feature = "games")]
+            Self::GameStatus => CommandKind::GameStatus,
+            Self::Raw { id, .. } => return CommandKind::from_id(*id),
+        };
+
+        Some(kind)
+    }
+
+    /// The ID the firmware knows this command by
+    pub fn id(&self) -> u8 {
         match self {
+            Self::Brightness(_) |
+            Self::GetBrightness => 0x00,
+            Self::Pattern(_) => 0x01,
+            Self::Bootloader => 0x02,
+            Self::Sleep(_) |
+            Self::GetSleep => 0x03,
+            Self::Animate |
+            Self::AnimateQuery => 0x04,
+            #[cfg(feature = "tuning")]
+            Self::AnimatePeriod(_) => 0x1c,
+            Self::Panic => 0x05,
+            Self::Draw(_) => 0x06,
+            #[cfg(feature = "tuning")]
+            Self::PwmFreq(_) => 0x1e,
+            Self::StageColumnBuffer(_) => 0x07,
+            Self::DrawBuffer => 0x08,
+            #[cfg(feature = "tuning")]
+            Self::DebugMode(_) => 0x1f,
+            Self::Version => 0x20,
+            #[cfg(feature = "games")]
+            Self::StartGame(_) => 0x10,
+            #[cfg(feature = "games")]
+            Self::GameControl(_) => 0x11,
+            #[cfg(feature = "games")]
+            Self::GameStatus => 0x12,
+            Self::Raw { id, .. } => *id,
+        }
+    }
+
+    /// Pack the command into `buffer` ready to write to the module, header
+    /// and all, for anything talking to it without a `LedMatrix`. Returns
+    /// how many bytes were used. The rest is left as it was, and
+    /// `LedMatrix` always sends it zeroed as padding, which `Animate`
+    /// relies on as its argument.
+    pub fn encode(self, buffer: &mut [u8; MAX_COMMAND_LENGTH]) -> Result<usize, PackError> {
+        buffer[0] = 0x32;
+        buffer[1] = 0xac;
+
+        Ok(2 + self.pack(&mut buffer[2..])?)
+    }
+
+    /// Returns how many bytes were used, including the command ID. The
+    /// firmware treats a command missing its argument as a query, so this
+    /// matters when expecting a reply. Fails for arguments the firmware
+    /// can't take, rather than sending them.
+    fn pack(self, data: &mut [u8]) -> Result<usize, PackError> {
+        data[0] = self.id();
+
+        let length = match self {
             Self::Brightness(x) => {
-                data[0] = 0x00;
                 data[1] = x;
+                2
             },
             Self::Pattern(pattern) => {
-                data[0] = 0x01;
-                pattern.pack(&mut data[1..3])
-            }
-            Self::Bootloader => {
-                data[0] = 0x02;
-            }
+                1 + pattern.pack(&mut data[1..3])?
+            },
             Self::Sleep(value) => {
-                data[0] = 0x03;
                 data[1] = if value {
                     1
                 } else {
                     0
                 };
+                2
             },
-            Self::Animate => {
-                data[0] = 0x04
-            }
-            Self::Panic => {
-                data[0] = 0x05
+            #[cfg(feature = "tuning")]
+            Self::DebugMode(value) => {
+                data[1] = if value {
+                    1
+                } else {
+                    0
+                };
+                2
+            },
+            #[cfg(feature = "tuning")]
+            Self::AnimatePeriod(period) => {
+                let millis = period.as_millis().min(u16::MAX as u128) as u16;
+
+                data[1..3].copy_from_slice(&millis.to_le_bytes());
+                3
+            },
+            #[cfg(feature = "tuning")]
+            Self::PwmFreq(frequency) => {
+                data[1] = frequency.index();
+                2
+            },
+            #[cfg(feature = "games")]
+            Self::StartGame(game) => {
+                1 + game.pack(&mut data[1..3])
+            },
+            #[cfg(feature = "games")]
+            Self::GameControl(key) => {
+                data[1] = key.index();
+                2
             },
             Self::Draw(bitmap) => {
-                data[0] = 0x06;
                 data[1..40].copy_from_slice(&bitmap.data);
+                40
+            },
+            Self::StageColumnBuffer(update) => {
+                data[1] = update.index.0;
+                data[2..DISPLAY_HEIGHT + 2].copy_from_slice(&update.pixels);
+                DISPLAY_HEIGHT + 2
             },
-            Self::StageColumnBuffer((index, value)) => {
-                if index as usize > DISPLAY_WIDTH {
-                    panic!("Wrong column index")
+            Self::Raw { payload, .. } => {
+                if payload.len() > MAX_RAW_PAYLOAD {
+                    return Err(PackError::PayloadLength(payload.len()));
                 }
 
-                data[0] = 0x07;
-                data[1] = index;
-                data[2..DISPLAY_HEIGHT + 2].copy_from_slice(&*value)
+                data[1..payload.len() + 1].copy_from_slice(payload);
+                payload.len() + 1
             },
-            Self::DrawBuffer => {
-                data[0] = 0x08;
-            }
-            Self::Version => {
-                data[0] = 0x20;
-            },
-        }
+            Self::Bootloader |
+            Self::GetBrightness |
+            Self::GetSleep |
+            Self::Animate |
+            Self::AnimateQuery |
+            Self::Panic |
+            Self::DrawBuffer |
+            Self::Version => 1,
+            #[cfg(feature = "games")]
+            Self::GameStatus => 1,
+        };
+
+        Ok(length)
     }
 }
 
 
-pub struct LedMatrix<'a> {
-    path: &'a str,
-    port: Option<Box<dyn SerialPort>>
+#[cfg(feature = "std")]
+#[derive(Clone, Default)]
+/// What to leave on the display when the application is done with it. It's
+/// shown when the matrix, or the `Display` that owns it, is dropped, which
+/// includes unwinding from a panic, so a crashed dashboard doesn't leave its
+/// last graph lit for days. Nothing can be done about an abort or a kill.
+pub enum ShutdownScreen {
+    /// Leave whatever was last drawn
+    #[default]
+    Unchanged,
+    /// Every LED off, as `clear()`
+    Clear,
+    Sleep,
+    Pattern(Patterns),
+    Draw(Box<Bitmap>),
+    Greyscale(Box<Bitmap8>),
 }
 
-impl<'a> LedMatrix<'a> {
-    pub fn new(path: &'a str) -> Result<Self, serialport::Error> {
-        let port = serialport::new(path, 115_200)
-            .timeout(CONNECT_DELAY)
-            .open()?;
-
-        Ok(Self {
-            path,
-            port: Some(port)
-        })
-    }
-
-    pub fn reconnect(&mut self) -> Result<(), serialport::Error> {
-        // Hopefully this will yeild the port fast enough
-        self.port = None;
 
-        self.port = Some(serialport::new(self.path, 115_200)
-            .timeout(RECONNECT_DELAY)
-            .open()?);
+#[cfg(feature = "std")]
+#[derive(Clone, Default)]
+/// What to show whenever the port is opened or reconnected, so there's
+/// something on the panel to say the link is alive after a replug or a
+/// resume. The application's own frames carry on from wherever it finishes.
+///
+/// The firmware has no command to turn off the animation it plays at
+/// power up, and keeps no settings between boots, so a blank `Greyscale`
+/// here is the soonest the panel can be made to go dark.
+pub enum StartupScreen {
+    /// Show nothing special
+    #[default]
+    Nothing,
+    Pattern(Patterns),
+    Greyscale(Box<Bitmap8>),
+    /// Played through once, blocking until it's done, so keep it short
+    Animation(animation::Animation),
+}
 
-        Ok(())
+#[cfg(feature = "std")]
+// Bitmaps and animations aren't worth printing, only which one it is
+impl std::fmt::Debug for StartupScreen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Nothing => write!(f, "Nothing"),
+            Self::Pattern(_) => write!(f, "Pattern"),
+            Self::Greyscale(_) => write!(f, "Greyscale"),
+            Self::Animation(x) => write!(f, "Animation({} frames)", x.len()),
+        }
     }
+}
 
-    pub fn execute(&mut self, command: Command) -> Result<usize, std::io::Error> {
-        let mut buffer = [0u8;MAX_COMMAND_LENGTH];
-
-        buffer[0] = 0x32;
-        buffer[1] = 0xac;
-
-        command.pack(&mut buffer[2..]);
-
-        match &mut self.port {
-            Some(x) => x.write(&buffer),
-            // TODO: This should return the correct ErrorKind, but I need Internet. :D
-            None => panic!("Attempted to write to the serial port without opening it")
+#[cfg(feature = "std")]
+impl std::fmt::Debug for ShutdownScreen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unchanged => write!(f, "Unchanged"),
+            Self::Clear => write!(f, "Clear"),
+            Self::Sleep => write!(f, "Sleep"),
+            Self::Pattern(x) => write!(f, "Pattern({:?})", x),
+            Self::Draw(_) => write!(f, "Draw"),
+            Self::Greyscale(_) => write!(f, "Greyscale"),
         }
     }
+}
 
-    pub fn path(&self) -> &'a str {
-        self.path
-    }
+
+/// Opens the transport again when reconnecting
+#[cfg(feature = "std")]
+pub(crate) type Reopen = Box<dyn Fn() -> Result<Box<dyn Transport>, serialport::Error> + Send>;
+
+#[cfg(feature = "std")]
+pub struct LedMatrix {
+    path: String,
+    baud_rate: u32,
+    connection: Connection,
+    /// Other threads' view of `connection`
+    watch: ConnectionWatch,
+    /// Where reconnecting gets a new transport from, instead of reopening
+    /// the serial port at `path`
+    reopen: Option<Reopen>,
+    /// Taps every port opened, see `recorder`
+    recorder: Option<Recorder>,
+    shutdown_screen: ShutdownScreen,
+    shutdown_brightness: Option<u8>,
+    startup_screen: StartupScreen,
+    remap: Remap,
+    gamma: GammaMap,
+    unsolicited: Vec<u8>,
+    filters: Pipeline,
+    budget: Option<PerformanceBudget>,
+    capabilities: Capabilities,
+    unsupported: UnsupportedPolicy,
+    /// Read the version again after every reconnect
+    probe_capabilities: bool,
+    probe_info: bool,
+    info: DeviceInfo,
+    events: Events,
+    /// Set once `on_disconnect` has fired, so a run of failures only reports
+    /// it once
+    link_lost: bool,
+    column_retries: u32,
+    /// Whole commands written, for `commands_delivered()`
+    delivered: u64,
+    partial_writes: u64,
+    verification: Verification,
+    /// When the module last answered a check, `None` before the first
+    last_verified: Option<std::time::Instant>,
+    stalls: u64,
+    throttle: Throttle,
+    /// When recent commands were sent, for the throttle
+    sent_at: std::collections::VecDeque<std::time::Instant>,
+    reconnect_policy: ReconnectPolicy,
+    /// Paces the throttle, reconnects, verification and identify flashes
+    clock: SharedClock,
+    /// Read and write timeout for the port
+    timeout: Duration,
+    /// Timeout while checking the firmware is there at all
+    connect_timeout: Duration,
 }
 
+#[cfg(feature = "std")]
+impl LedMatrix {
+    pub fn new(path: &str) -> Result<Self, serialport::Error> {
+        Self::builder(path).open()
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use sysinfo::System;
+    /// Open with settings other than the defaults
+    pub fn builder(path: &str) -> LedMatrixBuilder {
+        LedMatrixBuilder::new(path)
+    }
 
-    #[test]
-    fn set_brightness() {
-        let mut matrix = LedMatrix::new("/dev/ttyACM0")
-            .expect("Unable to open port");
+    /// Find every LED matrix module plugged in, with roles from the user's
+    /// saved config. See `discovery::discover()` to supply your own.
+    pub fn discover() -> Result<Vec<discovery::DiscoveredMatrix>, serialport::Error> {
+        let roles = roles::RoleConfig::load_default().unwrap_or_default();
 
-        let command = Command::Brightness(0x40);
+        discovery::discover(&roles)
+    }
 
-        matrix.execute(command).expect("Command failed");
+    /// Open a port and read everything the device will say about itself.
+    /// The information is refreshed whenever the port is reconnected.
+    pub fn with_info(path: &str) -> Result<Self, serialport::Error> {
+        Self::builder(path).info(true).open()
     }
 
-    #[test]
-    fn wake() {
-        let mut matrix = LedMatrix::new("/dev/ttyACM0")
-            .expect("Unable to open port");
+    pub fn with_baud_rate(path: &str, baud_rate: u32) -> Result<Self, serialport::Error> {
+        Self::builder(path).baud(baud_rate).open()
+    }
 
-        let command = Command::Sleep(false);
+    /// Talk to something other than a serial port, like a `MockTransport`.
+    /// `path` is only used to name it. Reconnecting carries on with a clone
+    /// of the same transport.
+    pub fn with_transport<T: Transport + Clone + 'static>(path: &str, transport: T) -> Self {
+        Self::builder(path).build_transport(transport)
+    }
 
-        matrix.execute(command).expect("Command failed");
+    pub(crate) fn from_builder(
+        builder: &LedMatrixBuilder,
+        port: Box<dyn Transport>,
+        reopen: Option<Reopen>,
+    ) -> Self {
+        Self {
+            path: builder.path.clone(),
+            baud_rate: builder.baud_rate,
+            connection: Connection::Connected(match &builder.recorder {
+                Some(recorder) => recorder.tap(port),
+                None => port,
+            }),
+            watch: ConnectionWatch::new(ConnectionState::Connected),
+            reopen,
+            recorder: builder.recorder.clone(),
+            shutdown_screen: builder.shutdown_screen.clone(),
+            shutdown_brightness: builder.shutdown_brightness,
+            startup_screen: builder.startup_screen.clone(),
+            remap: Remap::identity(),
+            gamma: builder.gamma.clone(),
+            unsolicited: Vec::new(),
+            filters: match builder.mounting {
+                config::Mounting::Normal => Pipeline::new(),
+                config::Mounting::UpsideDown => {
+                    let mut filters = Pipeline::new();
+                    filters.push(filter::Rotate180);
+                    filters
+                },
+            },
+            budget: None,
+            capabilities: Capabilities::unknown(),
+            unsupported: builder.unsupported,
+            probe_capabilities: false,
+            probe_info: false,
+            info: DeviceInfo::default(),
+            events: Events::new(),
+            link_lost: false,
+            column_retries: builder.column_retries,
+            delivered: 0,
+            partial_writes: 0,
+            verification: builder.verification,
+            last_verified: None,
+            stalls: 0,
+            throttle: builder.throttle,
+            sent_at: std::collections::VecDeque::new(),
+            reconnect_policy: builder.reconnect_policy,
+            clock: builder.clock.clone(),
+            timeout: builder.write_timeout,
+            connect_timeout: builder.connect_timeout,
+        }
     }
 
-    #[test]
-    fn draw() {
-        let mut matrix = LedMatrix::new("/dev/ttyACM1")
-            .expect("Unable to open port");
+    pub fn reconnect(&mut self) -> Result<(), serialport::Error> {
+        // Hopefully this will yeild the port fast enough
+        self.set_connection(Connection::Reconnecting);
+
+        let port = match &self.reopen {
+            Some(reopen) => reopen(),
+            None => platform::open_with_retry(
+                || serialport::new(&self.path, self.baud_rate).timeout(self.timeout).open(),
+                |x| self.clock.sleep(x),
+            ).map(|x| Box::new(x) as Box<dyn Transport>),
+        };
 
-        let mut data = [0u8; 39];
-        data[0] = 0xac;
-        data[1] = 0xac;
-        data[2] = 0xac;
+        let port = match port {
+            Ok(x) => match &self.recorder {
+                Some(recorder) => recorder.tap(x),
+                None => x,
+            },
+            Err(error) => {
+                trace::event!(warn, path = %self.path, %error, "Reconnect failed");
+                self.report_error(&std::io::Error::from(error.clone()));
+                self.report_disconnect();
+                return Err(error);
+            }
+        };
 
-        let command = Command::Brightness(0xff);
-        matrix.execute(command).expect("Command failed");
+        self.set_connection(Connection::Connected(port));
+        trace::event!(info, path = %self.path, "Reconnected");
+        self.link_lost = false;
+        self.events.connected(&self.path);
+
+        // The port is back either way, stale info isn't worth failing over.
+        // It may be different firmware if the module was flashed meanwhile.
+        if self.probe_info {
+            let _ = self.refresh_info();
+        } else if self.probe_capabilities {
+            let _ = self.refresh_capabilities();
+        }
 
-        let mut bitmap = Bitmap::new();
-        bitmap.draw_point(0, 0, true).unwrap();
-        bitmap.draw_point(4, 0, true).unwrap();
-        bitmap.draw_point(4, 4, true).unwrap();
-        bitmap.draw_point(0, 4, true).unwrap();
+        let _ = self.play_startup_screen();
 
-        let command = Command::Draw(Box::new(bitmap));
-        matrix.execute(command).expect("Command failed");
+        Ok(())
     }
 
+    /// Open the port at each of the given rates in turn and keep the first one
+    /// where the firmware answers a `Version` query. Pass `&PROBE_BAUD_RATES`
+    /// unless you know better.
+    pub fn autodetect(path: &str, baud_rates: &[u32]) -> Result<Self, serialport::Error> {
+        let mut last_error = serialport::Error::new(
+            serialport::ErrorKind::InvalidInput,
+            "No baud rates to try"
+        );
+
+        for baud_rate in baud_rates {
+            let mut matrix = match Self::builder(path).baud(*baud_rate).open() {
+                Ok(x) => x,
+                Err(error) => {
+                    last_error = error;
+                    continue;
+                }
+            };
+
+            match matrix.probe() {
+                Ok(true) => return Ok(matrix),
+                Ok(false) => {
+                    last_error = serialport::Error::new(
+                        serialport::ErrorKind::Io(std::io::ErrorKind::TimedOut),
+                        "Device didn't answer the version probe at any baud rate"
+                    );
+                },
+                Err(error) => last_error = error.into(),
+            }
+        }
 
-    #[test]
+        Err(last_error)
+    }
+
+    /// Read whatever the firmware has sent that nobody asked for (debug
+    /// output, replies that showed up late) so it isn't mistaken for the
+    /// answer to the next query. Returns how many bytes were drained. The
+    /// bytes are kept and can be collected with `take_unsolicited()`.
+    pub fn drain_input(&mut self) -> Result<usize, std::io::Error> {
+        let mut buffer = [0u8; 64];
+        let mut total = 0;
+
+        let port = match self.connection.port() {
+            Some(x) => x,
+            None => return Ok(0)
+        };
+
+        while port.bytes_to_read()? > 0 {
+            let count = match port.read(&mut buffer) {
+                Ok(x) => x,
+                Err(error) if error.kind() == std::io::ErrorKind::TimedOut => break,
+                Err(error) => return Err(error)
+            };
+
+            if count == 0 {
+                break;
+            }
+
+            self.unsolicited.extend_from_slice(&buffer[..count]);
+            total += count;
+        }
+
+        if self.unsolicited.len() > UNSOLICITED_BUFFER_LENGTH {
+            let excess = self.unsolicited.len() - UNSOLICITED_BUFFER_LENGTH;
+            self.unsolicited.drain(..excess);
+        }
+
+        Ok(total)
+    }
+
+    /// Hand over every unsolicited byte drained so far
+    pub fn take_unsolicited(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.unsolicited)
+    }
+
+    /// Send a `Version` query and report whether anything came back
+    fn probe(&mut self) -> Result<bool, std::io::Error> {
+        if !self.is_connected() {
+            return Ok(false);
+        }
+
+        let result = self.within_timeout(self.connect_timeout, |x| x.query(Command::Version));
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(error) if error.kind() == std::io::ErrorKind::TimedOut => Ok(false),
+            Err(error) => Err(error)
+        }
+    }
+
+    /// Run `action` with the port's timeout changed, putting it back after
+    pub(crate) fn within_timeout<T>(&mut self, timeout: Duration, action: impl FnOnce(&mut Self) -> Result<T, std::io::Error>) -> Result<T, std::io::Error> {
+        if let Some(port) = self.connection.port() {
+            port.set_timeout(timeout)?;
+        }
+
+        let result = action(self);
+
+        if let Some(port) = self.connection.port() {
+            port.set_timeout(self.timeout)?;
+        }
+
+        result
+    }
+
+    /// Ask the firmware for its version and remember what it can do.
+    /// Until this is called everything is assumed to be supported.
+    pub fn refresh_capabilities(&mut self) -> Result<Capabilities, std::io::Error> {
+        if let Response::Version(version) = self.query(Command::Version)? {
+            self.capabilities = Capabilities::new(version);
+        }
+
+        Ok(self.capabilities)
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Ask the module how bright it's set, such as to put it back after a
+    /// reconnect
+    pub fn current_brightness(&mut self) -> Result<u8, std::io::Error> {
+        match self.query(Command::GetBrightness)? {
+            Response::Brightness(x) => Ok(x),
+            _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Expected a brightness")),
+        }
+    }
+
+    /// Ask the module whether it's asleep
+    pub fn is_sleeping(&mut self) -> Result<bool, std::io::Error> {
+        match self.query(Command::GetSleep)? {
+            Response::Sleeping(x) => Ok(x),
+            _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Expected the sleep state")),
+        }
+    }
+
+    /// Whether the connected firmware handles a command, going by the
+    /// version read in `refresh_capabilities()`
+    pub fn supports(&self, kind: CommandKind) -> bool {
+        self.capabilities.supports(kind)
+    }
+
+    /// Whether commands the firmware is too old for are sent anyway or
+    /// refused. Greyscale frames aren't refused either way: without
+    /// `StageColumn` they're drawn in black and white instead.
+    pub fn set_unsupported_policy(&mut self, policy: UnsupportedPolicy) {
+        self.unsupported = policy;
+    }
+
+    pub fn unsupported_policy(&self) -> UnsupportedPolicy {
+        self.unsupported
+    }
+
+    pub fn baud_rate(&self) -> u32 {
+        self.baud_rate
+    }
+
+    pub fn execute(&mut self, command: Command) -> Result<usize, std::io::Error> {
+        let mut buffer = [0u8;MAX_COMMAND_LENGTH];
+        let _span = trace::span!(DEBUG, "execute", path = %self.path, kind = ?command.kind());
+
+        bootloader::refuse(&command)?;
+        refuse_query(&command)?;
+        self.unsupported.check(&self.capabilities, &command)?;
+
+        // Commands are always sent padded out to the full length. Some, like
+        // Animate, rely on the padding as their argument.
+        self.encode(command, &mut buffer)?;
+
+        self.send_packets(&buffer)
+    }
+
+    /// Collect commands to send in one write, see `CommandBatch`
+    pub fn batch(&mut self) -> CommandBatch<'_> {
+        CommandBatch::new(self)
+    }
+
+    /// Send a command the crate doesn't have typed support for yet. Fails
+    /// with `InvalidInput` if the payload won't fit in a command.
+    pub fn execute_raw(&mut self, id: u8, payload: &[u8]) -> Result<usize, std::io::Error> {
+        // Packing turns away a payload that's too long
+        self.execute(Command::Raw { id, payload })
+    }
+
+    /// Reboot the module into its bootloader to be flashed. It drops off USB
+    /// straight away, so there's no reply to wait for and no retrying: the
+    /// port is closed once the command is written and `is_connected()` is
+    /// false from then on. `on_disconnect` isn't called since this was
+    /// asked for. Once the module is flashed or power cycled it comes back
+    /// as a new device, so find it again with `LedMatrix::discover()`.
+    pub fn enter_bootloader(&mut self, _confirmation: bootloader::Confirmation) -> Result<(), std::io::Error> {
+        let mut buffer = [0u8;MAX_COMMAND_LENGTH];
+        self.encode(Command::Bootloader, &mut buffer)?;
+
+        let port = self.port()?;
+        let result = port.write_all(&buffer).and_then(|_| port.flush());
+        trace::event!(info, path = %self.path, ok = result.is_ok(), "Sent to the bootloader");
+
+        self.set_connection(Connection::Disconnected);
+        self.link_lost = true;
+
+        result
+    }
+
+    /// Write whole packed commands, all the way through to the port, and
+    /// reconnect and carry on as the reconnect policy says if that fails.
+    /// Commands that made it before a failure aren't sent again, but one
+    /// that only partly made it is resent from its start.
+    pub(crate) fn send_packets(&mut self, buffer: &[u8]) -> Result<usize, std::io::Error> {
+        #[cfg(feature = "tracing")]
+        let started = std::time::Instant::now();
+
+        let (mut sent, result) = self.write_packets(buffer);
+
+        if let Err(error) = result {
+            self.retry(error, |matrix| {
+                let (count, result) = matrix.write_packets(&buffer[sent..]);
+                sent += count;
+                result
+            })?;
+        }
+
+        trace::event!(
+            debug,
+            bytes = buffer.len(),
+            commands = buffer.len() / MAX_COMMAND_LENGTH,
+            latency = ?started.elapsed(),
+            "Written"
+        );
+
+        self.verify()?;
+
+        Ok(buffer.len())
+    }
+
+    /// Check the module is still answering, if the verification mode says
+    /// one is due. One that isn't is flagged as stalled and the port closed.
+    fn verify(&mut self) -> Result<(), std::io::Error> {
+        let now = self.clock.now();
+
+        if !self.verification.due(self.last_verified, now) {
+            return Ok(());
+        }
+
+        match self.within_timeout(self.connect_timeout, |x| x.query(Command::Version)) {
+            Ok(_) => {
+                self.last_verified = Some(now);
+                Ok(())
+            },
+            Err(error) => {
+                let error = std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("Module stopped answering: {}", error)
+                );
+
+                trace::event!(error, path = %self.path, stalls = self.stalls + 1, "Module stopped answering");
+                self.stalls += 1;
+                self.set_connection(Connection::Reconnecting);
+                self.report_error(&error);
+                self.report_disconnect();
+
+                Err(error)
+            },
+        }
+    }
+
+    /// One try at writing every byte of `buffer` and flushing. Returns how
+    /// much of it went through in whole commands, whether or not it all did.
+    fn write_packets(&mut self, buffer: &[u8]) -> (usize, Result<(), std::io::Error>) {
+        let mut written = 0;
+        let throttled = self.throttle.is_limited();
+
+        let result = match self.connection.port() {
+            // write_all() without losing count of what got through
+            Some(port) => loop {
+                if written == buffer.len() {
+                    break port.flush();
+                }
+
+                // Throttled, each command is flushed and waits its turn
+                let end = match throttled {
+                    true => (written - written % MAX_COMMAND_LENGTH + MAX_COMMAND_LENGTH).min(buffer.len()),
+                    false => buffer.len(),
+                };
+
+                if throttled && written % MAX_COMMAND_LENGTH == 0 {
+                    if written > 0 {
+                        if let Err(error) = port.flush() {
+                            break Err(error);
+                        }
+                    }
+
+                    self.clock.sleep(self.throttle.delay(&self.sent_at, self.clock.now()));
+                    self.throttle.record(&mut self.sent_at, self.clock.now());
+                }
+
+                match port.write(&buffer[written..end]) {
+                    Ok(0) => break Err(std::io::Error::new(std::io::ErrorKind::WriteZero, "Port took none of the command")),
+                    Ok(count) => written += count,
+                    Err(error) if error.kind() == std::io::ErrorKind::Interrupted => {},
+                    Err(error) => break Err(error),
+                }
+            },
+            None => Err(std::io::Error::new(std::io::ErrorKind::NotConnected, "Port isn't open")),
+        };
+
+        let whole = written - written % MAX_COMMAND_LENGTH;
+        self.delivered += (whole / MAX_COMMAND_LENGTH) as u64;
+
+        if let Err(error) = &result {
+            trace::event!(warn, path = %self.path, %error, written, delivered = whole, "Write failed");
+
+            if whole != written {
+                self.partial_writes += 1;
+            }
+
+            self.report_error(error);
+        }
+
+        (whole, result)
+    }
+
+    /// Commands written out to the port in full since the matrix was
+    /// opened, reconnects and all
+    pub fn commands_delivered(&self) -> u64 {
+        self.delivered
+    }
+
+    /// Times a write failed partway through a command. Each was sent again
+    /// from the start if there were retries left.
+    pub fn partial_writes(&self) -> u64 {
+        self.partial_writes
+    }
+
+    /// How sends are checked on, see `Verification`
+    pub fn set_verification(&mut self, verification: Verification) {
+        self.verification = verification;
+    }
+
+    pub fn verification(&self) -> Verification {
+        self.verification
+    }
+
+    /// Times the module stopped answering a check since it was opened
+    pub fn stalls(&self) -> u64 {
+        self.stalls
+    }
+
+    /// Hold commands back to leave gaps the firmware can keep up with, see
+    /// `Throttle`
+    pub fn set_throttle(&mut self, throttle: Throttle) {
+        self.throttle = throttle;
+    }
+
+    pub fn throttle(&self) -> Throttle {
+        self.throttle
+    }
+
+    /// What the throttle, the reconnect policy, `wait_connected()`,
+    /// verification and `identify()` wait on. A `ManualClock` lets tests go
+    /// through backoffs without really sleeping.
+    pub fn set_clock(&mut self, clock: impl Clock + Send + Sync + 'static) {
+        self.clock = SharedClock::new(clock);
+    }
+
+    /// Reconnect and try `attempt` again as the reconnect policy allows,
+    /// after the first try failed with `error`
+    fn retry<T>(&mut self, error: std::io::Error, mut attempt: impl FnMut(&mut Self) -> Result<T, std::io::Error>) -> Result<T, std::io::Error> {
+        let policy = self.reconnect_policy;
+
+        if !policy.should_retry(&error) {
+            return Err(error);
+        }
+
+        let mut last_error = error;
+
+        for retry in 0 .. policy.max_retries {
+            trace::event!(info, path = %self.path, retry = retry + 1, of = policy.max_retries, error = %last_error, "Retrying");
+            self.clock.sleep(policy.delay(retry));
+
+            if let Err(error) = self.reconnect() {
+                last_error = error.into();
+                continue;
+            }
+
+            match attempt(self) {
+                Ok(x) => return Ok(x),
+                Err(error) if policy.should_retry(&error) => last_error = error,
+                Err(error) => return Err(error),
+            }
+        }
+
+        trace::event!(error, path = %self.path, retries = policy.max_retries, error = %last_error, "Out of retries");
+        Err(RetriesExhausted::into_error(policy.max_retries, last_error))
+    }
+
+    /// Send a command and wait for its reply. Only the bytes the command
+    /// actually uses are sent, which is how the firmware tells a query from
+    /// a set. Anything unsolicited waiting on the port is drained first so it
+    /// isn't mistaken for the reply. Sets are refused with `InvalidInput`,
+    /// as `execute()` refuses queries.
+    pub fn query(&mut self, command: Command) -> Result<Response, std::io::Error> {
+        let mut buffer = [0u8;MAX_COMMAND_LENGTH];
+        let mut response = [0u8; RESPONSE_LENGTH];
+        let id = command.id();
+
+        bootloader::refuse(&command)?;
+        refuse_set(&command)?;
+        self.unsupported.check(&self.capabilities, &command)?;
+
+        let length = self.encode(command, &mut buffer)?;
+        self.transact(&buffer[..length], &mut response)?;
+
+        Response::parse(id, &response).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "Command doesn't have a reply")
+        })
+    }
+
+    /// Write a packed command exactly as given and read the reply
+    pub(crate) fn transact(&mut self, packet: &[u8], response: &mut [u8; RESPONSE_LENGTH]) -> Result<(), std::io::Error> {
+        #[cfg(feature = "tracing")]
+        let started = std::time::Instant::now();
+
+        let result = self.drain_input().and_then(|_| {
+            let port = self.port()?;
+            port.write_all(packet)?;
+            port.flush()?;
+            port.read_exact(response)
+        });
+
+        trace::event!(debug, path = %self.path, id = packet[2], latency = ?started.elapsed(), ok = result.is_ok(), "Queried");
+
+        if let Err(error) = &result {
+            self.report_error(error);
+        }
+
+        result
+    }
+
+    /// Query every piece of state the firmware reports. Queries the firmware
+    /// doesn't answer are left as `None` rather than failing the lot.
+    pub fn refresh_info(&mut self) -> Result<&DeviceInfo, std::io::Error> {
+        let mut info = DeviceInfo::default();
+
+        // Version decides which of the rest are worth asking for
+        if let Response::Version(version) = self.query(Command::Version)? {
+            self.capabilities = Capabilities::new(version);
+            info.version = Some(version);
+        }
+
+        let queries = [
+            (CommandKind::Brightness, 0x00),
+            (CommandKind::Sleep, 0x03),
+            (CommandKind::Animate, 0x04),
+            (CommandKind::AnimationPeriod, 0x1c),
+            (CommandKind::PwmFrequency, 0x1e),
+        ];
+
+        for (kind, id) in queries {
+            if !self.supports(kind) {
+                continue;
+            }
+
+            let response = match self.query_parameter(id) {
+                Ok(x) => x,
+                Err(error) if error.kind() == std::io::ErrorKind::TimedOut => continue,
+                Err(error) => return Err(error),
+            };
+
+            if let Some(x) = Response::parse(id, &response) {
+                info.update(x);
+            }
+        }
+
+        self.info = info;
+
+        Ok(&self.info)
+    }
+
+    /// Send a command ID with no parameter, which the firmware takes as a
+    /// request for the current setting, and return the raw reply
+    pub(crate) fn query_parameter(&mut self, id: u8) -> Result<[u8; RESPONSE_LENGTH], std::io::Error> {
+        let mut response = [0u8; RESPONSE_LENGTH];
+
+        self.transact(&[0x32, 0xac, id], &mut response)?;
+
+        Ok(response)
+    }
+
+    /// State as of the last `refresh_info()`
+    pub fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    /// Pack a command into `buffer` with any remapping and gamma applied.
+    /// Returns how many bytes of the buffer were used, header included.
+    fn encode(&self, command: Command, buffer: &mut [u8; MAX_COMMAND_LENGTH]) -> Result<usize, std::io::Error> {
+        encode(&self.remap, &self.gamma, command, buffer)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Open a different port from the next reconnect on, for a module that
+    /// came back under a new name. See `hotplug::Watcher::rebind()`.
+    pub fn set_path(&mut self, path: &str) {
+        self.path = path.to_owned();
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state() == ConnectionState::Connected
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.connection.state()
+    }
+
+    /// Follow `state()` from another thread, see `connection`
+    pub fn watch(&self) -> ConnectionWatch {
+        self.watch.clone()
+    }
+
+    /// Reconnect until the port is open or `timeout` passes, waiting
+    /// between tries as the reconnect policy says. Returns straight away if
+    /// it's already open, and fails with `TimedOut` if it never opened.
+    pub fn wait_connected(&mut self, timeout: Duration) -> Result<(), std::io::Error> {
+        let deadline = self.clock.now() + timeout;
+        let mut attempt = 0;
+
+        while !self.is_connected() {
+            let error = match self.reconnect() {
+                Ok(()) => break,
+                Err(error) => error,
+            };
+
+            let pause = self.reconnect_policy.delay(attempt);
+            attempt = attempt.saturating_add(1);
+
+            if self.clock.now() + pause >= deadline {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("Not connected after {:?}: {}", timeout, error)
+                ));
+            }
+
+            self.clock.sleep(pause);
+        }
+
+        Ok(())
+    }
+
+    /// The open port, or `NotConnected`
+    fn port(&mut self) -> Result<&mut Box<dyn Transport>, std::io::Error> {
+        self.connection.port().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotConnected, "Port isn't open")
+        })
+    }
+
+    fn set_connection(&mut self, connection: Connection) {
+        self.connection = connection;
+        self.watch.set(self.connection.state());
+    }
+
+    fn report_error(&mut self, error: &std::io::Error) {
+        self.events.error(&self.path, error);
+
+        if events::is_link_lost(error) {
+            // Nothing more is getting through that port
+            if self.is_connected() {
+                self.set_connection(Connection::Reconnecting);
+            }
+
+            self.report_disconnect();
+        }
+    }
+
+    fn report_disconnect(&mut self) {
+        if !self.link_lost {
+            self.link_lost = true;
+            self.events.disconnected(&self.path);
+        }
+    }
+
+    /// Called with the port path whenever `reconnect()` succeeds
+    pub fn on_connect(&mut self, callback: impl FnMut(&str) + Send + 'static) {
+        self.events.on_connect(callback);
+    }
+
+    /// Called once when the port stops working, until it's reconnected
+    pub fn on_disconnect(&mut self, callback: impl FnMut(&str) + Send + 'static) {
+        self.events.on_disconnect(callback);
+    }
+
+    /// Called for every failed write, read or reconnect
+    pub fn on_error(&mut self, callback: impl FnMut(&str, &std::io::Error) + Send + 'static) {
+        self.events.on_error(callback);
+    }
+
+    /// Column and row order used when staging. Change this for hardware
+    /// that isn't wired like the Framework module.
+    pub fn set_remap(&mut self, remap: Remap) {
+        self.remap = remap;
+    }
+
+    pub fn remap(&self) -> &Remap {
+        &self.remap
+    }
+
+    /// Correction applied to every staged column, see `GammaMap`
+    pub fn set_gamma(&mut self, gamma: GammaMap) {
+        self.gamma = gamma;
+    }
+
+    pub fn gamma(&self) -> &GammaMap {
+        &self.gamma
+    }
+
+    /// Set the brightness as a percentage of how bright the panel looks at
+    /// full, see `gamma::perceptual_brightness()`
+    pub fn set_brightness_percent(&mut self, percent: u8) -> Result<usize, std::io::Error> {
+        self.execute(Command::Brightness(gamma::perceptual_brightness(percent)))
+    }
+
+    /// Register what gets shown when `shutdown()` is called or the matrix is
+    /// dropped. The brightness, if any, is set before the screen is drawn.
+    pub fn set_shutdown_screen(&mut self, screen: ShutdownScreen, brightness: Option<u8>) {
+        self.shutdown_screen = screen;
+        self.shutdown_brightness = brightness;
+    }
+
+    pub fn shutdown_screen(&self) -> &ShutdownScreen {
+        &self.shutdown_screen
+    }
+
+    /// Register what gets shown every time the port is reconnected
+    pub fn set_startup_screen(&mut self, screen: StartupScreen) {
+        self.startup_screen = screen;
+    }
+
+    pub fn startup_screen(&self) -> &StartupScreen {
+        &self.startup_screen
+    }
+
+    /// Show the startup screen now. `reconnect()` calls this for you.
+    pub fn play_startup_screen(&mut self) -> Result<(), std::io::Error> {
+        // Taken out while it plays so a reconnect halfway through doesn't
+        // start it over again
+        let screen = std::mem::take(&mut self.startup_screen);
+
+        let result = match &screen {
+            StartupScreen::Nothing => Ok(()),
+            StartupScreen::Pattern(pattern) => {
+                self.execute(Command::Pattern(pattern.clone())).map(|_| ())
+            },
+            StartupScreen::Greyscale(bitmap) => self.stage_frame(bitmap),
+            StartupScreen::Animation(animation) => {
+                animation::Animator::new(animation.clone(), animation::PlayMode::Once).play(self)
+            },
+        };
+
+        self.startup_screen = screen;
+
+        result
+    }
+
+    /// Show the shutdown screen. This is a no-op if the port isn't open.
+    pub fn shutdown(&mut self) -> Result<(), std::io::Error> {
+        if !self.is_connected() {
+            return Ok(());
+        }
+
+        if let Some(brightness) = self.shutdown_brightness {
+            self.execute(Command::Brightness(brightness))?;
+        }
+
+        match self.shutdown_screen.clone() {
+            ShutdownScreen::Unchanged => (),
+            ShutdownScreen::Clear => self.clear()?,
+            ShutdownScreen::Sleep => {
+                self.execute(Command::Sleep(true))?;
+            },
+            ShutdownScreen::Pattern(pattern) => {
+                self.execute(Command::Pattern(pattern))?;
+            },
+            ShutdownScreen::Draw(bitmap) => {
+                self.execute(Command::Draw(bitmap))?;
+            },
+            ShutdownScreen::Greyscale(bitmap) => {
+                self.stage_frame(&bitmap)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Turn every LED off. Sent as one black on/off frame rather than nine
+    /// columns, and without going through the filters.
+    pub fn clear(&mut self) -> Result<(), std::io::Error> {
+        self.execute(Command::Draw(Box::default()))?;
+
+        Ok(())
+    }
+
+    /// Put the module back the way it starts up: awake, not animating, at
+    /// the default brightness and blank
+    pub fn reset_state(&mut self) -> Result<(), std::io::Error> {
+        self.execute(Command::Sleep(false))?;
+        // The padding after Animate's ID is read as "off"
+        self.execute(Command::Animate)?;
+        self.execute(Command::Brightness(DEFAULT_BRIGHTNESS))?;
+        self.clear()
+    }
+
+    /// Stage every column of a greyscale bitmap and then draw it
+    pub(crate) fn stage_frame(&mut self, bitmap: &Bitmap8) -> Result<(), std::io::Error> {
+        if !self.supports(CommandKind::StageColumn) {
+            let frame = self.filtered(bitmap);
+            return self.draw_binary_fallback(&frame);
+        }
+
+        self.stage_columns(bitmap)?;
+        self.execute(Command::DrawBuffer)?;
+
+        Ok(())
+    }
+
+    /// Draw an already filtered greyscale frame on firmware too old to
+    /// stage columns, thresholded to black and white
+    pub(crate) fn draw_binary_fallback(&mut self, frame: &Bitmap8) -> Result<(), std::io::Error> {
+        let binary = frame.to_binary(capabilities::BINARY_FALLBACK_THRESHOLD);
+        self.execute(Command::Draw(Box::new(binary)))?;
+
+        Ok(())
+    }
+
+    /// Post-processing applied to every greyscale frame this matrix draws
+    pub fn filters_mut(&mut self) -> &mut Pipeline {
+        &mut self.filters
+    }
+
+    pub fn filters(&self) -> &Pipeline {
+        &self.filters
+    }
+
+    /// Time how long the filters take on each frame and report frames that
+    /// take too long. `None` stops timing.
+    pub fn set_performance_budget(&mut self, budget: Option<PerformanceBudget>) {
+        self.budget = budget;
+    }
+
+    pub fn performance_budget(&self) -> Option<&PerformanceBudget> {
+        self.budget.as_ref()
+    }
+
+    /// Copy of a frame with the filter pipeline applied
+    pub(crate) fn filtered(&mut self, bitmap: &Bitmap8) -> Bitmap8 {
+        let mut frame = bitmap.clone();
+
+        match &mut self.budget {
+            Some(budget) => budget.measure(|| self.filters.apply(&mut frame)),
+            None => self.filters.apply(&mut frame),
+        }
+
+        frame
+    }
+
+    /// What `execute()` does when the port goes away or a write times out.
+    /// Reconnects a few times with backoff unless told otherwise.
+    pub fn set_reconnect_policy(&mut self, policy: ReconnectPolicy) {
+        self.reconnect_policy = policy;
+    }
+
+    pub fn reconnect_policy(&self) -> ReconnectPolicy {
+        self.reconnect_policy
+    }
+
+    /// How many times a staged column is resent after timing out before
+    /// the whole frame is sent again. Zero turns retrying off. These only
+    /// happen with a reconnect policy of `never()`, otherwise the policy
+    /// already resends the column and retrying on top would multiply the
+    /// tries one dead module takes.
+    pub fn set_column_retries(&mut self, retries: u32) {
+        self.column_retries = retries;
+    }
+
+    pub fn column_retries(&self) -> u32 {
+        self.column_retries
+    }
+
+    /// Column retries, unless the reconnect policy is doing the retrying
+    fn staging_retries(&self) -> u32 {
+        match self.reconnect_policy.max_retries {
+            0 => self.column_retries,
+            _ => 0,
+        }
+    }
+
+    /// Stage every column of a greyscale bitmap without drawing it. If a
+    /// column runs out of retries the frame is staged once more from the
+    /// start, since the firmware may have taken part of it.
+    pub(crate) fn stage_columns(&mut self, bitmap: &Bitmap8) -> Result<(), std::io::Error> {
+        let bitmap = &self.filtered(bitmap);
+
+        match self.stage_all_columns(bitmap) {
+            Err(error) if error.kind() == std::io::ErrorKind::TimedOut && self.staging_retries() > 0 => {
+                self.stage_all_columns(bitmap)
+            },
+            x => x,
+        }
+    }
+
+    fn stage_all_columns(&mut self, bitmap: &Bitmap8) -> Result<(), std::io::Error> {
+        for x in 0 .. DISPLAY_WIDTH {
+            self.stage_column(bitmap, x)?;
+        }
+
+        Ok(())
+    }
+
+    /// Stage one column of a greyscale bitmap, retrying time outs. Filters
+    /// aren't applied, this is for frames that have already been through
+    /// `filtered()`.
+    pub(crate) fn stage_column(&mut self, bitmap: &Bitmap8, x: usize) -> Result<(), std::io::Error> {
+        let index = ColumnIndex::new(x).ok_or(PackError::Column(x as u8))?;
+        let update = ColumnUpdate::from_bitmap(bitmap, index);
+        let mut attempt = 0;
+
+        loop {
+            match self.execute(Command::StageColumnBuffer(update)) {
+                Ok(_) => return Ok(()),
+                Err(error) if error.kind() == std::io::ErrorKind::TimedOut && attempt < self.staging_retries() => {
+                    attempt += 1;
+                },
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+/// Stop a query going out padded, where it would set something instead
+#[cfg(feature = "std")]
+pub(crate) fn refuse_query(command: &Command) -> Result<(), std::io::Error> {
+    if command.is_query() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Queries have to go through query()"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Stop a set going out short to `query()`, where it would only time out
+/// waiting on a reply that never comes
+#[cfg(feature = "std")]
+pub(crate) fn refuse_set(command: &Command) -> Result<(), std::io::Error> {
+    if !command.is_query() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Only queries have a reply, send this through execute()"
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+/// Pack a command into `buffer`, header and all, with `remap` and `gamma`
+/// applied. Returns how many bytes of the buffer were used.
+pub(crate) fn encode(remap: &Remap, gamma: &GammaMap, command: Command, buffer: &mut [u8; MAX_COMMAND_LENGTH]) -> Result<usize, std::io::Error> {
+    let command = match command {
+        Command::StageColumnBuffer(mut update) if !(remap.is_identity() && gamma.is_linear()) => {
+            let mut column = [0u8; DISPLAY_HEIGHT];
+
+            gamma.apply_column(&mut update.pixels);
+            remap.apply_column(&update.pixels, &mut column);
+
+            // A remap is always a shuffle of the columns there are
+            Command::StageColumnBuffer(ColumnUpdate {
+                index: ColumnIndex(remap.column(update.index.0)),
+                pixels: column,
+            })
+        },
+        Command::Draw(bitmap) if !remap.is_identity() => {
+            Command::Draw(Box::new(remap.apply_bitmap(&bitmap)))
+        },
+        x => x
+    };
+
+    Ok(command.encode(buffer)?)
+}
+
+#[cfg(feature = "std")]
+impl Drop for LedMatrix {
+    fn drop(&mut self) {
+        // Nothing useful can be done with an error at this point
+        let _ = self.shutdown();
+    }
+}
+
+
+/// What a no_std build has, the bitmaps and the command encoder
+#[cfg(test)]
+mod bitmap_tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn wrapping_and_saturating_canvases() {
+        let mut canvas = Bitmap8::new();
+        canvas.set_edge_mode(EdgeMode::Wrap);
+
+        canvas.set_pixel(Point::new(-1, -1), 1);
+        assert_eq!(canvas.pixel(Point::new(8, 33)), Some(1));
+        assert_eq!(canvas.pixel(Point::new(-1, 33)), Some(1));
+
+        canvas.fill_rect(Rect::new((7, 32), (3, 3)), 2);
+        assert_eq!(canvas.pixel(Point::new(0, 0)), Some(2));
+        assert_eq!(canvas.pixel(Point::new(7, 0)), Some(2));
+        assert_eq!(canvas.pixel(Point::new(2, 0)), Some(0));
+
+        let mut canvas = Bitmap8::new();
+        canvas.set_edge_mode(EdgeMode::Saturate);
+
+        canvas.fill_rect(Rect::new((-5, 40), (3, 3)), 3);
+        assert_eq!(canvas.data().iter().filter(|x| **x != 0).count(), 1);
+        assert_eq!(canvas.pixel(Point::new(0, 33)), Some(3));
+    }
+
+    #[test]
+    fn blitting() {
+        let mut sprite = Bitmap8::new();
+        sprite.fill(9);
+        sprite.fill_rect(Rect::new((0, 0), (2, 2)), 1);
+
+        let mut canvas = Bitmap8::new();
+        canvas.fill(5);
+        canvas.blit_masked(&sprite, -1, 30, 9);
+
+        assert_eq!(canvas.pixel(Point::new(0, 30)), Some(1));
+        assert_eq!(canvas.pixel(Point::new(1, 30)), Some(5));
+        assert_eq!(canvas.pixel(Point::new(0, 32)), Some(5));
+
+        canvas.blit(&sprite, 7, -33);
+        assert_eq!(canvas.pixel(Point::new(7, 0)), Some(9));
+        assert_eq!(canvas.pixel(Point::new(6, 0)), Some(5));
+        assert_eq!(canvas.pixel(Point::new(7, 1)), Some(5));
+    }
+
+    #[test]
+    fn column_and_row_views() {
+        let mut canvas = Bitmap8::new();
+        canvas.set_pixel(Point::new(2, 5), 7);
+
+        for (x, column) in canvas.columns_mut().enumerate() {
+            column[0] = x as u8;
+        }
+
+        assert_eq!(canvas.columns().len(), DISPLAY_WIDTH);
+        assert_eq!(canvas.column(2).unwrap()[5], 7);
+        assert!(canvas.column(DISPLAY_WIDTH).is_none());
+        assert_eq!(canvas.pixel(Point::new(4, 0)), Some(4));
+
+        let rows: Vec<_> = canvas.rows().collect();
+        assert_eq!(rows.len(), DISPLAY_HEIGHT);
+        assert_eq!(rows[0], [0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(rows[5][2], 7);
+    }
+
+    #[test]
+    fn scrolling_and_rotating() {
+        let mut canvas = Bitmap8::new();
+        canvas.set_pixel(Point::new(0, 0), 1);
+        canvas.set_pixel(Point::new(8, 33), 2);
+
+        let mut scrolled = canvas.clone();
+        scrolled.scroll_up(1, 9);
+        assert_eq!(scrolled.pixel(Point::new(8, 32)), Some(2));
+        assert_eq!(scrolled.pixel(Point::new(0, 33)), Some(9));
+        assert_eq!(scrolled.data().iter().filter(|x| **x == 1).count(), 0);
+
+        scrolled.scroll_right(2, 0);
+        assert_eq!(scrolled.column(0).unwrap(), &[0; DISPLAY_HEIGHT]);
+        assert_eq!(scrolled.pixel(Point::new(2, 33)), Some(9));
+
+        scrolled.scroll_down(100, 5);
+        scrolled.scroll_left(100, 5);
+        assert!(scrolled.data().iter().all(|x| *x == 5));
+
+        let mut rotated = canvas.clone();
+        rotated.rotate_down(1);
+        rotated.rotate_left(DISPLAY_WIDTH + 1);
+        assert_eq!(rotated.pixel(Point::new(7, 0)), Some(2));
+        assert_eq!(rotated.pixel(Point::new(8, 1)), Some(1));
+
+        rotated.rotate_right(1);
+        rotated.rotate_up(DISPLAY_HEIGHT + 1);
+        assert_eq!(rotated.data(), canvas.data());
+    }
+
+    #[test]
+    fn binary_and_greyscale_conversion() {
+        let mut greyscale = Bitmap8::new();
+        greyscale.set_pixel(Point::new(0, 0), 0x80);
+        greyscale.set_pixel(Point::new(3, 20), 0xff);
+        greyscale.set_pixel(Point::new(8, 33), 0x7f);
+
+        let binary = greyscale.to_binary(0x80);
+        assert_eq!(binary.pixel(Point::new(0, 0)), Some(true));
+        assert_eq!(binary.pixel(Point::new(3, 20)), Some(true));
+        assert_eq!(binary.pixel(Point::new(8, 33)), Some(false));
+        assert_eq!(binary.pixel(Point::new(9, 0)), None);
+
+        let back = binary.to_greyscale(0x40);
+        assert_eq!(back.pixel(Point::new(3, 20)), Some(0x40));
+        assert_eq!(back.data().iter().filter(|x| **x != 0).count(), 2);
+    }
+
+    #[test]
+    fn encodes_without_a_matrix() {
+        let mut buffer = [0u8; MAX_COMMAND_LENGTH];
+
+        assert_eq!(Command::Brightness(0x40).encode(&mut buffer), Ok(4));
+        assert_eq!(buffer[.. 4], [0x32, 0xac, 0x00, 0x40]);
+
+        assert_eq!(
+            Command::Pattern(Patterns::Percentage(101)).encode(&mut buffer),
+            Err(PackError::Percentage(101))
+        );
+    }
+}
+
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use clock::ManualClock;
+    use sysinfo::System;
+    use transport::MockTransport;
+
+    /// A matrix on a mock transport, and the mock to check what it was sent
+    fn mock_matrix() -> (LedMatrix, MockTransport) {
+        let mock = MockTransport::new();
+
+        (LedMatrix::with_transport("mock", mock.clone()), mock)
+    }
+
+    /// Every full length command written, minus the header
+    fn packets(mock: &MockTransport) -> Vec<Vec<u8>> {
+        let written = mock.take_written();
+
+        written.chunks(MAX_COMMAND_LENGTH)
+            .inspect(|x| assert_eq!(x[..2], [0x32, 0xac]))
+            .map(|x| x[2..].to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn set_brightness() {
+        let (mut matrix, mock) = mock_matrix();
+
+        let command = Command::Brightness(0x40);
+
+        assert_eq!(matrix.execute(command).expect("Command failed"), MAX_COMMAND_LENGTH);
+        assert_eq!(packets(&mock)[0][..2], [0x00, 0x40]);
+    }
+
+    #[test]
+    fn wake() {
+        let (mut matrix, mock) = mock_matrix();
+
+        let command = Command::Sleep(false);
+
+        matrix.execute(command).expect("Command failed");
+        assert_eq!(packets(&mock)[0][..2], [0x03, 0x00]);
+    }
+
+    #[test]
+    fn draw() {
+        let (mut matrix, mock) = mock_matrix();
+
+        let command = Command::Brightness(0xff);
+        matrix.execute(command).expect("Command failed");
+
+        let mut bitmap = Bitmap::new();
+        bitmap.draw_point(0, 0, true).unwrap();
+        bitmap.draw_point(4, 0, true).unwrap();
+        bitmap.draw_point(4, 4, true).unwrap();
+        bitmap.draw_point(0, 4, true).unwrap();
+
+        let command = Command::Draw(Box::new(bitmap.clone()));
+        matrix.execute(command).expect("Command failed");
+
+        let packet = &packets(&mock)[1];
+        assert_eq!(packet[0], 0x06);
+        assert_eq!(packet[1 .. DRAW_COMMAND_LENGTH + 1], bitmap.data);
+    }
+
+    #[test]
     fn draw_greyscale() {
         const BG_VALUE: u8 = 2;
 
-        let mut matrix = LedMatrix::new("/dev/ttyACM1")
-            .expect("Unable to open port");
+        let (mut matrix, mock) = mock_matrix();
         let mut sys = System::new();
         let mut image = Bitmap8::new();
 
         let command = Command::Brightness(0xff);
         matrix.execute(command).expect("Command failed");
 
-        loop {
-            let mut cpus = Vec::new();
-            //std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+        // One frame of what used to loop forever on a real module
+        let mut cpus = Vec::new();
+        //std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
 
-            sys.refresh_cpu(); // Refreshing CPU information.
-            for cpu in sys.cpus() {
-                cpus.push(cpu.cpu_usage() as u8);
-            }
-        
-            image.fill(BG_VALUE);
-            image.draw_box(0, DISPLAY_HEIGHT - 20, DISPLAY_WIDTH - 1, DISPLAY_HEIGHT - 1, 0);
-            image.draw_box(0, DISPLAY_HEIGHT - 19, DISPLAY_WIDTH - 1, DISPLAY_HEIGHT - 2, BG_VALUE);
-            image.draw_box(DISPLAY_WIDTH / 2, DISPLAY_HEIGHT - 19, DISPLAY_WIDTH / 2, DISPLAY_HEIGHT - 2, 0);
-
-            for (mut index, cpu) in sys.cpus().iter().take(8).enumerate() {
-                let value = cpu.cpu_usage() as usize;
-                let col_start = DISPLAY_HEIGHT - 2 - ((17 * value) / 100);
-                let col_end = DISPLAY_HEIGHT - 2;
-
-                // Skip over the middle. This is *all yucky*
-                if index > 3 {
-                    index += 1;
-                }
+        sys.refresh_cpu(); // Refreshing CPU information.
+        for cpu in sys.cpus() {
+            cpus.push(cpu.cpu_usage() as u8);
+        }
 
-                image.draw_box(index, col_start, index, col_end, 20);
-            }
+        image.fill(BG_VALUE);
+        image.draw_box(0, DISPLAY_HEIGHT - 20, DISPLAY_WIDTH - 1, DISPLAY_HEIGHT - 1, 0);
+        image.draw_box(0, DISPLAY_HEIGHT - 19, DISPLAY_WIDTH - 1, DISPLAY_HEIGHT - 2, BG_VALUE);
+        image.draw_box(DISPLAY_WIDTH / 2, DISPLAY_HEIGHT - 19, DISPLAY_WIDTH / 2, DISPLAY_HEIGHT - 2, 0);
 
-            for y in 0 .. DISPLAY_WIDTH {
-                let col_start = y * DISPLAY_HEIGHT;
-                let col_end = col_start + DISPLAY_HEIGHT;
+        for (mut index, cpu) in sys.cpus().iter().take(8).enumerate() {
+            let value = cpu.cpu_usage() as usize;
+            let col_start = DISPLAY_HEIGHT - 2 - ((17 * value) / 100);
+            let col_end = DISPLAY_HEIGHT - 2;
 
-                let command = Command::StageColumnBuffer((y as u8, &image.data[col_start..col_end]));
-                matrix.execute(command).expect("Command failed");
+            // Skip over the middle. This is *all yucky*
+            if index > 3 {
+                index += 1;
             }
 
-            let command = Command::DrawBuffer;
+            image.draw_box(index, col_start, index, col_end, 20);
+        }
+
+        for index in ColumnIndex::all() {
+            let command = Command::StageColumnBuffer(ColumnUpdate::from_bitmap(&image, index));
             matrix.execute(command).expect("Command failed");
         }
+
+        let command = Command::DrawBuffer;
+        matrix.execute(command).expect("Command failed");
+
+        let packets = &packets(&mock)[1 ..];
+        assert_eq!(packets.len(), DISPLAY_WIDTH + 1);
+
+        for (x, packet) in packets[.. DISPLAY_WIDTH].iter().enumerate() {
+            assert_eq!(packet[..2], [0x07, x as u8]);
+            assert_eq!(packet[2 .. DISPLAY_HEIGHT + 2], image.data[x * DISPLAY_HEIGHT .. (x + 1) * DISPLAY_HEIGHT]);
+        }
+
+        assert_eq!(packets[DISPLAY_WIDTH][0], 0x08);
     }
 
+    #[test]
+    fn gamma_is_applied_when_packing() {
+        let (mut matrix, mock) = mock_matrix();
+        matrix.set_gamma(GammaMap::curve(2.0));
+
+        let mut column = [0u8; DISPLAY_HEIGHT];
+        column[0] = 0xff;
+        column[1] = 0x80;
+        matrix.execute(Command::StageColumnBuffer(ColumnUpdate::from_slice(3, &column).unwrap())).unwrap();
+        matrix.set_brightness_percent(100).unwrap();
+
+        let packets = packets(&mock);
+        assert_eq!(packets[0][.. 4], [0x07, 3, 0xff, 0x40]);
+        assert_eq!(packets[1][.. 2], [0x00, 0xff]);
+    }
 
     #[test]
     fn display_progress() {
-        let mut matrix = LedMatrix::new("/dev/ttyACM1")
-            .expect("Unable to open port");
+        let (mut matrix, mock) = mock_matrix();
 
         let command = Command::Brightness(25);
         matrix.execute(command).expect("Command failed");
@@ -379,6 +2383,367 @@ mod tests {
         }
 
         let command = Command::Pattern(Patterns::DisplayLotus2);
-        matrix.execute(command).expect("Command failed");    
+        matrix.execute(command).expect("Command failed");
+
+        let packets = packets(&mock);
+        assert_eq!(packets[51][..3], [0x01, 0x00, 50]);
+        assert_eq!(packets[102][..2], [0x01, 0x07]);
+    }
+
+    #[test]
+    fn out_of_range_percentages_are_refused() {
+        let (mut matrix, mock) = mock_matrix();
+
+        let error = matrix.execute(Command::Pattern(Patterns::Percentage(101))).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(mock.take_written().is_empty());
+        assert_eq!(Patterns::progress(1.5), Patterns::Percentage(100));
+        assert_eq!(Patterns::progress(0.254), Patterns::Percentage(25));
+    }
+
+    #[test]
+    fn columns_stay_on_the_panel() {
+        let column = [0u8; DISPLAY_HEIGHT];
+        assert_eq!(ColumnIndex::new(DISPLAY_WIDTH - 1).map(|x| x.get()), Some(8));
+        assert_eq!(ColumnIndex::new(DISPLAY_WIDTH), None);
+        assert_eq!(ColumnUpdate::from_slice(DISPLAY_WIDTH as u8, &column), Err(PackError::Column(9)));
+        assert_eq!(ColumnUpdate::from_slice(0, &column[1..]), Err(PackError::ColumnLength(33)));
+    }
+
+    #[test]
+    fn broken_pipe_reconnects_and_retries() {
+        let (mut matrix, mock) = mock_matrix();
+        matrix.set_reconnect_policy(ReconnectPolicy { initial_delay: Duration::ZERO, ..ReconnectPolicy::default() });
+
+        mock.fail_next_write(std::io::ErrorKind::BrokenPipe);
+        matrix.execute(Command::Brightness(1)).expect("Command failed");
+        assert_eq!(packets(&mock).len(), 1);
+
+        for _ in 0 ..= matrix.reconnect_policy().max_retries {
+            mock.fail_next_write(std::io::ErrorKind::BrokenPipe);
+        }
+
+        let error = matrix.execute(Command::Brightness(1)).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::BrokenPipe);
+        assert_eq!(RetriesExhausted::find(&error).map(|x| x.attempts), Some(3));
+    }
+
+    #[test]
+    fn retries_wait_on_the_clock() {
+        let clock = ManualClock::new();
+        let (mut matrix, mock) = mock_matrix();
+        matrix.set_clock(clock.clone());
+
+        let start = clock.now();
+        let policy = matrix.reconnect_policy();
+
+        for _ in 0 ..= policy.max_retries {
+            mock.fail_next_write(std::io::ErrorKind::BrokenPipe);
+        }
+
+        assert!(matrix.execute(Command::Brightness(1)).is_err());
+        let backoff = (0 .. policy.max_retries).map(|x| policy.delay(x)).sum::<Duration>();
+        assert_eq!(clock.elapsed(start), backoff);
+
+        // The throttle's gap is taken from it as well
+        matrix.set_throttle(throttle::Throttle::new().min_gap(Duration::from_secs(1)));
+        matrix.execute(Command::Brightness(2)).unwrap();
+
+        let start = clock.now();
+        matrix.execute(Command::Brightness(3)).unwrap();
+        assert_eq!(clock.elapsed(start), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn staging_retries_once_per_layer() {
+        let clock = ManualClock::new();
+        let (mut matrix, mock) = mock_matrix();
+        matrix.set_clock(clock.clone());
+        let policy = matrix.reconnect_policy();
+        let start = clock.now();
+
+        // The policy gets its tries at the first column and nothing more
+        for _ in 0 ..= policy.max_retries {
+            mock.fail_next_write(std::io::ErrorKind::TimedOut);
+        }
+
+        let error = matrix.stage_columns(&Bitmap8::new()).unwrap_err();
+        assert_eq!(RetriesExhausted::find(&error).map(|x| x.attempts), Some(policy.max_retries));
+        assert_eq!(clock.elapsed(start), (0 .. policy.max_retries).map(|x| policy.delay(x)).sum());
+        assert!(packets(&mock).is_empty());
+
+        // Without one the column is resent instead
+        matrix.set_reconnect_policy(ReconnectPolicy::never());
+        matrix.reconnect().unwrap();
+        let start = clock.now();
+
+        for _ in 0 ..= matrix.column_retries() {
+            mock.fail_next_write(std::io::ErrorKind::TimedOut);
+        }
+
+        matrix.stage_columns(&Bitmap8::new()).unwrap();
+        assert_eq!(packets(&mock).len(), DISPLAY_WIDTH);
+        assert_eq!(clock.elapsed(start), Duration::ZERO);
+    }
+
+    #[test]
+    fn throttled_commands_are_spaced_out() {
+        let clock = ManualClock::new();
+        let mock = MockTransport::new();
+        let mut matrix = LedMatrix::builder("mock")
+            .clock(clock.clone())
+            .throttle(throttle::Throttle::new().min_gap(Duration::from_millis(5)))
+            .open_transport(mock.clone())
+            .unwrap();
+
+        let start = clock.now();
+        let mut batch = matrix.batch();
+        batch.push(Command::Brightness(1)).unwrap();
+        batch.push(Command::Brightness(2)).unwrap();
+        batch.push(Command::Brightness(3)).unwrap();
+        batch.flush().unwrap();
+
+        assert_eq!(packets(&mock).len(), 3);
+        assert_eq!(clock.elapsed(start), Duration::from_millis(10));
+    }
+
+    #[test]
+    fn stalled_modules_are_flagged() {
+        let (mut matrix, mock) = mock_matrix();
+        matrix.set_verification(Verification::EveryCommand);
+        matrix.set_reconnect_policy(ReconnectPolicy { initial_delay: Duration::ZERO, ..ReconnectPolicy::default() });
+
+        // The mock hands a reply to whatever's written next, so the first is
+        // drained as unsolicited and the second answers the check
+        mock.push_reply(&[]);
+        mock.push_reply(&[0, 0, 0]);
+        matrix.execute(Command::Brightness(1)).unwrap();
+        assert_eq!(packets(&mock).iter().map(|x| x[0]).collect::<Vec<_>>(), [0x00, 0x20]);
+
+        let error = matrix.execute(Command::Brightness(2)).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+        assert_eq!(matrix.stalls(), 1);
+        assert!(!matrix.is_connected());
+
+        // The next command reconnects before sending
+        matrix.set_verification(Verification::Off);
+        matrix.execute(Command::Brightness(3)).unwrap();
+        assert!(matrix.is_connected());
+    }
+
+    #[test]
+    fn short_writes_are_finished_or_resent() {
+        let (mut matrix, mock) = mock_matrix();
+        matrix.set_reconnect_policy(ReconnectPolicy { initial_delay: Duration::ZERO, ..ReconnectPolicy::default() });
+
+        // A port that takes a few bytes at a time still gets all of it
+        mock.short_next_write(5);
+        mock.short_next_write(5);
+        assert_eq!(matrix.execute(Command::Brightness(1)).unwrap(), MAX_COMMAND_LENGTH);
+        let sent = packets(&mock);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0][..2], [0x00, 1]);
+
+        // Losing the port halfway resends the command whole
+        mock.short_next_write(10);
+        mock.fail_next_write(std::io::ErrorKind::BrokenPipe);
+        matrix.execute(Command::Brightness(2)).unwrap();
+
+        let written = mock.take_written();
+        assert_eq!(written.len(), 10 + MAX_COMMAND_LENGTH);
+        assert_eq!(written[10 .. 14], [0x32, 0xac, 0x00, 2]);
+        assert_eq!(matrix.commands_delivered(), 2);
+        assert_eq!(matrix.partial_writes(), 1);
+    }
+
+    #[test]
+    fn startup_screen_plays_on_reconnect() {
+        let (mut matrix, mock) = mock_matrix();
+        matrix.set_reconnect_policy(ReconnectPolicy { initial_delay: Duration::ZERO, ..ReconnectPolicy::default() });
+        matrix.set_startup_screen(StartupScreen::Pattern(Patterns::FullBrightness));
+
+        mock.fail_next_write(std::io::ErrorKind::BrokenPipe);
+        matrix.execute(Command::Brightness(1)).expect("Command failed");
+
+        // The pattern goes up as soon as the port is back, then the retry
+        let written = packets(&mock);
+        assert_eq!(written.len(), 2);
+        assert_eq!(written[0][..2], [0x01, 0x05]);
+        assert_eq!(written[1][..2], [0x00, 1]);
+
+        let animation = animation::Animation::from_frames(vec![Bitmap8::new(); 2], Duration::ZERO);
+        let mock = MockTransport::new();
+        let matrix = LedMatrix::builder("mock")
+            .startup_screen(StartupScreen::Animation(animation))
+            .open_transport(mock.clone())
+            .unwrap();
+
+        assert_eq!(packets(&mock).len(), 2 * (DISPLAY_WIDTH + 1));
+        assert!(matches!(matrix.startup_screen(), StartupScreen::Animation(_)));
+    }
+
+    #[test]
+    fn dropping_leaves_the_shutdown_screen() {
+        let mock = MockTransport::new();
+        let mut config = config::Config::new();
+        config.on_exit = config::ExitScreen::Clear;
+
+        let matrix = LedMatrix::builder("mock").config(&config).open_transport(mock.clone()).unwrap();
+        assert!(matches!(matrix.shutdown_screen(), ShutdownScreen::Clear));
+        drop(matrix);
+
+        let written = packets(&mock);
+        assert_eq!(written.len(), 1);
+        assert_eq!(written[0][0], 0x06);
+        assert!(written[0][1 ..].iter().all(|x| *x == 0));
+
+        let matrix = LedMatrix::builder("mock")
+            .shutdown_screen(ShutdownScreen::Sleep, Some(0x10))
+            .open_transport(mock.clone())
+            .unwrap();
+
+        // Unwinding from a panic drops it too
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+            let _matrix = matrix;
+            panic!("Dashboard crashed");
+        }));
+
+        let written = packets(&mock);
+        assert_eq!(written[0][..2], [0x00, 0x10]);
+        assert_eq!(written[1][..2], [0x03, 1]);
+    }
+
+    #[test]
+    fn raw_commands() {
+        let (mut matrix, mock) = mock_matrix();
+
+        matrix.execute_raw(0x1f, &[1]).expect("Command failed");
+        assert_eq!(packets(&mock)[0][..3], [0x1f, 1, 0]);
+        assert_eq!(Command::Raw { id: 0x1f, payload: &[] }.kind(), Some(CommandKind::DebugMode));
+        assert_eq!(Command::Raw { id: 0x99, payload: &[] }.kind(), None);
+
+        let error = matrix.execute_raw(0x99, &[0; MAX_RAW_PAYLOAD + 1]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(mock.take_written().is_empty());
+
+        matrix.execute_raw(0x99, &[7; MAX_RAW_PAYLOAD]).expect("Command failed");
+        assert_eq!(packets(&mock)[0][1..], [7; MAX_RAW_PAYLOAD]);
+    }
+
+    #[test]
+    fn animate_period_packs() {
+        let mut data = [0u8; MAX_COMMAND_LENGTH];
+
+        assert_eq!(Command::AnimatePeriod(Duration::from_millis(300)).pack(&mut data).unwrap(), 3);
+        assert_eq!(data[..3], [0x1c, 0x2c, 0x01]);
+
+        assert_eq!(Command::AnimatePeriod(Duration::from_secs(3600)).pack(&mut data).unwrap(), 3);
+        assert_eq!(data[..3], [0x1c, 0xff, 0xff]);
+
+        assert_eq!(Command::AnimateQuery.pack(&mut data).unwrap(), 1);
+        assert_eq!(data[0], 0x04);
+    }
+
+    #[test]
+    fn brightness_and_sleep_queries() {
+        let (mut matrix, mock) = mock_matrix();
+
+        mock.push_reply(&[0x40]);
+        assert_eq!(matrix.current_brightness().unwrap(), 0x40);
+        assert_eq!(mock.take_written(), [0x32, 0xac, 0x00]);
+
+        mock.push_reply(&[1]);
+        assert_eq!(matrix.query(Command::GetSleep).unwrap(), Response::Sleeping(true));
+        assert_eq!(mock.take_written(), [0x32, 0xac, 0x03]);
+        assert_eq!(Command::GetSleep.kind(), Some(CommandKind::Sleep));
+    }
+
+    #[test]
+    fn execute_refuses_queries() {
+        let (mut matrix, mock) = mock_matrix();
+
+        for query in [Command::GetBrightness, Command::GetSleep, Command::Version] {
+            assert_eq!(matrix.execute(query).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+        }
+
+        assert!(matrix.batch().push(Command::GetSleep).is_err());
+        assert!(mock.take_written().is_empty());
+
+        // Only the ID goes out for the query, and a set keeps its padding
+        mock.push_reply(&[0x40]);
+        matrix.query(Command::GetBrightness).unwrap();
+        assert_eq!(mock.take_written(), [0x32, 0xac, 0x00]);
+
+        matrix.execute(Command::Brightness(0x40)).unwrap();
+        let mut packet = [0u8; MAX_COMMAND_LENGTH];
+        packet[..4].copy_from_slice(&[0x32, 0xac, 0x00, 0x40]);
+        assert_eq!(mock.take_written(), packet);
+    }
+
+    #[test]
+    fn query_refuses_sets() {
+        let (mut matrix, mock) = mock_matrix();
+
+        for set in [Command::Brightness(0x40), Command::Sleep(false), Command::Animate] {
+            assert_eq!(matrix.query(set).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+        }
+
+        assert!(mock.take_written().is_empty());
+    }
+
+    #[test]
+    fn animate_query_only_asks() {
+        let (mut matrix, mock) = mock_matrix();
+
+        assert_eq!(matrix.execute(Command::AnimateQuery).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+        assert!(mock.take_written().is_empty());
+
+        mock.push_reply(&[1]);
+        assert_eq!(matrix.query(Command::AnimateQuery).unwrap(), Response::Animating(true));
+        assert_eq!(mock.take_written(), [0x32, 0xac, 0x04]);
+    }
+
+    #[test]
+    fn debug_mode_sends_its_flag() {
+        let mut data = [0u8; MAX_COMMAND_LENGTH];
+
+        assert_eq!(Command::DebugMode(true).pack(&mut data).unwrap(), 2);
+        assert_eq!(data[..2], [0x1f, 0x01]);
+        assert_eq!(Command::DebugMode(false).pack(&mut data).unwrap(), 2);
+        assert_eq!(data[..2], [0x1f, 0x00]);
+        assert_eq!(Command::DebugMode(true).kind(), Some(CommandKind::DebugMode));
+
+        let (mut matrix, mock) = mock_matrix();
+        matrix.execute(Command::DebugMode(true)).unwrap();
+
+        let mut packet = [0u8; MAX_COMMAND_LENGTH];
+        packet[..4].copy_from_slice(&[0x32, 0xac, 0x1f, 0x01]);
+        assert_eq!(mock.take_written(), packet);
+    }
+
+    #[test]
+    fn pwm_frequency_round_trips() {
+        let mut data = [0u8; MAX_COMMAND_LENGTH];
+
+        for frequency in PwmFrequency::ALL {
+            assert_eq!(Command::PwmFreq(frequency).pack(&mut data).unwrap(), 2);
+            assert_eq!(data[0], 0x1e);
+            assert_eq!(PwmFrequency::from_index(data[1]), Some(frequency));
+        }
+    }
+
+    #[test]
+    fn reset_state_sequence() {
+        let (mut matrix, mock) = mock_matrix();
+
+        matrix.reset_state().unwrap();
+
+        let packets = packets(&mock);
+
+        assert_eq!(packets.iter().map(|x| x[0]).collect::<Vec<_>>(), [0x03, 0x04, 0x00, 0x06]);
+        assert_eq!(packets[0][1], 0);
+        assert_eq!(packets[1][1], 0);
+        assert_eq!(packets[2][1], DEFAULT_BRIGHTNESS);
+        assert!(packets[3][1 ..].iter().all(|x| *x == 0));
     }
 }
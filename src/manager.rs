@@ -0,0 +1,456 @@
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::events::Events;
+use crate::reconnect::ReconnectPolicy;
+use crate::response::RESPONSE_LENGTH;
+use crate::roles::{Role, RoleConfig};
+use crate::{Bitmap8, Command, LedMatrix, DISPLAY_WIDTH};
+
+/// How long to wait between attempts to bring a failed device back
+pub const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Health {
+    Healthy,
+    /// The last command failed. `attempts` counts reconnects tried since.
+    Failed { since: Instant, last_attempt: Instant, attempts: u32 },
+}
+
+struct Entry {
+    matrix: LedMatrix,
+    health: Health,
+}
+
+/// Owns every matrix an application uses, keyed by a name of the caller's
+/// choosing (a role, a serial number, ...). Commands sent through the manager
+/// keep track of which devices are failing and `supervise()` brings them back,
+/// so there's one retry loop instead of one per device.
+pub struct DeviceManager<C: Clock = SystemClock> {
+    devices: BTreeMap<String, Entry>,
+    retry_interval: Duration,
+    clock: C,
+    events: Events,
+}
+
+impl DeviceManager<SystemClock> {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl Default for DeviceManager<SystemClock> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clock> DeviceManager<C> {
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            devices: BTreeMap::new(),
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+            clock,
+            events: Events::new(),
+        }
+    }
+
+    pub fn set_retry_interval(&mut self, interval: Duration) {
+        self.retry_interval = interval;
+    }
+
+    /// Called with the device's name when `supervise()` brings it back
+    pub fn on_connect(&mut self, callback: impl FnMut(&str) + Send + 'static) {
+        self.events.on_connect(callback);
+    }
+
+    /// Called with the device's name when it's marked as failed
+    pub fn on_disconnect(&mut self, callback: impl FnMut(&str) + Send + 'static) {
+        self.events.on_disconnect(callback);
+    }
+
+    /// Called for every command sent through the manager that fails
+    pub fn on_error(&mut self, callback: impl FnMut(&str, &std::io::Error) + Send + 'static) {
+        self.events.on_error(callback);
+    }
+
+    /// Open the port at `path` and manage it under `name`
+    pub fn open(&mut self, name: &str, path: &str) -> Result<(), serialport::Error> {
+        let matrix = LedMatrix::new(path)?;
+        self.insert(name, matrix);
+
+        Ok(())
+    }
+
+    /// Open every module found on the bus, named by serial number (or path
+    /// when there isn't one). Returns how many were opened.
+    pub fn open_discovered(&mut self, roles: &RoleConfig) -> Result<usize, serialport::Error> {
+        let mut opened = 0;
+
+        for device in crate::discovery::discover(roles)? {
+            if let Ok(matrix) = device.open() {
+                self.insert(device.key(), matrix);
+                opened += 1;
+            }
+        }
+
+        Ok(opened)
+    }
+
+    /// Take ownership of an already open matrix. Replaces and returns any
+    /// device that already had this name. Its reconnect policy is set to
+    /// `never()`, since `supervise()` does the reconnecting and a matrix
+    /// retrying by itself would hold up every other device.
+    pub fn insert(&mut self, name: &str, mut matrix: LedMatrix) -> Option<LedMatrix> {
+        matrix.set_reconnect_policy(ReconnectPolicy::never());
+        let health = if matrix.is_connected() {
+            Health::Healthy
+        } else {
+            let now = self.clock.now();
+            Health::Failed { since: now, last_attempt: now, attempts: 0 }
+        };
+
+        self.devices
+            .insert(name.to_owned(), Entry { matrix, health })
+            .map(|x| x.matrix)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<LedMatrix> {
+        self.devices.remove(name).map(|x| x.matrix)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LedMatrix> {
+        self.devices.get(name).map(|x| &x.matrix)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut LedMatrix> {
+        self.devices.get_mut(name).map(|x| &mut x.matrix)
+    }
+
+    /// Find a device by role, for managers whose devices are named by serial
+    /// number
+    pub fn get_by_role_mut(&mut self, roles: &RoleConfig, role: &Role) -> Option<&mut LedMatrix> {
+        let serial = roles.serial(role)?;
+        self.get_mut(serial)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.devices.keys().map(|x| x.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    pub fn health(&self, name: &str) -> Option<Health> {
+        self.devices.get(name).map(|x| x.health)
+    }
+
+    /// Send a command to one device. Devices that are known to be broken
+    /// return `NotConnected` until `supervise()` has reconnected them.
+    pub fn execute(&mut self, name: &str, command: Command) -> Result<usize, std::io::Error> {
+        let result = self.ready(name)?.matrix.execute(command);
+
+        if let Err(error) = &result {
+            self.record_error(name, error);
+        }
+
+        result
+    }
+
+    /// Draw a greyscale frame on each of several devices at once. Columns
+    /// are staged in turn across the devices, the first column of each,
+    /// then the second and so on, and the `DrawBuffer` commands sent back to
+    /// back. Every panel changes at nearly the same time instead of one
+    /// after the other, without any threads.
+    ///
+    /// A device that fails drops out and the rest carry on. Results are in
+    /// the same order as `frames`.
+    pub fn present(&mut self, frames: &[(&str, &Bitmap8)]) -> Vec<(String, Result<(), std::io::Error>)> {
+        let mut results: Vec<(String, Result<(), std::io::Error>)> = Vec::with_capacity(frames.len());
+        let mut staged = Vec::with_capacity(frames.len());
+
+        for (name, frame) in frames {
+            match self.ready(name) {
+                Ok(entry) => {
+                    staged.push((results.len(), entry.matrix.filtered(frame)));
+                    results.push((name.to_string(), Ok(())));
+                },
+                Err(error) => results.push((name.to_string(), Err(error))),
+            }
+        }
+
+        for x in 0 .. DISPLAY_WIDTH {
+            for (index, frame) in &staged {
+                self.present_step(&mut results[*index], |matrix| matrix.stage_column(frame, x));
+            }
+        }
+
+        for (index, _) in &staged {
+            self.present_step(&mut results[*index], |matrix| matrix.execute(Command::DrawBuffer).map(|_| ()));
+        }
+
+        results
+    }
+
+    /// One step of `present()` for a device that hasn't failed yet
+    fn present_step<F>(&mut self, result: &mut (String, Result<(), std::io::Error>), step: F)
+    where F: FnOnce(&mut LedMatrix) -> Result<(), std::io::Error>
+    {
+        if result.1.is_err() {
+            return;
+        }
+
+        let outcome = match self.devices.get_mut(&result.0) {
+            Some(entry) => step(&mut entry.matrix),
+            None => return,
+        };
+
+        if let Err(error) = outcome {
+            self.record_error(&result.0, &error);
+            result.1 = Err(error);
+        }
+    }
+
+    /// The device, if it's there and not waiting to reconnect
+    fn ready(&mut self, name: &str) -> Result<&mut Entry, std::io::Error> {
+        let entry = match self.devices.get_mut(name) {
+            Some(x) => x,
+            None => return Err(std::io::Error::new(ErrorKind::NotFound, "No device with that name")),
+        };
+
+        if entry.health != Health::Healthy || !entry.matrix.is_connected() {
+            return Err(std::io::Error::new(ErrorKind::NotConnected, "Device is waiting to reconnect"));
+        }
+
+        Ok(entry)
+    }
+
+    fn record_error(&mut self, name: &str, error: &std::io::Error) {
+        self.events.error(name, error);
+
+        // Time outs are safe to retry, everything else needs a new port
+        if error.kind() != ErrorKind::TimedOut {
+            let now = self.clock.now();
+
+            if let Some(entry) = self.devices.get_mut(name) {
+                entry.health = Health::Failed { since: now, last_attempt: now, attempts: 0 };
+            }
+
+            self.events.disconnected(name);
+        }
+    }
+
+    /// Send a command to every healthy device. Failures are recorded against
+    /// each device rather than stopping the others.
+    pub fn execute_all(&mut self, command: Command) -> Vec<(String, Result<usize, std::io::Error>)> {
+        let names: Vec<String> = self.devices.keys().cloned().collect();
+
+        names.into_iter()
+            .map(|name| {
+                let result = self.execute(&name, command.clone());
+                (name, result)
+            })
+            .collect()
+    }
+
+    /// Write already packed commands to every healthy device as they are,
+    /// for a `MatrixGroup` mirroring one `LedMatrix` onto all of them.
+    /// Failures are recorded the same way as `execute_all()`.
+    pub(crate) fn send_all(&mut self, packets: &[u8]) -> Vec<(String, Result<usize, std::io::Error>)> {
+        let names: Vec<String> = self.devices.keys().cloned().collect();
+
+        names.into_iter()
+            .map(|name| {
+                let result = self.ready(&name).and_then(|x| x.matrix.send_packets(packets));
+
+                if let Err(error) = &result {
+                    if error.kind() != ErrorKind::NotConnected {
+                        self.record_error(&name, error);
+                    }
+                }
+
+                (name, result)
+            })
+            .collect()
+    }
+
+    /// Put a packed query to the healthy devices in turn until one answers
+    pub(crate) fn ask(&mut self, packet: &[u8], response: &mut [u8; RESPONSE_LENGTH]) -> Result<(), std::io::Error> {
+        let names: Vec<String> = self.devices.keys().cloned().collect();
+        let mut last = std::io::Error::new(ErrorKind::NotConnected, "No device is connected");
+
+        for name in names {
+            let result = match self.ready(&name) {
+                Ok(entry) => entry.matrix.transact(packet, response),
+                Err(_) => continue,
+            };
+
+            match result {
+                Ok(()) => return Ok(()),
+                Err(error) => {
+                    self.record_error(&name, &error);
+                    last = error;
+                },
+            }
+        }
+
+        Err(last)
+    }
+
+    /// Mark a device as broken, for when the caller noticed a problem
+    /// talking to it directly through `get_mut()`
+    pub fn report_failure(&mut self, name: &str) {
+        let now = self.clock.now();
+
+        if let Some(entry) = self.devices.get_mut(name) {
+            if entry.health == Health::Healthy {
+                entry.health = Health::Failed { since: now, last_attempt: now, attempts: 0 };
+                self.events.disconnected(name);
+            }
+        }
+    }
+
+    /// Try to reconnect failed devices whose retry interval has passed.
+    /// Call this regularly from the application's main loop. Returns the
+    /// names of devices that came back.
+    pub fn supervise(&mut self) -> Vec<String> {
+        let now = self.clock.now();
+        let mut recovered = Vec::new();
+
+        for (name, entry) in self.devices.iter_mut() {
+            let (since, attempts) = match entry.health {
+                Health::Healthy => continue,
+                Health::Failed { since, last_attempt, attempts } => {
+                    if attempts > 0 && now.saturating_duration_since(last_attempt) < self.retry_interval {
+                        continue;
+                    }
+                    (since, attempts)
+                }
+            };
+
+            if entry.matrix.reconnect().is_ok() {
+                entry.health = Health::Healthy;
+                self.events.connected(name);
+                recovered.push(name.clone());
+            } else {
+                entry.health = Health::Failed { since, last_attempt: now, attempts: attempts + 1 };
+            }
+        }
+
+        recovered
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::transport::MockTransport;
+    use crate::{ColumnIndex, ColumnUpdate};
+    use std::sync::{Arc, Mutex};
+
+    /// A transport that logs which device each packet went to
+    #[derive(Clone)]
+    struct Logged {
+        name: &'static str,
+        mock: MockTransport,
+        log: Arc<Mutex<Vec<(&'static str, u8)>>>,
+    }
+
+    impl std::io::Read for Logged {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            self.mock.read(buffer)
+        }
+    }
+
+    impl std::io::Write for Logged {
+        fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+            let written = self.mock.write(buffer)?;
+            self.log.lock().unwrap().push((self.name, buffer[2]));
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl crate::transport::Transport for Logged {
+        fn bytes_to_read(&self) -> std::io::Result<u32> {
+            self.mock.bytes_to_read()
+        }
+
+        fn timeout(&self) -> Duration {
+            self.mock.timeout()
+        }
+
+        fn set_timeout(&mut self, timeout: Duration) -> std::io::Result<()> {
+            self.mock.set_timeout(timeout)
+        }
+    }
+
+    #[test]
+    fn present_interleaves_columns() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut manager = DeviceManager::with_clock(ManualClock::new());
+        let broken = MockTransport::new();
+
+        for (name, mock) in [("a", MockTransport::new()), ("b", broken.clone())] {
+            let transport = Logged { name, mock, log: log.clone() };
+            manager.insert(name, LedMatrix::with_transport(name, transport));
+        }
+
+        let frame = Bitmap8::new();
+        let results = manager.present(&[("a", &frame), ("b", &frame)]);
+        assert!(results.iter().all(|(_, x)| x.is_ok()));
+
+        let log = std::mem::take(&mut *log.lock().unwrap());
+        let stage = Command::StageColumnBuffer(ColumnUpdate::from_bitmap(&frame, ColumnIndex::new(0).unwrap())).id();
+        assert_eq!(log[.. 4], [("a", stage), ("b", stage), ("a", stage), ("b", stage)]);
+        assert_eq!(log[log.len() - 2 ..], [("a", Command::DrawBuffer.id()), ("b", Command::DrawBuffer.id())]);
+
+        // A device that breaks part way drops out without holding up the other
+        manager.get_mut("b").unwrap().set_reconnect_policy(crate::reconnect::ReconnectPolicy::never());
+        broken.fail_next_write(ErrorKind::BrokenPipe);
+        let results = manager.present(&[("a", &frame), ("b", &frame), ("c", &frame)]);
+
+        assert!(results[0].1.is_ok());
+        assert!(results[1].1.is_err());
+        assert_eq!(results[2].1.as_ref().unwrap_err().kind(), ErrorKind::NotFound);
+        assert!(matches!(manager.health("b"), Some(Health::Failed { .. })));
+    }
+
+    #[test]
+    fn devices_leave_reconnecting_to_the_manager() {
+        let mut manager = DeviceManager::with_clock(ManualClock::new());
+        let (working, dead) = (MockTransport::new(), MockTransport::new());
+        let clock = ManualClock::new();
+
+        manager.insert("a", LedMatrix::with_transport("a", working.clone()));
+        manager.insert("b", LedMatrix::builder("b").clock(clock.clone()).open_transport(dead.clone()).unwrap());
+        assert_eq!(manager.get("b").unwrap().reconnect_policy(), ReconnectPolicy::never());
+
+        // The dead one is tried once per column, with no backoff
+        for _ in 0 ..= manager.get("b").unwrap().column_retries() {
+            dead.fail_next_write(ErrorKind::TimedOut);
+        }
+
+        let start = clock.now();
+        let frame = Bitmap8::new();
+        let results = manager.present(&[("a", &frame), ("b", &frame)]);
+
+        assert!(results[0].1.is_ok());
+        assert_eq!(results[1].1.as_ref().unwrap_err().kind(), ErrorKind::TimedOut);
+        assert_eq!(clock.elapsed(start), Duration::ZERO);
+        assert_eq!(working.take_written().len(), (DISPLAY_WIDTH + 1) * crate::MAX_COMMAND_LENGTH);
+        assert!(dead.take_written().is_empty());
+    }
+}
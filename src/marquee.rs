@@ -0,0 +1,285 @@
+use std::io::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::geometry::Size;
+use crate::text::{Orientation, TextStyle, FONT_3X5};
+use crate::{Bitmap8, Display, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Pixels scrolled per second unless told otherwise
+pub const DEFAULT_SPEED: u32 = 12;
+
+/// Text scrolling across the panel. Which way it scrolls follows the text
+/// orientation: `Vertical` and `Stacked` text moves up the length of the
+/// panel, `Horizontal` text moves right to left across it.
+///
+/// Call `tick()` and `render()` from your own loop, or hand it to `spawn()`
+/// to have a thread do that.
+pub struct Marquee<C: Clock = SystemClock> {
+    text: String,
+    style: TextStyle<'static>,
+    speed: u32,
+    looping: bool,
+    clock: C,
+    /// Pixels scrolled since the text started entering the panel
+    offset: i32,
+    last_step: Option<Instant>,
+}
+
+impl Marquee<SystemClock> {
+    pub fn new(text: &str) -> Self {
+        Self::with_clock(text, SystemClock)
+    }
+}
+
+impl<C: Clock> Marquee<C> {
+    /// Vertical text in the small font, looping forever
+    pub fn with_clock(text: &str, clock: C) -> Self {
+        Self {
+            text: text.to_owned(),
+            style: TextStyle::new(&FONT_3X5, 0xff).orientation(Orientation::Vertical),
+            speed: DEFAULT_SPEED,
+            looping: true,
+            clock,
+            offset: 0,
+            last_step: None,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Change the text and start scrolling it from the beginning
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.to_owned();
+        self.restart();
+    }
+
+    pub fn set_style(&mut self, style: TextStyle<'static>) {
+        self.style = style;
+        self.restart();
+    }
+
+    /// Pixels per second. Zero stops it where it is.
+    pub fn set_speed(&mut self, pixels_per_second: u32) {
+        self.speed = pixels_per_second;
+    }
+
+    /// Whether to start again once the text has scrolled off
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    pub fn restart(&mut self) {
+        self.offset = 0;
+        self.last_step = None;
+    }
+
+    /// A non-looping marquee is finished once the text has left the panel
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.offset >= self.distance()
+    }
+
+    /// Move along for however much time has passed. Returns whether the text
+    /// moved, so callers know whether it's worth drawing.
+    pub fn tick(&mut self) -> bool {
+        let now = self.clock.now();
+
+        let last_step = match self.last_step {
+            Some(x) => x,
+            None => {
+                self.last_step = Some(now);
+                return false;
+            },
+        };
+
+        if self.speed == 0 || self.is_finished() {
+            self.last_step = Some(now);
+            return false;
+        }
+
+        let interval = Duration::from_secs(1) / self.speed;
+        let steps = (now.saturating_duration_since(last_step).as_nanos() / interval.as_nanos()) as u32;
+
+        if steps == 0 {
+            return false;
+        }
+
+        // Keep the remainder so the speed doesn't depend on the tick rate
+        self.last_step = Some(last_step + interval * steps);
+        self.offset += steps as i32;
+
+        if self.looping {
+            self.offset %= self.distance();
+        } else {
+            self.offset = self.offset.min(self.distance());
+        }
+
+        true
+    }
+
+    /// Draw the text where it currently is, over whatever is on the canvas
+    pub fn render(&self, canvas: &mut Bitmap8) {
+        let Size { width, height } = self.style.measure(&self.text);
+
+        let (x, y) = match self.style.orientation {
+            Orientation::Horizontal => (
+                DISPLAY_WIDTH as i32 - self.offset,
+                (DISPLAY_HEIGHT as i32 - height as i32) / 2,
+            ),
+            Orientation::Vertical | Orientation::Stacked => (
+                (DISPLAY_WIDTH as i32 - width as i32) / 2,
+                DISPLAY_HEIGHT as i32 - self.offset,
+            ),
+        };
+
+        // Vertical lines are laid out from the right, so start at the right
+        // edge of the text rather than the left
+        let x = match self.style.orientation {
+            Orientation::Vertical => x + width as i32 - self.style.font.height as i32,
+            _ => x,
+        };
+
+        canvas.draw_text_styled((x, y), &self.text, &self.style);
+    }
+
+    /// A blank frame with the text drawn on it
+    pub fn frame(&self) -> Bitmap8 {
+        let mut canvas = Bitmap8::new();
+        self.render(&mut canvas);
+        canvas
+    }
+
+    /// How far the text travels from just off one edge to just off the other
+    fn distance(&self) -> i32 {
+        let Size { width, height } = self.style.measure(&self.text);
+
+        let length = match self.style.orientation {
+            Orientation::Horizontal => DISPLAY_WIDTH + width,
+            Orientation::Vertical | Orientation::Stacked => DISPLAY_HEIGHT + height,
+        };
+
+        length as i32
+    }
+}
+
+impl<C: Clock + Send + 'static> Marquee<C> {
+    /// Scroll on a thread of its own until stopped or, if it isn't looping,
+    /// until the text has gone by. Frames that fail are retried by `Display`.
+    pub fn spawn(mut self, mut display: Display) -> MarqueeThread {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+
+        let handle = std::thread::spawn(move || {
+            display.set_frame(&self.frame())?;
+
+            while !stopped.load(Ordering::Relaxed) && !self.is_finished() {
+                if self.tick() {
+                    display.set_frame(&self.frame())?;
+                }
+
+                // Check a few times per step so stopping is quick
+                let pause = Duration::from_secs(1) / self.speed.max(1) / 4;
+                self.clock.sleep(pause.max(Duration::from_millis(1)));
+            }
+
+            Ok(display)
+        });
+
+        MarqueeThread { stop, handle }
+    }
+}
+
+/// A marquee running on its own thread
+pub struct MarqueeThread {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Result<Display, Error>>,
+}
+
+impl MarqueeThread {
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Stop scrolling and get the display back, or the error that stopped
+    /// it early
+    pub fn stop(self) -> Result<Display, Error> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.join().expect("Marquee thread panicked")
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn lit_rows(bitmap: &Bitmap8) -> Vec<usize> {
+        (0 .. DISPLAY_HEIGHT)
+            .filter(|y| (0 .. DISPLAY_WIDTH).any(|x| bitmap.data()[x * DISPLAY_HEIGHT + y] != 0))
+            .collect()
+    }
+
+    #[test]
+    fn starts_off_panel_and_scrolls_up() {
+        let clock = ManualClock::new();
+        let mut marquee = Marquee::with_clock("I", clock.clone());
+
+        marquee.tick();
+        assert!(lit_rows(&marquee.frame()).is_empty());
+
+        // Four pixels at 12 a second
+        clock.advance(Duration::from_millis(334));
+        assert!(marquee.tick());
+        assert_eq!(lit_rows(&marquee.frame()), [30, 31, 32]);
+
+        clock.advance(Duration::from_millis(50));
+        assert!(!marquee.tick());
+    }
+
+    #[test]
+    fn one_shot_finishes() {
+        let clock = ManualClock::new();
+        let mut marquee = Marquee::with_clock("HI", clock.clone());
+
+        marquee.set_looping(false);
+        marquee.set_speed(100);
+        marquee.tick();
+
+        clock.advance(Duration::from_secs(10));
+        marquee.tick();
+
+        assert!(marquee.is_finished());
+        assert!(lit_rows(&marquee.frame()).is_empty());
+    }
+
+    #[test]
+    fn looping_wraps() {
+        let clock = ManualClock::new();
+        let mut marquee = Marquee::with_clock("I", clock.clone());
+
+        marquee.set_speed(1);
+        marquee.tick();
+
+        // "I" on its side is 3 long, so it's back where it started after 37
+        clock.advance(Duration::from_secs(37 + 4));
+        marquee.tick();
+
+        assert_eq!(lit_rows(&marquee.frame()), [30, 31, 32]);
+    }
+
+    #[test]
+    fn horizontal_is_centred_vertically() {
+        let mut marquee = Marquee::with_clock("1", ManualClock::new());
+
+        marquee.set_style(TextStyle::new(&FONT_3X5, 0xff));
+        marquee.offset = 9;
+
+        assert_eq!(lit_rows(&marquee.frame()), [14, 15, 16, 17, 18]);
+    }
+}
@@ -0,0 +1,514 @@
+//! System monitors built from parts: sources that measure something, bar
+//! graphs that show it, and a runner that keeps one or more modules drawing
+//! and picks them back up when they go away.
+//!
+//! ```no_run
+//! # #[cfg(feature = "dashboards")] {
+//! use f16_hid::geometry::Rect;
+//! use f16_hid::layout::Grid;
+//! use f16_hid::monitor::{CpuSource, MemorySource, MonitorRunner};
+//! use f16_hid::LedMatrix;
+//!
+//! let mut runner = MonitorRunner::new();
+//! let panel = runner.add_panel(LedMatrix::new("/dev/ttyACM0")?);
+//! let grid = Grid::new(Rect::display(), 1, 2);
+//!
+//! runner.add_source(panel, grid.cell(0, 0), MemorySource::new());
+//! runner.add_source(panel, grid.cell(0, 1), CpuSource::new());
+//! runner.run();
+//! # }
+//! # Ok::<(), serialport::Error>(())
+//! ```
+//!
+//! The sources that read the system come with the `dashboards` feature.
+//! Anything else can be measured by implementing `MetricSource`.
+
+use std::io::Error;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::events::Events;
+use crate::geometry::Rect;
+use crate::layout::Layout;
+use crate::trace;
+use crate::widgets::{BarGraph, Widget};
+use crate::{Bitmap8, LedMatrix};
+
+/// How often a source is read unless it says otherwise
+pub const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// Wait before trying a module that stopped responding again
+pub const DEFAULT_RETRY_PAUSE: Duration = Duration::from_secs(2);
+
+/// Something worth keeping an eye on, read as one or more values from 0 for
+/// idle or empty to 1 for flat out or full. A CPU gives a value per core,
+/// memory gives one.
+pub trait MetricSource {
+    /// Take a fresh reading
+    fn sample(&mut self) -> Result<Vec<f64>, Error>;
+
+    /// How often to take a reading
+    fn interval(&self) -> Duration {
+        DEFAULT_SAMPLE_INTERVAL
+    }
+}
+
+/// A source shown as a bar graph, a bar for each value. A source that fails
+/// shows empty bars until it recovers.
+pub struct MetricWidget {
+    source: Box<dyn MetricSource>,
+    graph: BarGraph,
+}
+
+impl MetricWidget {
+    /// Takes the first reading straight away
+    pub fn new(source: impl MetricSource + 'static) -> Self {
+        let mut widget = Self {
+            source: Box::new(source),
+            graph: BarGraph::new(0).range(0.0, 1.0),
+        };
+
+        widget.update();
+        widget
+    }
+
+    /// How the bars look. The number of bars is set to suit the source
+    /// whatever the graph had.
+    pub fn graph(mut self, graph: BarGraph) -> Self {
+        let values = self.graph.values().to_vec();
+
+        self.graph = graph.range(0.0, 1.0);
+        self.graph.set_bars(values.len());
+        self.graph.set_values(&values);
+        self
+    }
+}
+
+impl Widget for MetricWidget {
+    fn render(&mut self, canvas: &mut Bitmap8, area: Rect) {
+        self.graph.render(canvas, area);
+    }
+
+    fn is_dirty(&mut self) -> bool {
+        self.graph.is_dirty()
+    }
+
+    fn update_interval(&self) -> Option<Duration> {
+        Some(self.source.interval())
+    }
+
+    fn update(&mut self) {
+        let values = self.source.sample().unwrap_or_default();
+
+        self.graph.set_bars(values.len());
+        self.graph.set_values(&values);
+    }
+}
+
+struct Panel<C: Clock> {
+    matrix: LedMatrix,
+    layout: Layout<C>,
+    /// When it last failed, while it's waiting to be tried again
+    failed_at: Option<Instant>,
+}
+
+/// Keeps a layout of widgets drawing on each of one or more modules. A
+/// module that stops responding is left alone for a moment, reconnected
+/// and redrawn from scratch, without holding up the others.
+pub struct MonitorRunner<C: Clock + Clone = SystemClock> {
+    panels: Vec<Panel<C>>,
+    retry_pause: Duration,
+    idle: Duration,
+    clock: C,
+    events: Events,
+}
+
+impl MonitorRunner<SystemClock> {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl Default for MonitorRunner<SystemClock> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clock + Clone> MonitorRunner<C> {
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            panels: Vec::new(),
+            retry_pause: DEFAULT_RETRY_PAUSE,
+            idle: DEFAULT_SAMPLE_INTERVAL,
+            clock,
+            events: Events::new(),
+        }
+    }
+
+    /// Add a module to draw on, returning its index for `add_source()`
+    pub fn add_panel(&mut self, matrix: LedMatrix) -> usize {
+        self.panels.push(Panel {
+            matrix,
+            layout: Layout::with_clock(self.clock.clone()),
+            failed_at: None,
+        });
+
+        self.panels.len() - 1
+    }
+
+    pub fn panels(&self) -> usize {
+        self.panels.len()
+    }
+
+    /// Show a source as a bar graph in `area` of a panel. False if there's
+    /// no such panel.
+    pub fn add_source(&mut self, panel: usize, area: Rect, source: impl MetricSource + 'static) -> bool {
+        self.add_widget(panel, area, MetricWidget::new(source))
+    }
+
+    /// Put any other widget on a panel, including a `MetricWidget` with a
+    /// graph of its own. False if there's no such panel.
+    pub fn add_widget<W: Widget + 'static>(&mut self, panel: usize, area: Rect, widget: W) -> bool {
+        match self.panels.get_mut(panel) {
+            Some(x) => {
+                x.layout.add(area, widget);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn layout_mut(&mut self, panel: usize) -> Option<&mut Layout<C>> {
+        self.panels.get_mut(panel).map(|x| &mut x.layout)
+    }
+
+    pub fn matrix_mut(&mut self, panel: usize) -> Option<&mut LedMatrix> {
+        self.panels.get_mut(panel).map(|x| &mut x.matrix)
+    }
+
+    pub fn set_retry_pause(&mut self, pause: Duration) {
+        self.retry_pause = pause;
+    }
+
+    /// Longest to sleep between checks when no widget is due sooner
+    pub fn set_idle(&mut self, idle: Duration) {
+        self.idle = idle;
+    }
+
+    /// Called with the panel's port path whenever one fails to update,
+    /// from `tick()` or `run()`
+    pub fn on_error(&mut self, callback: impl FnMut(&str, &Error) + Send + 'static) {
+        self.events.on_error(callback);
+    }
+
+    /// Update and send whatever has changed on every panel. Panels that
+    /// failed recently are skipped until the retry pause is up. Every panel
+    /// gets its turn even if one fails, the first error is returned.
+    pub fn tick(&mut self) -> Result<(), Error> {
+        let now = self.clock.now();
+        let mut result = Ok(());
+
+        for panel in self.panels.iter_mut() {
+            let outcome = match panel.failed_at {
+                Some(when) if now.saturating_duration_since(when) < self.retry_pause => continue,
+                Some(_) => panel.matrix.reconnect().map_err(Error::from).and_then(|_| {
+                    panel.layout.invalidate();
+                    panel.layout.present(&mut panel.matrix)
+                }),
+                None => panel.layout.present(&mut panel.matrix),
+            };
+
+            match outcome {
+                Ok(_) => panel.failed_at = None,
+                Err(error) => {
+                    trace::event!(warn, path = %panel.matrix.path(), error = %error, "Unable to update monitor");
+                    self.events.error(panel.matrix.path(), &error);
+                    panel.failed_at = Some(now);
+
+                    if result.is_ok() {
+                        result = Err(error);
+                    }
+                },
+            }
+        }
+
+        result
+    }
+
+    /// How long until something needs doing
+    fn time_to_next_tick(&self) -> Duration {
+        self.panels.iter()
+            .filter_map(|x| x.layout.time_to_next_update())
+            .fold(self.idle, Duration::min)
+    }
+
+    /// Tick forever, sleeping in between. Errors only go to `on_error()`
+    /// callbacks, and to `tracing` with that feature on.
+    pub fn run(&mut self) -> ! {
+        loop {
+            // Each failure has already been reported
+            let _ = self.tick();
+
+            self.clock.sleep(self.time_to_next_tick());
+        }
+    }
+}
+
+#[cfg(feature = "dashboards")]
+pub use self::system::*;
+
+/// Sources that read the system through `sysinfo`
+#[cfg(feature = "dashboards")]
+mod system {
+    use std::io::{Error, ErrorKind};
+    use std::time::{Duration, Instant};
+
+    use sysinfo::{Components, Disks, Networks, System};
+
+    use super::{MetricSource, DEFAULT_SAMPLE_INTERVAL};
+
+    /// Use of each core
+    pub struct CpuSource {
+        system: System,
+    }
+
+    impl CpuSource {
+        pub fn new() -> Self {
+            Self { system: System::new() }
+        }
+    }
+
+    impl Default for CpuSource {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl MetricSource for CpuSource {
+        fn sample(&mut self) -> Result<Vec<f64>, Error> {
+            self.system.refresh_cpu();
+
+            Ok(self.system.cpus().iter().map(|x| (x.cpu_usage() as f64 / 100.0).clamp(0.0, 1.0)).collect())
+        }
+
+        // sysinfo can't measure any faster
+        fn interval(&self) -> Duration {
+            DEFAULT_SAMPLE_INTERVAL.max(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL)
+        }
+    }
+
+    /// Memory in use
+    pub struct MemorySource {
+        system: System,
+    }
+
+    impl MemorySource {
+        pub fn new() -> Self {
+            Self { system: System::new() }
+        }
+    }
+
+    impl Default for MemorySource {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl MetricSource for MemorySource {
+        fn sample(&mut self) -> Result<Vec<f64>, Error> {
+            self.system.refresh_memory();
+
+            let total = self.system.total_memory().max(1);
+            Ok(vec![self.system.used_memory() as f64 / total as f64])
+        }
+    }
+
+    /// Bytes received and sent a second across every interface, as a
+    /// fraction of a full scale
+    pub struct NetworkSource {
+        networks: Networks,
+        full_scale: u64,
+        last: Option<Instant>,
+    }
+
+    impl NetworkSource {
+        /// `full_scale` bytes a second fills a bar. 12,500,000 is 100 Mbit/s.
+        pub fn new(full_scale: u64) -> Self {
+            Self {
+                networks: Networks::new_with_refreshed_list(),
+                full_scale: full_scale.max(1),
+                last: None,
+            }
+        }
+    }
+
+    impl MetricSource for NetworkSource {
+        fn sample(&mut self) -> Result<Vec<f64>, Error> {
+            self.networks.refresh();
+
+            let now = Instant::now();
+            let elapsed = self.last.map_or(DEFAULT_SAMPLE_INTERVAL, |x| now - x).as_secs_f64().max(0.001);
+            self.last = Some(now);
+
+            let level = |bytes: u64| (bytes as f64 / elapsed / self.full_scale as f64).min(1.0);
+
+            Ok(vec![
+                level(self.networks.values().map(|x| x.received()).sum()),
+                level(self.networks.values().map(|x| x.transmitted()).sum()),
+            ])
+        }
+    }
+
+    /// How full each disk is
+    pub struct DiskSource {
+        disks: Disks,
+    }
+
+    impl DiskSource {
+        pub fn new() -> Self {
+            Self { disks: Disks::new_with_refreshed_list() }
+        }
+    }
+
+    impl Default for DiskSource {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl MetricSource for DiskSource {
+        fn sample(&mut self) -> Result<Vec<f64>, Error> {
+            self.disks.refresh();
+
+            Ok(self.disks.iter()
+                .filter(|x| x.total_space() > 0)
+                .map(|x| 1.0 - x.available_space() as f64 / x.total_space() as f64)
+                .collect())
+        }
+
+        // Disks don't fill up in a hurry
+        fn interval(&self) -> Duration {
+            Duration::from_secs(30)
+        }
+    }
+
+    /// The hottest sensor, on a scale from a cool to a hot temperature
+    pub struct TemperatureSource {
+        components: Components,
+        range: (f32, f32),
+    }
+
+    impl TemperatureSource {
+        /// 30°C is an empty bar and 100°C a full one
+        pub fn new() -> Self {
+            Self::with_range(30.0, 100.0)
+        }
+
+        /// In degrees Celsius
+        pub fn with_range(cool: f32, hot: f32) -> Self {
+            Self {
+                components: Components::new_with_refreshed_list(),
+                range: (cool, hot),
+            }
+        }
+    }
+
+    impl Default for TemperatureSource {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl MetricSource for TemperatureSource {
+        fn sample(&mut self) -> Result<Vec<f64>, Error> {
+            self.components.refresh();
+
+            let hottest = self.components.iter()
+                .map(|x| x.temperature())
+                .filter(|x| x.is_finite())
+                .fold(None, |hottest: Option<f32>, x| Some(hottest.map_or(x, |y| y.max(x))))
+                .ok_or_else(|| Error::new(ErrorKind::NotFound, "No temperature sensors"))?;
+
+            let (cool, hot) = self.range;
+            let span = (hot - cool).max(f32::EPSILON);
+
+            Ok(vec![((hottest - cool) / span).clamp(0.0, 1.0) as f64])
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::reconnect::ReconnectPolicy;
+    use crate::transport::MockTransport;
+    use crate::{DISPLAY_HEIGHT, MAX_COMMAND_LENGTH};
+    use std::io::ErrorKind;
+
+    struct Fixed(Vec<f64>);
+
+    impl MetricSource for Fixed {
+        fn sample(&mut self) -> Result<Vec<f64>, Error> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn sources_become_bars() {
+        let mut widget = MetricWidget::new(Fixed(vec![1.0, 0.0, 0.5]));
+
+        let mut canvas = Bitmap8::new();
+        widget.render(&mut canvas, Rect::new((0, 0), (3, 10)));
+
+        assert_eq!(canvas.data()[0], 0xff);
+        assert_eq!(canvas.data()[DISPLAY_HEIGHT + 9], 0);
+        assert_eq!(canvas.data()[2 * DISPLAY_HEIGHT + 4], 0);
+        assert_eq!(canvas.data()[2 * DISPLAY_HEIGHT + 5], 0xff);
+    }
+
+    #[test]
+    fn failed_panels_are_picked_back_up() {
+        let clock = ManualClock::new();
+        let mock = MockTransport::new();
+        let mut matrix = LedMatrix::with_transport("mock", mock.clone());
+        matrix.set_reconnect_policy(ReconnectPolicy::never());
+
+        let mut runner = MonitorRunner::with_clock(clock.clone());
+        let panel = runner.add_panel(matrix);
+        assert!(runner.add_source(panel, Rect::display(), Fixed(vec![0.5])));
+        assert!(!runner.add_source(3, Rect::display(), Fixed(vec![0.5])));
+
+        mock.fail_next_write(ErrorKind::BrokenPipe);
+        assert!(runner.tick().is_err());
+
+        // Left alone until the pause is up
+        runner.tick().unwrap();
+        assert!(mock.take_written().is_empty());
+
+        // Then everything is sent again, nine columns and a draw
+        clock.advance(DEFAULT_RETRY_PAUSE);
+        runner.tick().unwrap();
+        assert_eq!(mock.take_written().len(), 10 * MAX_COMMAND_LENGTH);
+    }
+
+    #[test]
+    fn failures_go_to_callbacks() {
+        let mock = MockTransport::new();
+        let mut matrix = LedMatrix::with_transport("mock", mock.clone());
+        matrix.set_reconnect_policy(ReconnectPolicy::never());
+
+        let mut runner = MonitorRunner::with_clock(ManualClock::new());
+        let panel = runner.add_panel(matrix);
+        runner.add_source(panel, Rect::display(), Fixed(vec![0.5]));
+
+        let errors = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = errors.clone();
+        runner.on_error(move |path, error| log.lock().unwrap().push((path.to_owned(), error.kind())));
+
+        mock.fail_next_write(ErrorKind::BrokenPipe);
+        assert!(runner.tick().is_err());
+        assert_eq!(*errors.lock().unwrap(), [("mock".to_owned(), ErrorKind::BrokenPipe)]);
+    }
+}
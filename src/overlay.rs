@@ -0,0 +1,337 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::filter::FrameFilter;
+use crate::geometry::{Point, Rect};
+use crate::text::{TextStyle, FONT_3X5};
+use crate::{Bitmap8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Room the two digits take, plus a blank pixel between them and the frame
+const STAMP_WIDTH: usize = 2 * FONT_3X5.width + 1;
+const STAMP_HEIGHT: usize = FONT_3X5.height;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Corner {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// What the overlay stamps on each frame, as two digits
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverlayContent {
+    /// Frames sent so far, counting 00 to 99 and round again. A skipped or
+    /// repeated number on the panel is a dropped or duplicated frame.
+    #[default]
+    FrameCounter,
+    /// Frames sent in the last second, up to 99
+    Fps,
+}
+
+struct OverlayState {
+    enabled: bool,
+    content: OverlayContent,
+    corner: Corner,
+    frames: u64,
+    /// When each frame in the last second went out
+    recent: VecDeque<Instant>,
+}
+
+/// A filter that stamps a frame counter or frame rate in a corner of every
+/// frame, to make dropped and duplicated frames visible on the device
+/// itself. Clones share their settings, so keep one to turn it on and off
+/// from elsewhere once it's in the pipeline:
+///
+/// ```
+/// # use f16_hid::{transport::MockTransport, LedMatrix};
+/// use f16_hid::overlay::DebugOverlay;
+///
+/// # let mut matrix = LedMatrix::with_transport("mock", MockTransport::new());
+/// let overlay = DebugOverlay::new();
+/// matrix.filters_mut().push(overlay.clone());
+///
+/// overlay.set_enabled(false);
+/// ```
+///
+/// While it's on, partial updates send whole frames so the stamp is never
+/// left behind.
+#[derive(Clone)]
+pub struct DebugOverlay<C: Clock = SystemClock> {
+    state: Arc<Mutex<OverlayState>>,
+    clock: C,
+}
+
+impl DebugOverlay<SystemClock> {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl Default for DebugOverlay<SystemClock> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clock> DebugOverlay<C> {
+    /// A frame counter in the top left, switched on
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(OverlayState {
+                enabled: true,
+                content: OverlayContent::FrameCounter,
+                corner: Corner::TopLeft,
+                frames: 0,
+                recent: VecDeque::new(),
+            })),
+            clock,
+        }
+    }
+
+    /// Frames are still counted while it's off, so the counter doesn't
+    /// jump back when it's turned on again
+    pub fn set_enabled(&self, enabled: bool) {
+        self.state().enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.state().enabled
+    }
+
+    pub fn set_content(&self, content: OverlayContent) {
+        self.state().content = content;
+    }
+
+    pub fn set_corner(&self, corner: Corner) {
+        self.state().corner = corner;
+    }
+
+    /// Frames that have gone through the filter
+    pub fn frames(&self) -> u64 {
+        self.state().frames
+    }
+
+    /// Frames that went through in the last second
+    pub fn fps(&self) -> usize {
+        let mut state = self.state();
+        self.forget_old(&mut state);
+
+        state.recent.len()
+    }
+
+    fn forget_old(&self, state: &mut OverlayState) {
+        let now = self.clock.now();
+
+        while let Some(first) = state.recent.front() {
+            if now.saturating_duration_since(*first) < Duration::from_secs(1) {
+                break;
+            }
+
+            state.recent.pop_front();
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, OverlayState> {
+        self.state.lock().expect("Debug overlay lock poisoned")
+    }
+}
+
+impl<C: Clock> FrameFilter for DebugOverlay<C> {
+    fn apply(&mut self, frame: &mut Bitmap8) {
+        let mut state = self.state();
+
+        state.frames += 1;
+        state.recent.push_back(self.clock.now());
+        self.forget_old(&mut state);
+
+        if !state.enabled {
+            return;
+        }
+
+        let number = match state.content {
+            OverlayContent::FrameCounter => (state.frames % 100) as usize,
+            OverlayContent::Fps => state.recent.len().min(99),
+        };
+
+        let (x, y) = match state.corner {
+            Corner::TopLeft => (0, 0),
+            Corner::TopRight => (DISPLAY_WIDTH - STAMP_WIDTH, 0),
+            Corner::BottomLeft => (0, DISPLAY_HEIGHT - STAMP_HEIGHT),
+            Corner::BottomRight => (DISPLAY_WIDTH - STAMP_WIDTH, DISPLAY_HEIGHT - STAMP_HEIGHT),
+        };
+
+        // A blank pixel around the inside edges keeps it readable over
+        // whatever is underneath
+        let left = x.saturating_sub(1);
+        let top = y.saturating_sub(1);
+        let right = (x + STAMP_WIDTH + 1).min(DISPLAY_WIDTH);
+        let bottom = (y + STAMP_HEIGHT + 1).min(DISPLAY_HEIGHT);
+        frame.fill_rect(Rect::new((left as i32, top as i32), (right - left, bottom - top)), 0);
+
+        let style = TextStyle::new(&FONT_3X5, 0xff);
+        frame.draw_text_styled((x as i32, y as i32), &format!("{:02}", number), &style);
+    }
+
+    // Not moving anything, but the stamp changes every frame whatever the
+    // application drew, so partial updates mustn't skip its columns
+    fn moves_pixels(&self) -> bool {
+        self.is_enabled()
+    }
+}
+
+
+/// Transient content shown over a `Display`'s own frames, such as a volume
+/// icon or a chat notification, see `Display::show_overlay()`. Only the
+/// pixels inside its area are drawn, and any of the transparent value let
+/// the frame underneath show through.
+#[derive(Clone)]
+pub struct Overlay {
+    image: Bitmap8,
+    area: Rect,
+    transparent: Option<u8>,
+}
+
+impl Overlay {
+    /// The part of `image` inside `area`, where it is on the panel
+    pub fn new(image: &Bitmap8, area: Rect) -> Self {
+        Self {
+            image: image.clone(),
+            area,
+            transparent: None,
+        }
+    }
+
+    /// All of `image`
+    pub fn full(image: &Bitmap8) -> Self {
+        Self::new(image, Rect::display())
+    }
+
+    /// Let the frame underneath show through pixels of `value`
+    pub fn transparent(mut self, value: u8) -> Self {
+        self.transparent = Some(value);
+        self
+    }
+
+    pub fn area(&self) -> Rect {
+        self.area
+    }
+
+    /// Draw the overlay onto `frame`
+    pub fn render(&self, frame: &mut Bitmap8) {
+        for x in self.area.columns() {
+            for y in self.area.rows() {
+                let point = Point::new(x as i32, y as i32);
+                let value = self.image.pixel(point).unwrap_or(0);
+
+                if Some(value) != self.transparent {
+                    frame.set_pixel(point, value);
+                }
+            }
+        }
+    }
+}
+
+/// Names an overlay on a `Display`, to take it down early
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OverlayId(u64);
+
+/// Overlays up on a display and when each comes down. Later ones are drawn
+/// over earlier ones.
+#[derive(Default)]
+pub(crate) struct OverlayStack {
+    overlays: Vec<(OverlayId, Overlay, Instant)>,
+    next_id: u64,
+}
+
+impl OverlayStack {
+    pub(crate) fn push(&mut self, overlay: Overlay, until: Instant) -> OverlayId {
+        let id = OverlayId(self.next_id);
+        self.next_id += 1;
+        self.overlays.push((id, overlay, until));
+
+        id
+    }
+
+    /// Returns whether it was up
+    pub(crate) fn remove(&mut self, id: OverlayId) -> bool {
+        let count = self.overlays.len();
+        self.overlays.retain(|x| x.0 != id);
+
+        self.overlays.len() != count
+    }
+
+    pub(crate) fn clear(&mut self) -> bool {
+        let any = !self.overlays.is_empty();
+        self.overlays.clear();
+
+        any
+    }
+
+    /// Take down every overlay due to come down by `now`. Returns whether
+    /// there were any.
+    pub(crate) fn expire(&mut self, now: Instant) -> bool {
+        let count = self.overlays.len();
+        self.overlays.retain(|x| x.2 > now);
+
+        self.overlays.len() != count
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.overlays.len()
+    }
+
+    /// `scene` with every overlay drawn over it
+    pub(crate) fn compose(&self, scene: &Bitmap8) -> Bitmap8 {
+        let mut frame = scene.clone();
+
+        for (_, overlay, _) in self.overlays.iter() {
+            overlay.render(&mut frame);
+        }
+
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn stamps_count_and_rate() {
+        let clock = ManualClock::new();
+        let overlay = DebugOverlay::with_clock(clock.clone());
+        let mut filter = overlay.clone();
+
+        let mut blank = Bitmap8::new();
+        blank.fill(0x40);
+
+        let mut expected = Bitmap8::new();
+        expected.fill(0x40);
+        expected.fill_rect(Rect::new((0, 0), (8, 6)), 0);
+        expected.draw_text((0, 0), "01", 0xff);
+
+        let mut frame = blank.clone();
+        filter.apply(&mut frame);
+        assert_eq!(frame.data(), expected.data());
+
+        for _ in 0 .. 4 {
+            clock.advance(Duration::from_millis(300));
+            filter.apply(&mut blank.clone());
+        }
+
+        // The first frame is more than a second old now
+        assert_eq!(overlay.frames(), 5);
+        assert_eq!(overlay.fps(), 4);
+
+        overlay.set_enabled(false);
+        let mut frame = blank.clone();
+        filter.apply(&mut frame);
+        assert_eq!(frame.data(), blank.data());
+        assert!(!filter.moves_pixels());
+    }
+}
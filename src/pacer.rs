@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+
+/// How far back `FramePacer::fps()` looks
+const FPS_WINDOW: Duration = Duration::from_secs(1);
+
+/// Keeps a render loop to a steady frame rate. Call `wait()` once a frame
+/// is sent and it sleeps off whatever is left of the frame's slot:
+///
+/// ```no_run
+/// use f16_hid::pacer::FramePacer;
+///
+/// let mut pacer = FramePacer::new(30.0);
+///
+/// loop {
+///     // Draw and send a frame
+///     pacer.wait();
+/// }
+/// ```
+///
+/// Frames are due at fixed intervals rather than a fixed time after the
+/// last one finished, so the rate doesn't drift with how long sending
+/// takes. A frame that runs over isn't made up for by rushing the next.
+pub struct FramePacer<C: Clock = SystemClock> {
+    clock: C,
+    period: Duration,
+    /// When the frame being worked on was due to start
+    due: Instant,
+    /// How long the last frame took before `wait()` was called
+    frame_time: Duration,
+    /// When each recent `wait()` returned
+    recent: VecDeque<Instant>,
+}
+
+impl FramePacer<SystemClock> {
+    /// Aim for `fps` frames a second
+    pub fn new(fps: f64) -> Self {
+        Self::with_clock(period(fps), SystemClock)
+    }
+
+    /// A frame every `period`
+    pub fn with_period(period: Duration) -> Self {
+        Self::with_clock(period, SystemClock)
+    }
+}
+
+impl<C: Clock> FramePacer<C> {
+    pub fn with_clock(period: Duration, clock: C) -> Self {
+        Self {
+            due: clock.now(),
+            clock,
+            period,
+            frame_time: Duration::ZERO,
+            recent: VecDeque::new(),
+        }
+    }
+
+    pub fn set_target_fps(&mut self, fps: f64) {
+        self.period = period(fps);
+    }
+
+    pub fn target_fps(&self) -> f64 {
+        1.0 / self.period.as_secs_f64().max(f64::EPSILON)
+    }
+
+    pub fn set_period(&mut self, period: Duration) {
+        self.period = period;
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Sleep until the next frame is due. Returns how long the frame took
+    /// from when it was due to now, before sleeping.
+    pub fn wait(&mut self) -> Duration {
+        let now = self.clock.now();
+        self.frame_time = now.saturating_duration_since(self.due);
+
+        let next = self.due + self.period;
+
+        // Running late, so start afresh from now instead of trying to
+        // catch up with a burst of frames
+        self.due = if next > now {
+            self.clock.sleep(next - now);
+            next
+        } else {
+            now
+        };
+
+        let end = self.clock.now();
+        self.recent.push_back(end);
+
+        while let Some(first) = self.recent.front() {
+            if end.saturating_duration_since(*first) <= FPS_WINDOW {
+                break;
+            }
+
+            self.recent.pop_front();
+        }
+
+        self.frame_time
+    }
+
+    /// How long the last frame took to draw and send, not counting sleep
+    pub fn frame_time(&self) -> Duration {
+        self.frame_time
+    }
+
+    /// Frames per second actually achieved over about the last second.
+    /// Zero until there have been two frames to measure between.
+    pub fn fps(&self) -> f64 {
+        let (first, last) = match (self.recent.front(), self.recent.back()) {
+            (Some(first), Some(last)) if self.recent.len() > 1 => (first, last),
+            _ => return 0.0,
+        };
+
+        let span = last.saturating_duration_since(*first).as_secs_f64();
+
+        if span <= 0.0 {
+            return 0.0;
+        }
+
+        (self.recent.len() - 1) as f64 / span
+    }
+}
+
+fn period(fps: f64) -> Duration {
+    Duration::from_secs_f64(1.0 / fps.max(f64::EPSILON))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn sleeps_off_the_rest_of_the_frame() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let mut pacer = FramePacer::with_clock(Duration::from_millis(100), clock.clone());
+
+        for _ in 0 .. 5 {
+            clock.advance(Duration::from_millis(30));
+            assert_eq!(pacer.wait(), Duration::from_millis(30));
+        }
+
+        // Slots start every 100 ms whatever the work took
+        assert_eq!(clock.now() - start, Duration::from_millis(500));
+        assert!((pacer.fps() - 10.0).abs() < 0.01);
+
+        // A slow frame pushes the schedule back instead of bunching up
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(pacer.wait(), Duration::from_millis(250));
+        assert_eq!(clock.now() - start, Duration::from_millis(750));
+
+        clock.advance(Duration::from_millis(10));
+        pacer.wait();
+        assert_eq!(clock.now() - start, Duration::from_millis(850));
+    }
+}
@@ -0,0 +1,371 @@
+use std::sync::Barrier;
+use std::time::{Duration, Instant};
+
+use crate::geometry::{Point, Rect};
+use crate::text::TextStyle;
+use crate::{Bitmap8, Command, LedMatrix, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Roughly how many LED columns would fit in the bezel between the two
+/// modules in a Framework 16 input deck
+pub const DEFAULT_GAP: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// Horizontal layout of the pair as one wide strip, including the columns
+/// that would be there if the bezel had LEDs. Working in these coordinates
+/// lets motion and spacing look physically continuous across the gap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PairGeometry {
+    pub gap: usize,
+}
+
+impl PairGeometry {
+    pub fn new(gap: usize) -> Self {
+        Self { gap }
+    }
+
+    /// Width of the strip, gap included
+    pub fn width(&self) -> usize {
+        DISPLAY_WIDTH * 2 + self.gap
+    }
+
+    /// First column of the right panel in strip coordinates
+    pub fn right_start(&self) -> usize {
+        DISPLAY_WIDTH + self.gap
+    }
+
+    /// Which panel and column a strip column lands on. `None` for columns in
+    /// the gap or past the end.
+    pub fn locate(&self, x: usize) -> Option<(Side, usize)> {
+        if x < DISPLAY_WIDTH {
+            Some((Side::Left, x))
+        } else if x >= self.right_start() && x < self.width() {
+            Some((Side::Right, x - self.right_start()))
+        } else {
+            None
+        }
+    }
+
+    /// Strip column for a panel column
+    pub fn to_strip(&self, side: Side, x: usize) -> usize {
+        match side {
+            Side::Left => x,
+            Side::Right => self.right_start() + x,
+        }
+    }
+
+    /// Whether something `width` columns wide starting at `x` would have
+    /// part of it hidden in the gap or split across both panels
+    pub fn crosses_bezel(&self, x: usize, width: usize) -> bool {
+        width > 0 && x < self.right_start() && x + width > DISPLAY_WIDTH
+    }
+
+    /// Nudge something that would cross the bezel onto the right panel.
+    /// Things wider than a panel can't avoid it and are left alone.
+    pub fn avoid_bezel(&self, x: usize, width: usize) -> usize {
+        if width <= DISPLAY_WIDTH && self.crosses_bezel(x, width) {
+            self.right_start()
+        } else {
+            x
+        }
+    }
+
+    /// Lay items of the given widths out left to right with `spacing`
+    /// between them, keeping each one entirely on one panel. Returns the
+    /// strip column of each item. Items that run off the end are still
+    /// placed so the caller can decide whether to clip or paginate.
+    pub fn flow(&self, widths: &[usize], spacing: usize) -> Vec<usize> {
+        let mut positions = Vec::with_capacity(widths.len());
+        let mut x = 0;
+
+        for width in widths {
+            x = self.avoid_bezel(x, *width);
+            positions.push(x);
+            x += width + spacing;
+        }
+
+        positions
+    }
+}
+
+impl Default for PairGeometry {
+    fn default() -> Self {
+        Self::new(DEFAULT_GAP)
+    }
+}
+
+/// Width of both panels side by side, ignoring the bezel
+pub const PAIR_WIDTH: usize = DISPLAY_WIDTH * 2;
+
+/// Both panels as one 18x34 canvas. Column 0 is the left edge of the left
+/// panel and column 9 the left edge of the right one, with nothing for the
+/// bezel. Use `PairGeometry` when spacing across the gap matters.
+#[derive(Clone, Default)]
+pub struct PairBitmap {
+    left: Bitmap8,
+    right: Bitmap8,
+}
+
+impl PairBitmap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Canvas made of a frame for each panel
+    pub fn from_halves(left: Bitmap8, right: Bitmap8) -> Self {
+        Self { left, right }
+    }
+
+    pub fn left(&self) -> &Bitmap8 {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Bitmap8 {
+        &self.right
+    }
+
+    pub fn into_halves(self) -> (Bitmap8, Bitmap8) {
+        (self.left, self.right)
+    }
+
+    pub fn fill(&mut self, value: u8) {
+        self.left.fill(value);
+        self.right.fill(value);
+    }
+
+    /// Value of a pixel, `None` if it's off both panels
+    pub fn pixel(&self, point: Point) -> Option<u8> {
+        let (half, point) = self.locate(point)?;
+
+        half.pixel(point)
+    }
+
+    /// Set a pixel. Anything off both panels is ignored.
+    pub fn set_pixel(&mut self, point: Point, value: u8) {
+        self.each_half(|half, offset| half.set_pixel(point - offset, value));
+    }
+
+    /// Fill a rectangle, split across the panels and clipped to them
+    pub fn fill_rect(&mut self, area: Rect, value: u8) {
+        self.each_half(|half, offset| half.fill_rect(area.translate(Point::ZERO - offset), value));
+    }
+
+    /// Draw text in the small font, running across the bezel if it has to
+    pub fn draw_text(&mut self, position: impl Into<Point>, text: &str, value: u8) {
+        let position = position.into();
+
+        self.each_half(|half, offset| half.draw_text(position - offset, text, value));
+    }
+
+    pub fn draw_text_styled(&mut self, position: impl Into<Point>, text: &str, style: &TextStyle) {
+        let position = position.into();
+
+        self.each_half(|half, offset| half.draw_text_styled(position - offset, text, style));
+    }
+
+    /// Run a drawing call on both halves, each given where it starts on the
+    /// canvas. Each half clips whatever isn't on it.
+    fn each_half(&mut self, mut draw: impl FnMut(&mut Bitmap8, Point)) {
+        draw(&mut self.left, Point::ZERO);
+        draw(&mut self.right, Point::new(DISPLAY_WIDTH as i32, 0));
+    }
+
+    fn locate(&self, point: Point) -> Option<(&Bitmap8, Point)> {
+        if point.y < 0 || point.y >= DISPLAY_HEIGHT as i32 {
+            return None;
+        }
+
+        match point.x {
+            x if (0 .. DISPLAY_WIDTH as i32).contains(&x) => Some((&self.left, point)),
+            x if (DISPLAY_WIDTH as i32 .. PAIR_WIDTH as i32).contains(&x) => {
+                Some((&self.right, point - Point::new(DISPLAY_WIDTH as i32, 0)))
+            },
+            _ => None,
+        }
+    }
+}
+
+/// How the two halves of a frame are committed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyncMode {
+    /// Draw the left panel completely, then the right. Simple, but anything
+    /// moving across the bezel visibly tears.
+    Sequential,
+    /// Stage both panels first, then send both `DrawBuffer` commands back to
+    /// back so the only gap is one small write.
+    #[default]
+    Staged,
+    /// Stage each panel on its own thread and release both `DrawBuffer`
+    /// commands from a barrier. Helps when one port is slower than the other.
+    Threaded,
+}
+
+/// The two modules in a Framework 16 input deck, driven together
+pub struct MatrixPair {
+    left: LedMatrix,
+    right: LedMatrix,
+    sync_mode: SyncMode,
+    last_skew: Option<Duration>,
+}
+
+impl MatrixPair {
+    pub fn new(left: LedMatrix, right: LedMatrix) -> Self {
+        Self {
+            left,
+            right,
+            sync_mode: SyncMode::default(),
+            last_skew: None,
+        }
+    }
+
+    /// Pair up two modules given which side the first one is physically on,
+    /// say from `RoleConfig`
+    pub fn with_sides(first: LedMatrix, second: LedMatrix, first_side: Side) -> Self {
+        match first_side {
+            Side::Left => Self::new(first, second),
+            Side::Right => Self::new(second, first),
+        }
+    }
+
+    pub fn left(&mut self) -> &mut LedMatrix {
+        &mut self.left
+    }
+
+    pub fn right(&mut self) -> &mut LedMatrix {
+        &mut self.right
+    }
+
+    pub fn into_inner(self) -> (LedMatrix, LedMatrix) {
+        (self.left, self.right)
+    }
+
+    pub fn set_sync_mode(&mut self, mode: SyncMode) {
+        self.sync_mode = mode;
+    }
+
+    pub fn sync_mode(&self) -> SyncMode {
+        self.sync_mode
+    }
+
+    /// Time between the two panels being told to draw on the last frame
+    pub fn last_skew(&self) -> Option<Duration> {
+        self.last_skew
+    }
+
+    /// Show one frame on each panel. Returns how far apart the two panels
+    /// were told to draw.
+    pub fn present(&mut self, left: &Bitmap8, right: &Bitmap8) -> Result<Duration, std::io::Error> {
+        let skew = match self.sync_mode {
+            SyncMode::Sequential => {
+                self.left.stage_frame(left)?;
+                let left_drawn = Instant::now();
+                self.right.stage_frame(right)?;
+                Instant::now() - left_drawn
+            },
+            SyncMode::Staged => {
+                self.left.stage_columns(left)?;
+                self.right.stage_columns(right)?;
+
+                self.left.execute(Command::DrawBuffer)?;
+                let left_drawn = Instant::now();
+                self.right.execute(Command::DrawBuffer)?;
+                Instant::now() - left_drawn
+            },
+            SyncMode::Threaded => {
+                let barrier = Barrier::new(2);
+
+                let (left_result, right_result) = std::thread::scope(|scope| {
+                    let left_thread = scope.spawn(|| draw_at_barrier(&mut self.left, left, &barrier));
+                    let right_result = draw_at_barrier(&mut self.right, right, &barrier);
+
+                    (left_thread.join().expect("Drawing thread panicked"), right_result)
+                });
+
+                let left_drawn = left_result?;
+                let right_drawn = right_result?;
+
+                if left_drawn > right_drawn {
+                    left_drawn - right_drawn
+                } else {
+                    right_drawn - left_drawn
+                }
+            }
+        };
+
+        self.last_skew = Some(skew);
+
+        Ok(skew)
+    }
+
+    /// Show a canvas covering both panels
+    pub fn present_canvas(&mut self, canvas: &PairBitmap) -> Result<Duration, std::io::Error> {
+        self.present(&canvas.left, &canvas.right)
+    }
+}
+
+// Both sides have to reach the barrier even when staging fails or the other
+// one waits forever, so the error is held until after it
+fn draw_at_barrier(matrix: &mut LedMatrix, frame: &Bitmap8, barrier: &Barrier) -> Result<Instant, std::io::Error> {
+    let staged = matrix.stage_columns(frame);
+    barrier.wait();
+    staged?;
+
+    matrix.execute(Command::DrawBuffer)?;
+
+    Ok(Instant::now())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gap_columns_are_hidden() {
+        let geometry = PairGeometry::new(2);
+
+        assert_eq!(geometry.width(), 20);
+        assert_eq!(geometry.locate(8), Some((Side::Left, 8)));
+        assert_eq!(geometry.locate(9), None);
+        assert_eq!(geometry.locate(10), None);
+        assert_eq!(geometry.locate(11), Some((Side::Right, 0)));
+        assert_eq!(geometry.locate(20), None);
+    }
+
+    #[test]
+    fn glyphs_stay_on_one_panel() {
+        let geometry = PairGeometry::new(2);
+
+        // Three glyphs three wide fit on the left, the third one wouldn't
+        // with spacing so it moves right
+        assert_eq!(geometry.flow(&[3, 3, 3], 1), vec![0, 4, 11]);
+        assert!(!geometry.crosses_bezel(4, 3));
+        assert!(geometry.crosses_bezel(8, 3));
+    }
+
+    #[test]
+    fn canvas_splits_across_panels() {
+        let mut canvas = PairBitmap::new();
+
+        canvas.fill_rect(Rect::new((7, 0), (4, 2)), 5);
+        canvas.set_pixel(Point::new(17, 33), 9);
+
+        assert_eq!(canvas.left().pixel(Point::new(7, 1)), Some(5));
+        assert_eq!(canvas.left().pixel(Point::new(8, 0)), Some(5));
+        assert_eq!(canvas.right().pixel(Point::new(1, 1)), Some(5));
+        assert_eq!(canvas.right().pixel(Point::new(2, 0)), Some(0));
+        assert_eq!(canvas.pixel(Point::new(17, 33)), Some(9));
+        assert_eq!(canvas.pixel(Point::new(18, 0)), None);
+    }
+
+    #[test]
+    fn wide_items_are_left_alone() {
+        let geometry = PairGeometry::new(2);
+
+        assert_eq!(geometry.avoid_bezel(2, 12), 2);
+    }
+}
@@ -0,0 +1,120 @@
+//! Where Linux, macOS and Windows differ in how serial ports come and go.
+//! `serialport` hides most of it, but not port naming or how long the OS
+//! holds on to a port after it's closed.
+
+use std::cmp::Ordering;
+use std::time::Duration;
+
+/// How many more times `LedMatrix::reconnect()` tries to open a port the
+/// OS still says is in use. Windows keeps a closed COM port's handle for a
+/// moment, so opening it straight away is refused.
+#[cfg(windows)]
+pub const REOPEN_ATTEMPTS: u32 = 5;
+#[cfg(not(windows))]
+pub const REOPEN_ATTEMPTS: u32 = 1;
+
+/// Pause between those attempts
+#[cfg(windows)]
+pub const REOPEN_PAUSE: Duration = Duration::from_millis(200);
+#[cfg(not(windows))]
+pub const REOPEN_PAUSE: Duration = Duration::from_millis(50);
+
+/// Whether opening failed because something, possibly our own old handle,
+/// still has the port. Windows says access is denied, Unix says busy.
+pub fn is_port_busy(error: &serialport::Error) -> bool {
+    use std::io::ErrorKind;
+
+    match error.kind() {
+        serialport::ErrorKind::Io(ErrorKind::PermissionDenied) => true,
+        serialport::ErrorKind::Io(ErrorKind::ResourceBusy) => true,
+        serialport::ErrorKind::NoDevice => error.description.contains("busy"),
+        _ => false,
+    }
+}
+
+/// Open something, trying again while the port is busy
+pub(crate) fn open_with_retry<T>(
+    mut open: impl FnMut() -> Result<T, serialport::Error>,
+    mut pause: impl FnMut(Duration),
+) -> Result<T, serialport::Error> {
+    let mut attempt = 0;
+
+    loop {
+        match open() {
+            Err(error) if is_port_busy(&error) && attempt < REOPEN_ATTEMPTS => {
+                attempt += 1;
+                pause(REOPEN_PAUSE);
+            },
+            x => return x,
+        }
+    }
+}
+
+/// macOS lists every USB serial device twice, as `/dev/tty.*` and
+/// `/dev/cu.*`. Opening the `tty` one waits for a carrier that never comes,
+/// so it's the `cu` one that's wanted.
+pub fn is_macos_dial_in(path: &str, others: &[&str]) -> bool {
+    match path.strip_prefix("/dev/tty.") {
+        Some(name) => others.iter().any(|x| x.strip_prefix("/dev/cu.") == Some(name)),
+        None => false,
+    }
+}
+
+/// Sort port names with their numbers in order, so `COM10` comes after
+/// `COM9` and `/dev/ttyACM10` after `/dev/ttyACM2`
+pub fn compare_port_names(a: &str, b: &str) -> Ordering {
+    fn split(name: &str) -> (&str, Option<u64>) {
+        let stem = name.trim_end_matches(|x: char| x.is_ascii_digit());
+        (stem, name[stem.len() ..].parse().ok())
+    }
+
+    split(a).cmp(&split(b)).then_with(|| a.cmp(b))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn busy_ports_are_retried() {
+        let busy = || serialport::Error::new(serialport::ErrorKind::Io(std::io::ErrorKind::PermissionDenied), "Access is denied");
+        let mut pauses = 0;
+
+        let mut failures = REOPEN_ATTEMPTS;
+        let opened = open_with_retry(|| {
+            if failures == 0 {
+                return Ok("port");
+            }
+
+            failures -= 1;
+            Err(busy())
+        }, |_| pauses += 1);
+
+        assert_eq!(opened.unwrap(), "port");
+        assert_eq!(pauses, REOPEN_ATTEMPTS);
+
+        // Gives up eventually, and a missing port isn't worth waiting for
+        assert!(open_with_retry(|| Err::<(), _>(busy()), |_| ()).is_err());
+
+        let mut tries = 0;
+        let missing = open_with_retry(|| {
+            tries += 1;
+            Err::<(), _>(serialport::Error::new(serialport::ErrorKind::NoDevice, "No such file"))
+        }, |_| ());
+        assert!(missing.is_err());
+        assert_eq!(tries, 1);
+    }
+
+    #[test]
+    fn port_names_sort_and_dedupe() {
+        let mut names = vec!["COM10", "COM3", "/dev/ttyACM10", "/dev/ttyACM2"];
+        names.sort_by(|a, b| compare_port_names(a, b));
+        assert_eq!(names, ["/dev/ttyACM2", "/dev/ttyACM10", "COM3", "COM10"]);
+
+        let listed = ["/dev/tty.usbmodem1", "/dev/cu.usbmodem1", "/dev/tty.usbmodem2"];
+        assert!(is_macos_dial_in(listed[0], &listed));
+        assert!(!is_macos_dial_in(listed[1], &listed));
+        assert!(!is_macos_dial_in(listed[2], &listed));
+    }
+}
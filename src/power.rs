@@ -0,0 +1,200 @@
+use std::io::{BufRead, BufReader, Error, ErrorKind};
+use std::process::{Child, Command as Process, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+use crate::{Command, LedMatrix};
+
+/// What logind says when the machine is about to sleep or has just woken
+pub const SUSPEND_MATCH: &str =
+    "type='signal',interface='org.freedesktop.login1.Manager',member='PrepareForSleep'";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerEvent {
+    /// The machine is about to suspend or hibernate
+    Suspending,
+    Resumed,
+}
+
+/// Listens for the host suspending and resuming, so the matrix can be put
+/// to sleep rather than staying lit with the lid shut. Linux only: it
+/// watches logind's `PrepareForSleep` signal on the system bus through
+/// `dbus-monitor`, which has to be installed.
+///
+/// Call `poll()` from the application's main loop, or iterate over it from
+/// a thread of its own, which blocks until the next event:
+///
+/// ```no_run
+/// use f16_hid::power::{PowerEvent, SuspendMonitor};
+/// use f16_hid::shared::SharedMatrix;
+/// use f16_hid::{Command, LedMatrix};
+///
+/// let matrix = SharedMatrix::new(LedMatrix::new("/dev/ttyACM0")?);
+///
+/// for event in SuspendMonitor::spawn()? {
+///     matrix.execute(Command::Sleep(event == PowerEvent::Suspending))?;
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// Nothing holds suspend back, so the sleep command races the machine going
+/// down. It nearly always wins, and on resume the panel is woken either
+/// way.
+pub struct SuspendMonitor {
+    child: Child,
+    events: Receiver<PowerEvent>,
+}
+
+impl SuspendMonitor {
+    pub fn spawn() -> Result<Self, Error> {
+        let mut monitor = Process::new("dbus-monitor");
+        monitor.arg("--system").arg(SUSPEND_MATCH);
+
+        Self::with_command(monitor)
+    }
+
+    /// Run something else that prints `dbus-monitor`'s format
+    pub fn with_command(mut command: Process) -> Result<Self, Error> {
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdout = child.stdout.take()
+            .ok_or_else(|| Error::new(ErrorKind::BrokenPipe, "No output from dbus-monitor"))?;
+
+        let (sender, events) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            let mut parser = SignalParser::default();
+
+            for line in BufReader::new(stdout).lines() {
+                let line = match line {
+                    Ok(x) => x,
+                    Err(_) => break,
+                };
+
+                if let Some(event) = parser.line(&line) {
+                    if sender.send(event).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self { child, events })
+    }
+
+    /// The next event if one has arrived. Doesn't wait. Fails once the
+    /// monitor has stopped.
+    pub fn try_next(&mut self) -> Result<Option<PowerEvent>, Error> {
+        match self.events.try_recv() {
+            Ok(x) => Ok(Some(x)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(Error::new(ErrorKind::BrokenPipe, "dbus-monitor stopped")),
+        }
+    }
+
+    /// Sleep or wake the matrix for anything that's happened since the last
+    /// poll. Returns the last event acted on.
+    pub fn poll(&mut self, matrix: &mut LedMatrix) -> Result<Option<PowerEvent>, Error> {
+        let mut last = None;
+
+        while let Some(event) = self.try_next()? {
+            matrix.execute(Command::Sleep(event == PowerEvent::Suspending))?;
+            last = Some(event);
+        }
+
+        Ok(last)
+    }
+}
+
+impl Iterator for SuspendMonitor {
+    type Item = PowerEvent;
+
+    /// Waits for the next event. Ends if the monitor stops.
+    fn next(&mut self) -> Option<Self::Item> {
+        self.events.recv().ok()
+    }
+}
+
+impl Drop for SuspendMonitor {
+    fn drop(&mut self) {
+        // The reader thread finishes once the output closes
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Picks `PrepareForSleep` signals out of `dbus-monitor` output. The
+/// argument comes on the line after the signal's header.
+#[derive(Default)]
+struct SignalParser {
+    in_signal: bool,
+}
+
+impl SignalParser {
+    fn line(&mut self, line: &str) -> Option<PowerEvent> {
+        let line = line.trim();
+
+        if line.starts_with("signal ") {
+            self.in_signal = line.contains("member=PrepareForSleep");
+            return None;
+        }
+
+        if !self.in_signal {
+            return None;
+        }
+
+        self.in_signal = false;
+
+        match line {
+            "boolean true" => Some(PowerEvent::Suspending),
+            "boolean false" => Some(PowerEvent::Resumed),
+            _ => None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MockTransport;
+
+    const OUTPUT: &str = "\
+signal time=1700000000.000000 sender=org.freedesktop.DBus -> destination=:1.90 serial=2 path=/org/freedesktop/DBus; interface=org.freedesktop.DBus; member=NameAcquired
+   string \":1.90\"
+signal time=1700000100.000000 sender=:1.3 -> destination=(null destination) serial=1201 path=/org/freedesktop/login1; interface=org.freedesktop.login1.Manager; member=PrepareForSleep
+   boolean true
+signal time=1700000200.000000 sender=:1.3 -> destination=(null destination) serial=1202 path=/org/freedesktop/login1; interface=org.freedesktop.login1.Manager; member=PrepareForSleep
+   boolean false
+";
+
+    #[test]
+    fn parses_monitor_output() {
+        let mut parser = SignalParser::default();
+        let events: Vec<_> = OUTPUT.lines().filter_map(|x| parser.line(x)).collect();
+
+        assert_eq!(events, [PowerEvent::Suspending, PowerEvent::Resumed]);
+    }
+
+    #[test]
+    fn sleeps_and_wakes_the_matrix() {
+        let mut printer = Process::new("printf");
+        printer.arg("%s").arg(OUTPUT);
+
+        let mut monitor = SuspendMonitor::with_command(printer).unwrap();
+        let mock = MockTransport::new();
+        let mut matrix = LedMatrix::with_transport("mock", mock.clone());
+
+        // Polls until the output runs out and the monitor counts as stopped
+        while monitor.poll(&mut matrix).is_ok() {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        let written = mock.take_written();
+        let commands: Vec<&[u8]> = written.chunks(crate::MAX_COMMAND_LENGTH).map(|x| &x[2..4]).collect();
+        assert_eq!(commands, [[0x03, 1], [0x03, 0]]);
+    }
+}
@@ -0,0 +1,15 @@
+//! The types most programs need, for a single `use f16_hid::prelude::*;`
+
+pub use crate::animation::{Animation, Animator, PlayMode};
+pub use crate::canvas::{Canvas, Rotation};
+pub use crate::discovery::DiscoveredMatrix;
+pub use crate::display::Display;
+pub use crate::geometry::{EdgeMode, Point, Rect, Size};
+pub use crate::layout::Layout;
+pub use crate::roles::Role;
+pub use crate::text::{Orientation, TextStyle, WrapMode, FONT_3X5, FONT_5X7};
+pub use crate::widgets::Widget;
+pub use crate::{
+    Bitmap, Bitmap8, Command, LedMatrix, Patterns, ShutdownScreen, StartupScreen,
+    DISPLAY_HEIGHT, DISPLAY_WIDTH,
+};
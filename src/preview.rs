@@ -0,0 +1,152 @@
+//! Draw a `Bitmap8` as text, for working on layouts without a module
+//! plugged in. The plain styles only use characters, so they also work as
+//! golden images to compare against in tests:
+//!
+//! ```
+//! use f16_hid::preview::{render, PreviewStyle};
+//! use f16_hid::Bitmap8;
+//!
+//! let mut frame = Bitmap8::new();
+//! frame.draw_text((0, 0), "HI", 0xff);
+//!
+//! println!("{}", render(&frame, PreviewStyle::Braille { threshold: 0x80 }));
+//! ```
+
+use crate::{Bitmap8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Darkest to brightest
+const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+/// Bit for each dot of a braille character, by column and then row
+const BRAILLE_DOTS: [[u32; 4]; 2] = [
+    [0x01, 0x02, 0x04, 0x40],
+    [0x08, 0x10, 0x20, 0x80],
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PreviewStyle {
+    /// Two shade characters per pixel so it comes out roughly square.
+    /// The only plain style that shows levels of brightness.
+    #[default]
+    Shades,
+    /// A pixel above and below in each character cell, each either on or
+    /// off
+    HalfBlocks { threshold: u8 },
+    /// Two by four pixels to a braille character, each either on or off.
+    /// The smallest of the lot.
+    Braille { threshold: u8 },
+    /// Every pixel in its actual grey using 24 bit terminal colours. Only
+    /// useful on a terminal that understands them.
+    Ansi,
+}
+
+/// The bitmap as lines of text, each ending in a newline
+pub fn render(bitmap: &Bitmap8, style: PreviewStyle) -> String {
+    let mut text = String::new();
+    let value = |x: usize, y: usize| {
+        if x < DISPLAY_WIDTH && y < DISPLAY_HEIGHT {
+            bitmap.data[x * DISPLAY_HEIGHT + y]
+        } else {
+            0
+        }
+    };
+
+    match style {
+        PreviewStyle::Shades => {
+            for y in 0 .. DISPLAY_HEIGHT {
+                for x in 0 .. DISPLAY_WIDTH {
+                    let shade = SHADES[(value(x, y) as usize * (SHADES.len() - 1) + 0x7f) / 0xff];
+                    text.push(shade);
+                    text.push(shade);
+                }
+
+                text.push('\n');
+            }
+        },
+        PreviewStyle::HalfBlocks { threshold } => {
+            for y in (0 .. DISPLAY_HEIGHT).step_by(2) {
+                for x in 0 .. DISPLAY_WIDTH {
+                    text.push(match (value(x, y) >= threshold, value(x, y + 1) >= threshold) {
+                        (false, false) => ' ',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (true, true) => '█',
+                    });
+                }
+
+                text.push('\n');
+            }
+        },
+        PreviewStyle::Braille { threshold } => {
+            for y in (0 .. DISPLAY_HEIGHT).step_by(4) {
+                for x in (0 .. DISPLAY_WIDTH).step_by(2) {
+                    let mut bits = 0;
+
+                    for (column, dots) in BRAILLE_DOTS.iter().enumerate() {
+                        for (row, dot) in dots.iter().enumerate() {
+                            // Padding past the edges reads as 0, so a
+                            // threshold of 0 doesn't light it
+                            let lit = x + column < DISPLAY_WIDTH
+                                && y + row < DISPLAY_HEIGHT
+                                && value(x + column, y + row) >= threshold;
+
+                            if lit {
+                                bits |= dot;
+                            }
+                        }
+                    }
+
+                    text.push(char::from_u32(0x2800 + bits).unwrap_or(' '));
+                }
+
+                text.push('\n');
+            }
+        },
+        PreviewStyle::Ansi => {
+            for y in 0 .. DISPLAY_HEIGHT {
+                for x in 0 .. DISPLAY_WIDTH {
+                    let grey = value(x, y);
+                    text.push_str(&format!("\x1b[48;2;{grey};{grey};{grey}m  "));
+                }
+
+                text.push_str("\x1b[0m\n");
+            }
+        },
+    }
+
+    text
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn styles_line_up_with_the_panel() {
+        let mut frame = Bitmap8::new();
+        frame.draw_point(0, 0, 0xff).unwrap();
+        frame.draw_point(1, 1, 0x80).unwrap();
+        frame.draw_point(8, 33, 0xff).unwrap();
+
+        let shades = render(&frame, PreviewStyle::Shades);
+        let lines: Vec<&str> = shades.lines().collect();
+        assert_eq!(lines.len(), DISPLAY_HEIGHT);
+        assert_eq!(lines[0], "██                ");
+        assert_eq!(lines[1], "  ▒▒              ");
+        assert_eq!(lines[33], "                ██");
+
+        let halves = render(&frame, PreviewStyle::HalfBlocks { threshold: 0x80 });
+        assert_eq!(halves.lines().count(), 17);
+        assert_eq!(halves.lines().next(), Some("▀▄       "));
+        assert_eq!(halves.lines().last(), Some("        ▄"));
+
+        let braille = render(&frame, PreviewStyle::Braille { threshold: 0x80 });
+        assert_eq!(braille.lines().count(), 9);
+        assert_eq!(braille.lines().next(), Some("⠑⠀⠀⠀⠀"));
+        assert_eq!(braille.lines().last(), Some("⠀⠀⠀⠀⠂"));
+
+        let everything = render(&Bitmap8::new(), PreviewStyle::Braille { threshold: 0 });
+        assert_eq!(everything.lines().last(), Some("⠛⠛⠛⠛⠃"));
+    }
+}
@@ -0,0 +1,183 @@
+use std::io::Error;
+
+use crate::clock::Clock;
+use crate::display::Display;
+use crate::text::{TextStyle, FONT_3X5};
+use crate::{Bitmap8, Command, Patterns, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Rows at the top of the panel taken by the percentage, with a blank row
+/// under it
+const LABEL_HEIGHT: usize = 7;
+
+/// How `Display::progress()` shows how far along something is
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProgressStyle {
+    /// The firmware's own `Patterns::Percentage`, a bar of full rows. It
+    /// doesn't go through the display's buffers or the matrix's filters.
+    Firmware,
+    /// An outlined bar that fills from the bottom, the top row dimmed for
+    /// part of a row
+    Bar,
+    /// `Bar`, under the percentage in digits
+    #[default]
+    LabelledBar,
+}
+
+/// The frame `ProgressStyle::Bar` or `LabelledBar` shows for `fraction` of
+/// the way, clamped to 0.0 to 1.0. Two digits fit across, so the label
+/// stops at 99 and only the bar shows when it's done.
+pub fn progress_frame(fraction: f32, labelled: bool) -> Bitmap8 {
+    let fraction = fraction.clamp(0.0, 1.0);
+    let mut frame = Bitmap8::new();
+    let right = DISPLAY_WIDTH - 1;
+    let bottom = DISPLAY_HEIGHT - 1;
+
+    let top = if labelled {
+        let percent = ((fraction * 100.0) as u32).min(99);
+        let label = format!("{:2}", percent);
+        frame.draw_text_styled((1, 0), &label, &TextStyle::new(&FONT_3X5, 0xff));
+
+        LABEL_HEIGHT
+    } else {
+        0
+    };
+
+    frame.draw_box(0, top, right, top, 0xff);
+    frame.draw_box(0, bottom, right, bottom, 0xff);
+    frame.draw_box(0, top, 0, bottom, 0xff);
+    frame.draw_box(right, top, right, bottom, 0xff);
+
+    // Inside the outline, in rows
+    let rows = bottom - top - 1;
+    let filled = fraction * rows as f32;
+    let whole = filled as usize;
+
+    if whole > 0 {
+        frame.draw_box(1, bottom - whole, right - 1, bottom - 1, 0xff);
+    }
+
+    let part = ((filled - whole as f32) * 255.0) as u8;
+    if whole < rows && part > 0 {
+        let y = bottom - 1 - whole;
+        frame.draw_box(1, y, right - 1, y, part);
+    }
+
+    frame
+}
+
+impl<C: Clock> Display<C> {
+    /// Show `fraction` of the way along as a `ProgressStyle::LabelledBar`,
+    /// for installers and build scripts. See `progress_guard()` to have it
+    /// cleared when the work's done.
+    pub fn progress(&mut self, fraction: f32) -> Result<(), Error> {
+        self.progress_styled(fraction, ProgressStyle::default())
+    }
+
+    pub fn progress_styled(&mut self, fraction: f32, style: ProgressStyle) -> Result<(), Error> {
+        match style {
+            ProgressStyle::Firmware => {
+                self.matrix_mut().execute(Command::Pattern(Patterns::progress(fraction)))?;
+                self.invalidate();
+
+                Ok(())
+            },
+            ProgressStyle::Bar => self.set_frame(&progress_frame(fraction, false)),
+            ProgressStyle::LabelledBar => self.set_frame(&progress_frame(fraction, true)),
+        }
+    }
+
+    /// Show progress in `style` until the guard is dropped, which blanks
+    /// the panel. Starts at zero.
+    ///
+    /// ```no_run
+    /// use f16_hid::progress::ProgressStyle;
+    /// use f16_hid::Display;
+    ///
+    /// let mut display = Display::open_default()?;
+    /// let mut progress = display.progress_guard(ProgressStyle::LabelledBar)?;
+    ///
+    /// for step in 0 .. 10 {
+    ///     // Do a tenth of the work
+    ///     progress.set((step + 1) as f32 / 10.0)?;
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn progress_guard(&mut self, style: ProgressStyle) -> Result<ProgressGuard<'_, C>, Error> {
+        self.progress_styled(0.0, style)?;
+
+        Ok(ProgressGuard { display: self, style })
+    }
+}
+
+/// Progress on a `Display`, blanked when this is dropped. See
+/// `Display::progress_guard()`.
+pub struct ProgressGuard<'a, C: Clock> {
+    display: &'a mut Display<C>,
+    style: ProgressStyle,
+}
+
+impl<C: Clock> ProgressGuard<'_, C> {
+    /// Show `fraction` of the way along
+    pub fn set(&mut self, fraction: f32) -> Result<(), Error> {
+        self.display.progress_styled(fraction, self.style)
+    }
+
+    pub fn style(&self) -> ProgressStyle {
+        self.style
+    }
+}
+
+impl<C: Clock> Drop for ProgressGuard<'_, C> {
+    fn drop(&mut self) {
+        // Nothing to be done about a failure here
+        let _ = self.display.set_frame(&Bitmap8::new());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::geometry::Point;
+    use crate::transport::MockTransport;
+    use crate::{LedMatrix, MAX_COMMAND_LENGTH};
+
+    #[test]
+    fn bars_fill_from_the_bottom() {
+        let lit = |frame: &Bitmap8, y: i32| frame.pixel(Point::new(4, y)).unwrap();
+
+        let empty = progress_frame(0.0, false);
+        assert_eq!(lit(&empty, 0), 0xff);
+        assert_eq!(lit(&empty, 32), 0);
+
+        // 32 rows inside the outline, half and a bit
+        let half = progress_frame(0.51, false);
+        assert_eq!(lit(&half, 32), 0xff);
+        assert_eq!(lit(&half, 17), 0xff);
+        assert!(lit(&half, 16) > 0 && lit(&half, 16) < 0xff);
+        assert_eq!(lit(&half, 15), 0);
+
+        let labelled = progress_frame(0.5, true);
+        assert_eq!(lit(&labelled, LABEL_HEIGHT as i32), 0xff);
+        assert_ne!(labelled.data()[.. LABEL_HEIGHT], empty.data()[.. LABEL_HEIGHT]);
+    }
+
+    #[test]
+    fn guard_blanks_the_panel() {
+        let mock = MockTransport::new();
+        let mut display = Display::with_clock(LedMatrix::with_transport("mock", mock.clone()), ManualClock::new());
+
+        {
+            let mut progress = display.progress_guard(ProgressStyle::Firmware).unwrap();
+            progress.set(0.5).unwrap();
+
+            let written = mock.take_written();
+            assert_eq!(written[MAX_COMMAND_LENGTH + 2 .. MAX_COMMAND_LENGTH + 5], [0x01, 0x00, 50]);
+        }
+
+        assert!(display.frame().data().iter().all(|x| *x == 0));
+        // The firmware's bar is covered by one blank Draw
+        assert_eq!(mock.take_written()[2], 0x06);
+    }
+}
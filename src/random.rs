@@ -0,0 +1,131 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Small, fast pseudo random number generator for visual effects. This is
+/// xorshift64* and isn't suitable for anything but making pixels twinkle.
+///
+/// Every effect that needs randomness takes one of these rather than reaching
+/// for a global source, so giving two effects the same seed makes them produce
+/// the same frames. That's handy for tests and for recordings.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    seed: u64,
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            state: Self::scramble(seed),
+        }
+    }
+
+    /// Seed from the wall clock for when reproducibility doesn't matter
+    pub fn from_time() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_nanos() as u64)
+            .unwrap_or(0);
+
+        Self::new(seed)
+    }
+
+    /// The seed this generator was created with, so a run can be reproduced
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Start the sequence over from the original seed
+    pub fn reset(&mut self) {
+        self.state = Self::scramble(self.seed);
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    /// Value in the range `0..max`. Returns 0 if `max` is 0.
+    pub fn below(&mut self, max: usize) -> usize {
+        if max == 0 {
+            return 0;
+        }
+
+        (self.next_u64() % max as u64) as usize
+    }
+
+    /// True roughly `percent` times out of a hundred
+    pub fn chance(&mut self, percent: u8) -> bool {
+        self.below(100) < percent as usize
+    }
+
+    // xorshift falls over with a zero state, so run the seed through
+    // splitmix64 first. This also spreads out small sequential seeds.
+    fn scramble(seed: u64) -> u64 {
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+
+        if z == 0 {
+            0x9e37_79b9_7f4a_7c15
+        } else {
+            z
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn reset_replays() {
+        let mut rng = Rng::new(7);
+        let first: Vec<u32> = (0..10).map(|_| rng.next_u32()).collect();
+
+        rng.reset();
+        let second: Vec<u32> = (0..10).map(|_| rng.next_u32()).collect();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn zero_seed_works() {
+        let mut rng = Rng::new(0);
+
+        assert_ne!(rng.next_u64(), rng.next_u64());
+    }
+
+    #[test]
+    fn below_stays_in_range() {
+        let mut rng = Rng::new(1);
+
+        for _ in 0..1000 {
+            assert!(rng.below(9) < 9);
+        }
+        assert_eq!(rng.below(0), 0);
+    }
+}
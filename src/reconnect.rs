@@ -0,0 +1,122 @@
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+
+use crate::events::is_link_lost;
+use crate::RECONNECT_DELAY;
+
+/// How hard `LedMatrix::execute()` tries when the port goes away or a write
+/// times out. Each retry waits, reopens the port and sends the command again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// Retries after the first failure. Zero turns reconnecting off.
+    pub max_retries: u32,
+    /// Wait before the first retry
+    pub initial_delay: Duration,
+    /// Each wait is this many times longer than the last...
+    pub multiplier: u32,
+    /// ...up to this long
+    pub max_delay: Duration,
+}
+
+impl ReconnectPolicy {
+    /// Fail straight away like a plain serial port would
+    pub fn never() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// How long to wait before retry number `attempt`, counting from zero
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.max(1).saturating_pow(attempt);
+
+        self.initial_delay.saturating_mul(factor).min(self.max_delay)
+    }
+
+    /// Whether an error is worth reconnecting over
+    pub(crate) fn should_retry(&self, error: &Error) -> bool {
+        self.max_retries > 0 && (is_link_lost(error) || error.kind() == ErrorKind::TimedOut)
+    }
+}
+
+/// Three tries over about three and a half seconds, which is long enough
+/// for the module to come back from a USB reset
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_delay: RECONNECT_DELAY,
+            multiplier: 2,
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+/// What's inside the `std::io::Error` returned once a `ReconnectPolicy` has
+/// run out of retries. The error keeps the kind of the last failure so
+/// checking `kind()` still works, this just says how hard we tried.
+#[derive(Debug)]
+pub struct RetriesExhausted {
+    pub attempts: u32,
+    pub last_error: Error,
+}
+
+impl RetriesExhausted {
+    pub(crate) fn into_error(attempts: u32, last_error: Error) -> Error {
+        Error::new(last_error.kind(), RetriesExhausted { attempts, last_error })
+    }
+
+    /// Look inside an error from `execute()` for one of these
+    pub fn find(error: &Error) -> Option<&RetriesExhausted> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+impl fmt::Display for RetriesExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Gave up after {} reconnects: {}", self.attempts, self.last_error)
+    }
+}
+
+impl std::error::Error for RetriesExhausted {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.last_error)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_is_capped() {
+        let policy = ReconnectPolicy::default();
+
+        assert_eq!(policy.delay(0), Duration::from_millis(500));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+        assert_eq!(policy.delay(10), Duration::from_secs(5));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn only_lost_links_and_timeouts_are_retried() {
+        let policy = ReconnectPolicy::default();
+
+        assert!(policy.should_retry(&Error::from(ErrorKind::BrokenPipe)));
+        assert!(policy.should_retry(&Error::from(ErrorKind::TimedOut)));
+        assert!(!policy.should_retry(&Error::from(ErrorKind::InvalidInput)));
+        assert!(!ReconnectPolicy::never().should_retry(&Error::from(ErrorKind::BrokenPipe)));
+    }
+
+    #[test]
+    fn exhausted_error_keeps_its_kind() {
+        let error = RetriesExhausted::into_error(3, Error::from(ErrorKind::BrokenPipe));
+
+        assert_eq!(error.kind(), ErrorKind::BrokenPipe);
+        assert_eq!(RetriesExhausted::find(&error).map(|x| x.attempts), Some(3));
+        assert!(RetriesExhausted::find(&Error::from(ErrorKind::BrokenPipe)).is_none());
+    }
+}
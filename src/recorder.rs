@@ -0,0 +1,404 @@
+//! Recording everything sent to a module and playing it back later, for
+//! chasing flicker that only shows up on the hardware or handing someone
+//! else an animation exactly as it went out. Give a `Recorder` to
+//! `LedMatrixBuilder::recorder()` and every byte written to the port is
+//! kept along with when it went, across reconnects:
+//!
+//! ```no_run
+//! use f16_hid::recorder::Recorder;
+//! use f16_hid::{Command, LedMatrix};
+//!
+//! let recorder = Recorder::new();
+//! let mut matrix = LedMatrix::builder("/dev/ttyACM0")
+//!     .recorder(recorder.clone())
+//!     .open()?;
+//!
+//! matrix.execute(Command::Brightness(0x40))?;
+//! recorder.save("session.rec")?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! A recording can be sent to a module again with `LedMatrix::replay()`,
+//! or watched in the terminal with `Recording::preview()`.
+
+use std::fmt;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::preview::{render, PreviewStyle};
+use crate::transport::Transport;
+use crate::{Bitmap, Bitmap8, LedMatrix, DISPLAY_HEIGHT, DRAW_COMMAND_LENGTH, MAX_COMMAND_LENGTH};
+
+/// One write to the port
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedWrite {
+    /// Time since recording started
+    pub at: Duration,
+    pub bytes: Vec<u8>,
+}
+
+/// Writes in the order they were made. Saved as text, one write per line:
+/// microseconds since the start, then the bytes in hex.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Recording {
+    writes: Vec<RecordedWrite>,
+}
+
+impl Recording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    /// Write to a file, creating parent directories as needed
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(path, self.to_string())
+    }
+
+    pub fn writes(&self) -> &[RecordedWrite] {
+        &self.writes
+    }
+
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Time from the start to the last write
+    pub fn duration(&self) -> Duration {
+        self.writes.last().map(|x| x.at).unwrap_or_default()
+    }
+
+    /// Every frame drawn, when it was drawn and as it was sent, after
+    /// gamma and remapping. Black and white draws come out at full
+    /// brightness. Writes that were cut short are skipped.
+    pub fn frames(&self) -> Vec<(Duration, Bitmap8)> {
+        let mut frames = Vec::new();
+        let mut staged = Bitmap8::new();
+
+        for write in &self.writes {
+            for packet in write.bytes.chunks_exact(MAX_COMMAND_LENGTH) {
+                if packet[.. 2] != [0x32, 0xac] {
+                    continue;
+                }
+
+                match packet[2] {
+                    0x06 => {
+                        let mut bitmap = Bitmap::new();
+                        bitmap.data.copy_from_slice(&packet[3 .. 3 + DRAW_COMMAND_LENGTH]);
+                        frames.push((write.at, bitmap.to_greyscale(0xff)));
+                    },
+                    0x07 => {
+                        if let Some(column) = staged.column_mut(packet[3] as usize) {
+                            column.copy_from_slice(&packet[4 .. 4 + DISPLAY_HEIGHT]);
+                        }
+                    },
+                    0x08 => frames.push((write.at, staged.clone())),
+                    _ => {},
+                }
+            }
+        }
+
+        frames
+    }
+
+    /// Write everything to `target` again with the same timing, for
+    /// anything that isn't an `LedMatrix`
+    pub fn play<W: Write>(&self, target: &mut W) -> Result<(), Error> {
+        self.play_with(target, &SystemClock)
+    }
+
+    pub fn play_with<W: Write, C: Clock>(&self, target: &mut W, clock: &C) -> Result<(), Error> {
+        let start = clock.now();
+
+        for write in &self.writes {
+            wait_until(clock, start, write.at);
+            target.write_all(&write.bytes)?;
+            target.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Draw each frame in the terminal as it happened, over the top of
+    /// the last
+    pub fn preview<W: Write>(&self, out: &mut W, style: PreviewStyle) -> Result<(), Error> {
+        self.preview_with(out, style, &SystemClock)
+    }
+
+    pub fn preview_with<W: Write, C: Clock>(&self, out: &mut W, style: PreviewStyle, clock: &C) -> Result<(), Error> {
+        let start = clock.now();
+
+        // Clear the screen once, then each frame goes back to the top
+        write!(out, "\x1b[2J")?;
+
+        for (at, frame) in self.frames() {
+            wait_until(clock, start, at);
+            write!(out, "\x1b[H{}", render(&frame, style))?;
+            out.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+fn wait_until<C: Clock>(clock: &C, start: Instant, at: Duration) {
+    let wait = at.saturating_sub(clock.elapsed(start));
+
+    if !wait.is_zero() {
+        clock.sleep(wait);
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# LED matrix recording, microseconds then the bytes written")?;
+
+        for write in &self.writes {
+            write!(f, "{} ", write.at.as_micros())?;
+
+            for byte in &write.bytes {
+                write!(f, "{:02x}", byte)?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Recording {
+    type Err = Error;
+
+    fn from_str(contents: &str) -> Result<Self, Self::Err> {
+        let mut recording = Self::new();
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |message: &str| {
+                Error::new(ErrorKind::InvalidData, format!("Line {}: {}", number + 1, message))
+            };
+
+            let (at, hex) = line.split_once(' ').ok_or_else(|| invalid("expected a time and bytes"))?;
+            let at = at.parse().map_err(|_| invalid("expected microseconds"))?;
+
+            if hex.len() % 2 != 0 || !hex.is_ascii() {
+                return Err(invalid("expected pairs of hex digits"));
+            }
+
+            let bytes = (0 .. hex.len()).step_by(2)
+                .map(|x| u8::from_str_radix(&hex[x .. x + 2], 16))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid("expected pairs of hex digits"))?;
+
+            recording.writes.push(RecordedWrite { at: Duration::from_micros(at), bytes });
+        }
+
+        Ok(recording)
+    }
+}
+
+struct RecorderState {
+    clock: Box<dyn Clock + Send>,
+    started: Instant,
+    recording: Recording,
+}
+
+/// Collects a `Recording` from every port it's tapped into. Clones share
+/// the same recording, so keep one to save it after handing the other to
+/// `LedMatrixBuilder::recorder()`.
+#[derive(Clone)]
+pub struct Recorder {
+    state: Arc<Mutex<RecorderState>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+
+    pub fn with_clock<C: Clock + Send + 'static>(clock: C) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(RecorderState {
+                started: clock.now(),
+                clock: Box::new(clock),
+                recording: Recording::new(),
+            })),
+        }
+    }
+
+    /// Everything recorded so far
+    pub fn recording(&self) -> Recording {
+        self.state().recording.clone()
+    }
+
+    /// Hand over everything recorded so far and start again from nothing,
+    /// with the time back at zero
+    pub fn take(&self) -> Recording {
+        let mut state = self.state();
+        state.started = state.clock.now();
+
+        std::mem::take(&mut state.recording)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        self.recording().save(path)
+    }
+
+    /// Wrap a port so everything written to it is recorded
+    pub(crate) fn tap(&self, port: Box<dyn Transport>) -> Box<dyn Transport> {
+        Box::new(Tap {
+            port,
+            recorder: self.clone(),
+        })
+    }
+
+    fn record(&self, bytes: &[u8]) {
+        let mut state = self.state();
+        let at = state.clock.elapsed(state.started);
+
+        state.recording.writes.push(RecordedWrite { at, bytes: bytes.to_vec() });
+    }
+
+    fn state(&self) -> MutexGuard<'_, RecorderState> {
+        self.state.lock().expect("Recorder lock poisoned")
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorder").field("writes", &self.state().recording.len()).finish()
+    }
+}
+
+/// A port with a recorder listening in. Only what the port took is
+/// recorded, so a short write shows up short.
+struct Tap {
+    port: Box<dyn Transport>,
+    recorder: Recorder,
+}
+
+impl Read for Tap {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        self.port.read(buffer)
+    }
+}
+
+impl Write for Tap {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize, Error> {
+        let count = self.port.write(buffer)?;
+
+        if count > 0 {
+            self.recorder.record(&buffer[.. count]);
+        }
+
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.port.flush()
+    }
+}
+
+impl Transport for Tap {
+    fn bytes_to_read(&self) -> Result<u32, Error> {
+        self.port.bytes_to_read()
+    }
+
+    fn timeout(&self) -> Duration {
+        self.port.timeout()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), Error> {
+        self.port.set_timeout(timeout)
+    }
+}
+
+impl LedMatrix {
+    /// Send a recording to the module with the same timing it was made
+    /// with. Each write goes out whole and is retried as the reconnect
+    /// policy says. Queries are sent too, and their replies left to be
+    /// drained.
+    pub fn replay(&mut self, recording: &Recording) -> Result<(), Error> {
+        let clock = SystemClock;
+        let start = clock.now();
+
+        for write in recording.writes() {
+            wait_until(&clock, start, write.at);
+            self.send_packets(&write.bytes)?;
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::transport::MockTransport;
+    use crate::Command;
+
+    #[test]
+    fn sessions_are_recorded_and_read_back() {
+        let clock = ManualClock::new();
+        let recorder = Recorder::with_clock(clock.clone());
+        let mock = MockTransport::new();
+        let mut matrix = LedMatrix::builder("mock").recorder(recorder.clone()).build_transport(mock.clone());
+
+        let mut frame = Bitmap8::new();
+        frame.draw_point(2, 5, 0x80).unwrap();
+
+        matrix.execute(Command::Brightness(0x40)).unwrap();
+        clock.advance(Duration::from_millis(15));
+        matrix.stage_frame(&frame).unwrap();
+
+        let recording = recorder.recording();
+        assert_eq!(recording.len(), 11);
+        assert_eq!(recording.duration(), Duration::from_millis(15));
+        assert_eq!(recording.writes().iter().flat_map(|x| x.bytes.clone()).collect::<Vec<_>>(), mock.written());
+
+        let frames = recording.frames();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].0, Duration::from_millis(15));
+        assert_eq!(frames[0].1.data(), frame.data());
+
+        let parsed: Recording = recording.to_string().parse().unwrap();
+        assert_eq!(parsed, recording);
+        assert!("10 32a".parse::<Recording>().is_err());
+
+        // Played back with the same bytes at the same times
+        let mut played = Vec::new();
+        let start = clock.now();
+        parsed.play_with(&mut played, &clock).unwrap();
+        assert_eq!(played, mock.written());
+        assert_eq!(clock.elapsed(start), Duration::from_millis(15));
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{Bitmap, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Maps logical columns and rows onto physical ones. The Framework module
+/// doesn't need this, but clones and odd firmware builds wire their LEDs in a
+/// different order. Drawing code keeps using logical coordinates and the
+/// matrix applies the table when commands are staged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Remap {
+    columns: [u8; DISPLAY_WIDTH],
+    rows: [u8; DISPLAY_HEIGHT],
+}
+
+impl Remap {
+    /// Logical and physical layouts are the same
+    pub fn identity() -> Self {
+        let mut columns = [0u8; DISPLAY_WIDTH];
+        let mut rows = [0u8; DISPLAY_HEIGHT];
+
+        for (index, value) in columns.iter_mut().enumerate() {
+            *value = index as u8;
+        }
+        for (index, value) in rows.iter_mut().enumerate() {
+            *value = index as u8;
+        }
+
+        Self { columns, rows }
+    }
+
+    /// Build a remap where `columns[logical]` and `rows[logical]` hold the
+    /// physical position. Both tables must be permutations.
+    pub fn new(columns: [u8; DISPLAY_WIDTH], rows: [u8; DISPLAY_HEIGHT]) -> Result<Self, &'static str> {
+        if !is_permutation(&columns) {
+            return Err("Column map must use every column exactly once");
+        } else if !is_permutation(&rows) {
+            return Err("Row map must use every row exactly once");
+        }
+
+        Ok(Self { columns, rows })
+    }
+
+    /// Columns are wired right to left
+    pub fn mirrored() -> Self {
+        let mut remap = Self::identity();
+        remap.columns.reverse();
+        remap
+    }
+
+    /// Rows are wired bottom to top
+    pub fn flipped() -> Self {
+        let mut remap = Self::identity();
+        remap.rows.reverse();
+        remap
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::identity()
+    }
+
+    /// Physical column for a logical one. Out of range values are passed
+    /// through untouched so the command's own validation can reject them.
+    pub fn column(&self, logical: u8) -> u8 {
+        match self.columns.get(logical as usize) {
+            Some(x) => *x,
+            None => logical
+        }
+    }
+
+    /// Physical row for a logical one
+    pub fn row(&self, logical: usize) -> usize {
+        match self.rows.get(logical) {
+            Some(x) => *x as usize,
+            None => logical
+        }
+    }
+
+    /// Reorder the pixels of one column from logical to physical rows
+    pub(crate) fn apply_column(&self, logical: &[u8], physical: &mut [u8; DISPLAY_HEIGHT]) {
+        for (y, value) in logical.iter().enumerate().take(DISPLAY_HEIGHT) {
+            physical[self.row(y)] = *value;
+        }
+    }
+
+    /// Rebuild a mono bitmap with physical pixel positions
+    pub(crate) fn apply_bitmap(&self, logical: &Bitmap) -> Bitmap {
+        let mut physical = Bitmap::new();
+
+        for x in 0 .. DISPLAY_WIDTH {
+            for y in 0 .. DISPLAY_HEIGHT {
+                let location = y + (x * DISPLAY_HEIGHT);
+                let value = logical.data[location / 8] & (1 << (location % 8)) != 0;
+
+                // Can't fail, both values come from the tables
+                let _ = physical.draw_point(self.column(x as u8) as usize, self.row(y), value);
+            }
+        }
+
+        physical
+    }
+}
+
+impl Default for Remap {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+fn is_permutation(values: &[u8]) -> bool {
+    let mut seen = [false; 256];
+
+    for value in values {
+        let value = *value as usize;
+        if value >= values.len() || seen[value] {
+            return false;
+        }
+        seen[value] = true;
+    }
+
+    true
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_duplicate_columns() {
+        let mut columns = [0u8; DISPLAY_WIDTH];
+        columns[8] = 1;
+
+        assert!(Remap::new(columns, Remap::identity().rows).is_err());
+    }
+
+    #[test]
+    fn mirrored_columns() {
+        let remap = Remap::mirrored();
+
+        assert_eq!(remap.column(0), 8);
+        assert_eq!(remap.column(8), 0);
+        assert_eq!(remap.row(3), 3);
+        assert_eq!(remap.column(20), 20);
+    }
+
+    #[test]
+    fn flipped_column_data() {
+        let remap = Remap::flipped();
+        let mut logical = [0u8; DISPLAY_HEIGHT];
+        let mut physical = [0u8; DISPLAY_HEIGHT];
+        logical[0] = 0xff;
+
+        remap.apply_column(&logical, &mut physical);
+
+        assert_eq!(physical[DISPLAY_HEIGHT - 1], 0xff);
+        assert_eq!(physical[0], 0);
+    }
+
+    #[test]
+    fn mirrored_bitmap() {
+        let remap = Remap::mirrored();
+        let mut logical = Bitmap::new();
+        logical.draw_point(0, 5, true).unwrap();
+
+        let physical = remap.apply_bitmap(&logical);
+
+        let mut expected = Bitmap::new();
+        expected.draw_point(8, 5, true).unwrap();
+        assert_eq!(physical.data(), expected.data());
+    }
+}
@@ -0,0 +1,118 @@
+use core::fmt;
+use core::time::Duration;
+
+use crate::PwmFrequency;
+
+/// Every reply from the firmware is this long, whatever it contains
+pub const RESPONSE_LENGTH: usize = 32;
+
+/// Firmware version as reported by `Command::Version`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+    pub pre_release: bool,
+}
+
+impl FirmwareVersion {
+    pub const fn new(major: u8, minor: u8, patch: u8) -> Self {
+        Self { major, minor, patch, pre_release: false }
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+
+        if self.pre_release {
+            write!(f, " (pre-release)")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A parsed reply to a query
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Response {
+    Version(FirmwareVersion),
+    Brightness(u8),
+    Sleeping(bool),
+    Animating(bool),
+    AnimationPeriod(Duration),
+    PwmFrequency(PwmFrequency),
+}
+
+impl Response {
+    /// Make sense of the reply to the command with the given ID. `None` if
+    /// that command doesn't have a reply or there isn't enough of it.
+    pub fn parse(command_id: u8, data: &[u8]) -> Option<Self> {
+        match command_id {
+            0x00 => Some(Self::Brightness(*data.first()?)),
+            0x03 => Some(Self::Sleeping(*data.first()? != 0)),
+            0x04 => Some(Self::Animating(*data.first()? != 0)),
+            0x1c => {
+                let millis = u16::from_le_bytes([*data.first()?, *data.get(1)?]);
+                Some(Self::AnimationPeriod(Duration::from_millis(millis as u64)))
+            },
+            0x1e => Some(Self::PwmFrequency(PwmFrequency::from_index(*data.first()?)?)),
+            0x20 => {
+                // The firmware sends its USB bcdDevice: major in the first
+                // byte, then a nibble each for minor and patch
+                if data.len() < 3 {
+                    return None;
+                }
+
+                Some(Self::Version(FirmwareVersion {
+                    major: data[0],
+                    minor: data[1] >> 4,
+                    patch: data[1] & 0x0f,
+                    pre_release: data[2] != 0,
+                }))
+            },
+            _ => None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_version() {
+        let mut data = [0u8; RESPONSE_LENGTH];
+        data[0] = 0;
+        data[1] = 0x52;
+        data[2] = 1;
+
+        let response = Response::parse(0x20, &data);
+
+        assert_eq!(response, Some(Response::Version(FirmwareVersion {
+            major: 0,
+            minor: 5,
+            patch: 2,
+            pre_release: true,
+        })));
+    }
+
+    #[test]
+    fn parse_state() {
+        assert_eq!(Response::parse(0x00, &[0x40]), Some(Response::Brightness(0x40)));
+        assert_eq!(Response::parse(0x03, &[1]), Some(Response::Sleeping(true)));
+        assert_eq!(Response::parse(0x04, &[0]), Some(Response::Animating(false)));
+        assert_eq!(Response::parse(0x1c, &[0xf4, 0x01]), Some(Response::AnimationPeriod(Duration::from_millis(500))));
+        assert_eq!(Response::parse(0x1c, &[0xf4]), None);
+        assert_eq!(Response::parse(0x1e, &[3]), Some(Response::PwmFrequency(PwmFrequency::Hz900)));
+        assert_eq!(Response::parse(0x1e, &[4]), None);
+        assert_eq!(Response::parse(0x06, &[0]), None);
+        assert_eq!(Response::parse(0x20, &[0]), None);
+    }
+
+    #[test]
+    fn versions_order() {
+        assert!(FirmwareVersion::new(0, 2, 0) > FirmwareVersion::new(0, 1, 9));
+    }
+}
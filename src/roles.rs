@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Name of the directory created under the platform's config directory
+pub const CONFIG_DIRECTORY: &str = "f16_hid";
+pub const ROLES_FILE: &str = "roles.conf";
+
+/// Where a module lives, as far as the application cares
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Role {
+    Left,
+    Right,
+    /// Anything not in the laptop's input deck, with a user-chosen label
+    External(String),
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Left => write!(f, "left"),
+            Self::Right => write!(f, "right"),
+            Self::External(label) => write!(f, "external:{}", label),
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "left" => Ok(Self::Left),
+            "right" => Ok(Self::Right),
+            "external" => Ok(Self::External(String::new())),
+            x => match x.strip_prefix("external:") {
+                Some(label) => Ok(Self::External(label.to_owned())),
+                None => Err("Unknown role"),
+            }
+        }
+    }
+}
+
+/// Which module (by USB serial number) plays which role. Serial numbers
+/// survive reboots and port renames where `/dev/ttyACM0` doesn't.
+///
+/// Stored as one `serial = role` pair per line. Lines starting with `#` are
+/// comments.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoleConfig {
+    roles: BTreeMap<String, Role>,
+}
+
+impl RoleConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Where the config lives for the current user, following each
+    /// platform's convention. `None` if the home directory can't be found.
+    pub fn default_path() -> Option<PathBuf> {
+        config_directory().map(|x| x.join(CONFIG_DIRECTORY).join(ROLES_FILE))
+    }
+
+    /// Load from the default location. A missing file is an empty config.
+    pub fn load_default() -> Result<Self, Error> {
+        match Self::default_path() {
+            Some(path) => Self::load(path),
+            None => Err(Error::new(ErrorKind::NotFound, "Unable to find a config directory")),
+        }
+    }
+
+    pub fn save_default(&self) -> Result<(), Error> {
+        match Self::default_path() {
+            Some(path) => self.save(path),
+            None => Err(Error::new(ErrorKind::NotFound, "Unable to find a config directory")),
+        }
+    }
+
+    /// Load from a file. A missing file is an empty config.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => contents.parse(),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::new()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Write to a file, creating parent directories as needed
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(path, self.to_string())
+    }
+
+    pub fn role(&self, serial: &str) -> Option<&Role> {
+        self.roles.get(serial)
+    }
+
+    /// Serial number of the module playing a role
+    pub fn serial(&self, role: &Role) -> Option<&str> {
+        self.roles.iter()
+            .find(|(_, x)| *x == role)
+            .map(|(serial, _)| serial.as_str())
+    }
+
+    /// Give a module a role. Only one module can have a role, so whichever
+    /// module had it before loses it.
+    pub fn assign(&mut self, serial: &str, role: Role) {
+        self.roles.retain(|_, x| *x != role);
+        self.roles.insert(serial.to_owned(), role);
+    }
+
+    pub fn unassign(&mut self, serial: &str) -> Option<Role> {
+        self.roles.remove(serial)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Role)> {
+        self.roles.iter().map(|(serial, role)| (serial.as_str(), role))
+    }
+}
+
+impl fmt::Display for RoleConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# LED matrix roles, one 'serial = role' per line")?;
+
+        for (serial, role) in &self.roles {
+            writeln!(f, "{} = {}", serial, role)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for RoleConfig {
+    type Err = Error;
+
+    fn from_str(contents: &str) -> Result<Self, Self::Err> {
+        let mut config = Self::new();
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |message: &str| {
+                Error::new(ErrorKind::InvalidData, format!("Line {}: {}", number + 1, message))
+            };
+
+            let (serial, role) = line.split_once('=')
+                .ok_or_else(|| invalid("expected 'serial = role'"))?;
+            let role = role.parse().map_err(invalid)?;
+
+            config.assign(serial.trim(), role);
+        }
+
+        Ok(config)
+    }
+}
+
+pub(crate) fn config_directory() -> Option<PathBuf> {
+    let home = || std::env::var_os("HOME").map(PathBuf::from);
+
+    if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|x| x.join("Library").join("Application Support"))
+    } else {
+        match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(x) if !x.is_empty() => Some(PathBuf::from(x)),
+            _ => home().map(|x| x.join(".config")),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_are_unique() {
+        let mut config = RoleConfig::new();
+
+        config.assign("AAAA", Role::Left);
+        config.assign("BBBB", Role::Left);
+
+        assert_eq!(config.role("AAAA"), None);
+        assert_eq!(config.serial(&Role::Left), Some("BBBB"));
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut config = RoleConfig::new();
+        config.assign("AAAA", Role::Left);
+        config.assign("BBBB", Role::Right);
+        config.assign("CCCC", Role::External("desk".to_owned()));
+
+        let path = std::env::temp_dir()
+            .join(format!("f16_hid_roles_{}", std::process::id()))
+            .join(ROLES_FILE);
+        config.save(&path).unwrap();
+        let loaded = RoleConfig::load(&path).unwrap();
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(config, loaded);
+    }
+
+    #[test]
+    fn bad_lines_are_reported() {
+        let error = "# comment\nAAAA = middle\n".parse::<RoleConfig>().unwrap_err();
+
+        assert!(error.to_string().contains("Line 2"));
+    }
+}
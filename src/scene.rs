@@ -0,0 +1,554 @@
+//! Scenes written as files rather than Rust, for people who want to put
+//! something on the panel without building anything. A scene is a list of
+//! frames, each shown for a while and faded or cut to, with widgets drawn
+//! over the top. It's written in RON:
+//!
+//! ```text
+//! (
+//!     mode: Loop,
+//!     frames: [
+//!         (show: Text("HI"), duration_ms: 1000),
+//!         (show: Fill(0x40), duration_ms: 500, transition: Fade(300)),
+//!         (show: Pixels([
+//!             "#.......#",
+//!             ".#.....#.",
+//!             // ... 34 rows in all
+//!         ]), duration_ms: 2000),
+//!     ],
+//!     widgets: [
+//!         (area: (0, 26, 9, 8), widget: Clock(offset_minutes: -300)),
+//!     ],
+//! )
+//! ```
+//!
+//! Or the same in TOML, for a file ending in `.toml`:
+//!
+//! ```text
+//! mode = "Loop"
+//!
+//! [[frames]]
+//! show = { Text = "HI" }
+//! duration_ms = 1000
+//!
+//! [[frames]]
+//! show = { Fill = 0x40 }
+//! duration_ms = 500
+//! transition = { Fade = 300 }
+//!
+//! [[widgets]]
+//! area = [0, 26, 9, 8]
+//! widget = { Clock = { offset_minutes = -300 } }
+//! ```
+//!
+//! Every field but a frame's `show` and `duration_ms` and a widget's `area`
+//! and `widget` can be left out. `Greyscale` frames are rows of nine values
+//! 0 to 255, as `Bitmap8` is serialized.
+//!
+//! A daemon picks up edits to the file with a `SceneWatcher`:
+//!
+//! ```no_run
+//! use f16_hid::scene::SceneWatcher;
+//! use f16_hid::LedMatrix;
+//!
+//! let mut matrix = LedMatrix::new("/dev/ttyACM0")?;
+//! let mut watcher = SceneWatcher::new("scene.ron");
+//! let mut layout = None;
+//!
+//! loop {
+//!     match watcher.poll() {
+//!         Ok(Some(scene)) => layout = Some(scene.layout()),
+//!         Ok(None) => (),
+//!         // Keep showing the last scene that loaded
+//!         Err(error) => eprintln!("{}", error),
+//!     }
+//!
+//!     if let Some(layout) = layout.as_mut() {
+//!         layout.present(&mut matrix)?;
+//!     }
+//!
+//!     std::thread::sleep(std::time::Duration::from_millis(20));
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Deserialize;
+
+use crate::animation::{Animation, PlayMode};
+use crate::clock::{Clock, SystemClock};
+use crate::fade::FrameFade;
+use crate::geometry::Rect;
+use crate::layout::Layout;
+use crate::widgets::{Battery, DigitalClock, TextBox, Widget};
+use crate::{Bitmap, Bitmap8};
+
+/// A scene as written in the file, before the frames are drawn
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDescription {
+    #[serde(default)]
+    pub mode: PlayMode,
+    /// What shows behind the widgets where there are no frames
+    #[serde(default)]
+    pub background: u8,
+    #[serde(default)]
+    pub frames: Vec<FrameDescription>,
+    /// Drawn over the frames, in order
+    #[serde(default)]
+    pub widgets: Vec<WidgetDescription>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FrameDescription {
+    pub show: Content,
+    #[serde(rename = "duration_ms", with = "crate::serde_impls::millis")]
+    pub duration: Duration,
+    /// How the frame takes over from the one before. Fades come out of
+    /// the frame's own time.
+    #[serde(default)]
+    pub transition: Transition,
+}
+
+/// What a frame shows
+#[derive(Clone, Deserialize)]
+pub enum Content {
+    Blank,
+    Fill(u8),
+    /// In the small font from the top left corner, lit fully
+    Text(String),
+    /// On/off rows of `#` and `.`
+    Pixels(Bitmap),
+    /// Rows of greyscale values
+    Greyscale(Box<Bitmap8>),
+}
+
+impl Content {
+    pub fn render(&self) -> Bitmap8 {
+        let mut frame = Bitmap8::new();
+
+        match self {
+            Self::Blank => (),
+            Self::Fill(value) => frame.fill(*value),
+            Self::Text(text) => frame.draw_text((0, 0), text, 0xff),
+            Self::Pixels(bitmap) => frame = bitmap.to_greyscale(0xff),
+            Self::Greyscale(bitmap) => frame = (**bitmap).clone(),
+        }
+
+        frame
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Transition {
+    #[default]
+    Cut,
+    /// Cross-fade over this many milliseconds
+    Fade(u64),
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WidgetDescription {
+    /// To find it in the layout with `Layout::area()` and friends
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Left, top, width and height
+    pub area: (i32, i32, usize, usize),
+    pub widget: WidgetKind,
+}
+
+/// The widgets a scene file can use
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub enum WidgetKind {
+    /// A `DigitalClock`
+    Clock {
+        #[serde(default)]
+        offset_minutes: i32,
+        #[serde(default)]
+        twelve_hour: bool,
+    },
+    /// A `Battery` reading the system's battery
+    Battery,
+    /// A `TextBox`
+    Text(String),
+}
+
+impl WidgetKind {
+    pub fn build(&self) -> Box<dyn Widget> {
+        match self {
+            Self::Clock { offset_minutes, twelve_hour } => {
+                Box::new(DigitalClock::new(*offset_minutes).twelve_hour(*twelve_hour))
+            },
+            Self::Battery => Box::new(Battery::new()),
+            Self::Text(text) => Box::new(TextBox::new(text)),
+        }
+    }
+}
+
+/// A scene ready to play, its frames drawn and transitions worked out
+#[derive(Clone)]
+pub struct Scene {
+    animation: Animation,
+    mode: PlayMode,
+    background: u8,
+    widgets: Vec<WidgetDescription>,
+}
+
+impl Scene {
+    /// Read a scene from RON. Fails with `InvalidData` saying where the
+    /// text went wrong.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let description: SceneDescription = ron::from_str(text)
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error.to_string()))?;
+
+        Ok(Self::new(description))
+    }
+
+    /// Read a scene from TOML, failing as `parse()` does
+    pub fn parse_toml(text: &str) -> Result<Self, Error> {
+        let description: SceneDescription = toml::from_str(text)
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error.to_string()))?;
+
+        Ok(Self::new(description))
+    }
+
+    /// Read a scene file, as TOML if it ends in `.toml` and RON otherwise
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+
+        match path.extension() {
+            Some(extension) if extension == "toml" => Self::parse_toml(&text),
+            _ => Self::parse(&text),
+        }
+    }
+
+    pub fn new(description: SceneDescription) -> Self {
+        let mut animation = Animation::new();
+        let mut previous = Bitmap8::new();
+        previous.fill(description.background);
+
+        for frame in &description.frames {
+            let bitmap = frame.show.render();
+            let mut left = frame.duration;
+
+            if let Transition::Fade(millis) = frame.transition {
+                let fade = FrameFade::new(&previous, &bitmap, Duration::from_millis(millis).min(frame.duration));
+                let interval = fade.interval();
+
+                let mut steps: Vec<Bitmap8> = fade.collect();
+
+                // The last step of the fade is the frame itself
+                steps.pop();
+
+                for step in steps {
+                    if left <= interval {
+                        break;
+                    }
+
+                    animation.push(step, interval);
+                    left -= interval;
+                }
+            }
+
+            animation.push(bitmap.clone(), left);
+            previous = bitmap;
+        }
+
+        Self {
+            animation,
+            mode: description.mode,
+            background: description.background,
+            widgets: description.widgets,
+        }
+    }
+
+    /// The frames with their fades, for playing with an `Animator`
+    pub fn animation(&self) -> &Animation {
+        &self.animation
+    }
+
+    pub fn mode(&self) -> PlayMode {
+        self.mode
+    }
+
+    pub fn widgets(&self) -> &[WidgetDescription] {
+        &self.widgets
+    }
+
+    /// Everything in the scene as one layout, the frames across the whole
+    /// panel under the widgets
+    pub fn layout(&self) -> Layout {
+        self.layout_with_clock(SystemClock)
+    }
+
+    pub fn layout_with_clock<C: Clock + Clone + 'static>(&self, clock: C) -> Layout<C> {
+        let mut layout = Layout::with_clock(clock.clone());
+        layout.set_background(self.background);
+
+        if !self.animation.is_empty() {
+            let widget = AnimationWidget::with_clock(self.animation.clone(), self.mode, clock);
+            layout.add(Rect::display(), widget);
+        }
+
+        for widget in &self.widgets {
+            let (x, y, width, height) = widget.area;
+            let area = Rect::new((x, y), (width, height));
+
+            match &widget.name {
+                Some(name) => layout.add_named(name, area, BoxedWidget(widget.widget.build())),
+                None => layout.add(area, BoxedWidget(widget.widget.build())),
+            }
+        }
+
+        layout
+    }
+}
+
+/// Lets a boxed widget go where `Layout` wants one by value
+struct BoxedWidget(Box<dyn Widget>);
+
+impl Widget for BoxedWidget {
+    fn render(&mut self, canvas: &mut Bitmap8, area: Rect) {
+        self.0.render(canvas, area)
+    }
+
+    fn is_dirty(&mut self) -> bool {
+        self.0.is_dirty()
+    }
+
+    fn update_interval(&self) -> Option<Duration> {
+        self.0.update_interval()
+    }
+
+    fn update(&mut self) {
+        self.0.update()
+    }
+}
+
+/// Plays an `Animation` in a layout region, the part of each frame under
+/// the region showing through. Which frame is up goes by the time since it
+/// was made, so it keeps time however often the layout renders.
+pub struct AnimationWidget<C: Clock = SystemClock> {
+    animation: Animation,
+    mode: PlayMode,
+    clock: C,
+    started: Instant,
+    showing: Option<usize>,
+}
+
+impl AnimationWidget<SystemClock> {
+    pub fn new(animation: Animation, mode: PlayMode) -> Self {
+        Self::with_clock(animation, mode, SystemClock)
+    }
+}
+
+impl<C: Clock> AnimationWidget<C> {
+    pub fn with_clock(animation: Animation, mode: PlayMode, clock: C) -> Self {
+        Self {
+            animation,
+            mode,
+            started: clock.now(),
+            clock,
+            showing: None,
+        }
+    }
+
+    /// The frame due after `elapsed`
+    fn frame_at(&self, elapsed: Duration) -> usize {
+        let count = self.animation.len();
+
+        // Frame numbers in the order they're played through once
+        let order: Vec<usize> = match self.mode {
+            PlayMode::PingPong if count > 2 => (0 .. count).chain((1 .. count - 1).rev()).collect(),
+            _ => (0 .. count).collect(),
+        };
+
+        let cycle: Duration = order.iter().map(|x| self.animation.frames()[*x].duration).sum();
+        if cycle.is_zero() {
+            return order.last().copied().unwrap_or(0);
+        }
+
+        let mut into = match self.mode {
+            PlayMode::Once if elapsed >= cycle => return count - 1,
+            PlayMode::Once => elapsed,
+            _ => Duration::from_nanos((elapsed.as_nanos() % cycle.as_nanos()) as u64),
+        };
+
+        for index in &order {
+            let duration = self.animation.frames()[*index].duration;
+
+            if into < duration {
+                return *index;
+            }
+
+            into -= duration;
+        }
+
+        count - 1
+    }
+}
+
+impl<C: Clock> Widget for AnimationWidget<C> {
+    fn render(&mut self, canvas: &mut Bitmap8, area: Rect) {
+        let Some(frame) = self.showing.and_then(|x| self.animation.frames().get(x)) else {
+            return;
+        };
+
+        for (column, source) in canvas.columns_mut().zip(frame.bitmap.columns()).take(area.columns().end).skip(area.columns().start) {
+            column[area.rows()].copy_from_slice(&source[area.rows()]);
+        }
+    }
+
+    fn is_dirty(&mut self) -> bool {
+        if self.animation.is_empty() {
+            return false;
+        }
+
+        let due = self.frame_at(self.clock.elapsed(self.started));
+        let changed = self.showing != Some(due);
+        self.showing = Some(due);
+
+        changed
+    }
+
+    /// The shortest frame, so the layout doesn't sleep through one
+    fn update_interval(&self) -> Option<Duration> {
+        self.animation.frames().iter().map(|x| x.duration).filter(|x| !x.is_zero()).min()
+    }
+}
+
+/// Loads a scene file again whenever it changes, for hot reloading
+pub struct SceneWatcher {
+    path: PathBuf,
+    /// Modification time and length as of the last look
+    seen: Option<(SystemTime, u64)>,
+}
+
+impl SceneWatcher {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            seen: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The scene, if the file changed since the last call, and always on
+    /// the first. A file that doesn't parse is reported once and then
+    /// left until it changes again.
+    pub fn poll(&mut self) -> Result<Option<Scene>, Error> {
+        let metadata = std::fs::metadata(&self.path)?;
+        let stamp = (metadata.modified()?, metadata.len());
+
+        if self.seen == Some(stamp) {
+            return Ok(None);
+        }
+
+        self.seen = Some(stamp);
+
+        Scene::load(&self.path).map(Some)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::geometry::Point;
+    use crate::DISPLAY_HEIGHT;
+
+    #[test]
+    fn scenes_load_and_play() {
+        let scene = Scene::parse(r#"(
+            mode: Loop,
+            background: 1,
+            frames: [
+                (show: Fill(0x40), duration_ms: 100),
+                (show: Text("A"), duration_ms: 200, transition: Fade(100)),
+            ],
+            widgets: [
+                (name: Some("status"), area: (0, 20, 9, 7), widget: Text("ok")),
+            ],
+        )"#).unwrap();
+
+        // The fade takes its steps out of the second frame's time
+        assert!(scene.animation().len() > 2);
+        assert_eq!(scene.animation().duration(), Duration::from_millis(300));
+
+        let clock = ManualClock::new();
+        let mut layout = scene.layout_with_clock(clock.clone());
+        assert!(layout.area("status").is_some());
+
+        layout.render();
+        assert_eq!(layout.frame().pixel(Point::new(0, 0)), Some(0x40));
+
+        clock.advance(Duration::from_millis(290));
+        layout.render();
+        assert_eq!(layout.frame().pixel(Point::new(8, 0)), Some(0));
+
+        // Round to the start again
+        clock.advance(Duration::from_millis(20));
+        layout.render();
+        assert_eq!(layout.frame().pixel(Point::new(8, 0)), Some(0x40));
+
+        let error = Scene::parse("(frames: [(show: Sparkle, duration_ms: 5)])").err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn toml_scenes_read_as_ron_ones_do() {
+        let rows = vec![r##""#.......#""##; DISPLAY_HEIGHT].join(", ");
+
+        let ron = Scene::parse(&r##"(
+            mode: Loop,
+            frames: [
+                (show: Pixels([ROWS]), duration_ms: 100),
+                (show: Fill(0x40), duration_ms: 200, transition: Fade(100)),
+            ],
+            widgets: [
+                (area: (0, 26, 9, 8), widget: Clock(offset_minutes: -300)),
+            ],
+        )"##.replace("ROWS", &rows)).unwrap();
+
+        let text = r##"
+            mode = "Loop"
+
+            [[frames]]
+            show = { Pixels = [ROWS] }
+            duration_ms = 100
+
+            [[frames]]
+            show = { Fill = 0x40 }
+            duration_ms = 200
+            transition = { Fade = 100 }
+
+            [[widgets]]
+            area = [0, 26, 9, 8]
+            widget = { Clock = { offset_minutes = -300 } }
+        "##.replace("ROWS", &rows);
+
+        // Picked by the file's extension
+        let path = std::env::temp_dir().join(format!("f16_hid_scene_{}.toml", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let toml = Scene::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let toml = toml.unwrap();
+
+        assert_eq!(toml.animation().duration(), ron.animation().duration());
+        assert!(toml.animation().frames().iter().zip(ron.animation().frames())
+            .all(|(a, b)| a.duration == b.duration && a.bitmap.data() == b.bitmap.data()));
+        assert_eq!(toml.widgets()[0].widget, ron.widgets()[0].widget);
+
+        let error = Scene::parse_toml("[[frames]]\nshow = \"Sparkle\"\nduration_ms = 5").err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,164 @@
+//! A quick run through the basics, for working out whether a problem is the
+//! module or the software driving it. Paste the report into a bug.
+
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, Instant};
+
+use crate::response::Response;
+use crate::{Bitmap, Bitmap8, Command, LedMatrix, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Brightness set and read back during the test
+const TEST_BRIGHTNESS: u8 = 0x40;
+
+pub struct SelfTestStep {
+    pub name: &'static str,
+    pub elapsed: Duration,
+    /// Anything worth knowing about a step that worked, like the version
+    pub detail: Option<String>,
+    pub error: Option<Error>,
+}
+
+impl SelfTestStep {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+pub struct SelfTestReport {
+    pub path: String,
+    pub steps: Vec<SelfTestStep>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.steps.iter().all(|x| x.passed())
+    }
+
+    /// The first step that failed, if any
+    pub fn failure(&self) -> Option<&SelfTestStep> {
+        self.steps.iter().find(|x| !x.passed())
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.steps.iter().map(|x| x.elapsed).sum()
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Self test of {}", self.path)?;
+
+        for step in &self.steps {
+            let outcome = match (&step.error, &step.detail) {
+                (Some(error), _) => format!("FAILED: {}", error),
+                (None, Some(detail)) => format!("ok, {}", detail),
+                (None, None) => "ok".to_owned(),
+            };
+
+            writeln!(f, "  {:<16} {:>6} ms  {}", step.name, step.elapsed.as_millis(), outcome)?;
+        }
+
+        write!(f, "{} in {} ms", if self.passed() { "Passed" } else { "Failed" }, self.elapsed().as_millis())
+    }
+}
+
+impl LedMatrix {
+    /// Set and read back the brightness, light every LED, stage a gradient a
+    /// column at a time and ask for the version, timing each. Steps carry on
+    /// after a failure so the report shows everything that's wrong. The
+    /// brightness is put back afterwards if it could be read.
+    pub fn self_test(&mut self) -> SelfTestReport {
+        let mut steps = Vec::new();
+        let mut original = None;
+
+        steps.push(timed("brightness", || {
+            original = self.read_brightness().ok();
+            self.execute(Command::Brightness(TEST_BRIGHTNESS))?;
+
+            match self.read_brightness()? {
+                TEST_BRIGHTNESS => Ok(None),
+                x => Err(Error::new(ErrorKind::InvalidData, format!("Read back {:#04x} after setting {:#04x}", x, TEST_BRIGHTNESS))),
+            }
+        }));
+
+        steps.push(timed("full frame", || {
+            let mut bitmap = Bitmap::new();
+            bitmap.data.fill(0xff);
+
+            self.execute(Command::Draw(Box::new(bitmap)))?;
+            Ok(None)
+        }));
+
+        steps.push(timed("column staging", || {
+            let mut gradient = Bitmap8::new();
+
+            for x in 0 .. DISPLAY_WIDTH {
+                for y in 0 .. DISPLAY_HEIGHT {
+                    gradient.data[x * DISPLAY_HEIGHT + y] = (y * 255 / (DISPLAY_HEIGHT - 1)) as u8;
+                }
+            }
+
+            self.stage_frame(&gradient)?;
+            Ok(Some(format!("{} columns", DISPLAY_WIDTH)))
+        }));
+
+        steps.push(timed("version", || {
+            match self.query(Command::Version)? {
+                Response::Version(version) => Ok(Some(version.to_string())),
+                _ => Err(Error::new(ErrorKind::InvalidData, "Unexpected reply")),
+            }
+        }));
+
+        if let Some(brightness) = original {
+            let _ = self.execute(Command::Brightness(brightness));
+        }
+
+        SelfTestReport {
+            path: self.path().to_owned(),
+            steps,
+        }
+    }
+
+    fn read_brightness(&mut self) -> Result<u8, Error> {
+        let response = self.query_parameter(0x00)?;
+
+        Ok(response[0])
+    }
+}
+
+fn timed(name: &'static str, step: impl FnOnce() -> Result<Option<String>, Error>) -> SelfTestStep {
+    let start = Instant::now();
+    let result = step();
+    let elapsed = start.elapsed();
+
+    match result {
+        Ok(detail) => SelfTestStep { name, elapsed, detail, error: None },
+        Err(error) => SelfTestStep { name, elapsed, detail: None, error: Some(error) },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_shows_failures() {
+        let report = SelfTestReport {
+            path: "/dev/ttyACM0".to_owned(),
+            steps: vec![
+                timed("version", || Ok(Some("0.1.9".to_owned()))),
+                timed("full frame", || Err(Error::from(ErrorKind::TimedOut))),
+            ],
+        };
+
+        assert!(!report.passed());
+        assert_eq!(report.failure().map(|x| x.name), Some("full frame"));
+
+        let text = report.to_string();
+        assert!(text.contains("ok, 0.1.9"));
+        assert!(text.contains("FAILED"));
+        assert!(text.ends_with(&format!("Failed in {} ms", report.elapsed().as_millis())));
+    }
+}
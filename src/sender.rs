@@ -0,0 +1,261 @@
+use std::io::Error;
+
+use crate::capabilities::CommandKind;
+use crate::layout::Damage;
+use crate::trace;
+use crate::{Bitmap8, ColumnIndex, ColumnUpdate, Command, LedMatrix, DISPLAY_WIDTH, MAX_COMMAND_LENGTH};
+
+/// How `FrameSender` gets a frame to the panel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SendPlan {
+    /// It's already showing
+    Skip,
+    /// Every pixel is off or full, so one on/off `Draw` covers the lot
+    Draw,
+    /// Stage these columns, then `DrawBuffer`. No columns at all means the
+    /// staging buffer already holds the frame.
+    Columns(Damage),
+}
+
+impl SendPlan {
+    /// Commands the plan takes
+    pub fn packets(&self) -> usize {
+        match self {
+            Self::Skip => 0,
+            Self::Draw => 1,
+            Self::Columns(damage) => damage.count() + 1,
+        }
+    }
+}
+
+/// Sends whole frames but only the columns that changed since the last one.
+/// A dashboard where one number ticks over sends one or two columns and a
+/// `DrawBuffer` instead of all nine, and a frame that's only off and full
+/// pixels goes as a single `Draw`.
+///
+/// Frames are compared after the matrix's filters, so the comparison is
+/// against what's actually on the panel.
+#[derive(Clone)]
+pub struct FrameSender {
+    last: Option<Bitmap8>,
+    /// What the firmware's staging buffer holds. A `Draw` goes straight to
+    /// the panel and leaves it alone, so it can be behind `last`.
+    staged: Option<Bitmap8>,
+    binary_draws: bool,
+    /// Every column and the draw for `send_packed()`, kept between frames
+    packed: Vec<u8>,
+}
+
+impl Default for FrameSender {
+    fn default() -> Self {
+        Self {
+            last: None,
+            staged: None,
+            binary_draws: true,
+            packed: Vec::new(),
+        }
+    }
+}
+
+impl FrameSender {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether frames of only off and full pixels go as one `Draw`, which
+    /// is on by default. Turn it off for firmware that draws on/off frames
+    /// at anything but full.
+    pub fn set_binary_draws(&mut self, enabled: bool) {
+        self.binary_draws = enabled;
+    }
+
+    /// How `frame`, filters already applied, would be sent right now
+    pub fn plan(&self, frame: &Bitmap8) -> SendPlan {
+        if self.last.as_ref().is_some_and(|x| x.data() == frame.data()) {
+            return SendPlan::Skip;
+        }
+
+        let columns = match &self.staged {
+            Some(staged) => Damage::from(staged.diff(frame)),
+            None => Damage::all(),
+        };
+
+        let binary = self.binary_draws
+            && frame.data().iter().all(|x| *x == 0 || *x == 0xff);
+
+        if binary && SendPlan::Draw.packets() < SendPlan::Columns(columns).packets() {
+            SendPlan::Draw
+        } else {
+            SendPlan::Columns(columns)
+        }
+    }
+
+    /// Send what it takes to show `frame`, nothing at all if it's already
+    /// showing. Returns which columns changed on the panel.
+    pub fn send(&mut self, matrix: &mut LedMatrix, frame: &Bitmap8) -> Result<Damage, Error> {
+        let frame = matrix.filtered(frame);
+
+        let damage = match &self.last {
+            Some(last) => Damage::from(last.diff(&frame)),
+            None => Damage::all(),
+        };
+
+        let plan = self.plan(&frame);
+        let _span = trace::span!(DEBUG, "frame", path = matrix.path(), plan = ?plan);
+
+        if plan == SendPlan::Skip {
+            return Ok(Damage::default());
+        }
+
+        // Whatever made it to the panel before a failure is anyone's guess
+        self.last = None;
+
+        // Firmware without column staging only draws whole frames
+        if !matrix.supports(CommandKind::StageColumn) {
+            trace::event!(debug, "No column staging, drawing on/off");
+            matrix.draw_binary_fallback(&frame)?;
+            self.last = Some(frame);
+
+            return Ok(Damage::all());
+        }
+
+        match plan {
+            SendPlan::Skip => (),
+            SendPlan::Draw => {
+                matrix.draw_binary_fallback(&frame)?;
+            },
+            SendPlan::Columns(columns) => {
+                self.staged = None;
+
+                for (x, dirty) in columns.columns.iter().enumerate() {
+                    if *dirty {
+                        matrix.stage_column(&frame, x)?;
+                    }
+                }
+
+                matrix.execute(Command::DrawBuffer)?;
+                self.staged = Some(frame.clone());
+            },
+        }
+
+        self.last = Some(frame);
+
+        Ok(damage)
+    }
+
+    /// Send every column and the `DrawBuffer` in a single write, whatever
+    /// changed. They're packed into a buffer kept for the next frame, so
+    /// nothing is allocated once it's warmed up. Quicker than `send()` for
+    /// frames that change all over, like video, see the `render` benchmarks.
+    pub fn send_packed(&mut self, matrix: &mut LedMatrix, frame: &Bitmap8) -> Result<Damage, Error> {
+        let frame = matrix.filtered(frame);
+        let _span = trace::span!(DEBUG, "frame", path = matrix.path(), plan = "packed");
+
+        let damage = match &self.last {
+            Some(last) => Damage::from(last.diff(&frame)),
+            None => Damage::all(),
+        };
+
+        self.last = None;
+
+        if !matrix.supports(CommandKind::StageColumn) {
+            matrix.draw_binary_fallback(&frame)?;
+            self.last = Some(frame);
+
+            return Ok(Damage::all());
+        }
+
+        self.staged = None;
+        self.packed.resize((DISPLAY_WIDTH + 1) * MAX_COMMAND_LENGTH, 0);
+
+        // Each command packs over the same one as last frame, so the
+        // padding past it is still zero
+        let (packets, _) = self.packed.as_chunks_mut::<MAX_COMMAND_LENGTH>();
+        let (draw, columns) = packets.split_last_mut().expect("Buffer holds a frame");
+
+        for (index, packet) in ColumnIndex::all().zip(columns) {
+            matrix.encode(Command::StageColumnBuffer(ColumnUpdate::from_bitmap(&frame, index)), packet)?;
+        }
+
+        matrix.encode(Command::DrawBuffer, draw)?;
+        matrix.send_packets(&self.packed)?;
+
+        self.staged = Some(frame.clone());
+        self.last = Some(frame);
+
+        Ok(damage)
+    }
+
+    /// Send every column with the next frame. Call this if something else
+    /// drew on the matrix, or it was reconnected and may have been reset.
+    pub fn invalidate(&mut self) {
+        self.last = None;
+        self.staged = None;
+    }
+
+    /// The last frame sent in full, filters applied
+    pub fn last(&self) -> Option<&Bitmap8> {
+        self.last.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Point;
+    use crate::transport::MockTransport;
+    use crate::{DISPLAY_WIDTH, MAX_COMMAND_LENGTH};
+
+    fn command_ids(mock: &MockTransport) -> Vec<u8> {
+        mock.take_written().chunks(MAX_COMMAND_LENGTH).map(|x| x[2]).collect()
+    }
+
+    #[test]
+    fn only_changed_columns_are_sent() {
+        let mock = MockTransport::new();
+        let mut matrix = LedMatrix::with_transport("mock", mock.clone());
+        let mut sender = FrameSender::new();
+        // Greyscale, so it can't go as a Draw
+        let mut frame = Bitmap8::new();
+        frame.fill(0x10);
+
+        assert_eq!(sender.send(&mut matrix, &frame).unwrap(), Damage::all());
+        assert_eq!(command_ids(&mock), [7, 7, 7, 7, 7, 7, 7, 7, 7, 8]);
+
+        assert!(sender.send(&mut matrix, &frame).unwrap().is_empty());
+        assert!(command_ids(&mock).is_empty());
+
+        frame.set_pixel(Point::new(5, 0), 0xff);
+        assert_eq!(sender.send(&mut matrix, &frame).unwrap().count(), 1);
+        assert_eq!(command_ids(&mock), [7, 8]);
+    }
+
+    #[test]
+    fn on_off_frames_go_as_one_draw() {
+        let mock = MockTransport::new();
+        let mut matrix = LedMatrix::with_transport("mock", mock.clone());
+        let mut sender = FrameSender::new();
+        let mut frame = Bitmap8::new();
+        frame.fill(0x10);
+
+        sender.send(&mut matrix, &frame).unwrap();
+        command_ids(&mock);
+
+        let mut lit = Bitmap8::new();
+        lit.set_pixel(Point::new(2, 2), 0xff);
+        assert_eq!(sender.plan(&lit), SendPlan::Draw);
+        assert_eq!(sender.send(&mut matrix, &lit).unwrap().count(), DISPLAY_WIDTH);
+        assert_eq!(command_ids(&mock), [6]);
+
+        // The staging buffer still has the greyscale frame, so going back to
+        // it is only a DrawBuffer
+        assert_eq!(sender.plan(&frame), SendPlan::Columns(Damage::default()));
+        sender.send(&mut matrix, &frame).unwrap();
+        assert_eq!(command_ids(&mock), [8]);
+
+        sender.set_binary_draws(false);
+        assert_eq!(sender.plan(&lit).packets(), DISPLAY_WIDTH + 1);
+        sender.send(&mut matrix, &frame).unwrap();
+        assert!(command_ids(&mock).is_empty());
+    }
+}
@@ -0,0 +1,169 @@
+//! `Serialize` and `Deserialize` for the bitmaps. Both are written as rows
+//! from the top of the panel down, the way they look, rather than in the
+//! column order they're stored in. A `Bitmap8` row is a list of nine
+//! values and a `Bitmap` row is a string of `#` for on and `.` for off:
+//!
+//! ```json
+//! ["#...#....", ".#.#.....", "..#......", ...]
+//! ```
+
+use std::fmt;
+use std::time::Duration;
+
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::ser::{SerializeSeq, Serializer};
+use serde::{Deserialize, Serialize};
+
+use crate::{Bitmap, Bitmap8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+impl Serialize for Bitmap8 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut rows = serializer.serialize_seq(Some(DISPLAY_HEIGHT))?;
+
+        for row in self.rows() {
+            rows.serialize_element(&row)?;
+        }
+
+        rows.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Bitmap8 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(RowsVisitor::<[u8; DISPLAY_WIDTH]>::new("rows of greyscale values"))
+            .map(|rows| {
+                let mut bitmap = Bitmap8::new();
+
+                for (y, row) in rows.iter().enumerate() {
+                    for (x, value) in row.iter().enumerate() {
+                        bitmap.data[x * DISPLAY_HEIGHT + y] = *value;
+                    }
+                }
+
+                bitmap
+            })
+    }
+}
+
+impl Serialize for Bitmap {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut rows = serializer.serialize_seq(Some(DISPLAY_HEIGHT))?;
+
+        for y in 0 .. DISPLAY_HEIGHT {
+            let row: String = (0 .. DISPLAY_WIDTH)
+                .map(|x| if self.pixel((x as i32, y as i32).into()) == Some(true) { '#' } else { '.' })
+                .collect();
+            rows.serialize_element(&row)?;
+        }
+
+        rows.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Bitmap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let rows = deserializer.deserialize_seq(RowsVisitor::<String>::new("rows of '#' and '.'"))?;
+        let mut bitmap = Bitmap::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != DISPLAY_WIDTH {
+                return Err(de::Error::invalid_length(row.chars().count(), &"a row as wide as the panel"));
+            }
+
+            for (x, pixel) in row.chars().enumerate() {
+                let value = match pixel {
+                    '#' => true,
+                    '.' => false,
+                    _ => return Err(de::Error::invalid_value(de::Unexpected::Char(pixel), &"'#' or '.'")),
+                };
+
+                // Both are on the panel, so this can't fail
+                let _ = bitmap.draw_point(x, y, value);
+            }
+        }
+
+        Ok(bitmap)
+    }
+}
+
+/// Reads exactly `DISPLAY_HEIGHT` rows, whatever each row is
+struct RowsVisitor<T> {
+    expecting: &'static str,
+    row: std::marker::PhantomData<T>,
+}
+
+impl<T> RowsVisitor<T> {
+    fn new(expecting: &'static str) -> Self {
+        Self { expecting, row: std::marker::PhantomData }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for RowsVisitor<T> {
+    type Value = Vec<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{} {}", DISPLAY_HEIGHT, self.expecting)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut rows = Vec::with_capacity(DISPLAY_HEIGHT);
+
+        while let Some(row) = seq.next_element()? {
+            rows.push(row);
+        }
+
+        if rows.len() != DISPLAY_HEIGHT {
+            return Err(de::Error::invalid_length(rows.len(), &self));
+        }
+
+        Ok(rows)
+    }
+}
+
+/// Durations as whole milliseconds, which are easier to write by hand than
+/// serde's seconds and nanoseconds
+pub(crate) mod millis {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::animation::Animation;
+    use super::*;
+
+    #[test]
+    fn bitmaps_round_trip_as_rows() {
+        let mut grey = Bitmap8::new();
+        grey.draw_point(1, 0, 0x80).unwrap();
+        grey.draw_point(8, 33, 0xff).unwrap();
+
+        let json = serde_json::to_value(&grey).unwrap();
+        assert_eq!(json[0], serde_json::json!([0, 0x80, 0, 0, 0, 0, 0, 0, 0]));
+        let back: Bitmap8 = serde_json::from_value(json).unwrap();
+        assert_eq!(back.data(), grey.data());
+
+        let binary = grey.to_binary(0x80);
+        let json = serde_json::to_value(&binary).unwrap();
+        assert_eq!(json[0], ".#.......");
+        assert_eq!(json[33], "........#");
+        let back: Bitmap = serde_json::from_value(json).unwrap();
+        assert_eq!(back.data(), binary.data());
+
+        assert!(serde_json::from_str::<Bitmap>(r##"["#"]"##).is_err());
+
+        let animation = Animation::from_frames([grey], Duration::from_millis(40));
+        let json = serde_json::to_string(&animation).unwrap();
+        assert!(json.contains("\"duration_ms\":40"));
+        let back: Animation = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.duration(), Duration::from_millis(40));
+    }
+}
@@ -0,0 +1,243 @@
+use std::time::Duration;
+
+use crate::clock::Clock;
+use crate::discovery::{self, DiscoveredMatrix};
+use crate::roles::{Role, RoleConfig};
+use crate::text::{TextStyle, FONT_5X7};
+use crate::{Bitmap8, Command, LedMatrix, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Brightness used while flashing a module so the user can spot it
+pub const IDENTIFY_BRIGHTNESS: u8 = 0x80;
+/// How long `LedMatrix::identify()` flashes for
+pub const IDENTIFY_DURATION: Duration = Duration::from_secs(2);
+/// How long each half of a flash lasts
+pub const IDENTIFY_FLASH: Duration = Duration::from_millis(250);
+
+/// A module being offered to the user during setup
+pub struct Candidate<'a> {
+    pub path: &'a str,
+    pub serial: &'a str,
+    /// Which module this is in the order they were offered, counting from
+    /// one
+    pub number: usize,
+}
+
+/// USB serial number of the device behind a port, if the OS knows it
+pub fn serial_number(path: &str) -> Option<String> {
+    let ports = serialport::available_ports().ok()?;
+
+    ports.into_iter()
+        .find(|x| x.port_name == path)
+        .and_then(|x| match x.port_type {
+            serialport::SerialPortType::UsbPort(info) => info.serial_number,
+            _ => None,
+        })
+}
+
+/// The frame `LedMatrix::identify()` shows: an outline round the panel,
+/// `label` near the top and an arrow pointing up at it, so it can't be
+/// mistaken for whatever the module was showing before. Only the first
+/// character of `label` fits across.
+pub fn identify_frame(label: &str) -> Bitmap8 {
+    let mut frame = Bitmap8::new();
+    let right = DISPLAY_WIDTH - 1;
+    let bottom = DISPLAY_HEIGHT - 1;
+
+    frame.draw_box(0, 0, right, 0, 0xff);
+    frame.draw_box(0, bottom, right, bottom, 0xff);
+    frame.draw_box(0, 0, 0, bottom, 0xff);
+    frame.draw_box(right, 0, right, bottom, 0xff);
+
+    let label: String = label.chars().take(1).collect();
+    frame.draw_text_styled((2, 3), &label, &TextStyle::new(&FONT_5X7, 0xff));
+
+    // Arrow head, then the shaft
+    for row in 0 .. 4 {
+        frame.draw_box(4 - row, 14 + row, 4 + row, 14 + row, 0xff);
+    }
+    frame.draw_box(3, 18, 5, 29, 0xff);
+
+    frame
+}
+
+impl LedMatrix {
+    /// Flash `identify_frame(label)` for `IDENTIFY_DURATION` so the user
+    /// can tell which module this is, then blank the panel. Brightness is
+    /// set to `IDENTIFY_BRIGHTNESS` and left there.
+    ///
+    /// Blocks while it flashes.
+    pub fn identify(&mut self, label: &str) -> Result<(), std::io::Error> {
+        self.identify_for(label, IDENTIFY_DURATION)
+    }
+
+    /// `identify()`, flashing for `duration` instead. A zero duration shows
+    /// the frame once.
+    pub fn identify_for(&mut self, label: &str, duration: Duration) -> Result<(), std::io::Error> {
+        let frame = identify_frame(label);
+        let mut inverted = frame.clone();
+        for pixel in inverted.data.iter_mut() {
+            *pixel = !*pixel;
+        }
+
+        self.execute(Command::Brightness(IDENTIFY_BRIGHTNESS))?;
+
+        let flashes = (duration.as_millis() / IDENTIFY_FLASH.as_millis()).max(1);
+
+        for flash in 0 .. flashes {
+            let shown = if flash % 2 == 0 { &frame } else { &inverted };
+            self.stage_frame(shown)?;
+            self.clock.sleep(IDENTIFY_FLASH.min(duration));
+        }
+
+        self.clear()
+    }
+}
+
+/// Find every module and work out which is which with `assign_roles()`.
+///
+/// Assignments are written into `roles` but not saved. Returns the modules
+/// found, with roles filled in and in `discovery::discover()` order, so left
+/// comes before right.
+pub fn discover_and_assign<F>(roles: &mut RoleConfig, ask: F) -> Result<Vec<DiscoveredMatrix>, serialport::Error>
+where F: FnMut(&Candidate) -> Option<Role>
+{
+    let found = discovery::discover(roles)?;
+    let paths: Vec<&str> = found.iter().map(|x| x.path.as_str()).collect();
+
+    assign_roles(&paths, roles, ask)?;
+
+    discovery::discover(roles)
+}
+
+/// Walk the user through assigning roles. Each module gets a number, shown
+/// on its panel the whole time, then in turn flashes with
+/// `LedMatrix::identify()` while `ask` is called to find out which one it
+/// is. `Candidate::number` is the number showing. Return `None` from `ask`
+/// to skip a module. Every panel is cleared at the end. Assignments are
+/// written into `roles` but not saved, so the caller can decide what to do
+/// with them.
+///
+/// Ports without a USB serial number are skipped since there'd be nothing to
+/// remember them by. Returns how many modules were assigned.
+pub fn assign_roles<F>(paths: &[&str], roles: &mut RoleConfig, ask: F) -> Result<usize, serialport::Error>
+where F: FnMut(&Candidate) -> Option<Role>
+{
+    let mut matrices = Vec::new();
+
+    for path in paths {
+        let serial = match serial_number(path) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        matrices.push((serial, LedMatrix::new(path)?));
+    }
+
+    Ok(assign(&mut matrices, roles, ask)?)
+}
+
+/// `assign_roles()` once the ports are open, each with its serial number
+fn assign<F>(matrices: &mut [(String, LedMatrix)], roles: &mut RoleConfig, mut ask: F) -> Result<usize, std::io::Error>
+where F: FnMut(&Candidate) -> Option<Role>
+{
+    for (index, (_, matrix)) in matrices.iter_mut().enumerate() {
+        matrix.execute(Command::Brightness(IDENTIFY_BRIGHTNESS))?;
+        matrix.stage_frame(&identify_frame(&(index + 1).to_string()))?;
+    }
+
+    let mut assigned = 0;
+
+    for (index, (serial, matrix)) in matrices.iter_mut().enumerate() {
+        let number = index + 1;
+        matrix.identify(&number.to_string())?;
+        matrix.stage_frame(&identify_frame(&number.to_string()))?;
+
+        let candidate = Candidate { path: matrix.path(), serial, number };
+
+        if let Some(role) = ask(&candidate) {
+            roles.assign(serial, role);
+            assigned += 1;
+        }
+    }
+
+    for (_, matrix) in matrices.iter_mut() {
+        matrix.clear()?;
+    }
+
+    Ok(assigned)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::transport::MockTransport;
+
+    #[test]
+    fn identify_flashes_then_blanks() {
+        let mock = MockTransport::new();
+        let mut matrix = LedMatrix::with_transport("mock", mock.clone());
+
+        let frame = identify_frame("2");
+        assert_ne!(frame.data(), identify_frame("1").data());
+        assert_eq!(frame.data()[0], 0xff);
+
+        matrix.identify_for("2", Duration::ZERO).unwrap();
+
+        let written = mock.take_written();
+        let packets: Vec<&[u8]> = written.chunks(crate::MAX_COMMAND_LENGTH).collect();
+        assert_eq!(packets[0][2 .. 4], [0x00, IDENTIFY_BRIGHTNESS]);
+        // Nine columns, the draw, then the blank
+        assert_eq!(packets.len(), 1 + DISPLAY_WIDTH + 1 + 1);
+        assert_eq!(packets[packets.len() - 1][2], 0x06);
+    }
+
+    #[test]
+    fn identify_flashes_on_the_matrix_clock() {
+        let clock = ManualClock::new();
+        let mock = MockTransport::new();
+        let mut matrix = LedMatrix::builder("mock").clock(clock.clone()).open_transport(mock.clone()).unwrap();
+
+        let start = clock.now();
+        matrix.identify("2").unwrap();
+
+        assert_eq!(clock.elapsed(start), IDENTIFY_DURATION);
+        let flashes = (IDENTIFY_DURATION.as_millis() / IDENTIFY_FLASH.as_millis()) as usize;
+        assert_eq!(mock.take_written().len(), (1 + flashes * (DISPLAY_WIDTH + 1) + 1) * crate::MAX_COMMAND_LENGTH);
+    }
+
+    #[test]
+    fn each_module_is_asked_about_in_turn() {
+        let clock = ManualClock::new();
+        let mocks = [MockTransport::new(), MockTransport::new()];
+        let mut matrices: Vec<(String, LedMatrix)> = mocks.iter()
+            .zip(["A1", "B2"])
+            .map(|(mock, serial)| {
+                let matrix = LedMatrix::builder(serial).clock(clock.clone()).open_transport(mock.clone()).unwrap();
+                (serial.to_owned(), matrix)
+            })
+            .collect();
+
+        let mut roles = RoleConfig::new();
+        let mut asked = Vec::new();
+
+        let assigned = assign(&mut matrices, &mut roles, |candidate| {
+            asked.push((candidate.serial.to_owned(), candidate.number));
+            (candidate.number == 1).then_some(Role::Left)
+        }).unwrap();
+
+        assert_eq!(assigned, 1);
+        assert_eq!(asked, [("A1".to_owned(), 1), ("B2".to_owned(), 2)]);
+        assert_eq!(roles.role("A1"), Some(&Role::Left));
+        assert_eq!(roles.role("B2"), None);
+
+        // Both ended up blank
+        for mock in &mocks {
+            let written = mock.take_written();
+            let last = written.chunks(crate::MAX_COMMAND_LENGTH).last().unwrap();
+            assert_eq!(last[2], 0x06);
+            assert!(last[3 ..].iter().all(|x| *x == 0));
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use crate::geometry::{EdgeMode, Point, Rect};
+use crate::{Bitmap8, DISPLAY_HEIGHT};
+
+impl Bitmap8 {
+    /// Straight line between two points, both ends included
+    pub fn draw_line(&mut self, from: impl Into<Point>, to: impl Into<Point>, value: u8) {
+        let (from, to) = (from.into(), to.into());
+
+        // Bresenham's, in i64 so far off points can't overflow
+        let (mut x, mut y) = (from.x as i64, from.y as i64);
+        let (end_x, end_y) = (to.x as i64, to.y as i64);
+        let dx = (end_x - x).abs();
+        let dy = -(end_y - y).abs();
+        let step_x = if x < end_x { 1 } else { -1 };
+        let step_y = if y < end_y { 1 } else { -1 };
+        let mut error = dx + dy;
+
+        loop {
+            self.set_pixel(Point::new(x as i32, y as i32), value);
+
+            if x == end_x && y == end_y {
+                break;
+            }
+
+            let doubled = 2 * error;
+
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    pub fn draw_circle(&mut self, center: impl Into<Point>, radius: u32, value: u8) {
+        let center = center.into();
+
+        for (x, y) in circle_octant(radius) {
+            for (x, y) in [(x, y), (y, x), (-x, y), (-y, x), (x, -y), (y, -x), (-x, -y), (-y, -x)] {
+                self.set_pixel(center + Point::new(x, y), value);
+            }
+        }
+    }
+
+    pub fn fill_circle(&mut self, center: impl Into<Point>, radius: u32, value: u8) {
+        let center = center.into();
+
+        for (x, y) in circle_octant(radius) {
+            self.span(center.y + y, center.x - x, center.x + x, value);
+            self.span(center.y - y, center.x - x, center.x + x, value);
+            self.span(center.y + x, center.x - y, center.x + y, value);
+            self.span(center.y - x, center.x - y, center.x + y, value);
+        }
+    }
+
+    pub fn draw_triangle(&mut self, a: impl Into<Point>, b: impl Into<Point>, c: impl Into<Point>, value: u8) {
+        self.draw_polygon(&[a.into(), b.into(), c.into()], value);
+    }
+
+    pub fn fill_triangle(&mut self, a: impl Into<Point>, b: impl Into<Point>, c: impl Into<Point>, value: u8) {
+        self.fill_polygon(&[a.into(), b.into(), c.into()], value);
+    }
+
+    /// Outline joining the points in order and back to the first
+    pub fn draw_polygon(&mut self, points: &[Point], value: u8) {
+        for (index, point) in points.iter().enumerate() {
+            let next = points[(index + 1) % points.len()];
+            self.draw_line(*point, next, value);
+        }
+    }
+
+    /// Filled polygon, outline included. Overlapping parts of a polygon that
+    /// crosses itself are left empty.
+    pub fn fill_polygon(&mut self, points: &[Point], value: u8) {
+        let (Some(top), Some(bottom)) = (points.iter().map(|p| p.y).min(), points.iter().map(|p| p.y).max()) else {
+            return;
+        };
+
+        // Rows off the panel can't show anything unless the edge mode brings
+        // them back
+        let (top, bottom) = match self.edge_mode() {
+            EdgeMode::Clip => (top.max(0), bottom.min(DISPLAY_HEIGHT as i32 - 1)),
+            _ => (top, bottom),
+        };
+
+        let mut crossings = Vec::new();
+
+        for y in top ..= bottom {
+            crossings.clear();
+
+            for (index, start) in points.iter().enumerate() {
+                let end = points[(index + 1) % points.len()];
+
+                if (start.y > y) != (end.y > y) {
+                    let along = (y - start.y) as f64 / (end.y - start.y) as f64;
+                    crossings.push(start.x as f64 + along * (end.x - start.x) as f64);
+                }
+            }
+
+            crossings.sort_by(f64::total_cmp);
+
+            for pair in crossings.chunks_exact(2) {
+                self.span(y, pair[0].ceil() as i32, pair[1].floor() as i32, value);
+            }
+        }
+
+        self.draw_polygon(points, value);
+    }
+
+    /// One row from `left` to `right` inclusive
+    fn span(&mut self, y: i32, left: i32, right: i32, value: u8) {
+        if left <= right {
+            self.fill_rect(Rect::from_corners(Point::new(left, y), Point::new(right.saturating_add(1), y + 1)), value);
+        }
+    }
+}
+
+/// Offsets for one eighth of a circle, from straight down round to the
+/// diagonal. Midpoint circle algorithm.
+fn circle_octant(radius: u32) -> Vec<(i32, i32)> {
+    let radius = radius.min(i32::MAX as u32) as i32;
+    let (mut x, mut y) = (0, radius);
+    let mut error = 1 - radius;
+    let mut points = Vec::new();
+
+    while x <= y {
+        points.push((x, y));
+        x += 1;
+
+        if error < 0 {
+            error += 2 * x + 1;
+        } else {
+            y -= 1;
+            error += 2 * (x - y) + 1;
+        }
+    }
+
+    points
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DISPLAY_WIDTH;
+
+    fn lit(bitmap: &Bitmap8) -> usize {
+        bitmap.data().iter().filter(|x| **x > 0).count()
+    }
+
+    #[test]
+    fn lines_include_both_ends() {
+        let mut bitmap = Bitmap8::new();
+        bitmap.draw_line((0, 0), (4, 2), 0xff);
+
+        assert_eq!(bitmap.pixel(Point::new(0, 0)), Some(0xff));
+        assert_eq!(bitmap.pixel(Point::new(4, 2)), Some(0xff));
+        assert_eq!(lit(&bitmap), 5);
+    }
+
+    #[test]
+    fn circles() {
+        let mut bitmap = Bitmap8::new();
+        bitmap.draw_circle((4, 10), 3, 0xff);
+
+        assert_eq!(bitmap.pixel(Point::new(4, 7)), Some(0xff));
+        assert_eq!(bitmap.pixel(Point::new(7, 10)), Some(0xff));
+        assert_eq!(bitmap.pixel(Point::new(4, 10)), Some(0));
+
+        bitmap.fill_circle((4, 10), 3, 0xff);
+        assert_eq!(bitmap.pixel(Point::new(4, 10)), Some(0xff));
+        assert_eq!(bitmap.pixel(Point::new(1, 7)), Some(0));
+    }
+
+    #[test]
+    fn filled_triangle_covers_inside() {
+        let mut bitmap = Bitmap8::new();
+        bitmap.fill_triangle((0, 0), (8, 0), (0, 8), 0xff);
+
+        assert_eq!(bitmap.pixel(Point::new(2, 2)), Some(0xff));
+        assert_eq!(bitmap.pixel(Point::new(4, 4)), Some(0xff));
+        assert_eq!(bitmap.pixel(Point::new(5, 5)), Some(0));
+        // Rows 0 to 8 shrinking by one each time
+        assert_eq!(lit(&bitmap), (1 ..= 9).sum::<usize>());
+    }
+
+    #[test]
+    fn shapes_off_the_panel_are_clipped() {
+        let mut bitmap = Bitmap8::new();
+
+        bitmap.draw_line((-100, -100), (100, 100), 0xff);
+        bitmap.fill_circle((4, 40), 10, 0xff);
+        bitmap.fill_triangle((-50, -50), (50, -50), (0, 1000), 0xff);
+        bitmap.draw_box(5, 30, 20, 50, 0xff);
+
+        assert_eq!(bitmap.pixel(Point::new(DISPLAY_WIDTH as i32 - 1, DISPLAY_HEIGHT as i32 - 1)), Some(0xff));
+    }
+}
@@ -0,0 +1,143 @@
+use std::io::{Error, ErrorKind};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::response::Response;
+use crate::{Bitmap8, Command, LedMatrix};
+
+type Job = Box<dyn FnOnce(&mut LedMatrix) + Send>;
+
+enum Request {
+    Run(Job),
+    Close(Sender<LedMatrix>),
+}
+
+/// A matrix owned by a worker thread. Handles are cheap to clone and can be
+/// given to as many threads as you like, say a CPU monitor and a notification
+/// listener. Each request waits its turn in the worker's queue, so commands
+/// from different threads never interleave on the port.
+///
+/// The worker stops when every handle is dropped, or when one of them calls
+/// `close()` to get the matrix back.
+#[derive(Clone)]
+pub struct SharedMatrix {
+    sender: Sender<Request>,
+}
+
+impl SharedMatrix {
+    pub fn new(mut matrix: LedMatrix) -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for request in receiver {
+                match request {
+                    Request::Run(job) => job(&mut matrix),
+                    Request::Close(reply) => {
+                        let _ = reply.send(matrix);
+                        return;
+                    },
+                }
+            }
+        });
+
+        Self { sender }
+    }
+
+    /// Run `job` on the worker thread with the matrix and wait for what it
+    /// returns. Anything that needs several commands in a row without other
+    /// threads getting in between belongs in here.
+    pub fn with<T: Send + 'static>(&self, job: impl FnOnce(&mut LedMatrix) -> T + Send + 'static) -> Result<T, Error> {
+        let (reply, result) = mpsc::channel();
+
+        self.submit(move |matrix| {
+            let _ = reply.send(job(matrix));
+        })?;
+
+        wait(result)
+    }
+
+    /// Queue `job` without waiting for it. Errors only if the worker is gone.
+    pub fn submit(&self, job: impl FnOnce(&mut LedMatrix) + Send + 'static) -> Result<(), Error> {
+        self.sender.send(Request::Run(Box::new(job))).map_err(|_| stopped())
+    }
+
+    pub fn execute(&self, command: Command<'static>) -> Result<usize, Error> {
+        self.with(move |matrix| matrix.execute(command))?
+    }
+
+    pub fn query(&self, command: Command<'static>) -> Result<Response, Error> {
+        self.with(move |matrix| matrix.query(command))?
+    }
+
+    /// Draw a greyscale frame, waiting until it's on the panel
+    pub fn stage_frame(&self, frame: &Bitmap8) -> Result<(), Error> {
+        let frame = frame.clone();
+
+        self.with(move |matrix| matrix.stage_frame(&frame))?
+    }
+
+    /// Stop the worker once everything queued before this is done and hand
+    /// back the matrix. Other handles get errors from then on.
+    pub fn close(self) -> Result<LedMatrix, Error> {
+        let (reply, result) = mpsc::channel();
+
+        self.sender.send(Request::Close(reply)).map_err(|_| stopped())?;
+
+        wait(result)
+    }
+}
+
+fn wait<T>(result: Receiver<T>) -> Result<T, Error> {
+    // The sender is dropped without a reply if the job panicked
+    result.recv().map_err(|_| stopped())
+}
+
+fn stopped() -> Error {
+    Error::new(ErrorKind::BrokenPipe, "Matrix worker has stopped")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MockTransport;
+
+    fn matrix() -> (LedMatrix, MockTransport) {
+        let mock = MockTransport::new();
+
+        (LedMatrix::with_transport("mock", mock.clone()), mock)
+    }
+
+    #[test]
+    fn commands_from_threads_stay_whole() {
+        let (matrix, mock) = matrix();
+        let shared = SharedMatrix::new(matrix);
+
+        std::thread::scope(|scope| {
+            for value in [0x10, 0x20] {
+                let shared = shared.clone();
+                scope.spawn(move || shared.execute(Command::Brightness(value)).unwrap());
+            }
+        });
+
+        let written = mock.written();
+        assert_eq!(written.len(), crate::MAX_COMMAND_LENGTH * 2);
+
+        for packet in written.chunks(crate::MAX_COMMAND_LENGTH) {
+            assert_eq!(packet[..3], [0x32, 0xac, 0x00]);
+            assert!(packet[3] == 0x10 || packet[3] == 0x20);
+        }
+    }
+
+    #[test]
+    fn closing_returns_the_matrix() {
+        let (matrix, _theirs) = matrix();
+        let shared = SharedMatrix::new(matrix);
+        let other = shared.clone();
+
+        assert_eq!(shared.with(|matrix| matrix.path().to_owned()).unwrap(), "mock");
+
+        let matrix = shared.close().unwrap();
+        assert_eq!(matrix.path(), "mock");
+        assert_eq!(other.execute(Command::Sleep(true)).unwrap_err().kind(), ErrorKind::BrokenPipe);
+    }
+}
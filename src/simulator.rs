@@ -0,0 +1,416 @@
+//! A pretend module for working on layouts, widgets and animations with no
+//! hardware plugged in. `SimulatedMatrix` is a transport that answers the
+//! wire protocol the way the firmware does and keeps track of what the
+//! LEDs would show, so everything built on `LedMatrix` and `Display` runs
+//! against it unchanged:
+//!
+//! ```
+//! use f16_hid::simulator::SimulatedMatrix;
+//! use f16_hid::Display;
+//!
+//! let simulator = SimulatedMatrix::new();
+//! let mut display = Display::new(simulator.matrix());
+//!
+//! display.back_mut().draw_text((0, 0), "HI", 0xff);
+//! display.present()?;
+//! assert_eq!(simulator.grid().data(), display.frame().data());
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! With the `simulator` feature, `open_window()` shows the panel in a
+//! desktop window, each LED scaled up to a square. Closing the window
+//! unplugs the module.
+
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::response::RESPONSE_LENGTH;
+use crate::text::{Orientation, TextStyle, FONT_3X5};
+use crate::transport::Transport;
+use crate::{Bitmap8, LedMatrix, CONNECT_DELAY, DEFAULT_BRIGHTNESS, DISPLAY_HEIGHT, DISPLAY_WIDTH, MAX_COMMAND_LENGTH};
+
+/// How many times bigger than one pixel each LED is drawn by default
+pub const DEFAULT_SCALE: usize = 10;
+
+/// Firmware version the simulator reports, new enough for every command
+const VERSION: [u8; 3] = [0x00, 0x19, 0x00];
+
+struct SimulatorState {
+    /// What the LEDs are set to, before brightness
+    grid: Bitmap8,
+    /// Columns staged for the next `DrawBuffer`
+    staged: Bitmap8,
+    brightness: u8,
+    sleeping: bool,
+    animating: bool,
+    /// Set by closing the window or jumping to the bootloader
+    unplugged: bool,
+    readable: VecDeque<u8>,
+    timeout: Duration,
+    /// Commands handled, for tests that want to know something arrived
+    commands: u64,
+}
+
+/// A module that only exists in memory, see the `simulator` module. Clones
+/// share everything, like `MockTransport`, so keep one to look at after
+/// handing another to `LedMatrix::with_transport()`.
+#[derive(Clone)]
+pub struct SimulatedMatrix {
+    state: Arc<Mutex<SimulatorState>>,
+}
+
+impl Default for SimulatedMatrix {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimulatedMatrix {
+    /// Blank, awake and at the firmware's default brightness
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(SimulatorState {
+                grid: Bitmap8::new(),
+                staged: Bitmap8::new(),
+                brightness: DEFAULT_BRIGHTNESS,
+                sleeping: false,
+                animating: false,
+                unplugged: false,
+                readable: VecDeque::new(),
+                timeout: CONNECT_DELAY,
+                commands: 0,
+            })),
+        }
+    }
+
+    /// An `LedMatrix` talking to this simulator
+    pub fn matrix(&self) -> LedMatrix {
+        LedMatrix::with_transport("simulator", self.clone())
+    }
+
+    /// What the LEDs show, with brightness applied and nothing while asleep
+    pub fn frame(&self) -> Bitmap8 {
+        let state = self.state();
+        let mut frame = Bitmap8::new();
+
+        if !state.sleeping {
+            for (pixel, value) in frame.data.iter_mut().zip(state.grid.data.iter()) {
+                *pixel = (*value as u16 * state.brightness as u16 / 0xff) as u8;
+            }
+        }
+
+        frame
+    }
+
+    /// What the LEDs are set to, as if at full brightness and awake
+    pub fn grid(&self) -> Bitmap8 {
+        self.state().grid.clone()
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.state().brightness
+    }
+
+    pub fn is_sleeping(&self) -> bool {
+        self.state().sleeping
+    }
+
+    /// Commands handled so far
+    pub fn commands(&self) -> u64 {
+        self.state().commands
+    }
+
+    /// Pull the plug, or put it back. Writes fail with `BrokenPipe` while
+    /// it's out, as they do when a real module goes away.
+    pub fn set_unplugged(&self, unplugged: bool) {
+        self.state().unplugged = unplugged;
+    }
+
+    pub fn is_unplugged(&self) -> bool {
+        self.state().unplugged
+    }
+
+    /// The panel as 0RGB pixels, each LED a grey square `scale` pixels a
+    /// side with a dark line between them, row by row. This is what the
+    /// window shows, and handy for screenshots. Returns the pixels with
+    /// their width and height.
+    pub fn render(&self, scale: usize) -> (Vec<u32>, usize, usize) {
+        let scale = scale.max(2);
+        let frame = self.frame();
+        let (width, height) = (DISPLAY_WIDTH * scale, DISPLAY_HEIGHT * scale);
+        let mut pixels = vec![0x0010_1010; width * height];
+
+        for x in 0 .. DISPLAY_WIDTH {
+            for y in 0 .. DISPLAY_HEIGHT {
+                let value = frame.data[x * DISPLAY_HEIGHT + y] as u32;
+                let colour = value << 16 | value << 8 | value;
+
+                for dy in 0 .. scale - 1 {
+                    let row = (y * scale + dy) * width;
+                    pixels[row + x * scale .. row + x * scale + scale - 1].fill(colour);
+                }
+            }
+        }
+
+        (pixels, width, height)
+    }
+
+    fn state(&self) -> MutexGuard<'_, SimulatorState> {
+        self.state.lock().expect("Simulator lock poisoned")
+    }
+
+    /// Carry out one command the way the firmware would. Short packets are
+    /// queries, full length ones set things.
+    fn handle(state: &mut SimulatorState, packet: &[u8]) {
+        if packet.len() < 3 || packet[.. 2] != [0x32, 0xac] {
+            return;
+        }
+
+        state.commands += 1;
+
+        let query = packet.len() < MAX_COMMAND_LENGTH;
+        let argument = |index: usize| packet.get(3 + index).copied().unwrap_or(0);
+
+        let reply = match (packet[2], query) {
+            (0x00, true) => Some(vec![state.brightness]),
+            (0x00, false) => {
+                state.brightness = argument(0);
+                None
+            },
+            (0x01, _) => {
+                state.grid = pattern(argument(0), argument(1));
+                None
+            },
+            (0x02, _) => {
+                // Gone to the bootloader, and off the serial port with it
+                state.unplugged = true;
+                None
+            },
+            (0x03, true) => Some(vec![state.sleeping as u8]),
+            (0x03, false) => {
+                state.sleeping = argument(0) != 0;
+                None
+            },
+            (0x04, true) => Some(vec![state.animating as u8]),
+            (0x04, false) => {
+                state.animating = argument(0) != 0;
+                None
+            },
+            (0x06, _) => {
+                for (index, pixel) in state.grid.data.iter_mut().enumerate() {
+                    let lit = argument(index / 8) & (1 << (index % 8)) != 0;
+                    *pixel = if lit { 0xff } else { 0 };
+                }
+                None
+            },
+            (0x07, _) => {
+                let column = argument(0) as usize;
+
+                if let Some(pixels) = state.staged.column_mut(column) {
+                    for (y, pixel) in pixels.iter_mut().enumerate() {
+                        *pixel = argument(1 + y);
+                    }
+                }
+                None
+            },
+            (0x08, _) => {
+                state.grid = state.staged.clone();
+                None
+            },
+            (0x20, _) => Some(VERSION.to_vec()),
+            _ => None,
+        };
+
+        if let Some(data) = reply {
+            let mut response = [0u8; RESPONSE_LENGTH];
+            response[.. data.len()].copy_from_slice(&data);
+            state.readable.extend(response);
+        }
+    }
+}
+
+/// What the firmware draws for a `Patterns`. The logos and the panic screen
+/// are stood in for by their initials.
+fn pattern(id: u8, argument: u8) -> Bitmap8 {
+    let mut frame = Bitmap8::new();
+    let bottom = DISPLAY_HEIGHT - 1;
+    let shade = |y: usize| (y * 0xff / bottom) as u8;
+
+    match id {
+        0x00 => {
+            let rows = DISPLAY_HEIGHT * argument.min(100) as usize / 100;
+            if rows > 0 {
+                frame.draw_box(0, DISPLAY_HEIGHT - rows, DISPLAY_WIDTH - 1, bottom, 0xff);
+            }
+        },
+        0x01 => {
+            for y in 0 .. DISPLAY_HEIGHT {
+                frame.draw_box(0, y, DISPLAY_WIDTH - 1, y, shade(y));
+            }
+        },
+        0x02 => {
+            for y in 0 .. DISPLAY_HEIGHT {
+                let value = shade((y * 2).min(2 * bottom - y * 2));
+                frame.draw_box(0, y, DISPLAY_WIDTH - 1, y, value);
+            }
+        },
+        0x04 => {
+            for y in 0 .. DISPLAY_HEIGHT {
+                let x = (y % 16).min(16 - y % 16);
+                frame.draw_box(x, y, x, y, 0xff);
+            }
+        },
+        0x05 => frame.fill(0xff),
+        _ => {
+            let initials = match id {
+                0x06 => "PANIC",
+                _ => "LOTUS",
+            };
+
+            let style = TextStyle::new(&FONT_3X5, 0xff).orientation(Orientation::Vertical);
+            frame.draw_text_styled((2, 2), initials, &style);
+        },
+    }
+
+    frame
+}
+
+impl Read for SimulatedMatrix {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let mut state = self.state();
+
+        if state.readable.is_empty() && !buffer.is_empty() {
+            return Err(Error::new(ErrorKind::TimedOut, "Nothing to read"));
+        }
+
+        let count = buffer.len().min(state.readable.len());
+
+        for (byte, reply) in buffer.iter_mut().zip(state.readable.drain(.. count)) {
+            *byte = reply;
+        }
+
+        Ok(count)
+    }
+}
+
+impl Write for SimulatedMatrix {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize, Error> {
+        let mut state = self.state();
+
+        if state.unplugged {
+            return Err(Error::new(ErrorKind::BrokenPipe, "Simulated module is unplugged"));
+        }
+
+        // Commands are sent padded to full length and queries short, and a
+        // query is always written on its own
+        for packet in buffer.chunks(MAX_COMMAND_LENGTH) {
+            Self::handle(&mut state, packet);
+        }
+
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Transport for SimulatedMatrix {
+    fn bytes_to_read(&self) -> Result<u32, Error> {
+        Ok(self.state().readable.len() as u32)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.state().timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), Error> {
+        self.state().timeout = timeout;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "simulator")]
+impl SimulatedMatrix {
+    /// Show the panel in a desktop window, each LED `scale` pixels a side,
+    /// until the window is closed. It runs on a thread of its own and
+    /// returns once the window is up. Closing it unplugs the module.
+    pub fn open_window(&self, scale: usize) -> Result<(), Error> {
+        let simulator = self.clone();
+        let (ready, opened) = std::sync::mpsc::channel();
+
+        std::thread::spawn(move || {
+            let (_, width, height) = simulator.render(scale);
+            let options = minifb::WindowOptions::default();
+
+            let mut window = match minifb::Window::new("LED matrix simulator", width, height, options) {
+                Ok(window) => {
+                    let _ = ready.send(Ok(()));
+                    window
+                },
+                Err(error) => {
+                    let _ = ready.send(Err(Error::other(error.to_string())));
+                    return;
+                },
+            };
+
+            window.set_target_fps(60);
+
+            while window.is_open() && !simulator.is_unplugged() {
+                let (pixels, width, height) = simulator.render(scale);
+
+                if window.update_with_buffer(&pixels, width, height).is_err() {
+                    break;
+                }
+            }
+
+            simulator.set_unplugged(true);
+        });
+
+        opened.recv().map_err(|_| Error::other("Simulator window thread died"))?
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, Patterns};
+
+    #[test]
+    fn answers_like_the_firmware() {
+        let simulator = SimulatedMatrix::new();
+        let mut matrix = simulator.matrix();
+
+        assert_eq!(matrix.refresh_capabilities().unwrap().version().map(|x| x.to_string()), Some("0.1.9".to_owned()));
+
+        let mut frame = Bitmap8::new();
+        frame.fill(0x80);
+        matrix.execute(Command::Brightness(0xff)).unwrap();
+        matrix.stage_frame(&frame).unwrap();
+        assert_eq!(simulator.frame().data(), frame.data());
+
+        matrix.execute(Command::Brightness(0x80)).unwrap();
+        assert_eq!(simulator.frame().data()[0], 0x40);
+        matrix.execute(Command::Sleep(true)).unwrap();
+        assert!(simulator.frame().data().iter().all(|x| *x == 0));
+        matrix.execute(Command::Sleep(false)).unwrap();
+
+        matrix.execute(Command::Pattern(Patterns::Percentage(50))).unwrap();
+        assert_eq!(simulator.grid().data()[DISPLAY_HEIGHT - 1], 0xff);
+        assert_eq!(simulator.grid().data()[0], 0);
+
+        matrix.clear().unwrap();
+        assert!(simulator.grid().data().iter().all(|x| *x == 0));
+
+        let (pixels, width, height) = simulator.render(DEFAULT_SCALE);
+        assert_eq!((width, height), (90, 340));
+        assert_eq!(pixels.len(), width * height);
+
+        simulator.set_unplugged(true);
+        let mut port = simulator.clone();
+        assert_eq!(port.write(&[0x32, 0xac, 0x20]).unwrap_err().kind(), ErrorKind::BrokenPipe);
+    }
+}
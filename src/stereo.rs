@@ -0,0 +1,224 @@
+//! Animations that use the two modules of an input deck as one pair. Motion
+//! is worked out on the `PairGeometry` strip, bezel included, so something
+//! crossing from one panel to the other disappears for as long as it would
+//! take to cross the gap and comes out moving at the same speed.
+
+use std::time::Instant;
+
+use crate::clock::{Clock, SystemClock};
+use crate::geometry::Rect;
+use crate::pair::{PairBitmap, PairGeometry, Side};
+use crate::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// A square that bounces around both panels, passing behind the bezel
+pub struct BouncingBall<C: Clock = SystemClock> {
+    geometry: PairGeometry,
+    clock: C,
+    /// Top left corner on the strip
+    position: (f64, f64),
+    /// Strip columns and rows per second
+    velocity: (f64, f64),
+    size: usize,
+    value: u8,
+    last_step: Option<Instant>,
+}
+
+impl BouncingBall<SystemClock> {
+    pub fn new(geometry: PairGeometry) -> Self {
+        Self::with_clock(geometry, SystemClock)
+    }
+}
+
+impl<C: Clock> BouncingBall<C> {
+    /// A 2x2 ball in the top left corner heading down and right
+    pub fn with_clock(geometry: PairGeometry, clock: C) -> Self {
+        Self {
+            geometry,
+            clock,
+            position: (0.0, 0.0),
+            velocity: (8.0, 12.0),
+            size: 2,
+            value: 0xff,
+            last_step: None,
+        }
+    }
+
+    /// Where the ball's top left corner is, in strip coordinates
+    pub fn set_position(&mut self, x: f64, y: f64) {
+        self.position = (x, y);
+    }
+
+    pub fn position(&self) -> (f64, f64) {
+        self.position
+    }
+
+    /// Strip columns and rows per second
+    pub fn set_velocity(&mut self, x: f64, y: f64) {
+        self.velocity = (x, y);
+    }
+
+    pub fn velocity(&self) -> (f64, f64) {
+        self.velocity
+    }
+
+    pub fn set_size(&mut self, size: usize) {
+        self.size = size.max(1);
+    }
+
+    pub fn set_value(&mut self, value: u8) {
+        self.value = value;
+    }
+
+    /// Which panel the ball is on. `None` while it's entirely behind the
+    /// bezel.
+    pub fn side(&self) -> Option<Side> {
+        let left = self.position.0.round() as usize;
+
+        (left .. left + self.size)
+            .find_map(|x| self.geometry.locate(x))
+            .map(|(side, _)| side)
+    }
+
+    /// Move along for however much time has passed, bouncing off the outer
+    /// edges of the pair and the top and bottom
+    pub fn tick(&mut self) {
+        let now = self.clock.now();
+        let elapsed = match self.last_step.replace(now) {
+            Some(last) => now.saturating_duration_since(last).as_secs_f64(),
+            None => return,
+        };
+
+        let width = self.geometry.width().saturating_sub(self.size) as f64;
+        let height = (DISPLAY_HEIGHT - self.size.min(DISPLAY_HEIGHT)) as f64;
+
+        let (x, dx) = bounce(self.position.0 + self.velocity.0 * elapsed, self.velocity.0, width);
+        let (y, dy) = bounce(self.position.1 + self.velocity.1 * elapsed, self.velocity.1, height);
+
+        self.position = (x, y);
+        self.velocity = (dx, dy);
+    }
+
+    /// Draw the ball over both halves of the canvas. Whatever is behind the
+    /// bezel isn't drawn.
+    pub fn render(&self, canvas: &mut PairBitmap) {
+        let (left, top) = (self.position.0.round() as usize, self.position.1.round() as i32);
+
+        for x in left .. left + self.size {
+            // The canvas has no bezel, so strip columns past it shift left
+            let column = match self.geometry.locate(x) {
+                Some((Side::Left, column)) => column,
+                Some((Side::Right, column)) => DISPLAY_WIDTH + column,
+                None => continue,
+            };
+
+            canvas.fill_rect(Rect::new((column as i32, top), (1, self.size)), self.value);
+        }
+    }
+
+    /// A blank canvas with the ball on it
+    pub fn frame(&self) -> PairBitmap {
+        let mut canvas = PairBitmap::new();
+        self.render(&mut canvas);
+        canvas
+    }
+}
+
+/// Reflect a position that went past 0 or `limit` back inside, flipping the
+/// velocity for each bounce
+fn bounce(mut position: f64, mut velocity: f64, limit: f64) -> (f64, f64) {
+    if limit <= 0.0 {
+        return (0.0, velocity);
+    }
+
+    // A long gap between ticks can bounce more than once
+    while position < 0.0 || position > limit {
+        if position < 0.0 {
+            position = -position;
+        } else {
+            position = 2.0 * limit - position;
+        }
+
+        velocity = -velocity;
+    }
+
+    (position, velocity)
+}
+
+/// Two level meters growing outward from the bezel, the left channel across
+/// the left panel and the right channel across the right. Levels are from
+/// 0.0 to 1.0 and light whole columns from the inner edges.
+pub fn draw_mirrored_levels(canvas: &mut PairBitmap, left: f64, right: f64, value: u8) {
+    let columns = |level: f64| (level.clamp(0.0, 1.0) * DISPLAY_WIDTH as f64).round() as usize;
+
+    for step in 0 .. columns(left) {
+        let x = DISPLAY_WIDTH - 1 - step;
+        canvas.fill_rect(Rect::new((x as i32, 0), (1, DISPLAY_HEIGHT)), value);
+    }
+
+    for step in 0 .. columns(right) {
+        let x = DISPLAY_WIDTH + step;
+        canvas.fill_rect(Rect::new((x as i32, 0), (1, DISPLAY_HEIGHT)), value);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::geometry::Point;
+
+    #[test]
+    fn ball_hides_behind_the_bezel() {
+        let clock = ManualClock::new();
+        let geometry = PairGeometry::new(2);
+        let mut ball = BouncingBall::with_clock(geometry, clock.clone());
+        ball.set_size(1);
+        ball.set_velocity(1.0, 0.0);
+        ball.set_position(8.0, 5.0);
+        ball.tick();
+
+        assert_eq!(ball.side(), Some(Side::Left));
+        assert_eq!(ball.frame().pixel(Point::new(8, 5)), Some(0xff));
+
+        // Two seconds to cross the two column gap
+        clock.advance(Duration::from_secs(1));
+        ball.tick();
+        assert_eq!(ball.side(), None);
+        assert!(ball.frame().left().data().iter().chain(ball.frame().right().data()).all(|x| *x == 0));
+
+        clock.advance(Duration::from_secs(2));
+        ball.tick();
+        assert_eq!(ball.side(), Some(Side::Right));
+        assert_eq!(ball.frame().pixel(Point::new(9, 5)), Some(0xff));
+    }
+
+    #[test]
+    fn ball_bounces_off_the_outer_edges() {
+        let clock = ManualClock::new();
+        let mut ball = BouncingBall::with_clock(PairGeometry::new(2), clock.clone());
+        ball.set_size(1);
+        ball.set_position(1.0, 32.0);
+        ball.set_velocity(-2.0, 2.0);
+        ball.tick();
+
+        clock.advance(Duration::from_secs(1));
+        ball.tick();
+
+        assert_eq!(ball.position(), (1.0, 32.0));
+        assert_eq!(ball.velocity(), (2.0, -2.0));
+    }
+
+    #[test]
+    fn levels_grow_outward() {
+        let mut canvas = PairBitmap::new();
+        draw_mirrored_levels(&mut canvas, 1.0 / 3.0, 1.0, 7);
+
+        assert_eq!(canvas.pixel(Point::new(8, 0)), Some(7));
+        assert_eq!(canvas.pixel(Point::new(6, 33)), Some(7));
+        assert_eq!(canvas.pixel(Point::new(5, 0)), Some(0));
+        assert_eq!(canvas.pixel(Point::new(17, 0)), Some(7));
+    }
+}
@@ -0,0 +1,172 @@
+//! Feeding a `Display` frames from another thread or another process.
+//!
+//! Within a program, send `Bitmap8`s down a channel to `Display::serve()`.
+//! From outside, write frames to the bridge's stdin and have it call
+//! `Display::serve_reader()`. Each frame on the wire is a two byte big
+//! endian length followed by that many bytes of greyscale, row by row from
+//! the top, so anything that can write bytes can drive the panel. From
+//! Python:
+//!
+//! ```python
+//! frame = bytes(306)  # 9 wide, 34 tall
+//! sys.stdout.buffer.write(len(frame).to_bytes(2, "big") + frame)
+//! ```
+
+use std::io::{Error, ErrorKind, Read, Write};
+use std::sync::mpsc::Receiver;
+
+use crate::clock::Clock;
+use crate::{Bitmap8, Display, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Length of a frame's payload on the wire
+pub const FRAME_LENGTH: usize = DISPLAY_WIDTH * DISPLAY_HEIGHT;
+
+/// Read the next frame. `None` if the stream ended cleanly between frames.
+pub fn read_frame<R: Read>(reader: &mut R) -> Result<Option<Bitmap8>, Error> {
+    let mut length = [0u8; 2];
+
+    match reader.read_exact(&mut length) {
+        Ok(()) => (),
+        Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    }
+
+    let length = u16::from_be_bytes(length) as usize;
+
+    if length != FRAME_LENGTH {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Frames are {} bytes, got {}", FRAME_LENGTH, length),
+        ));
+    }
+
+    let mut rows = [0u8; FRAME_LENGTH];
+    reader.read_exact(&mut rows)?;
+
+    let mut frame = Bitmap8::new();
+
+    for (index, value) in rows.iter().enumerate() {
+        let (x, y) = (index % DISPLAY_WIDTH, index / DISPLAY_WIDTH);
+        frame.data[x * DISPLAY_HEIGHT + y] = *value;
+    }
+
+    Ok(Some(frame))
+}
+
+/// Write a frame the way `read_frame()` expects it
+pub fn write_frame<W: Write>(writer: &mut W, frame: &Bitmap8) -> Result<(), Error> {
+    let mut packet = Vec::with_capacity(2 + FRAME_LENGTH);
+    packet.extend_from_slice(&(FRAME_LENGTH as u16).to_be_bytes());
+
+    for row in frame.rows() {
+        packet.extend_from_slice(&row);
+    }
+
+    writer.write_all(&packet)
+}
+
+/// Frames read from a stream until it ends
+pub struct FrameReader<R> {
+    reader: R,
+}
+
+impl<R: Read> FrameReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for FrameReader<R> {
+    type Item = Result<Bitmap8, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        read_frame(&mut self.reader).transpose()
+    }
+}
+
+impl<C: Clock> Display<C> {
+    /// Show frames from a channel until every sender is gone. A frame that
+    /// was overtaken by a newer one while the last was going out is skipped,
+    /// so a fast producer can't build up a backlog. Returns how many frames
+    /// were shown.
+    pub fn serve(&mut self, frames: &Receiver<Bitmap8>) -> Result<usize, Error> {
+        let mut shown = 0;
+
+        while let Ok(frame) = frames.recv() {
+            let latest = frames.try_iter().last().unwrap_or(frame);
+
+            *self.back_mut() = latest;
+            self.present()?;
+            shown += 1;
+        }
+
+        Ok(shown)
+    }
+
+    /// Show frames read from a stream, such as stdin, until it ends.
+    /// Returns how many frames were shown.
+    pub fn serve_reader<R: Read>(&mut self, reader: R) -> Result<usize, Error> {
+        let mut shown = 0;
+
+        for frame in FrameReader::new(reader) {
+            *self.back_mut() = frame?;
+            self.present()?;
+            shown += 1;
+        }
+
+        Ok(shown)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MockTransport;
+    use crate::LedMatrix;
+
+    #[test]
+    fn frames_cross_the_wire_in_rows() {
+        let mut frame = Bitmap8::new();
+        frame.draw_point(1, 0, 0x80).unwrap();
+        frame.draw_point(8, 33, 0xff).unwrap();
+
+        let mut wire = Vec::new();
+        write_frame(&mut wire, &frame).unwrap();
+        write_frame(&mut wire, &Bitmap8::new()).unwrap();
+
+        assert_eq!(&wire[.. 4], [0x01, 0x32, 0x00, 0x80]);
+        assert_eq!(wire[2 + FRAME_LENGTH - 1], 0xff);
+
+        let mock = MockTransport::new();
+        let mut display = Display::new(LedMatrix::with_transport("mock", mock.clone()));
+
+        assert_eq!(display.serve_reader(wire.as_slice()).unwrap(), 2);
+        assert_eq!(display.front().data(), Bitmap8::new().data());
+        assert!(!mock.written().is_empty());
+
+        // A frame cut short is an error rather than the end
+        assert!(read_frame(&mut &wire[.. 100]).is_err());
+        assert!(read_frame(&mut [0x00, 0x05].as_slice()).is_err());
+    }
+
+    #[test]
+    fn serving_skips_stale_frames() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut display = Display::new(LedMatrix::with_transport("mock", MockTransport::new()));
+
+        let mut last = Bitmap8::new();
+        last.fill(0x10);
+
+        sender.send(Bitmap8::new()).unwrap();
+        sender.send(last.clone()).unwrap();
+        drop(sender);
+
+        assert_eq!(display.serve(&receiver).unwrap(), 1);
+        assert_eq!(display.front().data(), last.data());
+    }
+}
@@ -0,0 +1,544 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::geometry::{Point, Rect, Size};
+use crate::Bitmap8;
+
+/// Fixed width bitmap font. Glyphs are stored a byte per column with the top
+/// row in the lowest bit.
+pub struct Font {
+    pub width: usize,
+    pub height: usize,
+    glyph: fn(char) -> Option<&'static [u8]>,
+}
+
+impl Font {
+    /// Columns for a character, or `None` if the font doesn't have it
+    pub fn glyph(&self, character: char) -> Option<&'static [u8]> {
+        (self.glyph)(character)
+    }
+
+    fn pixel(&self, character: char, x: usize, y: usize) -> bool {
+        // Unknown characters are drawn as a box so they're noticed
+        match self.glyph(character) {
+            Some(columns) => columns[x] & (1 << y) != 0,
+            None => x == 0 || y == 0 || x == self.width - 1 || y == self.height - 1,
+        }
+    }
+}
+
+/// 3x5 capitals, digits and a little punctuation. Two characters fit side by
+/// side across the panel. Lowercase is drawn as uppercase.
+pub const FONT_3X5: Font = Font {
+    width: 3,
+    height: 5,
+    glyph: small_glyph,
+};
+
+/// The classic 5x7 ASCII font. One character fits across the panel.
+pub const FONT_5X7: Font = Font {
+    width: 5,
+    height: 7,
+    glyph: large_glyph,
+};
+
+/// Which way the text runs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Orientation {
+    /// Left to right, lines going down
+    #[default]
+    Horizontal,
+    /// Turned a quarter clockwise so it reads top to bottom along the long
+    /// side of the panel
+    Vertical,
+    /// Upright characters stacked top to bottom, one per line
+    Stacked,
+}
+
+/// What `draw_paragraph()` does with text that's too long for a line
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WrapMode {
+    /// Break lines between words, and split words too long for a line of
+    /// their own. Lines past the end of the area are dropped.
+    #[default]
+    Word,
+    /// Like `Word`, but if anything is dropped the last character shown is
+    /// replaced with a `.` to say so
+    Ellipsis,
+    /// Only break at newlines, cutting each line off at the edge
+    Truncate,
+}
+
+/// Where a line sits across the space it's given
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Alignment {
+    #[default]
+    Left,
+    /// Centred, a pixel left of centre when it can't be exact
+    Center,
+    Right,
+}
+
+#[derive(Clone, Copy)]
+pub struct TextStyle<'a> {
+    pub font: &'a Font,
+    pub orientation: Orientation,
+    pub value: u8,
+    /// Blank pixels between characters
+    pub spacing: usize,
+}
+
+impl<'a> TextStyle<'a> {
+    pub fn new(font: &'a Font, value: u8) -> Self {
+        Self {
+            font,
+            orientation: Orientation::Horizontal,
+            value,
+            spacing: 1,
+        }
+    }
+
+    pub fn orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    /// Space taken up by `text`
+    pub fn measure(&self, text: &str) -> Size {
+        let font = self.font;
+        let lines: Vec<usize> = text.split('\n').map(|x| x.chars().count()).collect();
+        let longest = lines.iter().copied().max().unwrap_or(0);
+        let line_count = lines.len();
+
+        let along = |count: usize, size: usize| {
+            if count == 0 { 0 } else { count * (size + self.spacing) - self.spacing }
+        };
+
+        match self.orientation {
+            Orientation::Horizontal => Size::new(along(longest, font.width), along(line_count, font.height)),
+            Orientation::Vertical => Size::new(along(line_count, font.height), along(longest, font.width)),
+            Orientation::Stacked => Size::new(along(line_count, font.width), along(longest, font.height)),
+        }
+    }
+
+    /// Break `text` into lines that fit inside `size`
+    pub fn wrap(&self, text: &str, size: Size, mode: WrapMode) -> Vec<String> {
+        self.wrap_lines(text, size, mode).0
+    }
+
+    /// The lines, and whether all of the text made it into them
+    fn wrap_lines(&self, text: &str, size: Size, mode: WrapMode) -> (Vec<String>, bool) {
+        let font = self.font;
+        let fit = |extent: usize, glyph: usize| (extent + self.spacing) / (glyph + self.spacing);
+
+        // Characters to a line and lines to the area
+        let (columns, rows) = match self.orientation {
+            Orientation::Horizontal => (fit(size.width, font.width), fit(size.height, font.height)),
+            Orientation::Vertical => (fit(size.height, font.width), fit(size.width, font.height)),
+            Orientation::Stacked => (fit(size.height, font.height), fit(size.width, font.width)),
+        };
+
+        if columns == 0 || rows == 0 {
+            return (Vec::new(), text.is_empty());
+        }
+
+        let mut lines = Vec::new();
+        let mut complete = true;
+
+        for paragraph in text.split('\n') {
+            if mode == WrapMode::Truncate {
+                complete &= paragraph.chars().count() <= columns;
+                lines.push(paragraph.chars().take(columns).collect());
+                continue;
+            }
+
+            wrap_words(paragraph, columns, &mut lines);
+        }
+
+        if lines.len() > rows {
+            lines.truncate(rows);
+            complete = false;
+        }
+
+        if !complete && mode == WrapMode::Ellipsis {
+            if let Some(last) = lines.last_mut() {
+                if last.chars().count() >= columns {
+                    last.pop();
+                }
+
+                last.push('.');
+            }
+        }
+
+        (lines, complete)
+    }
+}
+
+/// Greedily fill lines `columns` characters long with the words of one
+/// paragraph
+fn wrap_words(paragraph: &str, columns: usize, lines: &mut Vec<String>) {
+    let mut line = String::new();
+
+    for word in paragraph.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+
+        // Too long for any line, so it gets split wherever it has to be
+        while word.len() > columns {
+            if !line.is_empty() {
+                lines.push(core::mem::take(&mut line));
+            }
+
+            lines.push(word.drain(.. columns).collect());
+        }
+
+        if word.is_empty() {
+            continue;
+        }
+
+        let length = line.chars().count();
+
+        if length > 0 && length + 1 + word.len() > columns {
+            lines.push(core::mem::take(&mut line));
+        } else if length > 0 {
+            line.push(' ');
+        }
+
+        line.extend(word);
+    }
+
+    // Blank paragraphs still take up a line
+    lines.push(line);
+}
+
+impl Default for TextStyle<'static> {
+    fn default() -> Self {
+        Self::new(&FONT_3X5, 0xff)
+    }
+}
+
+impl Bitmap8 {
+    /// Write text in the small font, left to right. Anything off the panel
+    /// is clipped, so negative positions work for scrolling.
+    pub fn draw_text(&mut self, position: impl Into<Point>, text: &str, value: u8) {
+        self.draw_text_styled(position, text, &TextStyle::new(&FONT_3X5, value));
+    }
+
+    pub fn draw_text_styled(&mut self, position: impl Into<Point>, text: &str, style: &TextStyle) {
+        let position = position.into();
+        let font = style.font;
+        let step_x = (font.width + style.spacing) as i32;
+        let step_y = (font.height + style.spacing) as i32;
+
+        for (line_index, line) in text.split('\n').enumerate() {
+            let line_index = line_index as i32;
+
+            for (index, character) in line.chars().enumerate() {
+                let index = index as i32;
+
+                let offset = match style.orientation {
+                    Orientation::Horizontal => Point::new(index * step_x, line_index * step_y),
+                    // Lines stack right to left so the first one is on the
+                    // outside edge when read with your head tilted
+                    Orientation::Vertical => Point::new(-line_index * step_y, index * step_x),
+                    Orientation::Stacked => Point::new(line_index * step_x, index * step_y),
+                };
+
+                self.draw_glyph(position + offset, character, style);
+            }
+        }
+    }
+
+    /// Write text in the small font wrapped to fit inside `area`, for
+    /// messages that should sit still rather than scroll. Returns whether
+    /// all of it fit.
+    pub fn draw_paragraph(&mut self, area: Rect, text: &str, mode: WrapMode, value: u8) -> bool {
+        self.draw_paragraph_styled(area, text, mode, &TextStyle::new(&FONT_3X5, value))
+    }
+
+    pub fn draw_paragraph_styled(&mut self, area: Rect, text: &str, mode: WrapMode, style: &TextStyle) -> bool {
+        let (lines, complete) = style.wrap_lines(text, area.size, mode);
+
+        // Vertical lines are laid out from the right
+        let origin = match style.orientation {
+            Orientation::Vertical => Point::new(area.right() - style.font.height as i32, area.top()),
+            _ => area.origin,
+        };
+
+        self.draw_text_styled(origin, &lines.join("\n"), style);
+
+        complete
+    }
+
+    fn draw_glyph(&mut self, origin: Point, character: char, style: &TextStyle) {
+        let font = style.font;
+
+        for glyph_x in 0 .. font.width {
+            for glyph_y in 0 .. font.height {
+                if !font.pixel(character, glyph_x, glyph_y) {
+                    continue;
+                }
+
+                let offset = match style.orientation {
+                    Orientation::Vertical => Point::new((font.height - 1 - glyph_y) as i32, glyph_x as i32),
+                    _ => Point::new(glyph_x as i32, glyph_y as i32),
+                };
+
+                self.set_pixel(origin + offset, style.value);
+            }
+        }
+    }
+}
+
+fn small_glyph(character: char) -> Option<&'static [u8]> {
+    let columns: &'static [u8] = match character.to_ascii_uppercase() {
+        ' ' => &[0x00, 0x00, 0x00],
+        '0' => &[0x1f, 0x11, 0x1f],
+        '1' => &[0x12, 0x1f, 0x10],
+        '2' => &[0x1d, 0x15, 0x17],
+        '3' => &[0x15, 0x15, 0x1f],
+        '4' => &[0x07, 0x04, 0x1f],
+        '5' => &[0x17, 0x15, 0x1d],
+        '6' => &[0x1f, 0x15, 0x1d],
+        '7' => &[0x01, 0x01, 0x1f],
+        '8' => &[0x1f, 0x15, 0x1f],
+        '9' => &[0x17, 0x15, 0x1f],
+        'A' => &[0x1e, 0x05, 0x1e],
+        'B' => &[0x1f, 0x15, 0x0a],
+        'C' => &[0x0e, 0x11, 0x11],
+        'D' => &[0x1f, 0x11, 0x0e],
+        'E' => &[0x1f, 0x15, 0x11],
+        'F' => &[0x1f, 0x05, 0x01],
+        'G' => &[0x0e, 0x11, 0x1d],
+        'H' => &[0x1f, 0x04, 0x1f],
+        'I' => &[0x11, 0x1f, 0x11],
+        'J' => &[0x08, 0x10, 0x0f],
+        'K' => &[0x1f, 0x04, 0x1b],
+        'L' => &[0x1f, 0x10, 0x10],
+        'M' => &[0x1f, 0x06, 0x1f],
+        'N' => &[0x1f, 0x01, 0x1e],
+        'O' => &[0x0e, 0x11, 0x0e],
+        'P' => &[0x1f, 0x05, 0x02],
+        'Q' => &[0x0e, 0x09, 0x16],
+        'R' => &[0x1f, 0x05, 0x1a],
+        'S' => &[0x12, 0x15, 0x09],
+        'T' => &[0x01, 0x1f, 0x01],
+        'U' => &[0x1f, 0x10, 0x1f],
+        'V' => &[0x0f, 0x10, 0x0f],
+        'W' => &[0x1f, 0x0c, 0x1f],
+        'X' => &[0x1b, 0x04, 0x1b],
+        'Y' => &[0x03, 0x1c, 0x03],
+        'Z' => &[0x19, 0x15, 0x13],
+        ':' => &[0x00, 0x0a, 0x00],
+        '.' => &[0x00, 0x10, 0x00],
+        ',' => &[0x10, 0x08, 0x00],
+        '-' => &[0x04, 0x04, 0x04],
+        '+' => &[0x04, 0x0e, 0x04],
+        '%' => &[0x19, 0x04, 0x13],
+        '/' => &[0x18, 0x04, 0x03],
+        '!' => &[0x00, 0x17, 0x00],
+        '?' => &[0x01, 0x15, 0x03],
+        '\'' => &[0x00, 0x03, 0x00],
+        '_' => &[0x10, 0x10, 0x10],
+        _ => return None,
+    };
+
+    Some(columns)
+}
+
+fn large_glyph(character: char) -> Option<&'static [u8]> {
+    let code = character as u32;
+
+    if !(0x20..0x7f).contains(&code) {
+        return None;
+    }
+
+    let start = (code as usize - 0x20) * 5;
+
+    Some(&FONT_5X7_DATA[start .. start + 5])
+}
+
+// Printable ASCII, 0x20 to 0x7e
+#[rustfmt::skip]
+const FONT_5X7_DATA: [u8; 95 * 5] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, // space
+    0x00, 0x00, 0x5f, 0x00, 0x00, // !
+    0x00, 0x07, 0x00, 0x07, 0x00, // "
+    0x14, 0x7f, 0x14, 0x7f, 0x14, // #
+    0x24, 0x2a, 0x7f, 0x2a, 0x12, // $
+    0x23, 0x13, 0x08, 0x64, 0x62, // %
+    0x36, 0x49, 0x55, 0x22, 0x50, // &
+    0x00, 0x05, 0x03, 0x00, 0x00, // '
+    0x00, 0x1c, 0x22, 0x41, 0x00, // (
+    0x00, 0x41, 0x22, 0x1c, 0x00, // )
+    0x08, 0x2a, 0x1c, 0x2a, 0x08, // *
+    0x08, 0x08, 0x3e, 0x08, 0x08, // +
+    0x00, 0x50, 0x30, 0x00, 0x00, // ,
+    0x08, 0x08, 0x08, 0x08, 0x08, // -
+    0x00, 0x60, 0x60, 0x00, 0x00, // .
+    0x20, 0x10, 0x08, 0x04, 0x02, // /
+    0x3e, 0x51, 0x49, 0x45, 0x3e, // 0
+    0x00, 0x42, 0x7f, 0x40, 0x00, // 1
+    0x42, 0x61, 0x51, 0x49, 0x46, // 2
+    0x21, 0x41, 0x45, 0x4b, 0x31, // 3
+    0x18, 0x14, 0x12, 0x7f, 0x10, // 4
+    0x27, 0x45, 0x45, 0x45, 0x39, // 5
+    0x3c, 0x4a, 0x49, 0x49, 0x30, // 6
+    0x01, 0x71, 0x09, 0x05, 0x03, // 7
+    0x36, 0x49, 0x49, 0x49, 0x36, // 8
+    0x06, 0x49, 0x49, 0x29, 0x1e, // 9
+    0x00, 0x36, 0x36, 0x00, 0x00, // :
+    0x00, 0x56, 0x36, 0x00, 0x00, // ;
+    0x00, 0x08, 0x14, 0x22, 0x41, // <
+    0x14, 0x14, 0x14, 0x14, 0x14, // =
+    0x41, 0x22, 0x14, 0x08, 0x00, // >
+    0x02, 0x01, 0x51, 0x09, 0x06, // ?
+    0x32, 0x49, 0x79, 0x41, 0x3e, // @
+    0x7e, 0x11, 0x11, 0x11, 0x7e, // A
+    0x7f, 0x49, 0x49, 0x49, 0x36, // B
+    0x3e, 0x41, 0x41, 0x41, 0x22, // C
+    0x7f, 0x41, 0x41, 0x22, 0x1c, // D
+    0x7f, 0x49, 0x49, 0x49, 0x41, // E
+    0x7f, 0x09, 0x09, 0x01, 0x01, // F
+    0x3e, 0x41, 0x41, 0x51, 0x32, // G
+    0x7f, 0x08, 0x08, 0x08, 0x7f, // H
+    0x00, 0x41, 0x7f, 0x41, 0x00, // I
+    0x20, 0x40, 0x41, 0x3f, 0x01, // J
+    0x7f, 0x08, 0x14, 0x22, 0x41, // K
+    0x7f, 0x40, 0x40, 0x40, 0x40, // L
+    0x7f, 0x02, 0x04, 0x02, 0x7f, // M
+    0x7f, 0x04, 0x08, 0x10, 0x7f, // N
+    0x3e, 0x41, 0x41, 0x41, 0x3e, // O
+    0x7f, 0x09, 0x09, 0x09, 0x06, // P
+    0x3e, 0x41, 0x51, 0x21, 0x5e, // Q
+    0x7f, 0x09, 0x19, 0x29, 0x46, // R
+    0x46, 0x49, 0x49, 0x49, 0x31, // S
+    0x01, 0x01, 0x7f, 0x01, 0x01, // T
+    0x3f, 0x40, 0x40, 0x40, 0x3f, // U
+    0x1f, 0x20, 0x40, 0x20, 0x1f, // V
+    0x7f, 0x20, 0x18, 0x20, 0x7f, // W
+    0x63, 0x14, 0x08, 0x14, 0x63, // X
+    0x03, 0x04, 0x78, 0x04, 0x03, // Y
+    0x61, 0x51, 0x49, 0x45, 0x43, // Z
+    0x00, 0x00, 0x7f, 0x41, 0x41, // [
+    0x02, 0x04, 0x08, 0x10, 0x20, // backslash
+    0x41, 0x41, 0x7f, 0x00, 0x00, // ]
+    0x04, 0x02, 0x01, 0x02, 0x04, // ^
+    0x40, 0x40, 0x40, 0x40, 0x40, // _
+    0x00, 0x01, 0x02, 0x04, 0x00, // `
+    0x20, 0x54, 0x54, 0x54, 0x78, // a
+    0x7f, 0x48, 0x44, 0x44, 0x38, // b
+    0x38, 0x44, 0x44, 0x44, 0x20, // c
+    0x38, 0x44, 0x44, 0x48, 0x7f, // d
+    0x38, 0x54, 0x54, 0x54, 0x18, // e
+    0x08, 0x7e, 0x09, 0x01, 0x02, // f
+    0x08, 0x14, 0x54, 0x54, 0x3c, // g
+    0x7f, 0x08, 0x04, 0x04, 0x78, // h
+    0x00, 0x44, 0x7d, 0x40, 0x00, // i
+    0x20, 0x40, 0x44, 0x3d, 0x00, // j
+    0x00, 0x7f, 0x10, 0x28, 0x44, // k
+    0x00, 0x41, 0x7f, 0x40, 0x00, // l
+    0x7c, 0x04, 0x18, 0x04, 0x78, // m
+    0x7c, 0x08, 0x04, 0x04, 0x78, // n
+    0x38, 0x44, 0x44, 0x44, 0x38, // o
+    0x7c, 0x14, 0x14, 0x14, 0x08, // p
+    0x08, 0x14, 0x14, 0x18, 0x7c, // q
+    0x7c, 0x08, 0x04, 0x04, 0x08, // r
+    0x48, 0x54, 0x54, 0x54, 0x20, // s
+    0x04, 0x3f, 0x44, 0x40, 0x20, // t
+    0x3c, 0x40, 0x40, 0x20, 0x7c, // u
+    0x1c, 0x20, 0x40, 0x20, 0x1c, // v
+    0x3c, 0x40, 0x30, 0x40, 0x3c, // w
+    0x44, 0x28, 0x10, 0x28, 0x44, // x
+    0x0c, 0x50, 0x50, 0x50, 0x3c, // y
+    0x44, 0x64, 0x54, 0x4c, 0x44, // z
+    0x00, 0x08, 0x36, 0x41, 0x00, // {
+    0x00, 0x00, 0x7f, 0x00, 0x00, // |
+    0x00, 0x41, 0x36, 0x08, 0x00, // }
+    0x08, 0x04, 0x08, 0x10, 0x08, // ~
+];
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lit(bitmap: &Bitmap8, x: i32, y: i32) -> bool {
+        bitmap.pixel(Point::new(x, y)) != Some(0)
+    }
+
+    #[test]
+    fn horizontal_digit() {
+        let mut bitmap = Bitmap8::new();
+
+        bitmap.draw_text((0, 0), "1", 0xff);
+
+        // Middle column of a 1 is solid
+        for y in 0 .. 5 {
+            assert!(lit(&bitmap, 1, y));
+        }
+        assert!(!lit(&bitmap, 2, 0));
+    }
+
+    #[test]
+    fn vertical_is_rotated() {
+        let mut bitmap = Bitmap8::new();
+        let style = TextStyle::new(&FONT_3X5, 0xff).orientation(Orientation::Vertical);
+
+        bitmap.draw_text_styled((0, 0), "L", &style);
+
+        // The upright stem of the L becomes the top row, the foot runs down
+        // the left
+        for x in 0 .. 5 {
+            assert!(lit(&bitmap, x, 0));
+        }
+        assert!(lit(&bitmap, 0, 2));
+        assert!(!lit(&bitmap, 4, 2));
+    }
+
+    #[test]
+    fn clipped_off_panel() {
+        let mut bitmap = Bitmap8::new();
+
+        bitmap.draw_text((-2, -2), "8", 0xff);
+        bitmap.draw_text((8, 32), "8", 0xff);
+
+        assert!(lit(&bitmap, 0, 0));
+        assert!(lit(&bitmap, 8, 33));
+    }
+
+    #[test]
+    fn measure_orientations() {
+        let style = TextStyle::new(&FONT_3X5, 0xff);
+
+        assert_eq!(style.measure("12:34"), Size::new(19, 5));
+        assert_eq!(style.orientation(Orientation::Stacked).measure("12\n34"), Size::new(7, 11));
+        assert_eq!(style.orientation(Orientation::Vertical).measure("HI"), Size::new(5, 7));
+        assert_eq!(style.measure(""), Size::new(0, 5));
+    }
+
+    #[test]
+    fn paragraphs_wrap_down_the_panel() {
+        let style = TextStyle::new(&FONT_3X5, 0xff);
+        let size = Size::new(11, 17);
+
+        assert_eq!(style.wrap("GO TO BED NOW", size, WrapMode::Word), ["GO", "TO", "BED"]);
+        assert_eq!(style.wrap("GO TO BED NOW", size, WrapMode::Ellipsis), ["GO", "TO", "BE."]);
+        assert_eq!(style.wrap("CPU 97%", size, WrapMode::Word), ["CPU", "97%"]);
+        assert_eq!(style.wrap("ABCDEFG", size, WrapMode::Word), ["ABC", "DEF", "G"]);
+        assert_eq!(style.wrap("ABCDEF\nX", size, WrapMode::Truncate), ["ABC", "X"]);
+
+        let mut bitmap = Bitmap8::new();
+        assert!(bitmap.draw_paragraph(Rect::new((0, 6), (9, 28)), "OK", WrapMode::Word, 0xff));
+        assert!(!lit(&bitmap, 1, 5));
+        assert!(lit(&bitmap, 1, 6));
+        assert!(!bitmap.draw_paragraph(Rect::display(), "A B C D E F G H I J K L", WrapMode::Word, 0xff));
+    }
+
+    #[test]
+    fn every_ascii_glyph_exists() {
+        for code in 0x20u8 .. 0x7f {
+            assert_eq!(FONT_5X7.glyph(code as char).map(|x| x.len()), Some(5));
+        }
+        assert!(FONT_5X7.glyph('é').is_none());
+    }
+}
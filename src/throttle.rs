@@ -0,0 +1,123 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long `max_per_second` counts commands over
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Limits on how fast `LedMatrix` sends commands. Some firmware revisions
+/// drop a command that arrives hard on the heels of the last one, and the
+/// only cure is to leave a gap. With a throttle set, every command waits
+/// its turn inside `execute()` and friends, so the application doesn't
+/// have to sleep between them.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use f16_hid::throttle::Throttle;
+/// use f16_hid::LedMatrix;
+///
+/// let mut matrix = LedMatrix::new("/dev/ttyACM0")?;
+/// matrix.set_throttle(Throttle::new().min_gap(Duration::from_millis(2)).max_per_second(200));
+/// # Ok::<(), serialport::Error>(())
+/// ```
+///
+/// A throttled matrix writes and flushes each command on its own, even
+/// ones packed together by a batch. Unthrottled, the default, writes go
+/// out as fast as the port takes them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Throttle {
+    min_gap: Duration,
+    max_per_second: Option<u32>,
+}
+
+impl Throttle {
+    /// No limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Leave at least `gap` between the start of one command and the next
+    pub fn min_gap(mut self, gap: Duration) -> Self {
+        self.min_gap = gap;
+        self
+    }
+
+    /// Send no more than `count` commands in any one second. Zero means no
+    /// limit.
+    pub fn max_per_second(mut self, count: u32) -> Self {
+        self.max_per_second = (count > 0).then_some(count);
+        self
+    }
+
+    pub fn gap(&self) -> Duration {
+        self.min_gap
+    }
+
+    pub fn rate(&self) -> Option<u32> {
+        self.max_per_second
+    }
+
+    /// Whether this holds anything back at all
+    pub fn is_limited(&self) -> bool {
+        !self.min_gap.is_zero() || self.max_per_second.is_some()
+    }
+
+    /// How long to wait at `now` before sending, given when recent commands
+    /// went out, oldest first
+    pub(crate) fn delay(&self, sent: &VecDeque<Instant>, now: Instant) -> Duration {
+        let gap = match sent.back() {
+            Some(last) => (*last + self.min_gap).saturating_duration_since(now),
+            None => Duration::ZERO,
+        };
+
+        let rate = match (self.max_per_second, sent.front()) {
+            (Some(max), Some(oldest)) if sent.len() >= max as usize => {
+                (*oldest + RATE_WINDOW).saturating_duration_since(now)
+            },
+            _ => Duration::ZERO,
+        };
+
+        gap.max(rate)
+    }
+
+    /// Note a command sent at `now`, forgetting any too old to matter
+    pub(crate) fn record(&self, sent: &mut VecDeque<Instant>, now: Instant) {
+        sent.push_back(now);
+
+        let keep = self.max_per_second.unwrap_or(1).max(1) as usize;
+        while sent.len() > keep {
+            sent.pop_front();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_wait_their_turn() {
+        let start = Instant::now();
+        let mut sent = VecDeque::new();
+        let ms = Duration::from_millis;
+
+        let gap = Throttle::new().min_gap(ms(10));
+        assert!(gap.is_limited() && !Throttle::new().is_limited());
+        assert_eq!(gap.delay(&sent, start), Duration::ZERO);
+        gap.record(&mut sent, start);
+        assert_eq!(gap.delay(&sent, start + ms(4)), ms(6));
+        assert_eq!(gap.delay(&sent, start + ms(20)), Duration::ZERO);
+
+        // Three a second, sent in a burst
+        let rate = Throttle::new().max_per_second(3);
+        let mut sent = VecDeque::new();
+        for step in 0 .. 3 {
+            assert_eq!(rate.delay(&sent, start + ms(step)), Duration::ZERO);
+            rate.record(&mut sent, start + ms(step));
+        }
+        assert_eq!(rate.delay(&sent, start + ms(100)), ms(900));
+        rate.record(&mut sent, start + ms(1000));
+        assert_eq!(sent.len(), 3);
+        assert_eq!(rate.delay(&sent, start + ms(1000)), ms(1));
+    }
+}
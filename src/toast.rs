@@ -0,0 +1,313 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::geometry::{Point, Rect, Size};
+use crate::marquee::Marquee;
+use crate::text::{Orientation, TextStyle, FONT_3X5};
+use crate::{Bitmap8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Rows at the top of the panel taken by a toast's icon, square with the
+/// panel's width
+pub const ICON_HEIGHT: usize = DISPLAY_WIDTH;
+
+/// Gap between the icon and the text
+const ICON_GAP: usize = 1;
+
+/// A short notification queued on a `Toaster`
+struct Toast {
+    icon: Option<Bitmap8>,
+    text: String,
+    duration: Duration,
+    priority: u8,
+}
+
+/// The toast on the panel right now
+struct Showing<C: Clock> {
+    toast: Toast,
+    started: Instant,
+    /// Only for text too long to fit
+    marquee: Option<Marquee<C>>,
+}
+
+/// Queues short notifications to show over whatever the application draws.
+/// Each toast is an optional icon at the top of the panel with text below,
+/// scrolled if it's too long to fit. Once the last toast is done the
+/// application's own frames show through again.
+///
+/// Higher priority toasts jump the queue, and interrupt a lower priority
+/// one that's already up. The interrupted toast goes back to the front of
+/// the queue to be shown again in full.
+///
+/// Call `tick()` from your render loop and pass each frame through
+/// `compose()`.
+pub struct Toaster<C: Clock + Clone = SystemClock> {
+    clock: C,
+    style: TextStyle<'static>,
+    queue: VecDeque<Toast>,
+    showing: Option<Showing<C>>,
+}
+
+impl Toaster<SystemClock> {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl Default for Toaster<SystemClock> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clock + Clone> Toaster<C> {
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            clock,
+            style: TextStyle::new(&FONT_3X5, 0xff).orientation(Orientation::Vertical),
+            queue: VecDeque::new(),
+            showing: None,
+        }
+    }
+
+    /// Style for the text of toasts shown from now on
+    pub fn set_style(&mut self, style: TextStyle<'static>) {
+        self.style = style;
+    }
+
+    /// Queue a toast. Only the top `ICON_HEIGHT` rows of the icon are used.
+    /// Text that has to scroll keeps the toast up until it has gone by once,
+    /// even if that takes longer than `duration`.
+    pub fn show(&mut self, icon: Option<&Bitmap8>, text: &str, duration: Duration, priority: u8) {
+        let toast = Toast {
+            icon: icon.cloned(),
+            text: text.to_owned(),
+            duration,
+            priority,
+        };
+
+        // Behind everything of the same priority or higher
+        let index = self.queue.iter()
+            .position(|x| x.priority < priority)
+            .unwrap_or(self.queue.len());
+        self.queue.insert(index, toast);
+
+        self.tick();
+    }
+
+    /// Whether a toast is on the panel
+    pub fn is_active(&self) -> bool {
+        self.showing.is_some()
+    }
+
+    /// Toasts waiting behind the current one
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Drop the current toast and everything queued
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.showing = None;
+    }
+
+    /// Scroll the current toast, retire it when it's done and bring up the
+    /// next. Returns whether the output changed, so callers know whether
+    /// it's worth drawing.
+    pub fn tick(&mut self) -> bool {
+        let mut changed = false;
+
+        let interrupted = match (&self.showing, self.queue.front()) {
+            (Some(showing), Some(next)) => next.priority > showing.toast.priority,
+            _ => false,
+        };
+
+        if interrupted {
+            if let Some(showing) = self.showing.take() {
+                let index = self.queue.iter()
+                    .position(|x| x.priority <= showing.toast.priority)
+                    .unwrap_or(self.queue.len());
+                self.queue.insert(index, showing.toast);
+            }
+        }
+
+        if let Some(showing) = &mut self.showing {
+            if let Some(marquee) = &mut showing.marquee {
+                changed |= marquee.tick();
+            }
+
+            let scrolled = showing.marquee.as_ref().map(|x| x.is_finished()).unwrap_or(true);
+
+            if scrolled && self.clock.elapsed(showing.started) >= showing.toast.duration {
+                self.showing = None;
+                changed = true;
+            }
+        }
+
+        if self.showing.is_none() {
+            if let Some(toast) = self.queue.pop_front() {
+                self.showing = Some(self.start(toast));
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    /// The current toast if there is one, otherwise `content` as it is
+    pub fn compose(&self, content: &Bitmap8) -> Bitmap8 {
+        let mut frame = content.clone();
+        self.render(&mut frame);
+        frame
+    }
+
+    /// Draw the current toast over the whole canvas. Does nothing when there
+    /// isn't one.
+    pub fn render(&self, canvas: &mut Bitmap8) {
+        let showing = match &self.showing {
+            Some(x) => x,
+            None => return,
+        };
+
+        canvas.fill(0);
+
+        let top = match &showing.toast.icon {
+            Some(icon) => {
+                for (column, icon) in canvas.columns_mut().zip(icon.columns()) {
+                    column[.. ICON_HEIGHT].copy_from_slice(&icon[.. ICON_HEIGHT]);
+                }
+
+                ICON_HEIGHT + ICON_GAP
+            },
+            None => 0,
+        };
+
+        let mut text = Bitmap8::new();
+
+        match &showing.marquee {
+            Some(marquee) => marquee.render(&mut text),
+            None => {
+                let Size { width, height } = self.style.measure(&showing.toast.text);
+                let x = (DISPLAY_WIDTH as i32 - width as i32) / 2;
+                let y = top as i32 + (DISPLAY_HEIGHT as i32 - top as i32 - height as i32) / 2;
+
+                // Vertical lines are laid out from the right
+                let x = match self.style.orientation {
+                    Orientation::Vertical => x + width as i32 - self.style.font.height as i32,
+                    _ => x,
+                };
+
+                text.draw_text_styled((x, y), &showing.toast.text, &self.style);
+            },
+        }
+
+        // Scrolling text disappears under the icon rather than over it
+        let area = Rect::from_corners(Point::new(0, top as i32), Point::new(DISPLAY_WIDTH as i32, DISPLAY_HEIGHT as i32));
+
+        for x in area.columns() {
+            for y in area.rows() {
+                let index = x * DISPLAY_HEIGHT + y;
+                canvas.data[index] = text.data[index];
+            }
+        }
+    }
+
+    fn start(&self, toast: Toast) -> Showing<C> {
+        let room = DISPLAY_HEIGHT - if toast.icon.is_some() { ICON_HEIGHT + ICON_GAP } else { 0 };
+        let size = self.style.measure(&toast.text);
+
+        let fits = size.width <= DISPLAY_WIDTH && size.height <= room;
+
+        let marquee = (!fits).then(|| {
+            let mut marquee = Marquee::with_clock(&toast.text, self.clock.clone());
+            marquee.set_style(self.style);
+            marquee.set_looping(false);
+            marquee.tick();
+            marquee
+        });
+
+        Showing {
+            toast,
+            started: self.clock.now(),
+            marquee,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn lit(bitmap: &Bitmap8) -> usize {
+        bitmap.data().iter().filter(|x| **x != 0).count()
+    }
+
+    #[test]
+    fn toasts_expire_and_hand_back() {
+        let clock = ManualClock::new();
+        let mut toaster = Toaster::with_clock(clock.clone());
+
+        let mut content = Bitmap8::new();
+        content.fill(0x10);
+
+        toaster.show(None, "HI", Duration::from_secs(2), 0);
+        assert!(toaster.is_active());
+        assert_ne!(toaster.compose(&content).data(), content.data());
+
+        clock.advance(Duration::from_secs(2));
+        assert!(toaster.tick());
+        assert!(!toaster.is_active());
+        assert_eq!(toaster.compose(&content).data(), content.data());
+    }
+
+    #[test]
+    fn icon_sits_above_the_text() {
+        let clock = ManualClock::new();
+        let mut toaster = Toaster::with_clock(clock.clone());
+
+        let mut icon = Bitmap8::new();
+        icon.fill(0xff);
+        toaster.show(Some(&icon), "", Duration::from_secs(1), 0);
+
+        let frame = toaster.compose(&Bitmap8::new());
+        assert_eq!(lit(&frame), ICON_HEIGHT * DISPLAY_WIDTH);
+    }
+
+    #[test]
+    fn higher_priority_interrupts() {
+        let clock = ManualClock::new();
+        let mut toaster = Toaster::with_clock(clock.clone());
+
+        toaster.show(None, "A", Duration::from_secs(5), 0);
+        toaster.show(None, "B", Duration::from_secs(5), 0);
+        toaster.show(None, "C", Duration::from_secs(1), 9);
+        let urgent = toaster.compose(&Bitmap8::new());
+
+        // The interrupted toast is next, ahead of the one queued behind it
+        clock.advance(Duration::from_secs(1));
+        toaster.tick();
+        assert_eq!(toaster.pending(), 1);
+
+        let mut expected = Toaster::with_clock(clock.clone());
+        expected.show(None, "A", Duration::from_secs(5), 0);
+        assert_eq!(toaster.compose(&Bitmap8::new()).data(), expected.compose(&Bitmap8::new()).data());
+        assert_ne!(urgent.data(), expected.compose(&Bitmap8::new()).data());
+    }
+
+    #[test]
+    fn long_text_scrolls_before_expiring() {
+        let clock = ManualClock::new();
+        let mut toaster = Toaster::with_clock(clock.clone());
+
+        toaster.show(None, "A MUCH LONGER MESSAGE", Duration::from_millis(100), 0);
+        clock.advance(Duration::from_secs(1));
+        assert!(toaster.tick());
+        assert!(toaster.is_active());
+
+        clock.advance(Duration::from_secs(60));
+        toaster.tick();
+        assert!(!toaster.is_active());
+    }
+}
@@ -0,0 +1,41 @@
+//! `tracing` spans and events for the `tracing` feature. With it off these
+//! expand to nothing, so the rest of the crate can say what it's doing
+//! without a `cfg` at every turn. Fields only worth working out for a
+//! subscriber should be worked out inside the macro call, and anything kept
+//! for one, like when a command started, put behind the feature too.
+
+/// `event!(level, fields...)`, as `tracing::debug!(fields...)` and friends
+#[cfg(feature = "tracing")]
+macro_rules! event {
+    ($level:ident, $($field:tt)*) => {
+        tracing::$level!($($field)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! event {
+    ($level:ident, $($field:tt)*) => {};
+}
+
+/// `span!(LEVEL, name, fields...)`, entered until what it returns is
+/// dropped. The level is one of `tracing::Level`'s constants.
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    ($level:ident, $($field:tt)*) => {
+        tracing::span!(tracing::Level::$level, $($field)*).entered()
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    ($level:ident, $($field:tt)*) => {
+        $crate::trace::NoSpan
+    };
+}
+
+/// What `span!` gives with the feature off
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
+
+pub(crate) use event;
+pub(crate) use span;
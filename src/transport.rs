@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use serialport::SerialPort;
+
+use crate::response::RESPONSE_LENGTH;
+use crate::CONNECT_DELAY;
+
+/// Whatever bytes to and from the matrix go over. Normally a serial port,
+/// but `MockTransport` stands in for one in tests.
+pub trait Transport: Read + Write + Send {
+    /// Bytes waiting to be read
+    fn bytes_to_read(&self) -> Result<u32, Error>;
+
+    fn timeout(&self) -> Duration;
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), Error>;
+}
+
+impl Transport for Box<dyn SerialPort> {
+    fn bytes_to_read(&self) -> Result<u32, Error> {
+        Ok(SerialPort::bytes_to_read(self.as_ref())?)
+    }
+
+    fn timeout(&self) -> Duration {
+        SerialPort::timeout(self.as_ref())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), Error> {
+        Ok(SerialPort::set_timeout(self.as_mut(), timeout)?)
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    written: Vec<u8>,
+    /// Replies held back until the next write
+    replies: VecDeque<Vec<u8>>,
+    readable: VecDeque<u8>,
+    failures: VecDeque<ErrorKind>,
+    /// Most each of the next writes takes
+    limits: VecDeque<usize>,
+    timeout: Duration,
+}
+
+/// A pretend matrix that records every byte written to it and answers writes
+/// with replies queued up beforehand. Reads with nothing to read time out
+/// like a real port would.
+///
+/// Clones share everything, so keep one to look at after handing the other
+/// to `LedMatrix::with_transport()`. Reconnecting a matrix built on a mock
+/// just carries on with the same mock.
+#[derive(Clone)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MockState {
+                timeout: CONNECT_DELAY,
+                ..MockState::default()
+            })),
+        }
+    }
+
+    /// Everything written so far
+    pub fn written(&self) -> Vec<u8> {
+        self.state().written.clone()
+    }
+
+    /// Everything written since the last call, clearing the record
+    pub fn take_written(&self) -> Vec<u8> {
+        std::mem::take(&mut self.state().written)
+    }
+
+    /// Make bytes available to read straight away, like debug output the
+    /// firmware sent without being asked
+    pub fn push_bytes(&self, bytes: &[u8]) {
+        self.state().readable.extend(bytes);
+    }
+
+    /// Queue a reply from the firmware, padded to the full reply length.
+    /// It can be read once the next command has been written, the way the
+    /// firmware only answers what it's sent.
+    pub fn push_reply(&self, data: &[u8]) {
+        let mut reply = vec![0u8; RESPONSE_LENGTH];
+        let length = data.len().min(RESPONSE_LENGTH);
+        reply[..length].copy_from_slice(&data[..length]);
+
+        self.state().replies.push_back(reply);
+    }
+
+    /// Make the next write fail with `kind`. Queue several to fail several.
+    pub fn fail_next_write(&self, kind: ErrorKind) {
+        self.state().failures.push_back(kind);
+    }
+
+    /// Make the next write take only the first `bytes` of what it's given,
+    /// the way a busy port can. Queue several to shorten several. Writes
+    /// queued to fail wait until these are used up.
+    pub fn short_next_write(&self, bytes: usize) {
+        self.state().limits.push_back(bytes);
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().expect("Mock transport lock poisoned")
+    }
+}
+
+impl Default for MockTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Read for MockTransport {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let mut state = self.state();
+
+        if state.readable.is_empty() && !buffer.is_empty() {
+            return Err(Error::new(ErrorKind::TimedOut, "Nothing to read"));
+        }
+
+        let count = buffer.len().min(state.readable.len());
+
+        for (byte, reply) in buffer.iter_mut().zip(state.readable.drain(..count)) {
+            *byte = reply;
+        }
+
+        Ok(count)
+    }
+}
+
+impl Write for MockTransport {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize, Error> {
+        let mut state = self.state();
+
+        // Shortened writes go first, so a failure can come partway through
+        let length = match state.limits.pop_front() {
+            Some(limit) => limit.min(buffer.len()),
+            None => match state.failures.pop_front() {
+                Some(kind) => return Err(Error::new(kind, "Mock write failure")),
+                None => buffer.len(),
+            },
+        };
+
+        state.written.extend_from_slice(&buffer[..length]);
+
+        if let Some(reply) = state.replies.pop_front() {
+            state.readable.extend(reply);
+        }
+
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Transport for MockTransport {
+    fn bytes_to_read(&self) -> Result<u32, Error> {
+        Ok(self.state().readable.len() as u32)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.state().timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), Error> {
+        self.state().timeout = timeout;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_records_and_replies() {
+        let mut mock = MockTransport::new();
+        let other = mock.clone();
+
+        mock.write_all(&[1, 2, 3]).unwrap();
+        assert_eq!(other.take_written(), [1, 2, 3]);
+        assert!(other.written().is_empty());
+
+        other.push_reply(&[9]);
+        let mut reply = [0u8; RESPONSE_LENGTH];
+        assert_eq!(mock.read(&mut reply).unwrap_err().kind(), ErrorKind::TimedOut);
+
+        mock.write_all(&[4]).unwrap();
+        mock.read_exact(&mut reply).unwrap();
+        assert_eq!(reply[0], 9);
+
+        assert_eq!(mock.read(&mut reply).unwrap_err().kind(), ErrorKind::TimedOut);
+
+        other.fail_next_write(ErrorKind::BrokenPipe);
+        assert_eq!(mock.write(&[1]).unwrap_err().kind(), ErrorKind::BrokenPipe);
+        assert_eq!(mock.write(&[1]).unwrap(), 1);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::pair::PairGeometry;
+use crate::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Physical spacing of the LEDs. The defaults are approximate figures for
+/// the Framework 16 module; measure your own hardware if you need better.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pitch {
+    /// Centre to centre distance between columns
+    pub x_mm: f32,
+    /// Centre to centre distance between rows
+    pub y_mm: f32,
+    /// Distance between the last column of the left module and the first
+    /// column of the right one, less one pitch
+    pub gap_mm: f32,
+}
+
+impl Pitch {
+    pub const FRAMEWORK: Pitch = Pitch {
+        x_mm: 1.8,
+        y_mm: 1.8,
+        gap_mm: 3.6,
+    };
+
+    /// Panel width in millimetres, first LED centre to last
+    pub fn panel_width_mm(&self) -> f32 {
+        (DISPLAY_WIDTH - 1) as f32 * self.x_mm
+    }
+
+    pub fn panel_height_mm(&self) -> f32 {
+        (DISPLAY_HEIGHT - 1) as f32 * self.y_mm
+    }
+
+    /// How many missing columns the bezel is worth
+    pub fn gap_columns(&self) -> usize {
+        (self.gap_mm / self.x_mm).round() as usize
+    }
+
+    /// Pair geometry matching this pitch
+    pub fn pair_geometry(&self) -> PairGeometry {
+        PairGeometry::new(self.gap_columns())
+    }
+}
+
+impl Default for Pitch {
+    fn default() -> Self {
+        Self::FRAMEWORK
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+/// A size along one axis, in whatever units are most natural for it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Length {
+    Pixels(usize),
+    Millimetres(f32),
+    /// Share of the space available, so `Fraction(0.5)` is half the panel
+    Fraction(f32),
+}
+
+impl Length {
+    /// Convert to whole pixels, never more than `available`
+    pub fn resolve(&self, axis: Axis, available: usize, pitch: &Pitch) -> usize {
+        let pixels = match *self {
+            Self::Pixels(x) => x,
+            Self::Millimetres(mm) => {
+                let per_pixel = match axis {
+                    Axis::Horizontal => pitch.x_mm,
+                    Axis::Vertical => pitch.y_mm,
+                };
+                (mm.max(0.0) / per_pixel).round() as usize
+            },
+            Self::Fraction(fraction) => (fraction.clamp(0.0, 1.0) * available as f32).round() as usize,
+        };
+
+        pixels.min(available)
+    }
+}
+
+impl From<usize> for Length {
+    fn from(value: usize) -> Self {
+        Self::Pixels(value)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn millimetres_round_to_pixels() {
+        let pitch = Pitch { x_mm: 2.0, y_mm: 2.0, gap_mm: 4.0 };
+
+        assert_eq!(Length::Millimetres(10.0).resolve(Axis::Vertical, DISPLAY_HEIGHT, &pitch), 5);
+        assert_eq!(Length::Millimetres(100.0).resolve(Axis::Vertical, DISPLAY_HEIGHT, &pitch), DISPLAY_HEIGHT);
+        assert_eq!(pitch.gap_columns(), 2);
+    }
+
+    #[test]
+    fn fractions_of_the_panel() {
+        let pitch = Pitch::default();
+
+        assert_eq!(Length::Fraction(0.5).resolve(Axis::Vertical, DISPLAY_HEIGHT, &pitch), 17);
+        assert_eq!(Length::Fraction(2.0).resolve(Axis::Horizontal, DISPLAY_WIDTH, &pitch), DISPLAY_WIDTH);
+    }
+}
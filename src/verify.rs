@@ -0,0 +1,53 @@
+use std::time::{Duration, Instant};
+
+/// Whether `LedMatrix` checks the module is still answering after sending
+/// it something. Commands that set things get no reply, so a module that
+/// locked up after a USB hiccup looks no different to one that's working,
+/// and everything written to it goes nowhere. Checking sends a `Version`
+/// query, which the firmware answers straight away.
+///
+/// A module that doesn't answer within the connect timeout is counted as
+/// stalled: the send fails with `ErrorKind::TimedOut`, `on_disconnect`
+/// fires and the port is closed, so the next command reconnects as the
+/// reconnect policy says instead of writing into the void.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Verification {
+    /// Trust every write, as a plain serial port would
+    #[default]
+    Off,
+    /// Check after every send. A frame is ten sends, so this costs ten
+    /// round trips a frame.
+    EveryCommand,
+    /// Check after a send once this long has gone by since the last check
+    Interval(Duration),
+}
+
+impl Verification {
+    /// Whether a check is due after a send at `now`, the last having been
+    /// at `last`
+    pub(crate) fn due(&self, last: Option<Instant>, now: Instant) -> bool {
+        match (self, last) {
+            (Self::Off, _) => false,
+            (Self::EveryCommand, _) | (Self::Interval(_), None) => true,
+            (Self::Interval(interval), Some(last)) => now.saturating_duration_since(last) >= *interval,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_fall_due() {
+        let now = Instant::now();
+        let interval = Verification::Interval(Duration::from_secs(1));
+
+        assert!(!Verification::Off.due(None, now));
+        assert!(Verification::EveryCommand.due(Some(now), now));
+        assert!(interval.due(None, now));
+        assert!(!interval.due(Some(now), now + Duration::from_millis(500)));
+        assert!(interval.due(Some(now), now + Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,285 @@
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::filter::FrameFilter;
+use crate::geometry::Point;
+use crate::{Bitmap8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// How much each LED has been used: the time it's been lit multiplied by how
+/// brightly, in "value seconds". A pixel at 0xff for one second scores 255.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WearMap {
+    exposure: Vec<f64>,
+}
+
+impl WearMap {
+    pub fn new() -> Self {
+        Self {
+            exposure: vec![0.0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+        }
+    }
+
+    /// Count `frame` as having been up for `duration`
+    pub fn add(&mut self, frame: &Bitmap8, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+
+        for (exposure, value) in self.exposure.iter_mut().zip(frame.data()) {
+            *exposure += *value as f64 * seconds;
+        }
+    }
+
+    /// Value seconds for one pixel, `None` off the panel
+    pub fn exposure(&self, point: Point) -> Option<f64> {
+        let (x, y) = point.on_panel()?;
+
+        Some(self.exposure[x * DISPLAY_HEIGHT + y])
+    }
+
+    /// How long the pixel would have had to be fully on to wear this much
+    pub fn full_brightness_time(&self, point: Point) -> Option<Duration> {
+        self.exposure(point).map(|x| Duration::from_secs_f64(x / 255.0))
+    }
+
+    /// The most worn pixel and how worn it is
+    pub fn most_worn(&self) -> (Point, f64) {
+        let (index, exposure) = self.exposure.iter()
+            .enumerate()
+            .fold((0, 0.0), |best, (index, x)| if *x > best.1 { (index, *x) } else { best });
+
+        let point = Point::new((index / DISPLAY_HEIGHT) as i32, (index % DISPLAY_HEIGHT) as i32);
+
+        (point, exposure)
+    }
+
+    /// Wear scaled so the most worn pixel is 0xff, for showing on the panel
+    /// itself or anywhere else
+    pub fn heatmap(&self) -> Bitmap8 {
+        let (_, most) = self.most_worn();
+        let mut bitmap = Bitmap8::new();
+
+        if most > 0.0 {
+            for (value, exposure) in bitmap.data.iter_mut().zip(&self.exposure) {
+                *value = (exposure / most * 255.0).round() as u8;
+            }
+        }
+
+        bitmap
+    }
+
+    /// Load from a file. A missing file is a fresh map.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => contents.parse(),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::new()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Write to a file, creating parent directories as needed
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(path, self.to_string())
+    }
+}
+
+impl Default for WearMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One line per row of the panel, value seconds for each column
+impl fmt::Display for WearMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# LED matrix wear, value seconds per pixel, one row per line")?;
+
+        for y in 0 .. DISPLAY_HEIGHT {
+            let row: Vec<_> = (0 .. DISPLAY_WIDTH)
+                .map(|x| format!("{:.1}", self.exposure[x * DISPLAY_HEIGHT + y]))
+                .collect();
+
+            writeln!(f, "{}", row.join(" "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for WearMap {
+    type Err = Error;
+
+    fn from_str(contents: &str) -> Result<Self, Self::Err> {
+        let mut map = Self::new();
+        let rows = contents.lines()
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty() && !x.starts_with('#'));
+        let mut count = 0;
+
+        for (y, line) in rows.enumerate() {
+            let invalid = |message: &str| Error::new(ErrorKind::InvalidData, format!("Row {}: {}", y + 1, message));
+
+            if y >= DISPLAY_HEIGHT {
+                return Err(invalid("too many rows"));
+            }
+
+            let values = line.split_whitespace()
+                .map(|x| x.parse::<f64>().map_err(|_| invalid("expected numbers")))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if values.len() != DISPLAY_WIDTH {
+                return Err(invalid("wrong number of columns"));
+            }
+
+            for (x, value) in values.into_iter().enumerate() {
+                map.exposure[x * DISPLAY_HEIGHT + y] = value;
+            }
+
+            count += 1;
+        }
+
+        if count != DISPLAY_HEIGHT {
+            return Err(Error::new(ErrorKind::InvalidData, "Wrong number of rows"));
+        }
+
+        Ok(map)
+    }
+}
+
+struct TrackerState {
+    map: WearMap,
+    /// The frame on the panel and when it went up
+    showing: Option<(Bitmap8, Instant)>,
+}
+
+/// Keeps a `WearMap` up to date from the frames a matrix draws. Add a clone
+/// to the end of the matrix's filters and keep the other to read the map or
+/// save it now and then.
+///
+/// Each frame is counted once the next one replaces it, so the last frame
+/// only counts up to the last time anything was drawn or `map()` was read.
+#[derive(Clone)]
+pub struct WearTracker<C: Clock = SystemClock> {
+    state: Arc<Mutex<TrackerState>>,
+    clock: C,
+}
+
+impl WearTracker<SystemClock> {
+    pub fn new() -> Self {
+        Self::with_clock(WearMap::new(), SystemClock)
+    }
+
+    /// Carry on from a map saved earlier. A missing file starts afresh.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(Self::with_clock(WearMap::load(path)?, SystemClock))
+    }
+}
+
+impl Default for WearTracker<SystemClock> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clock> WearTracker<C> {
+    pub fn with_clock(map: WearMap, clock: C) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(TrackerState { map, showing: None })),
+            clock,
+        }
+    }
+
+    /// Wear so far, including the frame that's up now
+    pub fn map(&self) -> WearMap {
+        let mut state = self.state();
+        self.catch_up(&mut state);
+
+        state.map.clone()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        self.map().save(path)
+    }
+
+    /// Start counting from zero
+    pub fn reset(&self) {
+        let mut state = self.state();
+
+        state.map = WearMap::new();
+        if let Some((_, since)) = &mut state.showing {
+            *since = self.clock.now();
+        }
+    }
+
+    /// Count the frame on the panel up to now
+    fn catch_up(&self, state: &mut TrackerState) {
+        let now = self.clock.now();
+
+        if let Some((frame, since)) = &mut state.showing {
+            state.map.add(frame, now.saturating_duration_since(*since));
+            *since = now;
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, TrackerState> {
+        self.state.lock().expect("Wear tracker lock poisoned")
+    }
+}
+
+impl<C: Clock> FrameFilter for WearTracker<C> {
+    fn apply(&mut self, frame: &mut Bitmap8) {
+        let mut state = self.state();
+        self.catch_up(&mut state);
+
+        state.showing = Some((frame.clone(), self.clock.now()));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn exposure_accumulates() {
+        let clock = ManualClock::new();
+        let tracker = WearTracker::with_clock(WearMap::new(), clock.clone());
+        let mut filter = tracker.clone();
+
+        let mut frame = Bitmap8::new();
+        frame.set_pixel(Point::new(1, 2), 0xff);
+        frame.set_pixel(Point::new(3, 4), 0x80);
+        filter.apply(&mut frame);
+
+        clock.advance(Duration::from_secs(10));
+        filter.apply(&mut Bitmap8::new());
+        clock.advance(Duration::from_secs(10));
+
+        let map = tracker.map();
+        assert_eq!(map.exposure(Point::new(1, 2)), Some(2550.0));
+        assert_eq!(map.full_brightness_time(Point::new(1, 2)), Some(Duration::from_secs(10)));
+        assert_eq!(map.most_worn().0, Point::new(1, 2));
+        assert_eq!(map.heatmap().pixel(Point::new(3, 4)), Some(0x80));
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut map = WearMap::new();
+        let mut frame = Bitmap8::new();
+        frame.set_pixel(Point::new(8, 33), 10);
+        map.add(&frame, Duration::from_millis(1500));
+
+        let parsed: WearMap = map.to_string().parse().unwrap();
+
+        assert_eq!(parsed, map);
+        assert!("1 2 3".parse::<WearMap>().is_err());
+    }
+}
@@ -0,0 +1,994 @@
+use std::collections::VecDeque;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::geometry::{Point, Rect};
+use crate::text::{Alignment, Orientation, TextStyle, WrapMode, FONT_3X5};
+use crate::{Bitmap8, DISPLAY_HEIGHT};
+
+/// Where Linux lists batteries, see `BatteryState::read()`
+pub const POWER_SUPPLY_DIRECTORY: &str = "/sys/class/power_supply";
+
+/// Something that draws itself into an area of a frame. Widgets usually hold
+/// a `binding::Watch` for their data and report dirty when it changes.
+pub trait Widget {
+    /// Draw into `area` of `canvas`. The area has already been cleared to
+    /// the layout's background and the widget shouldn't touch anything
+    /// outside it.
+    fn render(&mut self, canvas: &mut Bitmap8, area: Rect);
+
+    /// Whether the widget needs to be drawn again. Called once per frame, so
+    /// it's fine for this to consume a change notification.
+    fn is_dirty(&mut self) -> bool;
+
+    /// How often the widget wants `update()` called, for widgets that go
+    /// and fetch what they show. A clock might want a second, a weather
+    /// forecast ten minutes. `None` for widgets that only change when told.
+    fn update_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Refresh whatever the widget shows. The layout calls this every
+    /// `update_interval()`, and the widget should report itself dirty
+    /// afterwards if anything changed.
+    fn update(&mut self) {}
+}
+
+/// Which way the bars of a `BarGraph` grow
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BarOrientation {
+    /// From the bottom up, side by side
+    #[default]
+    Vertical,
+    /// From the left, stacked top to bottom
+    Horizontal,
+}
+
+/// Where a `BarGraph` draws a border
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BarBorder {
+    #[default]
+    None,
+    /// All four sides
+    Around,
+    /// Only across the ends of the bars, top and bottom for vertical bars,
+    /// leaving every column free for bars on a panel as narrow as this one
+    Ends,
+}
+
+/// A row of bars, like a CPU meter or a VU meter
+///
+/// ```
+/// use f16_hid::widgets::{BarBorder, BarGraph};
+///
+/// // Eight cores with an empty column between each set of four
+/// let mut graph = BarGraph::new(8)
+///     .border(BarBorder::Ends, 0)
+///     .divider(Some(4));
+/// graph.set_values(&[12.0, 50.0, 100.0, 0.0, 3.0, 80.0, 7.0, 64.0]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct BarGraph {
+    values: Vec<f64>,
+    orientation: BarOrientation,
+    range: (f64, f64),
+    foreground: u8,
+    background: u8,
+    border: BarBorder,
+    border_value: u8,
+    divider: Option<usize>,
+    dirty: bool,
+}
+
+impl BarGraph {
+    /// `bars` bars from 0 to 100, growing up, no border
+    pub fn new(bars: usize) -> Self {
+        Self {
+            values: vec![0.0; bars],
+            orientation: BarOrientation::Vertical,
+            range: (0.0, 100.0),
+            foreground: 0xff,
+            background: 0,
+            border: BarBorder::None,
+            border_value: 0,
+            divider: None,
+            dirty: true,
+        }
+    }
+
+    pub fn orientation(mut self, orientation: BarOrientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    /// Values for an empty and a full bar. Anything outside is clamped.
+    pub fn range(mut self, empty: f64, full: f64) -> Self {
+        self.range = (empty, full);
+        self
+    }
+
+    pub fn foreground(mut self, value: u8) -> Self {
+        self.foreground = value;
+        self
+    }
+
+    pub fn background(mut self, value: u8) -> Self {
+        self.background = value;
+        self
+    }
+
+    pub fn border(mut self, border: BarBorder, value: u8) -> Self {
+        self.border = border;
+        self.border_value = value;
+        self
+    }
+
+    /// Leave a slot before bar `index` drawn in the border value, splitting
+    /// the bars into two groups
+    pub fn divider(mut self, index: Option<usize>) -> Self {
+        self.divider = index;
+        self
+    }
+
+    pub fn bars(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// Change how many bars there are. New bars start empty.
+    pub fn set_bars(&mut self, bars: usize) {
+        if bars != self.values.len() {
+            self.values.resize(bars, self.range.0);
+            self.dirty = true;
+        }
+    }
+
+    /// Set every bar at once. Extra values are ignored and missing ones
+    /// leave their bars empty.
+    pub fn set_values(&mut self, values: &[f64]) {
+        for (index, bar) in self.values.iter_mut().enumerate() {
+            *bar = values.get(index).copied().unwrap_or(self.range.0);
+        }
+
+        self.dirty = true;
+    }
+
+    pub fn set_value(&mut self, index: usize, value: f64) {
+        if let Some(bar) = self.values.get_mut(index) {
+            *bar = value;
+            self.dirty = true;
+        }
+    }
+
+    /// How much of a bar's length a value fills, from 0 to 1
+    fn fraction(&self, value: f64) -> f64 {
+        let (empty, full) = self.range;
+
+        if full == empty {
+            return 0.0;
+        }
+
+        ((value - empty) / (full - empty)).clamp(0.0, 1.0)
+    }
+}
+
+impl Widget for BarGraph {
+    fn render(&mut self, canvas: &mut Bitmap8, area: Rect) {
+        let vertical = self.orientation == BarOrientation::Vertical;
+
+        let (inset_x, inset_y) = match (self.border, vertical) {
+            (BarBorder::None, _) => (0, 0),
+            (BarBorder::Around, _) => (1, 1),
+            (BarBorder::Ends, true) => (0, 1),
+            (BarBorder::Ends, false) => (1, 0),
+        };
+
+        let inside = Rect::from_corners(
+            Point::new(area.left() + inset_x, area.top() + inset_y),
+            Point::new(area.right() - inset_x, area.bottom() - inset_y),
+        );
+
+        canvas.fill_rect(area, self.border_value);
+        canvas.fill_rect(inside, self.background);
+
+        let (across, along) = match vertical {
+            true => (inside.size.width, inside.size.height),
+            false => (inside.size.height, inside.size.width),
+        };
+
+        let slots = self.values.len() + self.divider.is_some() as usize;
+
+        if slots == 0 {
+            return;
+        }
+
+        // Whatever doesn't divide evenly is left as background at the end
+        let thickness = across / slots;
+        let mut slot = 0;
+
+        for (index, value) in self.values.iter().enumerate() {
+            let place = |slot: usize, length: usize| {
+                let start = (slot * thickness) as i32;
+
+                match vertical {
+                    true => Rect::new((inside.left() + start, inside.bottom() - length as i32), (thickness, length)),
+                    false => Rect::new((inside.left(), inside.top() + start), (length, thickness)),
+                }
+            };
+
+            if self.divider == Some(index) {
+                canvas.fill_rect(place(slot, along), self.border_value);
+                slot += 1;
+            }
+
+            let length = (along as f64 * self.fraction(*value)).round() as usize;
+            canvas.fill_rect(place(slot, length), self.foreground);
+            slot += 1;
+        }
+    }
+
+    fn is_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+}
+
+
+/// How a `HistoryGraph` draws each sample
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HistoryStyle {
+    /// A bar from the left as long as the sample
+    #[default]
+    Bars,
+    /// A single pixel where the bar would end, like a sparkline
+    Line,
+}
+
+/// Recent samples as a waterfall, one row each, the newest at the top and
+/// the rest scrolling down as more arrive. Network throughput, temperature
+/// and the like get a history rather than only what they are right now.
+///
+/// ```
+/// use f16_hid::widgets::HistoryGraph;
+///
+/// // Scaled to the busiest second on screen
+/// let mut graph = HistoryGraph::new().auto_range(true);
+///
+/// for kilobytes in [120.0, 80.0, 950.0, 400.0] {
+///     graph.push(kilobytes);
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct HistoryGraph {
+    /// Newest first
+    samples: VecDeque<f64>,
+    capacity: usize,
+    range: (f64, f64),
+    auto_range: bool,
+    style: HistoryStyle,
+    newest_at_bottom: bool,
+    foreground: u8,
+    background: u8,
+    dirty: bool,
+}
+
+impl Default for HistoryGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HistoryGraph {
+    /// As many samples as the panel is tall, from 0 to 100, drawn as bars
+    pub fn new() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(DISPLAY_HEIGHT),
+            capacity: DISPLAY_HEIGHT,
+            range: (0.0, 100.0),
+            auto_range: false,
+            style: HistoryStyle::Bars,
+            newest_at_bottom: false,
+            foreground: 0xff,
+            background: 0,
+            dirty: true,
+        }
+    }
+
+    /// How many samples are kept. Only as many as the area has rows are
+    /// drawn, but keeping more lets a taller area show them later.
+    pub fn capacity(mut self, samples: usize) -> Self {
+        self.capacity = samples;
+        self.samples.truncate(samples);
+        self
+    }
+
+    /// Values for an empty and a full row. Anything outside is clamped.
+    pub fn range(mut self, empty: f64, full: f64) -> Self {
+        self.range = (empty, full);
+        self
+    }
+
+    /// Scale to the largest sample kept instead of the top of the range.
+    /// The bottom of the range still counts as empty.
+    pub fn auto_range(mut self, enabled: bool) -> Self {
+        self.auto_range = enabled;
+        self
+    }
+
+    pub fn style(mut self, style: HistoryStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Have the newest sample come in at the bottom and scroll up instead
+    pub fn newest_at_bottom(mut self, enabled: bool) -> Self {
+        self.newest_at_bottom = enabled;
+        self
+    }
+
+    pub fn foreground(mut self, value: u8) -> Self {
+        self.foreground = value;
+        self
+    }
+
+    pub fn background(mut self, value: u8) -> Self {
+        self.background = value;
+        self
+    }
+
+    /// Add the newest sample, dropping the oldest if it's full
+    pub fn push(&mut self, value: f64) {
+        self.samples.push_front(value);
+        self.samples.truncate(self.capacity);
+        self.dirty = true;
+    }
+
+    /// Newest first
+    pub fn samples(&self) -> impl Iterator<Item = f64> + '_ {
+        self.samples.iter().copied()
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.dirty = true;
+    }
+
+    /// How much of a row a value fills, from 0 to 1
+    fn fraction(&self, value: f64) -> f64 {
+        let (empty, full) = match self.auto_range {
+            true => (self.range.0, self.samples().fold(self.range.0, f64::max)),
+            false => self.range,
+        };
+
+        if full == empty {
+            return 0.0;
+        }
+
+        ((value - empty) / (full - empty)).clamp(0.0, 1.0)
+    }
+}
+
+impl Widget for HistoryGraph {
+    fn render(&mut self, canvas: &mut Bitmap8, area: Rect) {
+        canvas.fill_rect(area, self.background);
+
+        let width = area.size.width;
+        if width == 0 {
+            return;
+        }
+
+        for (row, value) in self.samples().take(area.size.height).enumerate() {
+            let y = match self.newest_at_bottom {
+                true => area.bottom() - 1 - row as i32,
+                false => area.top() + row as i32,
+            };
+
+            let fraction = self.fraction(value);
+
+            match self.style {
+                HistoryStyle::Bars => {
+                    let length = (width as f64 * fraction).round() as usize;
+                    canvas.fill_rect(Rect::new((area.left(), y), (length, 1)), self.foreground);
+                },
+                HistoryStyle::Line => {
+                    let x = ((width - 1) as f64 * fraction).round() as i32;
+                    canvas.set_pixel(Point::new(area.left() + x, y), self.foreground);
+                },
+            }
+        }
+    }
+
+    fn is_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+}
+
+
+/// Hours and minutes stacked one above the other, which is the only way
+/// four digits fit across the panel. The dots between them blink every
+/// second unless told not to.
+///
+/// The time comes from the system clock at a fixed offset from UTC, since
+/// the standard library knows nothing of time zones. Pass a source from
+/// `chrono` or similar to follow the local zone, daylight saving and all.
+pub struct DigitalClock {
+    source: Box<dyn FnMut() -> (u8, u8, u8)>,
+    twelve_hour: bool,
+    blink: bool,
+    value: u8,
+    /// Hours, minutes and whether the dots are lit, as last drawn
+    shown: Option<(u8, u8, bool)>,
+    dirty: bool,
+}
+
+impl DigitalClock {
+    /// UTC plus `offset_minutes`, 24 hour
+    pub fn new(offset_minutes: i32) -> Self {
+        Self::with_source(move || {
+            let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |x| x.as_secs() as i64);
+            let local = (seconds + offset_minutes as i64 * 60).rem_euclid(24 * 60 * 60);
+
+            ((local / 3600) as u8, (local / 60 % 60) as u8, (local % 60) as u8)
+        })
+    }
+
+    /// Take the hour, minute and second from somewhere else
+    pub fn with_source(source: impl FnMut() -> (u8, u8, u8) + 'static) -> Self {
+        let mut clock = Self {
+            source: Box::new(source),
+            twelve_hour: false,
+            blink: true,
+            value: 0xff,
+            shown: None,
+            dirty: true,
+        };
+
+        clock.update();
+        clock
+    }
+
+    pub fn twelve_hour(mut self, twelve_hour: bool) -> Self {
+        self.twelve_hour = twelve_hour;
+        self.shown = None;
+        self.update();
+        self
+    }
+
+    pub fn blink(mut self, blink: bool) -> Self {
+        self.blink = blink;
+        self.update();
+        self
+    }
+
+    pub fn value(mut self, value: u8) -> Self {
+        self.value = value;
+        self
+    }
+}
+
+impl Widget for DigitalClock {
+    fn render(&mut self, canvas: &mut Bitmap8, area: Rect) {
+        let (hours, minutes, dots) = match self.shown {
+            Some(x) => x,
+            None => return,
+        };
+
+        let style = TextStyle::new(&FONT_3X5, self.value);
+        let digits = style.measure("00");
+        // Two rows of digits with a row of dots between, a blank row apart
+        let height = 2 * digits.height + 3;
+
+        let x = area.left() + (area.size.width as i32 - digits.width as i32) / 2;
+        let y = area.top() + (area.size.height as i32 - height as i32) / 2;
+
+        canvas.draw_text_styled((x, y), &format!("{:02}", hours), &style);
+        canvas.draw_text_styled((x, y + digits.height as i32 + 3), &format!("{:02}", minutes), &style);
+
+        if dots {
+            let middle = y + digits.height as i32 + 1;
+            canvas.set_pixel(Point::new(x + 1, middle), self.value);
+            canvas.set_pixel(Point::new(x + digits.width as i32 - 2, middle), self.value);
+        }
+    }
+
+    fn is_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    fn update_interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(1))
+    }
+
+    fn update(&mut self) {
+        let (hours, minutes, seconds) = (self.source)();
+
+        let hours = match (self.twelve_hour, hours % 12) {
+            (true, 0) => 12,
+            (true, x) => x,
+            (false, _) => hours,
+        };
+
+        let shown = Some((hours, minutes, !self.blink || seconds % 2 == 0));
+
+        if shown != self.shown {
+            self.shown = shown;
+            self.dirty = true;
+        }
+    }
+}
+
+/// Charge as a fraction from 0 to 1, and whether it's going up
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BatteryState {
+    pub level: f64,
+    pub charging: bool,
+}
+
+impl BatteryState {
+    /// The first battery the OS lists. Only Linux for now, anywhere else
+    /// this is always `None` and the widget has to be told.
+    pub fn read() -> Option<Self> {
+        Self::read_from(Path::new(POWER_SUPPLY_DIRECTORY))
+    }
+
+    /// Like `read()` from a different `power_supply` directory
+    pub fn read_from(directory: &Path) -> Option<Self> {
+        let mut entries: Vec<_> = std::fs::read_dir(directory).ok()?
+            .filter_map(|x| x.ok())
+            .map(|x| x.path())
+            .collect();
+        entries.sort();
+
+        entries.into_iter().find_map(|path| {
+            let read = |name: &str| std::fs::read_to_string(path.join(name)).ok();
+
+            if read("type")?.trim() != "Battery" {
+                return None;
+            }
+
+            let capacity: f64 = read("capacity")?.trim().parse().ok()?;
+            let charging = read("status").is_some_and(|x| x.trim() == "Charging");
+
+            Some(Self { level: (capacity / 100.0).clamp(0.0, 1.0), charging })
+        })
+    }
+}
+
+/// A battery standing on end, filled to the charge level. While charging
+/// the empty part glows faintly.
+pub struct Battery {
+    source: Option<Box<dyn FnMut() -> Option<BatteryState>>>,
+    state: Option<BatteryState>,
+    interval: Duration,
+    value: u8,
+    dirty: bool,
+}
+
+impl Battery {
+    /// Reads the laptop's own battery every 30 seconds
+    pub fn new() -> Self {
+        Self::with_source(BatteryState::read)
+    }
+
+    pub fn with_source(source: impl FnMut() -> Option<BatteryState> + 'static) -> Self {
+        let mut battery = Self {
+            source: Some(Box::new(source)),
+            state: None,
+            interval: Duration::from_secs(30),
+            value: 0xff,
+            dirty: true,
+        };
+
+        battery.update();
+        battery
+    }
+
+    /// No source of its own, only what's passed to `set_state()`
+    pub fn manual() -> Self {
+        Self {
+            source: None,
+            state: None,
+            interval: Duration::from_secs(30),
+            value: 0xff,
+            dirty: true,
+        }
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn value(mut self, value: u8) -> Self {
+        self.value = value;
+        self
+    }
+
+    pub fn state(&self) -> Option<BatteryState> {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: Option<BatteryState>) {
+        if state != self.state {
+            self.state = state;
+            self.dirty = true;
+        }
+    }
+}
+
+impl Default for Battery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Widget for Battery {
+    fn render(&mut self, canvas: &mut Bitmap8, area: Rect) {
+        if area.size.width < 3 || area.size.height < 4 {
+            return;
+        }
+
+        // A terminal a third of the width on top, the body below it
+        let nub_width = (area.size.width / 3).max(1);
+        let nub = Rect::new((area.left() + ((area.size.width - nub_width) / 2) as i32, area.top()), (nub_width, 1));
+        let body = Rect::new((area.left(), area.top() + 1), (area.size.width, area.size.height - 1));
+        let inside = Rect::from_corners(
+            Point::new(body.left() + 1, body.top() + 1),
+            Point::new(body.right() - 1, body.bottom() - 1),
+        );
+
+        canvas.fill_rect(nub, self.value);
+        canvas.fill_rect(body, self.value);
+        canvas.fill_rect(inside, 0);
+
+        let state = match self.state {
+            Some(x) => x,
+            None => return,
+        };
+
+        if state.charging {
+            canvas.fill_rect(inside, self.value / 8);
+        }
+
+        let filled = (inside.size.height as f64 * state.level).round() as usize;
+        canvas.fill_rect(Rect::new((inside.left(), inside.bottom() - filled as i32), (inside.size.width, filled)), self.value);
+    }
+
+    fn is_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    fn update_interval(&self) -> Option<Duration> {
+        self.source.as_ref().map(|_| self.interval)
+    }
+
+    fn update(&mut self) {
+        if let Some(source) = &mut self.source {
+            let state = source();
+            self.set_state(state);
+        }
+    }
+}
+
+/// A block of text wrapped to the width of its area, lines running down
+/// the panel. Two characters of the small font fit across it, so anything
+/// much longer than a word or two runs past the bottom, and is scrolled
+/// with `set_scroll()` or a page at a time with `set_page()`. Text is set
+/// upright whatever the style's orientation says.
+///
+/// ```
+/// use f16_hid::widgets::TextBox;
+///
+/// let mut text = TextBox::new("GO TO BED NOW");
+///
+/// // Six lines, one more than fits on the panel
+/// assert_eq!(text.lines(9), ["GO", "TO", "BE", "D", "NO", "W"]);
+/// assert_eq!(text.height(9), 35);
+/// assert_eq!(text.pages(9, 34), 2);
+/// text.set_page(1, 34);
+/// ```
+pub struct TextBox {
+    text: String,
+    style: TextStyle<'static>,
+    alignment: Alignment,
+    /// Rows of pixels scrolled off the top
+    scroll: usize,
+    dirty: bool,
+}
+
+impl TextBox {
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.to_owned(),
+            style: TextStyle::default(),
+            alignment: Alignment::Left,
+            scroll: 0,
+            dirty: true,
+        }
+    }
+
+    pub fn style(mut self, style: TextStyle<'static>) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Show something else, scrolled back to the top
+    pub fn set_text(&mut self, text: &str) {
+        if text != self.text {
+            self.text = text.to_owned();
+            self.scroll = 0;
+            self.dirty = true;
+        }
+    }
+
+    /// The text broken into lines that fit `width` pixels
+    pub fn lines(&self, width: usize) -> Vec<String> {
+        let style = self.upright();
+        let step = style.font.height + style.spacing;
+
+        // Room for a line per character, which is as many as wrapping makes
+        let rows = self.text.chars().count() + 1;
+
+        style.wrap(&self.text, (width, rows * step).into(), WrapMode::Word)
+    }
+
+    /// Rows of pixels the text takes up laid out `width` pixels wide
+    pub fn height(&self, width: usize) -> usize {
+        let style = self.upright();
+        let lines = self.lines(width).len();
+
+        (lines * (style.font.height + style.spacing)).saturating_sub(style.spacing)
+    }
+
+    /// How many `height` pixel pages the text fills laid out `width`
+    /// pixels wide, at least one. Pages break between lines, so none
+    /// starts with half a line.
+    pub fn pages(&self, width: usize, height: usize) -> usize {
+        let per_page = self.lines_per_page(height);
+
+        self.lines(width).len().div_ceil(per_page).max(1)
+    }
+
+    /// Scroll to the start of page `page`, counting from zero
+    pub fn set_page(&mut self, page: usize, height: usize) {
+        let step = self.style.font.height + self.style.spacing;
+
+        self.set_scroll(page * self.lines_per_page(height) * step);
+    }
+
+    pub fn scroll(&self) -> usize {
+        self.scroll
+    }
+
+    /// Rows of pixels to leave off the top
+    pub fn set_scroll(&mut self, rows: usize) {
+        if rows != self.scroll {
+            self.scroll = rows;
+            self.dirty = true;
+        }
+    }
+
+    fn lines_per_page(&self, height: usize) -> usize {
+        let step = self.style.font.height + self.style.spacing;
+
+        ((height + self.style.spacing) / step).max(1)
+    }
+
+    fn upright(&self) -> TextStyle<'static> {
+        self.style.orientation(Orientation::Horizontal)
+    }
+}
+
+impl Widget for TextBox {
+    fn render(&mut self, canvas: &mut Bitmap8, area: Rect) {
+        let style = self.upright();
+        let step = (style.font.height + style.spacing) as i32;
+
+        // Lines are drawn whole then cut to the area, so half a line can
+        // show at the edges while scrolling
+        let mut scratch = canvas.clone();
+
+        for (index, line) in self.lines(area.size.width).iter().enumerate() {
+            let top = area.top() + index as i32 * step - self.scroll as i32;
+
+            if top + step <= area.top() || top > area.bottom() {
+                continue;
+            }
+
+            let spare = area.size.width.saturating_sub(style.measure(line).width) as i32;
+            let left = match self.alignment {
+                Alignment::Left => 0,
+                Alignment::Center => spare / 2,
+                Alignment::Right => spare,
+            };
+
+            scratch.draw_text_styled((area.left() + left, top), line, &style);
+        }
+
+        for x in area.left() ..= area.right() {
+            for y in area.top() ..= area.bottom() {
+                if let Some(value) = scratch.pixel(Point::new(x, y)) {
+                    canvas.set_pixel(Point::new(x, y), value);
+                }
+            }
+        }
+    }
+
+    fn is_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DISPLAY_HEIGHT;
+
+    #[test]
+    fn vertical_bars_with_a_divider() {
+        let mut graph = BarGraph::new(8)
+            .foreground(9)
+            .background(1)
+            .border(BarBorder::Ends, 0)
+            .divider(Some(4));
+        graph.set_values(&[100.0, 50.0, 0.0, 0.0, 25.0]);
+
+        let mut canvas = Bitmap8::new();
+        let area = Rect::new((0, 14), (9, 20));
+        graph.render(&mut canvas, area);
+
+        // Ends of the border, then 18 rows inside
+        assert_eq!(canvas.pixel(Point::new(0, 14)), Some(0));
+        assert_eq!(canvas.pixel(Point::new(0, 15)), Some(9));
+        assert_eq!(canvas.pixel(Point::new(1, 23)), Some(1));
+        assert_eq!(canvas.pixel(Point::new(1, 24)), Some(9));
+        assert_eq!(canvas.pixel(Point::new(2, 32)), Some(1));
+        // The divider, and the fifth bar pushed past it
+        assert_eq!(canvas.pixel(Point::new(4, 20)), Some(0));
+        assert_eq!(canvas.pixel(Point::new(5, 28)), Some(9));
+        assert_eq!(canvas.pixel(Point::new(5, 27)), Some(1));
+        assert_eq!(canvas.pixel(Point::new(8, DISPLAY_HEIGHT as i32 - 1)), Some(0));
+    }
+
+    #[test]
+    fn horizontal_bars_in_a_range() {
+        let mut graph = BarGraph::new(2)
+            .orientation(BarOrientation::Horizontal)
+            .range(-60.0, 0.0)
+            .border(BarBorder::Around, 5);
+        assert!(graph.is_dirty());
+        graph.set_values(&[-90.0]);
+        graph.set_value(1, -30.0);
+        assert!(graph.is_dirty());
+        assert!(!graph.is_dirty());
+
+        let mut canvas = Bitmap8::new();
+        graph.render(&mut canvas, Rect::new((0, 0), (9, 6)));
+
+        assert_eq!(canvas.pixel(Point::new(0, 1)), Some(5));
+        // Two bars two rows thick, the second half full
+        assert_eq!(canvas.pixel(Point::new(1, 2)), Some(0));
+        assert_eq!(canvas.pixel(Point::new(1, 3)), Some(0xff));
+        assert_eq!(canvas.pixel(Point::new(4, 4)), Some(0xff));
+        assert_eq!(canvas.pixel(Point::new(5, 4)), Some(0));
+    }
+
+    #[test]
+    fn history_scrolls_down() {
+        let mut graph = HistoryGraph::new().capacity(3).range(0.0, 9.0);
+        let area = Rect::new((0, 10), (9, 4));
+        let mut canvas = Bitmap8::new();
+
+        for value in [9.0, 3.0, 0.0, 6.0] {
+            graph.push(value);
+        }
+
+        assert_eq!(graph.samples().collect::<Vec<_>>(), [6.0, 0.0, 3.0]);
+        assert!(graph.is_dirty());
+        graph.render(&mut canvas, area);
+
+        let lit = |canvas: &Bitmap8, y: i32| (0 .. 9).filter(|x| canvas.pixel(Point::new(*x, y)) == Some(0xff)).count();
+        assert_eq!([lit(&canvas, 10), lit(&canvas, 11), lit(&canvas, 12), lit(&canvas, 13)], [6, 0, 3, 0]);
+
+        // A sparkline scaled to the biggest sample, coming in at the bottom
+        let mut graph = graph.style(HistoryStyle::Line).auto_range(true).newest_at_bottom(true);
+        graph.render(&mut canvas, area);
+        assert_eq!(canvas.pixel(Point::new(8, 13)), Some(0xff));
+        assert_eq!(canvas.pixel(Point::new(0, 12)), Some(0xff));
+        assert_eq!(canvas.pixel(Point::new(4, 11)), Some(0xff));
+        assert_eq!(lit(&canvas, 10), 0);
+    }
+
+    #[test]
+    fn clock_stacks_hours_over_minutes() {
+        let time = std::rc::Rc::new(std::cell::Cell::new((13, 5, 0)));
+        let source = time.clone();
+        let mut clock = DigitalClock::with_source(move || source.get()).twelve_hour(true);
+
+        let mut canvas = Bitmap8::new();
+        clock.render(&mut canvas, Rect::display());
+
+        let mut expected = Bitmap8::new();
+        expected.draw_text((1, 10), "01", 0xff);
+        expected.draw_text((1, 18), "05", 0xff);
+        expected.set_pixel(Point::new(2, 16), 0xff);
+        expected.set_pixel(Point::new(6, 16), 0xff);
+        assert_eq!(canvas.data(), expected.data());
+
+        // The dots go out on odd seconds, and nothing else changes
+        assert!(clock.is_dirty());
+        time.set((13, 5, 1));
+        clock.update();
+        assert!(clock.is_dirty());
+        clock.update();
+        assert!(!clock.is_dirty());
+    }
+
+    #[test]
+    fn battery_reads_and_fills() {
+        let root = std::env::temp_dir().join(format!("f16_hid_battery_{}", std::process::id()));
+        for (name, kind) in [("AC", "Mains"), ("BAT1", "Battery")] {
+            std::fs::create_dir_all(root.join(name)).unwrap();
+            std::fs::write(root.join(name).join("type"), format!("{}\n", kind)).unwrap();
+        }
+        std::fs::write(root.join("BAT1/capacity"), "50\n").unwrap();
+        std::fs::write(root.join("BAT1/status"), "Discharging\n").unwrap();
+
+        let state = BatteryState::read_from(&root);
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(state, Some(BatteryState { level: 0.5, charging: false }));
+
+        let mut battery = Battery::manual();
+        assert_eq!(battery.update_interval(), None);
+        battery.set_state(state);
+
+        let mut canvas = Bitmap8::new();
+        battery.render(&mut canvas, Rect::new((0, 0), (5, 11)));
+
+        // Eight rows inside the outline, the bottom four lit
+        assert_eq!(canvas.pixel(Point::new(2, 0)), Some(0xff));
+        assert_eq!(canvas.pixel(Point::new(0, 0)), Some(0));
+        assert_eq!(canvas.pixel(Point::new(2, 5)), Some(0));
+        assert_eq!(canvas.pixel(Point::new(2, 6)), Some(0xff));
+        assert_eq!(canvas.pixel(Point::new(2, 10)), Some(0xff));
+    }
+
+    #[test]
+    fn text_boxes_wrap_align_and_scroll() {
+        let mut text = TextBox::new("HI A BC").alignment(Alignment::Right);
+        assert_eq!(text.lines(9), ["HI", "A", "BC"]);
+        assert_eq!(text.height(9), 17);
+        assert_eq!(text.pages(9, 12), 2);
+
+        let mut canvas = Bitmap8::new();
+        let area = Rect::new((0, 0), (9, 12));
+        text.render(&mut canvas, area);
+
+        // The A sits against the right edge, its crossbar at the top
+        assert_ne!(canvas.pixel(Point::new(7, 6)), Some(0));
+        assert_eq!(canvas.pixel(Point::new(0, 7)), Some(0));
+        // The third line is cut off at the bottom of the area
+        assert!((12 .. DISPLAY_HEIGHT as i32).all(|y| canvas.pixel(Point::new(0, y)) == Some(0)));
+
+        text.set_page(1, 12);
+        assert_eq!(text.scroll(), 12);
+        assert!(text.is_dirty());
+
+        let mut canvas = Bitmap8::new();
+        text.render(&mut canvas, area);
+        // The last line, right aligned
+        assert_ne!(canvas.pixel(Point::new(2, 0)), Some(0));
+    }
+}